@@ -133,8 +133,8 @@ static SHIFTED_KEYMAP: &[Keymap] = &[
 pub fn on_keyboard_interrupt() {
     // Modifier keys
     let shift: bool = L_SHIFT.load(Relaxed) || R_SHIFT.load(Relaxed);
-    // TODO: Handle ctrl and alt?
-    let _ctrl: bool = L_CTRL.load(Relaxed) || R_CTRL.load(Relaxed);
+    // TODO: Handle alt?
+    let ctrl: bool = L_CTRL.load(Relaxed) || R_CTRL.load(Relaxed);
     let _alt: bool = L_ALT.load(Relaxed) || R_ALT.load(Relaxed);
 
     // Read the scancode
@@ -186,6 +186,11 @@ pub fn on_keyboard_interrupt() {
             c = c.to_ascii_lowercase();
         }
 
+        // Control characters, e.g. Ctrl+D is 0x04
+        if ctrl && (b'@'..=b'_').contains(&c.to_ascii_uppercase()) {
+            c = c.to_ascii_uppercase() & 0x1f;
+        }
+
         // Add to buffer
         unwrap_system().input_buffer.lock().putc(c);
     } else {
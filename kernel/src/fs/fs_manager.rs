@@ -1,10 +1,15 @@
 use crate::fs::pipe::{PipeInner, PipeReadEnd, PipeWriteEnd};
+use crate::fs::socket::{Socket, SocketHandle};
 use crate::fs::{FileDescriptor, ProcessFileDescriptor};
-use crate::mem::vma::{VMAInfo, VMA};
+use crate::mem::vma::{SharedMemory, VMAInfo, VMAList, VMA};
 use crate::sync::mutex::Mutex;
-use crate::system::{running_process, unwrap_system};
+use crate::sync::wait_queue::WaitQueue;
+use crate::system::unwrap_system;
 use crate::threading::{process::Pid, thread_control_block::ProcessControlBlock};
-use crate::user_program::syscall::Dirent;
+use crate::user_program::syscall::{
+    Dirent, WinSize, O_ACCMODE, O_APPEND, O_CLOEXEC, O_CREATE, O_DIRECTORY, O_EXCL, O_NONBLOCK,
+    O_RDONLY, O_RDWR, O_TRUNC, O_WRONLY, POLLIN, POLLOUT,
+};
 use crate::vfs::{
     Error, FileHandle, FileInfo, FileSystem, INodeNum, INodeType, OwnedDirEntry, OwnedPath, Path,
     Result,
@@ -13,7 +18,7 @@ use alloc::borrow::Cow;
 use alloc::sync::Arc;
 use alloc::{
     boxed::Box,
-    collections::{btree_map::Entry as BTreeMapEntry, BTreeMap},
+    collections::{btree_map::Entry as BTreeMapEntry, BTreeMap, BTreeSet},
     format,
     string::String,
     vec,
@@ -24,7 +29,8 @@ use core::fmt::Debug;
 use core::mem::{align_of, size_of};
 use core::num::NonZeroUsize;
 use core::sync::atomic::Ordering;
-use kidneyos_shared::mem::PAGE_FRAME_SIZE;
+use kidneyos_shared::mem::{OFFSET, PAGE_FRAME_SIZE};
+use kidneyos_shared::video_memory::{VIDEO_MEMORY_COLS, VIDEO_MEMORY_LINES};
 
 /// Possible places to seek from
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
@@ -41,11 +47,24 @@ pub enum Mode {
     ReadWrite,
     /// Open or create file for read/write access
     CreateReadWrite,
-    // could add ReadOnly, WriteOnly, etc. here
-    // - depends whether we want support for file permissions
-    // (if not, we could just do that at the libc level)
 }
 
+impl Mode {
+    /// The open flags this mode stands for, see [`RootFileSystem::open_with_flags`].
+    pub fn flags(self) -> usize {
+        match self {
+            Mode::ReadWrite => O_RDWR,
+            Mode::CreateReadWrite => O_RDWR | O_CREATE,
+        }
+    }
+}
+
+/// The open flags which are kept for as long as a file is open: its access mode, `O_APPEND`
+/// and `O_NONBLOCK`.
+const STATUS_FLAGS: usize = O_ACCMODE | O_APPEND | O_NONBLOCK;
+/// The status flags which can be changed after opening a file.
+const SETTABLE_STATUS_FLAGS: usize = O_APPEND | O_NONBLOCK;
+
 /// Maximum number of simultaneously open files for a process.
 ///
 /// 1024 is the default on Linux.
@@ -289,6 +308,7 @@ trait FileSystemManagerTrait: 'static + Send + Sync {
     fn write(&mut self, fd: ProcessFileDescriptor, offset: u64, buf: &[u8]) -> Result<usize>;
     fn sync(&mut self) -> Result<()>;
     fn mkdir(&mut self, parent: INodeNum, name: &Path) -> Result<()>;
+    fn mknod(&mut self, parent: INodeNum, name: &Path, r#type: INodeType) -> Result<INodeNum>;
     fn can_be_safely_unmounted(&self) -> bool;
     fn mount(&mut self, dir: INodeNum, fs: FileSystemID) -> Result<()>;
     fn unmount(&mut self, dir: INodeNum) -> Result<()>;
@@ -329,6 +349,8 @@ trait FileSystemManagerTrait: 'static + Send + Sync {
     fn dec_ref(&mut self, inode: INodeNum);
     /// Read bytes directly from a file
     fn read_direct(&mut self, inode: INodeNum, offset: u64, buf: &mut [u8]) -> Result<usize>;
+    /// Write bytes directly to a file, without growing it
+    fn write_direct(&mut self, inode: INodeNum, offset: u64, buf: &[u8]) -> Result<usize>;
 }
 
 /// get parent directory and name of absolute path
@@ -410,6 +432,22 @@ impl<F: 'static + FileSystem> FileSystemManagerTrait for FileSystemManager<F> {
         self.directories.insert(inode, Directory::empty(parent));
         Ok(())
     }
+    fn mknod(&mut self, parent: INodeNum, name: &Path, r#type: INodeType) -> Result<INodeNum> {
+        if name.is_empty() || name == "." || name == ".." {
+            return Err(Error::Exists);
+        }
+        // ensure directory entries are in cache, so that the new one can be added
+        let _ = self.lookup(parent, "x");
+        let mut parent_dir = self.temp_open(parent)?;
+        let result = self.fs.mknod(&mut parent_dir.handle, name, r#type);
+        self.temp_close(parent_dir);
+        let inode = result?;
+        self.directories
+            .get_mut(&parent)
+            .unwrap()
+            .add(inode, r#type, name);
+        Ok(inode)
+    }
     fn read(&mut self, fd: ProcessFileDescriptor, offset: u64, buf: &mut [u8]) -> Result<usize> {
         let handle = self.open_files.get_mut(&fd).ok_or(Error::BadFd)?;
         self.fs.read(handle, offset, buf)
@@ -577,7 +615,7 @@ impl<F: 'static + FileSystem> FileSystemManagerTrait for FileSystemManager<F> {
         self.directories
             .get_mut(&parent)
             .unwrap()
-            .add(source, INodeType::File, name);
+            .add(source, source_info.r#type, name);
         Ok(())
     }
     fn symlink(&mut self, link: &Path, parent: INodeNum, name: &Path) -> Result<()> {
@@ -671,6 +709,27 @@ impl<F: 'static + FileSystem> FileSystemManagerTrait for FileSystemManager<F> {
             }
         }
     }
+    fn write_direct(&mut self, inode: INodeNum, offset: u64, buf: &[u8]) -> Result<usize> {
+        let mut handle = self.temp_open(inode)?;
+        let result = self.fs.stat(&handle.handle).and_then(|info| {
+            // only overwrite the data that's already there
+            let len = min(info.size.saturating_sub(offset), buf.len() as u64) as usize;
+            let mut bytes_written = 0;
+            while bytes_written < len {
+                match self.fs.write(
+                    &mut handle.handle,
+                    offset + bytes_written as u64,
+                    &buf[bytes_written..len],
+                )? {
+                    0 => break,
+                    n => bytes_written += n,
+                }
+            }
+            Ok(bytes_written)
+        });
+        self.temp_close(handle);
+        result
+    }
 }
 
 pub type FileSystemID = u16;
@@ -686,8 +745,8 @@ enum OpenFile {
         is_dir: bool,
     },
 
-    /// standard output
-    StdOut,
+    /// the console terminal, which reads from the keyboard and writes to the screen
+    Terminal,
     /// `/dev/null` (discards reads/writes)
     Null,
 
@@ -695,6 +754,16 @@ enum OpenFile {
     PipeRead(PipeReadEnd),
     // Write end of a pipe
     PipeWrite(PipeWriteEnd),
+    /// a named pipe, with the ends of it that were opened. It's also open in
+    /// its file system, like a regular file.
+    Fifo {
+        fs: FileSystemID,
+        inode: INodeNum,
+        read: Option<PipeReadEnd>,
+        write: Option<PipeWriteEnd>,
+    },
+    /// a Unix domain socket
+    Socket(SocketHandle),
 }
 
 // wrapper around an array of filesystems for convenience
@@ -758,6 +827,14 @@ pub struct RootFileSystem {
     file_systems: FileSystemList,
     root_mount: Option<FileSystemID>,
     open_files: BTreeMap<ProcessFileDescriptor, OpenFile>,
+    /// Status flags of each open file, see [`STATUS_FLAGS`]
+    flags: BTreeMap<ProcessFileDescriptor, usize>,
+    /// File descriptors which should be closed when their process calls execve
+    close_on_exec: BTreeSet<ProcessFileDescriptor>,
+    /// The pipe of each named pipe which is open
+    fifos: BTreeMap<(FileSystemID, INodeNum), Arc<PipeInner>>,
+    /// The socket bound to each socket file, while it's open
+    sockets: BTreeMap<(FileSystemID, INodeNum), Arc<Socket>>,
 }
 
 impl RootFileSystem {
@@ -766,6 +843,10 @@ impl RootFileSystem {
             file_systems: FileSystemList::new(),
             root_mount: None,
             open_files: BTreeMap::new(),
+            flags: BTreeMap::new(),
+            close_on_exec: BTreeSet::new(),
+            fifos: BTreeMap::new(),
+            sockets: BTreeMap::new(),
         }
     }
     fn resolve_path_relative_to(
@@ -838,16 +919,43 @@ impl RootFileSystem {
         let root_fs = self.root_mount.ok_or(Error::NotFound)?;
         Ok((root_fs, self.file_systems.get(root_fs).root()))
     }
-    fn new_fd(&mut self, pid: Pid, file_info: OpenFile) -> Result<ProcessFileDescriptor> {
-        for fd in 0..MAX_OPEN_FILES as FileDescriptor {
+    fn new_fd(
+        &mut self,
+        pid: Pid,
+        file_info: OpenFile,
+        flags: usize,
+    ) -> Result<ProcessFileDescriptor> {
+        self.new_fd_from(pid, 0, file_info, flags)
+    }
+    /// Add an open file as the lowest file descriptor of `pid` that is free and at least
+    /// `lowest`, with the status flags in `flags`, closing it on exec if they include
+    /// `O_CLOEXEC`.
+    fn new_fd_from(
+        &mut self,
+        pid: Pid,
+        lowest: FileDescriptor,
+        file_info: OpenFile,
+        flags: usize,
+    ) -> Result<ProcessFileDescriptor> {
+        for fd in lowest..MAX_OPEN_FILES as FileDescriptor {
             let fd = ProcessFileDescriptor { pid, fd };
             if let alloc::collections::btree_map::Entry::Vacant(entry) = self.open_files.entry(fd) {
                 entry.insert(file_info);
+                self.flags.insert(fd, flags & STATUS_FLAGS);
+                if (flags & O_CLOEXEC) != 0 {
+                    self.close_on_exec.insert(fd);
+                }
                 return Ok(fd);
             }
         }
         Err(Error::TooManyOpenFiles)
     }
+    /// Forget about `fd` without closing it in its file system.
+    fn remove_fd(&mut self, fd: ProcessFileDescriptor) {
+        self.open_files.remove(&fd);
+        self.flags.remove(&fd);
+        self.close_on_exec.remove(&fd);
+    }
     pub fn mount<F: FileSystem + 'static>(
         &mut self,
         process: &ProcessControlBlock,
@@ -896,37 +1004,64 @@ impl RootFileSystem {
         let read_end = self.new_fd(
             pid,
             OpenFile::PipeRead(PipeInner::read_end(pipe_inner.clone())),
+            O_RDONLY,
         )?;
 
-        let write_end = self.new_fd(pid, OpenFile::PipeWrite(PipeInner::write_end(pipe_inner)))?;
+        let write_end = self.new_fd(
+            pid,
+            OpenFile::PipeWrite(PipeInner::write_end(pipe_inner)),
+            O_WRONLY,
+        )?;
 
         Ok((read_end.fd, write_end.fd))
     }
     fn dup_inc_ref(&mut self, open_file: &OpenFile) {
-        if let OpenFile::Regular { fs, inode, .. } = open_file {
+        if let OpenFile::Regular { fs, inode, .. } | OpenFile::Fifo { fs, inode, .. } = open_file {
             self.file_systems.get_mut(*fs).inc_ref(*inode);
         }
     }
     pub fn dup(&mut self, pid: Pid, fd: ProcessFileDescriptor) -> Result<FileDescriptor> {
+        self.dup_from(pid, fd, 0, false)
+    }
+    /// Duplicate `fd` into the lowest file descriptor of `pid` that is free and at least
+    /// `lowest`, which is closed on exec if `close_on_exec`.
+    pub fn dup_from(
+        &mut self,
+        pid: Pid,
+        fd: ProcessFileDescriptor,
+        lowest: FileDescriptor,
+        close_on_exec: bool,
+    ) -> Result<FileDescriptor> {
         let open_file = self.open_files.get_mut(&fd).ok_or(Error::BadFd)?;
 
         let new_file = open_file.clone();
+        let mut flags = self.status_flags(fd)?;
+        if close_on_exec {
+            flags |= O_CLOEXEC;
+        }
         self.dup_inc_ref(&new_file);
 
-        Ok(self.new_fd(pid, new_file)?.fd)
+        Ok(self.new_fd_from(pid, lowest, new_file, flags)?.fd)
     }
     pub fn dup2(&mut self, fd: ProcessFileDescriptor, into: ProcessFileDescriptor) -> Result<()> {
-        if self.open_files.contains_key(&into) {
-            self.close(fd).ok(); // errors are discarded
-        }
-
         let open_file = self.open_files.get_mut(&fd).ok_or(Error::BadFd)?;
+        if fd == into {
+            return Ok(());
+        }
 
         // Note on cloning in self.dup() function.
         let new_file = open_file.clone();
+        let flags = self.status_flags(fd)?;
+
+        if self.open_files.contains_key(&into) {
+            self.close(into).ok(); // errors are discarded
+        }
+
         self.dup_inc_ref(&new_file);
 
         self.open_files.insert(into, new_file);
+        self.flags.insert(into, flags);
+        self.close_on_exec.remove(&into);
 
         Ok(())
     }
@@ -936,9 +1071,25 @@ impl RootFileSystem {
         path: &Path,
         mode: Mode,
     ) -> Result<FileDescriptor> {
-        let (fs, inode) = match mode {
-            Mode::ReadWrite => self.resolve_path(process, path)?,
-            Mode::CreateReadWrite => self.resolve_path(process, dirname_of(path))?,
+        self.open_with_flags(process, path, mode.flags())
+    }
+    /// Open a file with the `O_*` flags of the open syscall.
+    ///
+    /// Flags which don't matter to the kernel, such as `O_NOCTTY`, are ignored.
+    pub fn open_with_flags(
+        &mut self,
+        process: &ProcessControlBlock,
+        path: &Path,
+        flags: usize,
+    ) -> Result<FileDescriptor> {
+        let create = (flags & O_CREATE) != 0;
+        if create && (flags & O_EXCL) != 0 && self.resolve_path(process, path).is_ok() {
+            return Err(Error::Exists);
+        }
+        let (fs, inode) = if create {
+            self.resolve_path(process, dirname_of(path))?
+        } else {
+            self.resolve_path(process, path)?
         };
         let fd = self.new_fd(
             process.pid,
@@ -948,37 +1099,141 @@ impl RootFileSystem {
                 offset: 0,
                 is_dir: false,
             },
+            flags,
         )?;
-        let fs = self.file_systems.get_mut(fs);
-        let result = match mode {
-            Mode::ReadWrite => {
-                fs.open(inode, fd).and_then(|()| {
-                    if fs.fstat(fd)?.r#type == INodeType::Directory {
-                        // set is_dir to true in open file info
-                        let OpenFile::Regular { is_dir, .. } =
-                            self.open_files.get_mut(&fd).unwrap()
-                        else {
-                            panic!();
-                        };
-                        *is_dir = true;
-                    }
-                    Ok(())
-                })
-            }
-            Mode::CreateReadWrite => fs.create(inode, filename_of(path), fd),
+        let file_system = self.file_systems.get_mut(fs);
+        let result = if create {
+            file_system.create(inode, filename_of(path), fd)
+        } else {
+            file_system.open(inode, fd)
         };
         if let Err(e) = result {
-            self.open_files.remove(&fd);
+            self.remove_fd(fd);
+            return Err(e);
+        }
+        // the file system has the file open now, so it has to be closed properly
+        if let Err(e) = self.finish_open(fd, flags) {
+            let _ = self.close(fd);
+            return Err(e);
+        }
+        Ok(fd.fd)
+    }
+    /// Check that the file just opened as `fd` is the kind `flags` asks for, and truncate it if
+    /// they ask for that.
+    fn finish_open(&mut self, fd: ProcessFileDescriptor, flags: usize) -> Result<()> {
+        let OpenFile::Regular { fs, is_dir, .. } = self.open_files.get_mut(&fd).unwrap() else {
+            panic!();
+        };
+        let fs = self.file_systems.get_mut(*fs);
+        let r#type = fs.fstat(fd)?.r#type;
+        if r#type == INodeType::Socket {
+            // sockets are connected to, not opened
+            return Err(Error::NoDeviceOrAddress);
+        }
+        if r#type == INodeType::Fifo {
+            if (flags & O_DIRECTORY) != 0 {
+                return Err(Error::NotDirectory);
+            }
+            return self.open_fifo(fd, flags);
+        }
+        *is_dir = r#type == INodeType::Directory;
+        if *is_dir {
+            if (flags & O_CREATE) != 0 {
+                return Err(Error::IsDirectory);
+            }
+        } else {
+            if (flags & O_DIRECTORY) != 0 {
+                return Err(Error::NotDirectory);
+            }
+            if (flags & O_TRUNC) != 0 && (flags & O_ACCMODE) != O_RDONLY {
+                fs.ftruncate(fd, 0)?;
+            }
+        }
+        Ok(())
+    }
+    /// Turn `fd`, which was just opened on a named pipe, into the ends of its pipe that `flags`
+    /// ask for. Every open file on the same named pipe shares its pipe.
+    fn open_fifo(&mut self, fd: ProcessFileDescriptor, flags: usize) -> Result<()> {
+        let OpenFile::Regular { fs, inode, .. } = *self.open_files.get(&fd).unwrap() else {
+            panic!();
+        };
+        let pipe = self.fifos.entry((fs, inode)).or_default().clone();
+        let access = flags & O_ACCMODE;
+        let (read, write) = (access != O_WRONLY, access != O_RDONLY);
+        if !read && (flags & O_NONBLOCK) != 0 && pipe.read_ends.load(Ordering::SeqCst) == 0 {
+            // Without waiting, there's no reader for writes to go to.
+            self.release_fifo((fs, inode));
+            return Err(Error::NoDeviceOrAddress);
+        }
+        let (read, write) = PipeInner::open_ends(&pipe, read, write);
+        self.open_files.insert(
+            fd,
+            OpenFile::Fifo {
+                fs,
+                inode,
+                read,
+                write,
+            },
+        );
+        Ok(())
+    }
+    /// Forget the pipe of a named pipe once it's no longer open, discarding anything left in it.
+    fn release_fifo(&mut self, fifo: (FileSystemID, INodeNum)) {
+        if self.fifos.get(&fifo).is_some_and(|pipe| pipe.is_closed()) {
+            self.fifos.remove(&fifo);
+        }
+    }
+    /// Open a file like [`Self::open_with_flags`], then, if it's a named pipe opened just for
+    /// reading or just for writing, block until its other end is opened, unless `flags` include
+    /// `O_NONBLOCK`.
+    ///
+    /// This takes a `Mutex<Self>` for the same reason as [`Self::read`].
+    pub fn open_and_wait(
+        fs: &Mutex<Self>,
+        process: &Mutex<ProcessControlBlock>,
+        path: &Path,
+        flags: usize,
+    ) -> Result<FileDescriptor> {
+        let mut file_system = fs.lock();
+        let pcb = process.lock();
+        let fd = ProcessFileDescriptor {
+            pid: pcb.pid,
+            fd: file_system.open_with_flags(&pcb, path, flags)?,
+        };
+        drop(pcb);
+        let (pipe, write) = match file_system.open_files.get(&fd) {
+            Some(OpenFile::Fifo {
+                read: Some(pipe),
+                write: None,
+                ..
+            }) => (pipe.0.clone(), false),
+            Some(OpenFile::Fifo {
+                read: None,
+                write: Some(pipe),
+                ..
+            }) => (pipe.0.clone(), true),
+            _ => return Ok(fd.fd),
+        };
+        if (flags & O_NONBLOCK) != 0 {
+            return Ok(fd.fd);
+        }
+        // Ends are only opened with the file system locked, so this counts
+        // every open of the other end up to ours.
+        let seen = pipe.peer_opens(write);
+        drop(file_system); // don't hold the mutex while we wait for the other end
+
+        if let Err(e) = pipe.wait_for_peer(write, seen) {
+            let _ = fs.lock().close(fd);
             return Err(e);
         }
         Ok(fd.fd)
     }
-    pub fn open_stdout(&mut self, pid: Pid) -> Result<FileDescriptor> {
-        let fd = self.new_fd(pid, OpenFile::StdOut)?;
+    pub fn open_terminal(&mut self, pid: Pid) -> Result<FileDescriptor> {
+        let fd = self.new_fd(pid, OpenFile::Terminal, O_RDWR)?;
         Ok(fd.fd)
     }
     pub fn open_null(&mut self, pid: Pid) -> Result<FileDescriptor> {
-        let fd = self.new_fd(pid, OpenFile::Null)?;
+        let fd = self.new_fd(pid, OpenFile::Null, O_RDWR)?;
         Ok(fd.fd)
     }
     /// Close an open file
@@ -987,13 +1242,28 @@ impl RootFileSystem {
     /// and you should not try to close it again (as on Linux).
     pub fn close(&mut self, fd: ProcessFileDescriptor) -> Result<()> {
         let mut result = Ok(());
+        let mut fifo = None;
+        let mut socket = None;
         let file_info = self.open_files.get(&fd).ok_or(Error::BadFd)?;
-        if let OpenFile::Regular { fs, .. } = file_info {
-            let fs = self.file_systems.get_mut(*fs);
-            result = fs.close(fd);
+        match file_info {
+            &OpenFile::Regular { fs, .. } => {
+                result = self.file_systems.get_mut(fs).close(fd);
+            }
+            &OpenFile::Fifo { fs, inode, .. } => {
+                result = self.file_systems.get_mut(fs).close(fd);
+                fifo = Some((fs, inode));
+            }
+            OpenFile::Socket(handle) => socket = Some(handle.0.clone()),
+            // don't need to do anything for other files
+            _ => {}
+        }
+        self.remove_fd(fd);
+        if let Some(fifo) = fifo {
+            self.release_fifo(fifo);
+        }
+        if let Some(socket) = socket {
+            self.release_socket(&socket);
         }
-        // don't need to do anything for non-regular files
-        self.open_files.remove(&fd);
         result
     }
     pub fn mkdir(&mut self, process: &ProcessControlBlock, path: &Path) -> Result<()> {
@@ -1002,6 +1272,84 @@ impl RootFileSystem {
         let fs = self.file_systems.get_mut(fs);
         fs.mkdir(parent, name)
     }
+    pub fn mkfifo(&mut self, process: &ProcessControlBlock, path: &Path) -> Result<()> {
+        self.mknod(process, path, INodeType::Fifo)?;
+        Ok(())
+    }
+    /// Create a special file of type `r#type` (a named pipe or socket) at `path`, returning
+    /// where it is.
+    fn mknod(
+        &mut self,
+        process: &ProcessControlBlock,
+        path: &Path,
+        r#type: INodeType,
+    ) -> Result<(FileSystemID, INodeNum)> {
+        let (parent, name) = dirname_and_filename(path);
+        let (fs, parent) = self.resolve_path(process, parent)?;
+        let inode = self.file_systems.get_mut(fs).mknod(parent, name, r#type)?;
+        Ok((fs, inode))
+    }
+    /// Open `socket` as a new file descriptor of `pid`, with the status flags in `flags`.
+    pub fn socket(
+        &mut self,
+        pid: Pid,
+        socket: Arc<Socket>,
+        flags: usize,
+    ) -> Result<FileDescriptor> {
+        let file_info = OpenFile::Socket(SocketHandle::new(socket));
+        let fd = self.new_fd(pid, file_info, O_RDWR | flags)?;
+        Ok(fd.fd)
+    }
+    /// The socket open as `fd`.
+    pub fn socket_of(&self, fd: ProcessFileDescriptor) -> Result<Arc<Socket>> {
+        match self.open_files.get(&fd).ok_or(Error::BadFd)? {
+            OpenFile::Socket(handle) => Ok(handle.0.clone()),
+            _ => Err(Error::NotSocket),
+        }
+    }
+    /// Bind the socket open as `fd` to `path`, creating a socket file there, so that other
+    /// sockets can find it with [`Self::bound_socket`].
+    pub fn bind(
+        &mut self,
+        process: &ProcessControlBlock,
+        fd: ProcessFileDescriptor,
+        path: &Path,
+    ) -> Result<()> {
+        let socket = self.socket_of(fd)?;
+        if socket.name().is_some() {
+            return Err(Error::BadSocketState);
+        }
+        let key = match self.mknod(process, path, INodeType::Socket) {
+            Err(Error::Exists) => return Err(Error::AddressInUse),
+            result => result?,
+        };
+        socket.set_name(path)?;
+        self.sockets.insert(key, socket);
+        Ok(())
+    }
+    /// The socket bound to `path`.
+    pub fn bound_socket(
+        &mut self,
+        process: &ProcessControlBlock,
+        path: &Path,
+    ) -> Result<Arc<Socket>> {
+        let (fs, inode) = self.resolve_path(process, path)?;
+        // The inode could have been reused since the socket was bound.
+        if self.file_systems.get_mut(fs).inode_type(inode)? != INodeType::Socket {
+            return Err(Error::ConnectionRefused);
+        }
+        self.sockets
+            .get(&(fs, inode))
+            .cloned()
+            .ok_or(Error::ConnectionRefused)
+    }
+    /// Forget the file `socket` is bound to once it's no longer open, so that connecting to it
+    /// is refused.
+    fn release_socket(&mut self, socket: &Arc<Socket>) {
+        if socket.is_closed() {
+            self.sockets.retain(|_, bound| !Arc::ptr_eq(bound, socket));
+        }
+    }
 
     // Why take a Mutex<Self> instead of just &mut self?
     // Reads/Writes can be asynchronous, for example:
@@ -1011,6 +1359,7 @@ impl RootFileSystem {
         let mut file_system_guard = fs.lock();
         let file_system = &mut *file_system_guard;
 
+        let flags = file_system.check_access(fd, false)?;
         let file_info = file_system.open_files.get_mut(&fd).ok_or(Error::BadFd)?;
         match file_info {
             OpenFile::Regular {
@@ -1026,51 +1375,32 @@ impl RootFileSystem {
                 *offset += read_count as u64;
                 Ok(read_count)
             }
-            OpenFile::StdOut => {
-                // shouldn't read from stdout
-                Err(Error::BadFd)
+            OpenFile::Terminal => {
+                drop(file_system_guard); // don't hold the mutex while we wait for input
+
+                unwrap_system().tty.read(buf, (flags & O_NONBLOCK) != 0)
             }
-            OpenFile::PipeRead(pipe) => {
+            OpenFile::PipeRead(pipe)
+            | OpenFile::Fifo {
+                read: Some(pipe), ..
+            } => {
                 let inner = pipe.0.clone();
 
-                drop(file_system_guard); // don't hold the mutex while we are holding the condvar
-
-                loop {
-                    // forget = use the write
-                    inner.semaphore.acquire().forget();
-
-                    {
-                        let mut contents = inner.contents.lock();
-
-                        if !contents.is_empty() {
-                            let bytes_read = min(contents.len(), buf.len());
-
-                            // No easy way here to memcpy out of VecDeque. Might be the wrong type.
-                            for (i, byte) in contents.drain(0..bytes_read).enumerate() {
-                                buf[i] = byte
-                            }
-
-                            if !contents.is_empty() {
-                                // let another process know that the pipe is not empty
-                                inner.semaphore.post();
-                            }
-
-                            return Ok(bytes_read);
-                        }
-                    }
+                drop(file_system_guard); // don't hold the mutex while we wait for a writer
 
-                    if inner.write_ends.load(Ordering::SeqCst) == 0 {
-                        // keep spreading the signal, we don't have a broadcast
-                        inner.semaphore.post();
-
-                        return Ok(0); // no bytes left to read
-                    }
-                }
+                inner.read(buf, (flags & O_NONBLOCK) != 0)
             }
-            OpenFile::PipeWrite(_) => {
-                // Not open for writing.
+            OpenFile::PipeWrite(_) | OpenFile::Fifo { read: None, .. } => {
+                // Not open for reading.
                 Err(Error::BadFd)
             }
+            OpenFile::Socket(handle) => {
+                let socket = handle.0.clone();
+
+                drop(file_system_guard); // don't hold the mutex while we wait for the other end
+
+                Ok(socket.recv(buf, (flags & O_NONBLOCK) != 0)?.0)
+            }
             OpenFile::Null => Ok(0),
         }
     }
@@ -1078,6 +1408,7 @@ impl RootFileSystem {
         let mut file_system_guard = fs.lock();
         let file_system = &mut *file_system_guard;
 
+        let flags = file_system.check_access(fd, true)?;
         let file_info = file_system.open_files.get_mut(&fd).ok_or(Error::BadFd)?;
         match file_info {
             OpenFile::Regular {
@@ -1087,11 +1418,14 @@ impl RootFileSystem {
                     return Err(Error::IsDirectory);
                 }
                 let fs = file_system.file_systems.get_mut(*fs);
+                if (flags & O_APPEND) != 0 {
+                    *offset = fs.size_of_file(fd)?;
+                }
                 let write_count = fs.write(fd, *offset, buf)?;
                 *offset += write_count as u64;
                 Ok(write_count)
             }
-            OpenFile::StdOut => {
+            OpenFile::Terminal => {
                 use core::fmt::Write;
                 let string = String::from_utf8_lossy(buf);
                 // SAFETY: no other mut references to VIDEO_MEMORY_WRITER here
@@ -1104,32 +1438,70 @@ impl RootFileSystem {
                     Ok(buf.len())
                 }
             }
-            OpenFile::PipeRead(_) => {
+            OpenFile::PipeRead(_) | OpenFile::Fifo { write: None, .. } => {
                 // Not open for writing
                 Err(Error::BadFd)
             }
-            OpenFile::PipeWrite(pipe) => {
+            OpenFile::PipeWrite(pipe)
+            | OpenFile::Fifo {
+                write: Some(pipe), ..
+            } => {
                 let inner = pipe.0.clone();
 
                 drop(file_system_guard);
 
-                {
-                    let mut contents = inner.contents.lock();
-
-                    contents.extend(buf.iter());
-                }
-
-                if inner.read_ends.load(Ordering::SeqCst) == 0 {
-                    return Err(Error::PipeClosed);
-                }
+                inner.write(buf, (flags & O_NONBLOCK) != 0)
+            }
+            OpenFile::Socket(handle) => {
+                let socket = handle.0.clone();
 
-                inner.semaphore.post();
+                drop(file_system_guard);
 
-                Ok(buf.len())
+                socket.send(buf, None, (flags & O_NONBLOCK) != 0)
             }
             OpenFile::Null => Ok(buf.len()),
         }
     }
+    /// Read from `fd` at `offset`, without moving its file position.
+    pub fn read_at(
+        &mut self,
+        fd: ProcessFileDescriptor,
+        offset: u64,
+        buf: &mut [u8],
+    ) -> Result<usize> {
+        self.check_access(fd, false)?;
+        let fs = self.seekable_file_system(fd)?;
+        self.file_systems.get_mut(fs).read(fd, offset, buf)
+    }
+    /// Write to `fd` at `offset`, without moving its file position.
+    pub fn write_at(
+        &mut self,
+        fd: ProcessFileDescriptor,
+        offset: u64,
+        buf: &[u8],
+    ) -> Result<usize> {
+        self.check_access(fd, true)?;
+        let fs = self.seekable_file_system(fd)?;
+        self.file_systems.get_mut(fs).write(fd, offset, buf)
+    }
+    /// The status flags of `fd`, if it was opened for writing when `write` is true, or for
+    /// reading when it's false.
+    fn check_access(&self, fd: ProcessFileDescriptor, write: bool) -> Result<usize> {
+        let flags = self.status_flags(fd)?;
+        match (flags & O_ACCMODE, write) {
+            (O_RDONLY, true) | (O_WRONLY, false) => Err(Error::BadFd),
+            _ => Ok(flags),
+        }
+    }
+    /// The file system `fd` is on, if it's a file that can be read or written
+    /// at an offset.
+    fn seekable_file_system(&self, fd: ProcessFileDescriptor) -> Result<FileSystemID> {
+        match self.open_files.get(&fd).ok_or(Error::BadFd)? {
+            OpenFile::Regular { is_dir: true, .. } => Err(Error::IsDirectory),
+            &OpenFile::Regular { fs, .. } => Ok(fs),
+            _ => Err(Error::IllegalSeek),
+        }
+    }
     pub fn lseek(
         &mut self,
         fd: ProcessFileDescriptor,
@@ -1174,13 +1546,11 @@ impl RootFileSystem {
     ///
     /// Panics if the file descriptors 0, 1, 2 are already in use for pid.
     pub fn open_standard_fds(&mut self, pid: Pid) {
-        // for now, ignore stdin (we don't have keyboard input set up yet)
-        let stdin = self.open_null(pid).unwrap();
+        let stdin = self.open_terminal(pid).unwrap();
         assert_eq!(stdin, 0);
-        let stdout = self.open_stdout(pid).unwrap();
+        let stdout = self.open_terminal(pid).unwrap();
         assert_eq!(stdout, 1);
-        // stderr and stdout can just go to the same place for now
-        let stderr = self.open_stdout(pid).unwrap();
+        let stderr = self.open_terminal(pid).unwrap();
         assert_eq!(stderr, 2);
     }
     pub fn chdir(&mut self, process: &mut ProcessControlBlock, path: &Path) -> Result<()> {
@@ -1225,9 +1595,82 @@ impl RootFileSystem {
         }
         Ok(())
     }
+    /// Which of `POLLIN`, `POLLOUT`, `POLLHUP` and `POLLERR` `fd` is ready
+    /// for, and the queue which is woken when that changes, unless it never
+    /// does.
+    pub fn poll(&self, fd: ProcessFileDescriptor) -> Result<(i16, Option<WaitQueue>)> {
+        Ok(match self.open_files.get(&fd).ok_or(Error::BadFd)? {
+            // Regular files never block.
+            OpenFile::Regular { .. } | OpenFile::Null => (POLLIN | POLLOUT, None),
+            OpenFile::Terminal => {
+                let tty = &unwrap_system().tty;
+                (tty.read_readiness() | POLLOUT, Some(tty.waiters.clone()))
+            }
+            OpenFile::PipeRead(pipe) => (pipe.0.read_readiness(), Some(pipe.0.waiters.clone())),
+            OpenFile::PipeWrite(pipe) => (pipe.0.write_readiness(), Some(pipe.0.waiters.clone())),
+            OpenFile::Fifo { read, write, .. } => {
+                let read_events = read.as_ref().map_or(0, |pipe| pipe.0.read_readiness());
+                let write_events = write.as_ref().map_or(0, |pipe| pipe.0.write_readiness());
+                let pipe = match (read, write) {
+                    (Some(pipe), _) => &pipe.0,
+                    (None, Some(pipe)) => &pipe.0,
+                    (None, None) => unreachable!("named pipes are open for reading or writing"),
+                };
+                (read_events | write_events, Some(pipe.waiters.clone()))
+            }
+            OpenFile::Socket(handle) => {
+                let (events, waiters) = handle.0.readiness();
+                (events, Some(waiters))
+            }
+        })
+    }
+    /// The status flags of `fd`: its access mode, `O_APPEND` and `O_NONBLOCK`.
+    pub fn status_flags(&self, fd: ProcessFileDescriptor) -> Result<usize> {
+        self.flags.get(&fd).copied().ok_or(Error::BadFd)
+    }
+    /// Set the status flags of `fd` which can be changed after opening it, `O_APPEND` and
+    /// `O_NONBLOCK`, to those in `flags`. The others are ignored.
+    pub fn set_status_flags(&mut self, fd: ProcessFileDescriptor, flags: usize) -> Result<()> {
+        let status_flags = self.flags.get_mut(&fd).ok_or(Error::BadFd)?;
+        *status_flags = (*status_flags & !SETTABLE_STATUS_FLAGS) | (flags & SETTABLE_STATUS_FLAGS);
+        Ok(())
+    }
+    /// Whether `fd` is closed when its process calls execve.
+    pub fn is_close_on_exec(&self, fd: ProcessFileDescriptor) -> Result<bool> {
+        if !self.open_files.contains_key(&fd) {
+            return Err(Error::BadFd);
+        }
+        Ok(self.close_on_exec.contains(&fd))
+    }
+    pub fn set_close_on_exec(
+        &mut self,
+        fd: ProcessFileDescriptor,
+        close_on_exec: bool,
+    ) -> Result<()> {
+        if !self.open_files.contains_key(&fd) {
+            return Err(Error::BadFd);
+        }
+        if close_on_exec {
+            self.close_on_exec.insert(fd);
+        } else {
+            self.close_on_exec.remove(&fd);
+        }
+        Ok(())
+    }
+    /// The size of the terminal `fd` refers to.
+    pub fn window_size(&self, fd: ProcessFileDescriptor) -> Result<WinSize> {
+        match self.open_files.get(&fd).ok_or(Error::BadFd)? {
+            OpenFile::Terminal => Ok(WinSize {
+                rows: VIDEO_MEMORY_LINES as u16,
+                cols: VIDEO_MEMORY_COLS as u16,
+                ..WinSize::default()
+            }),
+            _ => Err(Error::NotTerminal),
+        }
+    }
     pub fn fstat(&mut self, fd: ProcessFileDescriptor) -> Result<FileInfo> {
         let file = self.open_files.get_mut(&fd).ok_or(Error::BadFd)?;
-        if let OpenFile::Regular { fs, .. } = file {
+        if let OpenFile::Regular { fs, .. } | OpenFile::Fifo { fs, .. } = file {
             self.file_systems.get_mut(*fs).fstat(fd)
         } else {
             Err(Error::NotFound)
@@ -1334,6 +1777,7 @@ impl RootFileSystem {
     }
 
     pub fn ftruncate(&mut self, fd: ProcessFileDescriptor, size: u64) -> Result<()> {
+        self.check_access(fd, true)?;
         let file_info = self.open_files.get_mut(&fd).ok_or(Error::BadFd)?;
         match file_info {
             OpenFile::Regular {
@@ -1370,13 +1814,97 @@ impl RootFileSystem {
             let pcb = pcb.lock();
             let (cwd_fs, cwd_inode) = pcb.cwd;
             self.file_systems.get_mut(cwd_fs).dec_ref(cwd_inode);
-            for (_addr, vma) in pcb.vmas.iter() {
-                if let VMAInfo::MMap { fs, inode, .. } = vma.info() {
-                    // decrease reference count to inode to let it be released.
-                    self.file_systems.get_mut(*fs).dec_ref(*inode);
+            self.release_mmaps(&pcb.vmas);
+        }
+    }
+
+    /// Release the inodes of all files mapped in `vmas`
+    ///
+    /// This should be called when the VMAs are being discarded.
+    pub fn release_mmaps(&mut self, vmas: &VMAList) {
+        for (_addr, vma) in vmas.iter() {
+            self.release_mmap(vma);
+        }
+    }
+
+    /// Write back the changes to a shared mapping of a file, and release its inode. Does nothing
+    /// if `vma` doesn't map a file.
+    ///
+    /// This should be called when the VMA is being discarded. All errors that occur while
+    /// writing back the changes are ignored.
+    pub fn release_mmap(&mut self, vma: &VMA) {
+        let VMAInfo::MMap { fs, inode, .. } = *vma.info() else {
+            return;
+        };
+        if let Some(shared) = vma.shared() {
+            let pages = vma.backing_page(0)..vma.backing_page(vma.size());
+            for (page, phys_addr) in shared.lock().frames(pages) {
+                // SAFETY: The shared memory holds a reference to the frame, so it can't be freed.
+                let data = unsafe {
+                    core::slice::from_raw_parts((phys_addr + OFFSET) as *const u8, PAGE_FRAME_SIZE)
+                };
+                let offset = u64::from(page) * PAGE_FRAME_SIZE as u64;
+                let _ = self
+                    .file_systems
+                    .get_mut(fs)
+                    .write_direct(inode, offset, data);
+            }
+        }
+        // decrease reference count to inode to let it be released.
+        self.file_systems.get_mut(fs).dec_ref(inode);
+    }
+
+    /// Close all of a process' files which are marked close-on-exec
+    ///
+    /// This should be called when the process calls execve.
+    /// All errors that occur while closing files are ignored.
+    pub fn close_on_exec_files(&mut self, pid: Pid) {
+        let fds: Vec<ProcessFileDescriptor> = self
+            .close_on_exec
+            .iter()
+            .filter(|fd| fd.pid == pid)
+            .copied()
+            .collect();
+        for fd in fds {
+            let _ = self.close(fd);
+        }
+    }
+
+    /// Give the `child` process a copy of each of `parent`'s open files, and a
+    /// reference to its current working directory.
+    ///
+    /// This should be called when the parent process forks.
+    pub fn fork(&mut self, parent: &ProcessControlBlock, child: Pid) -> Result<()> {
+        let files: Vec<(FileDescriptor, OpenFile)> = self
+            .open_files
+            .iter()
+            .filter(|(fd, _)| fd.pid == parent.pid)
+            .map(|(fd, file)| (fd.fd, file.clone()))
+            .collect();
+        for (fd, file) in files {
+            let parent_fd = ProcessFileDescriptor {
+                pid: parent.pid,
+                fd,
+            };
+            let fd = ProcessFileDescriptor { pid: child, fd };
+            if let OpenFile::Regular { fs, inode, .. } | OpenFile::Fifo { fs, inode, .. } = file {
+                if let Err(e) = self.file_systems.get_mut(fs).open(inode, fd) {
+                    self.close_all(child);
+                    return Err(e);
                 }
             }
+            self.open_files.insert(fd, file);
+            self.flags.insert(fd, self.status_flags(parent_fd)?);
+            if self.close_on_exec.contains(&parent_fd) {
+                self.close_on_exec.insert(fd);
+            }
+        }
+        if parent.cwd_path != "/" {
+            // increment reference count to cwd, like chdir does
+            let (cwd_fs, cwd_inode) = parent.cwd;
+            self.file_systems.get_mut(cwd_fs).inc_ref(cwd_inode);
         }
+        Ok(())
     }
 
     pub fn inode_of(&self, fd: ProcessFileDescriptor) -> Result<(FileSystemID, INodeNum)> {
@@ -1410,53 +1938,43 @@ impl RootFileSystem {
             .read_direct(inode, offset, buffer)
     }
 
-    /// Map file by inode into memory
+    /// Create a VMA mapping `length` bytes of a file into memory, starting at `offset`.
     ///
-    /// Returns `Ok(false)` if there is already something mapped in `addr..addr + length`
-    pub fn mmap_inode(
-        &mut self,
-        addr: usize,
-        fs_id: FileSystemID,
-        inode: INodeNum,
-        length: usize,
-        offset_in_pages: u32,
-        writeable: bool,
-    ) -> Result<bool> {
-        // increase reference count to ensure that file data is kept around even if file is unlinked and all descriptors are closed.
-        self.file_systems.get_mut(fs_id).inc_ref(inode);
-        let pcb = running_process();
-        let mut pcb = pcb.lock();
-        Ok(pcb.vmas.add_vma(
-            VMA::new(
-                VMAInfo::MMap {
-                    fs: fs_id,
-                    inode,
-                    offset: offset_in_pages,
-                },
-                length,
-                writeable,
-            ),
-            addr,
-        ))
-    }
-
-    /// Map file into memory
-    ///
-    /// Returns `Ok(false)` if the requested address range is unavailable.
+    /// If `shared`, the VMA shares its pages with every other shared mapping of the file, and
+    /// changes to them are written back to the file. Otherwise the process gets a private copy.
     pub fn mmap_file(
         &mut self,
-        addr: usize,
         fd: ProcessFileDescriptor,
         length: usize,
         offset: i64,
         writeable: bool,
-    ) -> Result<bool> {
+        shared: bool,
+    ) -> Result<VMA> {
         let offset = u64::try_from(offset).map_err(|_| Error::BadOffset)?;
+        if offset % PAGE_FRAME_SIZE as u64 != 0 {
+            return Err(Error::BadOffset);
+        }
+        let access = self.status_flags(fd)? & O_ACCMODE;
+        // mappings can always be read, and writes to shared ones go back to the file
+        if access == O_WRONLY || (shared && writeable && access != O_RDWR) {
+            return Err(Error::PermissionDenied);
+        }
         let (fs, inode) = self.inode_of(fd)?;
         let offset_in_pages: u32 = (offset / PAGE_FRAME_SIZE as u64)
             .try_into()
             .map_err(|_| Error::BadOffset)?;
-        self.mmap_inode(addr, fs, inode, length, offset_in_pages, writeable)
+        // increase reference count to ensure that file data is kept around even if file is unlinked and all descriptors are closed.
+        self.file_systems.get_mut(fs).inc_ref(inode);
+        let info = VMAInfo::MMap {
+            fs,
+            inode,
+            offset: offset_in_pages,
+        };
+        Ok(if shared {
+            VMA::new_shared(info, length, writeable, SharedMemory::for_file(fs, inode))
+        } else {
+            VMA::new(info, length, writeable)
+        })
     }
 }
 
@@ -1470,10 +1988,17 @@ mod test {
         ProcessControlBlock {
             pid: 0,
             ppid: 0,
+            pgid: 0,
             child_tids: vec![],
-            waiting_thread: None,
-            exit_code: None,
+            children: vec![],
+            waiting_threads: vec![],
+            exited_threads: vec![],
+            joining_threads: vec![],
+            exit_status: None,
+            signals: Default::default(),
             vmas: Default::default(),
+            heap_start: 0,
+            program_break: 0,
             cwd: root.get_root().unwrap(),
             cwd_path: "/".into(),
         }
@@ -1709,4 +2234,175 @@ mod test {
         assert_eq!(&buf, b"test\0\0\0\0\0\0");
         root_mutex.lock().close(fd).unwrap();
     }
+    #[test]
+    fn read_and_write_at_offset() {
+        let root_mutex = Mutex::new(RootFileSystem::new());
+        root_mutex.lock().mount_root(TempFS::new()).unwrap();
+        let fd = create(&root_mutex, "/file", b"hello world").unwrap();
+        let mut root = root_mutex.lock();
+        assert_eq!(root.write_at(fd, 6, b"there").unwrap(), 5);
+        let mut buf = [0; 5];
+        assert_eq!(root.read_at(fd, 0, &mut buf).unwrap(), 5);
+        assert_eq!(&buf, b"hello");
+        // the file position is still at the end of what create wrote
+        assert_eq!(root.lseek(fd, SeekFrom::Current, 0).unwrap(), 11);
+        drop(root);
+        RootFileSystem::write(&root_mutex, fd, b"!").unwrap();
+        let mut buf = [0; 12];
+        root_mutex.lock().read_at(fd, 0, &mut buf).unwrap();
+        assert_eq!(&buf, b"hello there!");
+
+        let mut root = root_mutex.lock();
+        root.open_standard_fds(1);
+        let stdout = ProcessFileDescriptor { pid: 1, fd: 1 };
+        assert!(matches!(
+            root.write_at(stdout, 0, b"x"),
+            Err(Error::IllegalSeek)
+        ));
+    }
+    #[test]
+    fn open_flags() {
+        let root_mutex = Mutex::new(RootFileSystem::new());
+        root_mutex.lock().mount_root(TempFS::new()).unwrap();
+        let fd = create(&root_mutex, "/file", b"hello").unwrap();
+        root_mutex.lock().close(fd).unwrap();
+        let open = |flags| {
+            let mut root = root_mutex.lock();
+            let pcb = test_pcb(&root);
+            let fd = root.open_with_flags(&pcb, "/file", flags)?;
+            Ok(ProcessFileDescriptor { pid: pcb.pid, fd })
+        };
+        assert!(matches!(open(O_CREATE | O_EXCL), Err(Error::Exists)));
+        assert!(matches!(open(O_DIRECTORY), Err(Error::NotDirectory)));
+
+        // access modes are enforced
+        let fd = open(O_RDONLY).unwrap();
+        assert!(matches!(
+            RootFileSystem::write(&root_mutex, fd, b"x"),
+            Err(Error::BadFd)
+        ));
+        root_mutex.lock().close(fd).unwrap();
+        let fd = open(O_WRONLY | O_APPEND).unwrap();
+        let mut buf = [0; 6];
+        assert!(matches!(
+            RootFileSystem::read(&root_mutex, fd, &mut buf),
+            Err(Error::BadFd)
+        ));
+        // appending writes go to the end, wherever the file position is
+        RootFileSystem::write(&root_mutex, fd, b"!").unwrap();
+        root_mutex.lock().close(fd).unwrap();
+        let fd = open(O_RDONLY).unwrap();
+        assert_eq!(RootFileSystem::read(&root_mutex, fd, &mut buf).unwrap(), 6);
+        assert_eq!(&buf, b"hello!");
+        root_mutex.lock().close(fd).unwrap();
+
+        let fd = open(O_RDWR | O_TRUNC).unwrap();
+        assert_eq!(root_mutex.lock().fstat(fd).unwrap().size, 0);
+        root_mutex.lock().close(fd).unwrap();
+    }
+    #[test]
+    fn status_and_descriptor_flags() {
+        let mut root = RootFileSystem::new();
+        root.mount_root(TempFS::new()).unwrap();
+        let pcb = test_pcb(&root);
+        let fd = root
+            .open_with_flags(&pcb, "/file", O_CREATE | O_WRONLY | O_CLOEXEC)
+            .unwrap();
+        let fd = ProcessFileDescriptor { pid: pcb.pid, fd };
+        assert_eq!(root.status_flags(fd).unwrap(), O_WRONLY);
+        assert!(root.is_close_on_exec(fd).unwrap());
+
+        // only O_APPEND and O_NONBLOCK can be changed
+        root.set_status_flags(fd, O_RDWR | O_APPEND | O_TRUNC)
+            .unwrap();
+        assert_eq!(root.status_flags(fd).unwrap(), O_WRONLY | O_APPEND);
+
+        // duplicates share the status flags, but aren't closed on exec
+        let dup = root.dup_from(pcb.pid, fd, 10, false).unwrap();
+        assert_eq!(dup, 10);
+        let dup = ProcessFileDescriptor {
+            pid: pcb.pid,
+            fd: dup,
+        };
+        assert_eq!(root.status_flags(dup).unwrap(), O_WRONLY | O_APPEND);
+        assert!(!root.is_close_on_exec(dup).unwrap());
+
+        root.close_on_exec_files(pcb.pid);
+        assert!(matches!(root.status_flags(fd), Err(Error::BadFd)));
+        assert!(root.status_flags(dup).is_ok());
+    }
+    #[test]
+    fn window_size() {
+        let mut root = RootFileSystem::new();
+        root.mount_root(TempFS::new()).unwrap();
+        root.open_standard_fds(0);
+        let fd = |fd| ProcessFileDescriptor { pid: 0, fd };
+        let window_size = root.window_size(fd(1)).unwrap();
+        assert_eq!((window_size.rows, window_size.cols), (25, 80));
+        assert!(root.window_size(fd(0)).is_ok());
+        assert_eq!(root.open_null(0).unwrap(), 3);
+        assert!(matches!(root.window_size(fd(3)), Err(Error::NotTerminal)));
+        assert!(matches!(root.window_size(fd(4)), Err(Error::BadFd)));
+    }
+
+    #[test]
+    fn named_pipes() {
+        let mut root = RootFileSystem::new();
+        root.mount_root(TempFS::new()).unwrap();
+        let pcb = test_pcb(&root);
+        root.mkfifo(&pcb, "/fifo").unwrap();
+        assert!(matches!(root.mkfifo(&pcb, "/fifo"), Err(Error::Exists)));
+        assert!(matches!(
+            root.open_with_flags(&pcb, "/fifo", O_RDONLY | O_DIRECTORY),
+            Err(Error::NotDirectory)
+        ));
+        // nothing could read what a writer writes
+        assert!(matches!(
+            root.open_with_flags(&pcb, "/fifo", O_WRONLY | O_NONBLOCK),
+            Err(Error::NoDeviceOrAddress)
+        ));
+        assert!(root.fifos.is_empty());
+        root.unlink(&pcb, "/fifo").unwrap();
+        assert!(matches!(
+            root.open_with_flags(&pcb, "/fifo", O_RDONLY),
+            Err(Error::NotFound)
+        ));
+    }
+    #[test]
+    fn socket_files() {
+        let mut root = RootFileSystem::new();
+        root.mount_root(TempFS::new()).unwrap();
+        let pcb = test_pcb(&root);
+        root.mknod(&pcb, "/socket", INodeType::Socket).unwrap();
+        assert!(matches!(
+            root.mknod(&pcb, "/socket", INodeType::Socket),
+            Err(Error::Exists)
+        ));
+        // sockets are connected to, not opened
+        assert!(matches!(
+            root.open_with_flags(&pcb, "/socket", O_RDWR),
+            Err(Error::NoDeviceOrAddress)
+        ));
+        // nothing is bound to it
+        assert!(matches!(
+            root.bound_socket(&pcb, "/socket"),
+            Err(Error::ConnectionRefused)
+        ));
+
+        let fd = root
+            .open_with_flags(&pcb, "/file", O_CREATE | O_RDWR)
+            .unwrap();
+        let fd = ProcessFileDescriptor { pid: pcb.pid, fd };
+        assert!(matches!(root.socket_of(fd), Err(Error::NotSocket)));
+        assert!(matches!(
+            root.bind(&pcb, fd, "/other"),
+            Err(Error::NotSocket)
+        ));
+        assert!(matches!(
+            root.bound_socket(&pcb, "/file"),
+            Err(Error::ConnectionRefused)
+        ));
+        root.close(fd).unwrap();
+        assert!(matches!(root.socket_of(fd), Err(Error::BadFd)));
+    }
 }
@@ -1,7 +1,10 @@
 pub mod fat;
 pub mod fs_manager;
 pub mod pipe;
+pub mod poll;
+pub mod socket;
 pub mod syscalls;
+pub mod tty;
 pub mod vsfs;
 
 use crate::fs::fs_manager::{Mode, RootFileSystem};
@@ -1,16 +1,32 @@
-use crate::sync::mutex::sleep::SleepMutex;
-use crate::sync::semaphore::Semaphore;
+use crate::interrupts::{mutex_irq::hold_interrupts, IntrLevel};
+use crate::sync::mutex::Mutex;
+use crate::sync::wait_queue::WaitQueue;
+use crate::system::running_process;
+use crate::user_program::signal::send_signal;
+use crate::user_program::syscall::{PIPE_BUF, POLLERR, POLLHUP, POLLIN, POLLOUT, SIGPIPE};
+use crate::vfs::{Error, Result};
 use alloc::collections::VecDeque;
 use alloc::sync::Arc;
+use core::cmp::min;
 use core::fmt::{Debug, Formatter};
 use core::sync::atomic::{AtomicUsize, Ordering};
 
+/// The most bytes a pipe holds before writers have to wait for them to be read.
+pub const PIPE_CAPACITY: usize = 64 * 1024;
+
 pub struct PipeInner {
     pub read_ends: AtomicUsize,
     pub write_ends: AtomicUsize,
+    /// How many times each end of a named pipe has been opened, so that an
+    /// open waiting for the other end notices it even if it's closed again
+    /// straight away.
+    pub read_opens: AtomicUsize,
+    pub write_opens: AtomicUsize,
 
-    pub semaphore: Semaphore,
-    pub contents: SleepMutex<VecDeque<u8>>,
+    /// Never holds more than `PIPE_CAPACITY` bytes.
+    pub contents: Mutex<VecDeque<u8>>,
+    /// Woken whenever the pipe is read from, written to, or an end is closed.
+    pub waiters: WaitQueue,
 }
 
 pub struct PipeReadEnd(pub Arc<PipeInner>);
@@ -21,9 +37,11 @@ impl Default for PipeInner {
         Self {
             read_ends: AtomicUsize::new(0),
             write_ends: AtomicUsize::new(0),
+            read_opens: AtomicUsize::new(0),
+            write_opens: AtomicUsize::new(0),
 
-            semaphore: Semaphore::new(0),
-            contents: SleepMutex::new(VecDeque::new()),
+            contents: Mutex::new(VecDeque::new()),
+            waiters: WaitQueue::new(),
         }
     }
 }
@@ -40,6 +58,180 @@ impl PipeInner {
 
         PipeWriteEnd(inner)
     }
+
+    /// Opens the ends of a named pipe asked for, waking anyone waiting for
+    /// them in [`PipeInner::wait_for_peer`].
+    pub fn open_ends(
+        inner: &Arc<PipeInner>,
+        read: bool,
+        write: bool,
+    ) -> (Option<PipeReadEnd>, Option<PipeWriteEnd>) {
+        let read_end = read.then(|| {
+            inner.read_opens.fetch_add(1, Ordering::SeqCst);
+            PipeInner::read_end(inner.clone())
+        });
+        let write_end = write.then(|| {
+            inner.write_opens.fetch_add(1, Ordering::SeqCst);
+            PipeInner::write_end(inner.clone())
+        });
+        inner.waiters.wake_all();
+        (read_end, write_end)
+    }
+
+    /// How many times the end of a named pipe opposite the one opened for
+    /// writing when `write` is true, or for reading otherwise, has been opened.
+    pub fn peer_opens(&self, write: bool) -> usize {
+        let opens = if write {
+            &self.read_opens
+        } else {
+            &self.write_opens
+        };
+        opens.load(Ordering::SeqCst)
+    }
+
+    /// Blocks until the opposite end to the one just opened (see
+    /// [`PipeInner::peer_opens`]) is open, or has been opened since it was
+    /// opened `seen` times.
+    pub fn wait_for_peer(&self, write: bool, seen: usize) -> Result<()> {
+        let ends = if write {
+            &self.read_ends
+        } else {
+            &self.write_ends
+        };
+        loop {
+            let _guard = hold_interrupts(IntrLevel::IntrOff);
+            if ends.load(Ordering::SeqCst) > 0 || self.peer_opens(write) != seen {
+                return Ok(());
+            }
+            if running_process().lock().signals.has_deliverable() {
+                return Err(Error::Interrupted);
+            }
+            self.waiters.wait(None);
+        }
+    }
+
+    /// Whether every end of the pipe has been closed.
+    pub fn is_closed(&self) -> bool {
+        self.read_ends.load(Ordering::SeqCst) == 0 && self.write_ends.load(Ordering::SeqCst) == 0
+    }
+
+    /// Reads whatever is in the pipe, up to `buf.len()` bytes, blocking until
+    /// something is written or every write end is closed, unless `nonblocking`.
+    pub fn read(&self, buf: &mut [u8], nonblocking: bool) -> Result<usize> {
+        loop {
+            let _guard = hold_interrupts(IntrLevel::IntrOff);
+            {
+                let mut contents = self.contents.lock();
+                if !contents.is_empty() || buf.is_empty() {
+                    let bytes_read = min(contents.len(), buf.len());
+                    for (byte, value) in buf.iter_mut().zip(contents.drain(..bytes_read)) {
+                        *byte = value;
+                    }
+                    drop(contents);
+                    self.waiters.wake_all();
+                    return Ok(bytes_read);
+                }
+            }
+            if self.write_ends.load(Ordering::SeqCst) == 0 {
+                return Ok(0); // no bytes left to read
+            }
+            if nonblocking {
+                return Err(Error::WouldBlock);
+            }
+            if running_process().lock().signals.has_deliverable() {
+                return Err(Error::Interrupted);
+            }
+            self.waiters.wait(None);
+        }
+    }
+
+    /// Writes `buf` to the pipe, blocking while it's full unless `nonblocking`.
+    ///
+    /// Writes of up to `PIPE_BUF` bytes are written all at once. Larger ones
+    /// are written as room frees up, so if they're interrupted or would block
+    /// partway through, they return how much was written.
+    ///
+    /// Writing with no read ends left fails with `PipeClosed`, and sends the
+    /// writer `SIGPIPE`.
+    pub fn write(&self, buf: &[u8], nonblocking: bool) -> Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let mut written = 0;
+        let error = loop {
+            let _guard = hold_interrupts(IntrLevel::IntrOff);
+            if self.read_ends.load(Ordering::SeqCst) == 0 {
+                break Error::PipeClosed;
+            }
+            {
+                let mut contents = self.contents.lock();
+                let count = write_size(PIPE_CAPACITY - contents.len(), buf.len(), written);
+                if count > 0 {
+                    contents.extend(&buf[written..written + count]);
+                    written += count;
+                    drop(contents);
+                    self.waiters.wake_all();
+                    if written == buf.len() {
+                        return Ok(written);
+                    }
+                    continue;
+                }
+            }
+            if nonblocking {
+                break Error::WouldBlock;
+            }
+            if running_process().lock().signals.has_deliverable() {
+                break Error::Interrupted;
+            }
+            self.waiters.wait(None);
+        };
+
+        if let Error::PipeClosed = error {
+            send_signal(&running_process(), SIGPIPE);
+        }
+        if written > 0 {
+            Ok(written)
+        } else {
+            Err(error)
+        }
+    }
+
+    /// The poll events the read end of the pipe is ready for.
+    pub fn read_readiness(&self) -> i16 {
+        let mut events = 0;
+        if !self.contents.lock().is_empty() {
+            events |= POLLIN;
+        }
+        if self.write_ends.load(Ordering::SeqCst) == 0 {
+            events |= POLLHUP;
+        }
+        events
+    }
+
+    /// The poll events the write end of the pipe is ready for. It's writable
+    /// once there's room for an atomic write.
+    pub fn write_readiness(&self) -> i16 {
+        if self.read_ends.load(Ordering::SeqCst) == 0 {
+            POLLOUT | POLLERR
+        } else if PIPE_CAPACITY - self.contents.lock().len() >= PIPE_BUF {
+            POLLOUT
+        } else {
+            0
+        }
+    }
+}
+
+/// How many more bytes of a write of `len` bytes, of which `written` have been
+/// written already, can be written to a pipe with room for `space` bytes. If
+/// it's 0, the writer has to wait.
+fn write_size(space: usize, len: usize, written: usize) -> usize {
+    let remaining = len - written;
+    // Atomic writes either fit or wait.
+    if len <= PIPE_BUF && space < remaining {
+        0
+    } else {
+        min(space, remaining)
+    }
 }
 
 impl Clone for PipeReadEnd {
@@ -61,12 +253,14 @@ impl Clone for PipeWriteEnd {
 impl Drop for PipeReadEnd {
     fn drop(&mut self) {
         self.0.read_ends.fetch_sub(1, Ordering::SeqCst);
+        self.0.waiters.wake_all();
     }
 }
 
 impl Drop for PipeWriteEnd {
     fn drop(&mut self) {
         self.0.write_ends.fetch_sub(1, Ordering::SeqCst);
+        self.0.waiters.wake_all();
     }
 }
 
@@ -82,3 +276,23 @@ impl Debug for PipeWriteEnd {
         write!(f, "Pipe Write End")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn atomic_writes_wait_for_room() {
+        assert_eq!(write_size(PIPE_BUF, PIPE_BUF, 0), PIPE_BUF);
+        assert_eq!(write_size(PIPE_BUF - 1, PIPE_BUF, 0), 0);
+        assert_eq!(write_size(10, 5, 0), 5);
+    }
+
+    #[test]
+    fn large_writes_fill_the_room_there_is() {
+        let len = PIPE_BUF + 1;
+        assert_eq!(write_size(100, len, 0), 100);
+        assert_eq!(write_size(PIPE_CAPACITY, len, 100), len - 100);
+        assert_eq!(write_size(0, len, 100), 0);
+    }
+}
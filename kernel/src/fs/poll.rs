@@ -0,0 +1,182 @@
+// Ordinarily, a function dereferencing a raw pointer argument almost always requires it to be unsafe.
+// Here we should be fine since we are checking the validity of pointers.
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+
+use crate::fs::fs_manager::MAX_OPEN_FILES;
+use crate::fs::{FileDescriptor, ProcessFileDescriptor};
+use crate::interrupts::{mutex_irq::hold_interrupts, timer, IntrLevel};
+use crate::mem::util::{get_mut_from_user_space, get_mut_slice_from_user_space};
+use crate::sync::wait_queue::block;
+use crate::system::{root_filesystem, running_process, running_thread_pid};
+use crate::user_program::syscall::{
+    FdSet, PollFd, TimeVal, EBADF, EFAULT, EINTR, EINVAL, FD_SETSIZE, POLLERR, POLLHUP, POLLIN,
+    POLLNVAL, POLLOUT, POLLPRI,
+};
+use alloc::vec::Vec;
+use core::time::Duration;
+
+/// Waits until at least one of `fds` has an event, a signal arrives, or
+/// `timeout` passes, filling in their `revents`. Returns how many have events.
+fn poll_files(fds: &mut [PollFd], timeout: Option<Duration>) -> isize {
+    let deadline = timeout.map(|timeout| timer::now().saturating_add(timeout));
+    let pid = running_thread_pid();
+    let pcb = running_process();
+    loop {
+        // Interrupts must stay off from checking the files until we're
+        // blocked, so that we can't miss a wakeup.
+        let _guard = hold_interrupts(IntrLevel::IntrOff);
+        let mut queues = Vec::new();
+        let mut ready = 0;
+        let root = root_filesystem().lock();
+        for poll_fd in fds.iter_mut() {
+            poll_fd.revents = 0;
+            if poll_fd.fd < 0 {
+                continue;
+            }
+            let result = FileDescriptor::try_from(poll_fd.fd)
+                .map_err(|_| ())
+                .and_then(|fd| root.poll(ProcessFileDescriptor { pid, fd }).map_err(|_| ()));
+            poll_fd.revents = match result {
+                Ok((events, queue)) => {
+                    queues.extend(queue);
+                    events & (poll_fd.events | POLLERR | POLLHUP)
+                }
+                Err(()) => POLLNVAL,
+            };
+            if poll_fd.revents != 0 {
+                ready += 1;
+            }
+        }
+        drop(root);
+        if ready > 0 {
+            return ready;
+        }
+
+        let timeout = match deadline {
+            None => None,
+            Some(deadline) => match deadline.checked_sub(timer::now()) {
+                Some(remaining) if !remaining.is_zero() => Some(remaining),
+                _ => return 0,
+            },
+        };
+        if pcb.lock().signals.has_deliverable() {
+            return -EINTR;
+        }
+        for queue in &queues {
+            queue.add();
+        }
+        block(timeout);
+        for queue in &queues {
+            queue.remove();
+        }
+    }
+}
+
+/// Waits for one of the `nfds` files in `fds` to have one of the events it
+/// asks for, for up to `timeout` milliseconds, or forever if it's negative.
+pub fn poll(fds: *mut PollFd, nfds: usize, timeout: i32) -> isize {
+    if nfds > MAX_OPEN_FILES.into() {
+        return -EINVAL;
+    }
+    let Some(fds) = (unsafe { get_mut_slice_from_user_space(fds, nfds) }) else {
+        return -EFAULT;
+    };
+    let timeout = u64::try_from(timeout).ok().map(Duration::from_millis);
+    poll_files(fds, timeout)
+}
+
+/// Waits for one of the files below `nfds` in `read_fds` to be readable, one in
+/// `write_fds` to be writable, or one in `except_fds` to have an exceptional
+/// condition, for up to `timeout`, or forever if it's null. Any of the sets may
+/// be null.
+///
+/// On return, the sets contain only the files which are ready, and `timeout`
+/// holds the time which was left.
+pub fn select(
+    nfds: i32,
+    read_fds: *mut FdSet,
+    write_fds: *mut FdSet,
+    except_fds: *mut FdSet,
+    timeout: *mut TimeVal,
+) -> isize {
+    // The event each set waits for, and the ones which make it ready.
+    const EVENTS: [(i16, i16); 3] = [
+        (POLLIN, POLLIN | POLLHUP | POLLERR),
+        (POLLOUT, POLLOUT | POLLERR),
+        (POLLPRI, POLLPRI),
+    ];
+
+    let nfds = match usize::try_from(nfds) {
+        Ok(nfds) if nfds <= FD_SETSIZE => nfds,
+        _ => return -EINVAL,
+    };
+    let mut sets = [None, None, None];
+    for (set, pointer) in sets.iter_mut().zip([read_fds, write_fds, except_fds]) {
+        if !pointer.is_null() {
+            let Some(pointer) = (unsafe { get_mut_from_user_space(pointer) }) else {
+                return -EFAULT;
+            };
+            *set = Some(pointer);
+        }
+    }
+    let (timeout, duration) = if timeout.is_null() {
+        (None, None)
+    } else {
+        let Some(timeout) = (unsafe { get_mut_from_user_space(timeout) }) else {
+            return -EFAULT;
+        };
+        let (Ok(sec), Ok(usec)) = (u64::try_from(timeout.sec), u32::try_from(timeout.usec)) else {
+            return -EINVAL;
+        };
+        if usec >= 1_000_000 {
+            return -EINVAL;
+        }
+        (Some(timeout), Some(Duration::new(sec, usec * 1000)))
+    };
+
+    let mut fds = Vec::new();
+    for fd in 0..nfds {
+        let events = sets
+            .iter()
+            .zip(EVENTS)
+            .filter(|(set, _)| set.as_ref().is_some_and(|set| set.contains(fd)))
+            .fold(0, |events, (_, (event, _))| events | event);
+        if events != 0 {
+            fds.push(PollFd {
+                fd: fd as i32,
+                events,
+                revents: 0,
+            });
+        }
+    }
+
+    let start = timer::now();
+    let result = poll_files(&mut fds, duration);
+    if let (Some(timeout), Some(duration)) = (timeout, duration) {
+        let remaining = duration.saturating_sub(timer::now() - start);
+        timeout.sec = remaining.as_secs() as i32;
+        timeout.usec = remaining.subsec_micros() as i32;
+    }
+    if result < 0 {
+        return result;
+    }
+    if fds.iter().any(|poll_fd| poll_fd.revents & POLLNVAL != 0) {
+        return -EBADF;
+    }
+
+    let mut count = 0;
+    for set in sets.iter_mut().flatten() {
+        set.clear();
+    }
+    for poll_fd in fds {
+        for (set, (event, ready)) in sets.iter_mut().zip(EVENTS) {
+            if let Some(set) = set {
+                if poll_fd.events & event != 0 && poll_fd.revents & ready != 0 {
+                    set.insert(poll_fd.fd as usize);
+                    count += 1;
+                }
+            }
+        }
+    }
+    count
+}
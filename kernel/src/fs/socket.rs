@@ -0,0 +1,848 @@
+// Ordinarily, a function dereferencing a raw pointer argument almost always requires it to be unsafe.
+// Here we should be fine since we are checking the validity of pointers.
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+
+//! Unix domain sockets.
+//!
+//! A connected stream socket is a pair of pipes, one each way, which share a
+//! wait queue so that either end can wait on both. A datagram socket keeps the
+//! messages sent to it in its own queue.
+
+use crate::fs::pipe::{PipeInner, PipeReadEnd, PipeWriteEnd, PIPE_CAPACITY};
+use crate::fs::syscalls::{process_fd, to_result, MAX_TRANSFER};
+use crate::fs::ProcessFileDescriptor;
+use crate::interrupts::{mutex_irq::hold_interrupts, IntrLevel};
+use crate::mem::util::{
+    get_mut_from_user_space, get_mut_slice_from_user_space, get_ref_from_user_space,
+    get_slice_from_user_space,
+};
+use crate::sync::mutex::Mutex;
+use crate::sync::wait_queue::WaitQueue;
+use crate::system::{root_filesystem, running_process, running_thread_pid};
+use crate::user_program::signal::send_signal;
+use crate::user_program::syscall::{
+    SockAddrUn, SockLen, AF_UNIX, EAFNOSUPPORT, EBADF, EFAULT, EINVAL, EPROTONOSUPPORT,
+    MSG_DONTWAIT, O_CLOEXEC, O_NONBLOCK, POLLERR, POLLHUP, POLLIN, POLLOUT, SHUT_RD, SHUT_RDWR,
+    SHUT_WR, SIGPIPE, SOCK_CLOEXEC, SOCK_DGRAM, SOCK_NONBLOCK, SOCK_STREAM, UNIX_PATH_MAX,
+};
+use crate::vfs::{Error, OwnedPath, Path, Result};
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::cmp::min;
+use core::fmt::{Debug, Formatter};
+use core::mem::{offset_of, size_of};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// The most connections a listening socket queues up before connecting to it
+/// has to wait.
+const MAX_BACKLOG: usize = 128;
+/// The most datagrams queued on a socket before senders have to wait, however
+/// small they are.
+const MAX_DATAGRAMS: usize = 256;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SocketKind {
+    /// A connected, two-way stream of bytes (`SOCK_STREAM`).
+    Stream,
+    /// Separate messages, each sent to a bound socket (`SOCK_DGRAM`).
+    Datagram,
+}
+
+enum State {
+    /// A stream socket which hasn't been connected, or made to listen, yet.
+    Unconnected,
+    /// Connections made to the socket, which haven't been accepted yet.
+    Listening {
+        backlog: VecDeque<Arc<Socket>>,
+        limit: usize,
+    },
+    /// The ends of the pipes to and from the other socket which haven't been shut down, and
+    /// the queue both of them wake.
+    Connected {
+        read: Option<PipeReadEnd>,
+        write: Option<PipeWriteEnd>,
+        waiters: WaitQueue,
+    },
+    /// The messages sent to a datagram socket, with the name of the socket each came from,
+    /// and `size`, the bytes in all of them.
+    Datagram {
+        messages: VecDeque<(Vec<u8>, Option<OwnedPath>)>,
+        size: usize,
+        /// Where to send messages which don't say
+        peer: Option<Arc<Socket>>,
+        read_shut: bool,
+        write_shut: bool,
+    },
+    /// Every file descriptor for the socket has been closed.
+    Closed,
+}
+
+pub struct Socket {
+    pub kind: SocketKind,
+    /// Woken whenever the state changes, or a message is sent to or received from a datagram
+    /// socket. A connected stream socket's pipes have their own queue.
+    pub waiters: WaitQueue,
+    /// How many [`SocketHandle`]s there are.
+    handles: AtomicUsize,
+    state: Mutex<State>,
+    /// The path the socket is bound to, if it is
+    name: Mutex<Option<OwnedPath>>,
+}
+
+/// A reference to a socket from an open file. The socket is closed when the last one is
+/// dropped.
+pub struct SocketHandle(pub Arc<Socket>);
+
+impl Socket {
+    pub fn new(kind: SocketKind) -> Self {
+        let state = match kind {
+            SocketKind::Stream => State::Unconnected,
+            SocketKind::Datagram => State::Datagram {
+                messages: VecDeque::new(),
+                size: 0,
+                peer: None,
+                read_shut: false,
+                write_shut: false,
+            },
+        };
+        Self::with_state(kind, state)
+    }
+
+    fn with_state(kind: SocketKind, state: State) -> Self {
+        Self {
+            kind,
+            waiters: WaitQueue::new(),
+            handles: AtomicUsize::new(0),
+            state: Mutex::new(state),
+            name: Mutex::new(None),
+        }
+    }
+
+    /// Two sockets connected to each other.
+    pub fn pair(kind: SocketKind) -> (Arc<Self>, Arc<Self>) {
+        match kind {
+            SocketKind::Stream => {
+                let (first, second) = connection();
+                (
+                    Arc::new(Self::with_state(kind, first)),
+                    Arc::new(Self::with_state(kind, second)),
+                )
+            }
+            SocketKind::Datagram => {
+                let (first, second) = (Arc::new(Self::new(kind)), Arc::new(Self::new(kind)));
+                first.set_peer(second.clone());
+                second.set_peer(first.clone());
+                (first, second)
+            }
+        }
+    }
+
+    /// Whether every file descriptor for the socket has been closed.
+    pub fn is_closed(&self) -> bool {
+        self.handles.load(Ordering::SeqCst) == 0
+    }
+
+    pub fn name(&self) -> Option<OwnedPath> {
+        self.name.lock().clone()
+    }
+
+    /// Record that the socket is bound to `path`, unless it's bound already.
+    pub fn set_name(&self, path: &Path) -> Result<()> {
+        let mut name = self.name.lock();
+        if name.is_some() {
+            return Err(Error::BadSocketState);
+        }
+        *name = Some(path.into());
+        Ok(())
+    }
+
+    fn set_peer(&self, socket: Arc<Socket>) {
+        if let State::Datagram { peer, .. } = &mut *self.state.lock() {
+            *peer = Some(socket);
+        }
+    }
+
+    /// Start accepting up to `backlog` connections at a time.
+    pub fn listen(&self, backlog: usize) -> Result<()> {
+        if self.kind != SocketKind::Stream {
+            return Err(Error::SocketOperationNotSupported);
+        }
+        if self.name.lock().is_none() {
+            // nothing could connect to it
+            return Err(Error::BadSocketState);
+        }
+        let limit = backlog.clamp(1, MAX_BACKLOG);
+        let mut state = self.state.lock();
+        match &mut *state {
+            State::Unconnected => {
+                *state = State::Listening {
+                    backlog: VecDeque::new(),
+                    limit,
+                }
+            }
+            State::Listening { limit: old, .. } => *old = limit,
+            _ => return Err(Error::BadSocketState),
+        }
+        Ok(())
+    }
+
+    /// Take the oldest connection made to a listening socket, blocking until there is one
+    /// unless `nonblocking`.
+    pub fn accept(&self, nonblocking: bool) -> Result<Arc<Socket>> {
+        loop {
+            let _guard = hold_interrupts(IntrLevel::IntrOff);
+            match &mut *self.state.lock() {
+                State::Listening { backlog, .. } => {
+                    if let Some(socket) = backlog.pop_front() {
+                        // there's room for another connection
+                        self.waiters.wake_all();
+                        return Ok(socket);
+                    }
+                }
+                _ => return Err(Error::BadSocketState),
+            }
+            if nonblocking {
+                return Err(Error::WouldBlock);
+            }
+            if running_process().lock().signals.has_deliverable() {
+                return Err(Error::Interrupted);
+            }
+            self.waiters.wait(None);
+        }
+    }
+
+    /// Connect to `target`. A stream socket queues a new socket for `target`, which must be
+    /// listening, to accept, blocking while its backlog is full unless `nonblocking`. A
+    /// datagram socket just sends to `target` from now on.
+    pub fn connect(&self, target: &Arc<Socket>, nonblocking: bool) -> Result<()> {
+        if self.kind != target.kind {
+            return Err(Error::WrongSocketType);
+        }
+        if self.kind == SocketKind::Datagram {
+            self.set_peer(target.clone());
+            return Ok(());
+        }
+        if core::ptr::eq(self, &**target) {
+            // it isn't listening, since it's connecting
+            return Err(Error::ConnectionRefused);
+        }
+        loop {
+            let _guard = hold_interrupts(IntrLevel::IntrOff);
+            {
+                let mut state = self.state.lock();
+                match *state {
+                    State::Unconnected => {}
+                    State::Connected { .. } => return Err(Error::AlreadyConnected),
+                    _ => return Err(Error::BadSocketState),
+                }
+                let mut target_state = target.state.lock();
+                let State::Listening { backlog, limit } = &mut *target_state else {
+                    return Err(Error::ConnectionRefused);
+                };
+                if backlog.len() < *limit {
+                    let (ours, theirs) = connection();
+                    backlog.push_back(Arc::new(Socket::with_state(self.kind, theirs)));
+                    *state = ours;
+                    drop(target_state);
+                    drop(state);
+                    target.waiters.wake_all();
+                    self.waiters.wake_all();
+                    return Ok(());
+                }
+            }
+            if nonblocking {
+                return Err(Error::WouldBlock);
+            }
+            if running_process().lock().signals.has_deliverable() {
+                return Err(Error::Interrupted);
+            }
+            target.waiters.wait(None);
+        }
+    }
+
+    /// Send `buf`, to `to` if it's given, which only datagram sockets can do, or otherwise to
+    /// the socket this is connected to. Blocks while there's no room for it unless
+    /// `nonblocking`.
+    ///
+    /// A stream socket sends like writing to a pipe. Sending after shutting down sending fails
+    /// with `PipeClosed`, and sends the sender `SIGPIPE`.
+    pub fn send(&self, buf: &[u8], to: Option<Arc<Socket>>, nonblocking: bool) -> Result<usize> {
+        let (pipe, peer) = match &*self.state.lock() {
+            State::Connected { write, .. } => {
+                if to.is_some() {
+                    return Err(Error::AlreadyConnected);
+                }
+                (write.as_ref().map(|pipe| pipe.0.clone()), None)
+            }
+            State::Datagram {
+                peer, write_shut, ..
+            } => {
+                if *write_shut {
+                    (None, None)
+                } else {
+                    let to = to.or_else(|| peer.clone()).ok_or(Error::NotConnected)?;
+                    (None, Some(to))
+                }
+            }
+            State::Unconnected | State::Listening { .. } => {
+                return Err(match to {
+                    Some(_) => Error::SocketOperationNotSupported,
+                    None => Error::NotConnected,
+                });
+            }
+            State::Closed => return Err(Error::BadFd),
+        };
+        if let Some(pipe) = pipe {
+            return pipe.write(buf, nonblocking);
+        }
+        match peer {
+            Some(peer) => self.send_datagram(buf, &peer, nonblocking),
+            None => {
+                send_signal(&running_process(), SIGPIPE);
+                Err(Error::PipeClosed)
+            }
+        }
+    }
+
+    fn send_datagram(&self, buf: &[u8], to: &Socket, nonblocking: bool) -> Result<usize> {
+        if to.kind != SocketKind::Datagram {
+            return Err(Error::WrongSocketType);
+        }
+        if buf.len() > PIPE_CAPACITY {
+            return Err(Error::MessageTooLong);
+        }
+        let name = self.name();
+        loop {
+            let _guard = hold_interrupts(IntrLevel::IntrOff);
+            match &mut *to.state.lock() {
+                State::Datagram { messages, size, .. } => {
+                    if *size + buf.len() <= PIPE_CAPACITY && messages.len() < MAX_DATAGRAMS {
+                        messages.push_back((buf.into(), name));
+                        *size += buf.len();
+                        to.waiters.wake_all();
+                        return Ok(buf.len());
+                    }
+                }
+                _ => return Err(Error::ConnectionRefused),
+            }
+            if nonblocking {
+                return Err(Error::WouldBlock);
+            }
+            if running_process().lock().signals.has_deliverable() {
+                return Err(Error::Interrupted);
+            }
+            to.waiters.wait(None);
+        }
+    }
+
+    /// Receive into `buf`, blocking until there's something to receive unless `nonblocking`.
+    /// Returns how many bytes were received, and the name of the socket which sent them, for
+    /// datagram sockets.
+    ///
+    /// A stream socket receives like reading from a pipe. A datagram socket receives one
+    /// message, cut short if it doesn't fit. Both receive nothing once there's nothing more
+    /// to come.
+    pub fn recv(&self, buf: &mut [u8], nonblocking: bool) -> Result<(usize, Option<OwnedPath>)> {
+        if self.kind == SocketKind::Stream {
+            let pipe = match &*self.state.lock() {
+                State::Connected { read, .. } => read.as_ref().map(|pipe| pipe.0.clone()),
+                State::Closed => return Err(Error::BadFd),
+                _ => return Err(Error::NotConnected),
+            };
+            return match pipe {
+                Some(pipe) => Ok((pipe.read(buf, nonblocking)?, None)),
+                None => Ok((0, None)),
+            };
+        }
+        loop {
+            let _guard = hold_interrupts(IntrLevel::IntrOff);
+            match &mut *self.state.lock() {
+                State::Datagram {
+                    messages,
+                    size,
+                    read_shut,
+                    ..
+                } => {
+                    if let Some((message, from)) = messages.pop_front() {
+                        *size -= message.len();
+                        self.waiters.wake_all();
+                        let count = min(message.len(), buf.len());
+                        buf[..count].copy_from_slice(&message[..count]);
+                        return Ok((count, from));
+                    }
+                    if *read_shut {
+                        return Ok((0, None));
+                    }
+                }
+                _ => return Err(Error::BadFd),
+            }
+            if nonblocking {
+                return Err(Error::WouldBlock);
+            }
+            if running_process().lock().signals.has_deliverable() {
+                return Err(Error::Interrupted);
+            }
+            self.waiters.wait(None);
+        }
+    }
+
+    /// Stop receiving if `read`, and sending if `write`.
+    pub fn shutdown(&self, read: bool, write: bool) -> Result<()> {
+        let mut state = self.state.lock();
+        let (read_end, write_end) = match &mut *state {
+            State::Connected {
+                read: read_end,
+                write: write_end,
+                ..
+            } => (
+                if read { read_end.take() } else { None },
+                if write { write_end.take() } else { None },
+            ),
+            State::Datagram {
+                read_shut,
+                write_shut,
+                ..
+            } => {
+                *read_shut |= read;
+                *write_shut |= write;
+                (None, None)
+            }
+            _ => return Err(Error::NotConnected),
+        };
+        drop(state);
+        // dropping the ends wakes anyone waiting on them
+        drop((read_end, write_end));
+        self.waiters.wake_all();
+        Ok(())
+    }
+
+    /// The poll events the socket is ready for, and the queue which is woken when that
+    /// changes.
+    pub fn readiness(&self) -> (i16, WaitQueue) {
+        match &*self.state.lock() {
+            State::Connected {
+                read,
+                write,
+                waiters,
+            } => {
+                let read_events = read.as_ref().map_or(POLLIN, |pipe| pipe.0.read_readiness());
+                let write_events = write
+                    .as_ref()
+                    .map_or(POLLOUT, |pipe| pipe.0.write_readiness());
+                let mut events = read_events | write_events;
+                if (events & POLLHUP) != 0 {
+                    // Receiving returns 0 once the other end stops sending, but it's only hung
+                    // up once it stops receiving too.
+                    events |= POLLIN;
+                    if (events & POLLERR) == 0 {
+                        events &= !POLLHUP;
+                    }
+                }
+                (events, waiters.clone())
+            }
+            State::Listening { backlog, .. } => {
+                let events = if backlog.is_empty() { 0 } else { POLLIN };
+                (events, self.waiters.clone())
+            }
+            State::Datagram {
+                messages,
+                read_shut,
+                ..
+            } => {
+                let readable = !messages.is_empty() || *read_shut;
+                let events = if readable { POLLIN | POLLOUT } else { POLLOUT };
+                (events, self.waiters.clone())
+            }
+            State::Unconnected | State::Closed => (POLLHUP, self.waiters.clone()),
+        }
+    }
+}
+
+/// The states of the two ends of a new stream connection.
+fn connection() -> (State, State) {
+    let waiters = WaitQueue::new();
+    let pipe = || {
+        Arc::new(PipeInner {
+            waiters: waiters.clone(),
+            ..PipeInner::default()
+        })
+    };
+    let (there, back) = (pipe(), pipe());
+    (
+        State::Connected {
+            read: Some(PipeInner::read_end(back.clone())),
+            write: Some(PipeInner::write_end(there.clone())),
+            waiters: waiters.clone(),
+        },
+        State::Connected {
+            read: Some(PipeInner::read_end(there)),
+            write: Some(PipeInner::write_end(back)),
+            waiters,
+        },
+    )
+}
+
+impl SocketHandle {
+    pub fn new(socket: Arc<Socket>) -> Self {
+        socket.handles.fetch_add(1, Ordering::SeqCst);
+
+        Self(socket)
+    }
+}
+
+impl Clone for SocketHandle {
+    fn clone(&self) -> Self {
+        Self::new(self.0.clone())
+    }
+}
+
+impl Drop for SocketHandle {
+    fn drop(&mut self) {
+        if self.0.handles.fetch_sub(1, Ordering::SeqCst) == 1 {
+            // This drops the connection or queued messages, and any peer, which might have
+            // this socket as its peer in turn.
+            let state = core::mem::replace(&mut *self.0.state.lock(), State::Closed);
+            drop(state);
+            self.0.waiters.wake_all();
+        }
+    }
+}
+
+impl Debug for SocketHandle {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{:?} Socket", self.0.kind)
+    }
+}
+
+/// The kind of socket `r#type` asks for, and the file flags to open it with.
+fn socket_type(r#type: i32) -> core::result::Result<(SocketKind, usize), isize> {
+    let kind = match r#type & !(SOCK_NONBLOCK | SOCK_CLOEXEC) {
+        SOCK_STREAM => SocketKind::Stream,
+        SOCK_DGRAM => SocketKind::Datagram,
+        _ => return Err(-EINVAL),
+    };
+    Ok((kind, open_flags(r#type)))
+}
+
+/// The file flags `SOCK_NONBLOCK` and `SOCK_CLOEXEC` in `flags` ask for.
+fn open_flags(flags: i32) -> usize {
+    let mut open_flags = 0;
+    if (flags & SOCK_NONBLOCK) != 0 {
+        open_flags |= O_NONBLOCK;
+    }
+    if (flags & SOCK_CLOEXEC) != 0 {
+        open_flags |= O_CLOEXEC;
+    }
+    open_flags
+}
+
+fn check_domain(domain: i32, protocol: i32) -> core::result::Result<(), isize> {
+    if domain != AF_UNIX {
+        return Err(-EAFNOSUPPORT);
+    }
+    if protocol != 0 {
+        return Err(-EPROTONOSUPPORT);
+    }
+    Ok(())
+}
+
+/// The path in the socket address `addr`, which is `addr_len` bytes long.
+fn socket_path(
+    addr: *const SockAddrUn,
+    addr_len: SockLen,
+) -> core::result::Result<&'static str, isize> {
+    let addr_len = addr_len as usize;
+    let path_offset = offset_of!(SockAddrUn, path);
+    if !(path_offset..=size_of::<SockAddrUn>()).contains(&addr_len) {
+        return Err(-EINVAL);
+    }
+    let addr = unsafe { get_ref_from_user_space(addr) }.ok_or(-EFAULT)?;
+    if addr.family != AF_UNIX as u16 {
+        return Err(-EINVAL);
+    }
+    let path = &addr.path[..addr_len - path_offset];
+    let path = match path.iter().position(|&byte| byte == 0) {
+        Some(end) => &path[..end],
+        None => path,
+    };
+    match core::str::from_utf8(path) {
+        Ok(path) if !path.is_empty() => Ok(path),
+        _ => Err(-EINVAL),
+    }
+}
+
+/// Store the address of the socket bound to `name` in `addr`, unless it's null, cutting it
+/// short to the length in `addr_len`, and set that to its full length. An unbound socket's
+/// address is just its family.
+fn store_address(
+    name: Option<&Path>,
+    addr: *mut SockAddrUn,
+    addr_len: *mut SockLen,
+) -> core::result::Result<(), isize> {
+    if addr.is_null() {
+        return Ok(());
+    }
+    let addr_len = unsafe { get_mut_from_user_space(addr_len) }.ok_or(-EFAULT)?;
+    let mut address = SockAddrUn {
+        family: AF_UNIX as u16,
+        path: [0; UNIX_PATH_MAX],
+    };
+    let mut len = offset_of!(SockAddrUn, path);
+    if let Some(name) = name {
+        let name = &name.as_bytes()[..min(name.len(), address.path.len())];
+        address.path[..name.len()].copy_from_slice(name);
+        // include the null terminator, if there's room
+        len = min(len + name.len() + 1, size_of::<SockAddrUn>());
+    }
+    let count = min(*addr_len as usize, len);
+    let bytes =
+        unsafe { core::slice::from_raw_parts((&address as *const SockAddrUn).cast::<u8>(), count) };
+    let dest = unsafe { get_mut_slice_from_user_space(addr.cast::<u8>(), count) }.ok_or(-EFAULT)?;
+    dest.copy_from_slice(bytes);
+    *addr_len = len as SockLen;
+    Ok(())
+}
+
+/// Creates a socket of the type in `r#type`, which may include `SOCK_NONBLOCK` and
+/// `SOCK_CLOEXEC`. `domain` must be `AF_UNIX` and `protocol` 0.
+pub fn socket(domain: i32, r#type: i32, protocol: i32) -> isize {
+    let (kind, flags) = match check_domain(domain, protocol).and_then(|()| socket_type(r#type)) {
+        Ok(result) => result,
+        Err(e) => return e,
+    };
+    let socket = Arc::new(Socket::new(kind));
+    match root_filesystem()
+        .lock()
+        .socket(running_thread_pid(), socket, flags)
+    {
+        Err(e) => -e.to_isize(),
+        Ok(fd) => fd.into(),
+    }
+}
+
+/// Creates two sockets connected to each other, like `socket`, storing their file
+/// descriptors in `fds`.
+pub fn socketpair(domain: i32, r#type: i32, protocol: i32, fds: *mut i32) -> isize {
+    let (kind, flags) = match check_domain(domain, protocol).and_then(|()| socket_type(r#type)) {
+        Ok(result) => result,
+        Err(e) => return e,
+    };
+    let Some(fds) = (unsafe { get_mut_slice_from_user_space(fds, 2) }) else {
+        return -EFAULT;
+    };
+    let pid = running_thread_pid();
+    let (first, second) = Socket::pair(kind);
+    let mut root = root_filesystem().lock();
+    let first = match root.socket(pid, first, flags) {
+        Ok(fd) => fd,
+        Err(e) => return -e.to_isize(),
+    };
+    let second = match root.socket(pid, second, flags) {
+        Ok(fd) => fd,
+        Err(e) => {
+            let _ = root.close(ProcessFileDescriptor { pid, fd: first });
+            return -e.to_isize();
+        }
+    };
+    fds[0] = first.into();
+    fds[1] = second.into();
+    0
+}
+
+/// Binds the socket `fd` to the path in `addr`, creating a socket file there.
+pub fn bind(fd: usize, addr: *const SockAddrUn, addr_len: SockLen) -> isize {
+    let path = match socket_path(addr, addr_len) {
+        Ok(path) => path,
+        Err(e) => return e,
+    };
+    let Some(fd) = process_fd(fd) else {
+        return -EBADF;
+    };
+    match root_filesystem()
+        .lock()
+        .bind(&running_process().lock(), fd, path)
+    {
+        Err(e) => -e.to_isize(),
+        Ok(()) => 0,
+    }
+}
+
+/// Makes the bound stream socket `fd` accept connections, queueing up to `backlog` of them.
+pub fn listen(fd: usize, backlog: i32) -> isize {
+    let Some(fd) = process_fd(fd) else {
+        return -EBADF;
+    };
+    // a negative backlog means as many as possible
+    let backlog = usize::try_from(backlog).unwrap_or(MAX_BACKLOG);
+    let result = root_filesystem().lock().socket_of(fd);
+    match result.and_then(|socket| socket.listen(backlog)) {
+        Err(e) => -e.to_isize(),
+        Ok(()) => 0,
+    }
+}
+
+/// Waits for a connection to the listening socket `fd`, and returns a new socket connected to
+/// it, opened with the flags `SOCK_NONBLOCK` and `SOCK_CLOEXEC` in `flags` ask for. The
+/// connecting socket's address is stored in `addr` unless it's null, but it's never bound.
+pub fn accept4(fd: usize, addr: *mut SockAddrUn, addr_len: *mut SockLen, flags: i32) -> isize {
+    if (flags & !(SOCK_NONBLOCK | SOCK_CLOEXEC)) != 0 {
+        return -EINVAL;
+    }
+    let flags = open_flags(flags);
+    let Some(fd) = process_fd(fd) else {
+        return -EBADF;
+    };
+    let (listener, nonblocking) = match socket_and_flags(fd, 0) {
+        Ok(result) => result,
+        Err(e) => return -e.to_isize(),
+    };
+    // don't hold the file system while we wait for a connection
+    let socket = match listener.accept(nonblocking) {
+        Ok(socket) => socket,
+        Err(e) => return -e.to_isize(),
+    };
+    if let Err(e) = store_address(None, addr, addr_len) {
+        return e;
+    }
+    match root_filesystem().lock().socket(fd.pid, socket, flags) {
+        Err(e) => -e.to_isize(),
+        Ok(fd) => fd.into(),
+    }
+}
+
+/// Connects the socket `fd` to the socket bound to the path in `addr`.
+pub fn connect(fd: usize, addr: *const SockAddrUn, addr_len: SockLen) -> isize {
+    let path = match socket_path(addr, addr_len) {
+        Ok(path) => path,
+        Err(e) => return e,
+    };
+    let Some(fd) = process_fd(fd) else {
+        return -EBADF;
+    };
+    let (socket, nonblocking) = match socket_and_flags(fd, 0) {
+        Ok(result) => result,
+        Err(e) => return -e.to_isize(),
+    };
+    let target = root_filesystem()
+        .lock()
+        .bound_socket(&running_process().lock(), path);
+    match target.and_then(|target| socket.connect(&target, nonblocking)) {
+        Err(e) => -e.to_isize(),
+        Ok(()) => 0,
+    }
+}
+
+/// The socket `fd`, and whether operations on it shouldn't block, either because it's
+/// nonblocking or `flags` include `MSG_DONTWAIT`.
+fn socket_and_flags(fd: ProcessFileDescriptor, flags: i32) -> Result<(Arc<Socket>, bool)> {
+    let root = root_filesystem().lock();
+    let socket = root.socket_of(fd)?;
+    let nonblocking = (root.status_flags(fd)? & O_NONBLOCK) != 0 || (flags & MSG_DONTWAIT) != 0;
+    Ok((socket, nonblocking))
+}
+
+/// Sends `len` bytes from `buf` on the socket `fd`, to the datagram socket bound to the path
+/// in `addr` if it isn't null.
+pub fn sendto(
+    fd: usize,
+    buf: *const u8,
+    len: usize,
+    flags: i32,
+    addr: *const SockAddrUn,
+    addr_len: SockLen,
+) -> isize {
+    if (flags & !MSG_DONTWAIT) != 0 {
+        return -EINVAL;
+    }
+    let path = if addr.is_null() {
+        None
+    } else {
+        match socket_path(addr, addr_len) {
+            Ok(path) => Some(path),
+            Err(e) => return e,
+        }
+    };
+    let Some(fd) = process_fd(fd) else {
+        return -EBADF;
+    };
+    let len = min(len, MAX_TRANSFER);
+    let Some(buf) = (unsafe { get_slice_from_user_space(buf, len) }) else {
+        return -EFAULT;
+    };
+    let (socket, nonblocking) = match socket_and_flags(fd, flags) {
+        Ok(result) => result,
+        Err(e) => return -e.to_isize(),
+    };
+    let to = match path {
+        None => None,
+        Some(path) => match root_filesystem()
+            .lock()
+            .bound_socket(&running_process().lock(), path)
+        {
+            Ok(to) => Some(to),
+            Err(e) => return -e.to_isize(),
+        },
+    };
+    to_result(socket.send(buf, to, nonblocking))
+}
+
+/// Receives up to `len` bytes into `buf` from the socket `fd`, storing the address of the
+/// socket which sent them in `addr` unless it's null.
+pub fn recvfrom(
+    fd: usize,
+    buf: *mut u8,
+    len: usize,
+    flags: i32,
+    addr: *mut SockAddrUn,
+    addr_len: *mut SockLen,
+) -> isize {
+    if (flags & !MSG_DONTWAIT) != 0 {
+        return -EINVAL;
+    }
+    let Some(fd) = process_fd(fd) else {
+        return -EBADF;
+    };
+    let len = min(len, MAX_TRANSFER);
+    let Some(buf) = (unsafe { get_mut_slice_from_user_space(buf, len) }) else {
+        return -EFAULT;
+    };
+    let (socket, nonblocking) = match socket_and_flags(fd, flags) {
+        Ok(result) => result,
+        Err(e) => return -e.to_isize(),
+    };
+    let (count, from) = match socket.recv(buf, nonblocking) {
+        Ok(result) => result,
+        Err(e) => return -e.to_isize(),
+    };
+    if socket.kind == SocketKind::Stream {
+        // The other end is already known.
+        if !addr.is_null() {
+            let Some(addr_len) = (unsafe { get_mut_from_user_space(addr_len) }) else {
+                return -EFAULT;
+            };
+            *addr_len = 0;
+        }
+    } else if let Err(e) = store_address(from.as_deref(), addr, addr_len) {
+        return e;
+    }
+    count as isize
+}
+
+/// Stops receiving on the socket `fd`, sending on it, or both, as `how` says.
+pub fn shutdown(fd: usize, how: i32) -> isize {
+    let (read, write) = match how {
+        SHUT_RD => (true, false),
+        SHUT_WR => (false, true),
+        SHUT_RDWR => (true, true),
+        _ => return -EINVAL,
+    };
+    let Some(fd) = process_fd(fd) else {
+        return -EBADF;
+    };
+    let result = root_filesystem().lock().socket_of(fd);
+    match result.and_then(|socket| socket.shutdown(read, write)) {
+        Err(e) => -e.to_isize(),
+        Ok(()) => 0,
+    }
+}
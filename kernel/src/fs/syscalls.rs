@@ -2,25 +2,44 @@
 // Here we should be fine since we are checking the validity of pointers.
 #![allow(clippy::not_unsafe_ptr_arg_deref)]
 
-use crate::fs::fs_manager::RootFileSystem;
-use crate::fs::{
-    fs_manager::{Mode, SeekFrom},
-    FileDescriptor, ProcessFileDescriptor,
-};
+use crate::fs::fs_manager::{RootFileSystem, MAX_OPEN_FILES};
+use crate::fs::{fs_manager::SeekFrom, FileDescriptor, ProcessFileDescriptor};
 use crate::mem::util::{
     get_cstr_from_user_space, get_mut_from_user_space, get_mut_slice_from_user_space,
-    get_slice_from_user_space, CStrError,
+    get_ref_from_user_space, get_slice_from_user_space, CStrError,
 };
-use crate::system::{root_filesystem, running_process, running_thread_pid};
+use crate::mem::vma::{SharedMemory, VMAInfo, VMA};
+use crate::paging::unmap_user_range;
+use crate::system::{root_filesystem, running_process, running_thread_pid, unwrap_system};
+use crate::threading::process::Pid;
 use crate::user_program::syscall::{
-    Dirent, Stat, EBADF, EFAULT, EINVAL, ENODEV, ENOENT, ENOMEM, ERANGE, O_CREATE, PROT_EXEC,
-    PROT_READ, PROT_WRITE, SEEK_CUR, SEEK_END, SEEK_SET,
+    Dirent, IoVec, Stat, Termios, WinSize, EBADF, EFAULT, EINVAL, ENODEV, ENOENT, ENOMEM, EPERM,
+    ERANGE, FD_CLOEXEC, F_DUPFD, F_DUPFD_CLOEXEC, F_GETFD, F_GETFL, F_SETFD, F_SETFL, IOV_MAX,
+    MAP_ANONYMOUS, MAP_FIXED, MAP_PRIVATE, MAP_SHARED, O_ACCMODE, O_APPEND, O_CLOEXEC, O_CREATE,
+    O_DIRECTORY, O_EXCL, O_LARGEFILE, O_NOCTTY, O_NONBLOCK, O_RDWR, O_TRUNC, PROT_EXEC, PROT_READ,
+    PROT_WRITE, SEEK_CUR, SEEK_END, SEEK_SET, S_IFIFO, S_IFMT, TCGETS, TCSETS, TCSETSF, TCSETSW,
+    TIOCGPGRP, TIOCGWINSZ, TIOCSPGRP,
 };
 use crate::vfs::tempfs::TempFS;
-use kidneyos_shared::mem::PAGE_FRAME_SIZE;
+use crate::vfs::Result;
+use alloc::{vec, vec::Vec};
+use core::cmp::min;
+use kidneyos_shared::mem::{OFFSET, PAGE_FRAME_SIZE};
+
+/// The open flags the kernel knows about.
+const OPEN_FLAGS: usize = O_ACCMODE
+    | O_CREATE
+    | O_EXCL
+    | O_NOCTTY
+    | O_TRUNC
+    | O_APPEND
+    | O_NONBLOCK
+    | O_LARGEFILE
+    | O_DIRECTORY
+    | O_CLOEXEC;
 
 pub fn open(path: *const u8, flags: usize) -> isize {
-    if (flags & !O_CREATE) != 0 {
+    if (flags & !OPEN_FLAGS) != 0 || (flags & O_ACCMODE) > O_RDWR {
         return -EINVAL;
     }
     let path = match unsafe { get_cstr_from_user_space(path) } {
@@ -28,56 +47,159 @@ pub fn open(path: *const u8, flags: usize) -> isize {
         Err(CStrError::BadUtf8) => return -ENOENT,
         Err(CStrError::Fault) => return -EFAULT,
     };
-    let mode = if (flags & O_CREATE) != 0 {
-        Mode::CreateReadWrite
-    } else {
-        Mode::ReadWrite
-    };
-    match root_filesystem()
-        .lock()
-        .open(&running_process().lock(), path, mode)
-    {
+    match RootFileSystem::open_and_wait(root_filesystem(), &running_process(), path, flags) {
         Err(e) => -e.to_isize(),
         Ok(fd) => fd.into(),
     }
 }
 
+/// The most bytes a single read or write transfers, so that one process can't
+/// starve the others.
+pub(super) const MAX_TRANSFER: usize = 128 << 10;
+
+pub(super) fn process_fd(fd: usize) -> Option<ProcessFileDescriptor> {
+    Some(ProcessFileDescriptor {
+        pid: running_thread_pid(),
+        fd: FileDescriptor::try_from(fd).ok()?,
+    })
+}
+
+pub(super) fn to_result(result: Result<usize>) -> isize {
+    match result {
+        Err(e) => -e.to_isize(),
+        Ok(n) => n as isize,
+    }
+}
+
 pub fn read(fd: usize, buf: *mut u8, count: usize) -> isize {
-    let Ok(fd) = FileDescriptor::try_from(fd) else {
+    let Some(fd) = process_fd(fd) else {
         return -EBADF;
     };
-    // do reads of at most 128KB to not starve other processes
-    let count = core::cmp::min(count, 128 << 10);
+    let count = min(count, MAX_TRANSFER);
     let Some(buf) = (unsafe { get_mut_slice_from_user_space::<u8>(buf, count) }) else {
         return -EFAULT;
     };
-    let fd = ProcessFileDescriptor {
-        pid: running_thread_pid(),
-        fd,
-    };
-    match RootFileSystem::read(root_filesystem(), fd, buf) {
-        Err(e) => -e.to_isize(),
-        Ok(n) => n as isize,
-    }
+    to_result(RootFileSystem::read(root_filesystem(), fd, buf))
 }
 
 pub fn write(fd: usize, buf: *const u8, count: usize) -> isize {
-    let Ok(fd) = FileDescriptor::try_from(fd) else {
+    let Some(fd) = process_fd(fd) else {
         return -EBADF;
     };
-    // do writes of at most 128KB to not starve other processes
-    let count = core::cmp::min(count, 128 << 10);
+    let count = min(count, MAX_TRANSFER);
     let Some(buf) = (unsafe { get_slice_from_user_space::<u8>(buf, count) }) else {
         return -EFAULT;
     };
-    let fd = ProcessFileDescriptor {
-        pid: running_thread_pid(),
-        fd,
+    to_result(RootFileSystem::write(root_filesystem(), fd, buf))
+}
+
+/// The `iovcnt` buffers in `iov`, cut short so that they add up to at most
+/// `MAX_TRANSFER` bytes.
+fn io_vectors(iov: *const IoVec, iovcnt: usize) -> core::result::Result<Vec<IoVec>, isize> {
+    if iovcnt > IOV_MAX {
+        return Err(-EINVAL);
+    }
+    let iov = unsafe { get_slice_from_user_space(iov, iovcnt) }.ok_or(-EFAULT)?;
+    let mut remaining = MAX_TRANSFER;
+    Ok(iov
+        .iter()
+        .map(|&buffer| {
+            let len = min(buffer.len, remaining);
+            remaining -= len;
+            IoVec { len, ..buffer }
+        })
+        .collect())
+}
+
+/// Reads from `fd` into the `iovcnt` buffers in `iov`, filling each before
+/// moving on to the next.
+///
+/// This is a single read of the total length, so a pipe is read from only once.
+pub fn readv(fd: usize, iov: *const IoVec, iovcnt: usize) -> isize {
+    let Some(fd) = process_fd(fd) else {
+        return -EBADF;
     };
-    match RootFileSystem::write(root_filesystem(), fd, buf) {
-        Err(e) => -e.to_isize(),
-        Ok(n) => n as isize,
+    let iov = match io_vectors(iov, iovcnt) {
+        Ok(iov) => iov,
+        Err(e) => return e,
+    };
+    // Check every buffer before reading, so that nothing read is lost.
+    let mut buffers = Vec::with_capacity(iov.len());
+    for buffer in iov {
+        let Some(buffer) =
+            (unsafe { get_mut_slice_from_user_space(buffer.base.cast::<u8>(), buffer.len) })
+        else {
+            return -EFAULT;
+        };
+        buffers.push(buffer);
+    }
+
+    let mut data = vec![0; buffers.iter().map(|buffer| buffer.len()).sum()];
+    let count = match RootFileSystem::read(root_filesystem(), fd, &mut data) {
+        Ok(count) => count,
+        Err(e) => return -e.to_isize(),
+    };
+    let mut data = &data[..count];
+    for buffer in buffers {
+        let len = min(buffer.len(), data.len());
+        buffer[..len].copy_from_slice(&data[..len]);
+        data = &data[len..];
+    }
+    count as isize
+}
+
+/// Writes the `iovcnt` buffers in `iov` to `fd`, one after the other.
+///
+/// This is a single write of the buffers joined together, so a write to a pipe
+/// or the screen isn't interleaved with anyone else's.
+pub fn writev(fd: usize, iov: *const IoVec, iovcnt: usize) -> isize {
+    let Some(fd) = process_fd(fd) else {
+        return -EBADF;
+    };
+    let iov = match io_vectors(iov, iovcnt) {
+        Ok(iov) => iov,
+        Err(e) => return e,
+    };
+    let mut data = Vec::new();
+    for buffer in iov {
+        let Some(buffer) =
+            (unsafe { get_slice_from_user_space(buffer.base.cast::<u8>(), buffer.len) })
+        else {
+            return -EFAULT;
+        };
+        data.extend_from_slice(buffer);
     }
+    to_result(RootFileSystem::write(root_filesystem(), fd, &data))
+}
+
+/// Reads from `fd` at `offset`, without moving its file position.
+pub fn pread64(fd: usize, buf: *mut u8, count: usize, offset: i64) -> isize {
+    let Some(fd) = process_fd(fd) else {
+        return -EBADF;
+    };
+    let Ok(offset) = u64::try_from(offset) else {
+        return -EINVAL;
+    };
+    let count = min(count, MAX_TRANSFER);
+    let Some(buf) = (unsafe { get_mut_slice_from_user_space::<u8>(buf, count) }) else {
+        return -EFAULT;
+    };
+    to_result(root_filesystem().lock().read_at(fd, offset, buf))
+}
+
+/// Writes to `fd` at `offset`, without moving its file position.
+pub fn pwrite64(fd: usize, buf: *const u8, count: usize, offset: i64) -> isize {
+    let Some(fd) = process_fd(fd) else {
+        return -EBADF;
+    };
+    let Ok(offset) = u64::try_from(offset) else {
+        return -EINVAL;
+    };
+    let count = min(count, MAX_TRANSFER);
+    let Some(buf) = (unsafe { get_slice_from_user_space::<u8>(buf, count) }) else {
+        return -EFAULT;
+    };
+    to_result(root_filesystem().lock().write_at(fd, offset, buf))
 }
 
 pub fn lseek64(fd: usize, offset: *mut i64, whence: isize) -> isize {
@@ -106,6 +228,90 @@ pub fn lseek64(fd: usize, offset: *mut i64, whence: isize) -> isize {
     }
 }
 
+/// Gets or changes the settings of the terminal `fd`, its foreground process
+/// group, or its size.
+pub fn ioctl(fd: usize, request: usize, arg: usize) -> isize {
+    let Some(fd) = process_fd(fd) else {
+        return -EBADF;
+    };
+    // This also checks that `fd` is the terminal.
+    let window_size = match root_filesystem().lock().window_size(fd) {
+        Ok(window_size) => window_size,
+        Err(e) => return -e.to_isize(),
+    };
+    let tty = &unwrap_system().tty;
+    match request {
+        TCGETS => {
+            let Some(arg) = (unsafe { get_mut_from_user_space(arg as *mut Termios) }) else {
+                return -EFAULT;
+            };
+            *arg = tty.termios();
+        }
+        TCSETS | TCSETSW | TCSETSF => {
+            let Some(arg) = (unsafe { get_ref_from_user_space(arg as *const Termios) }) else {
+                return -EFAULT;
+            };
+            tty.set_termios(*arg, request == TCSETSF);
+        }
+        TIOCGPGRP => {
+            let Some(arg) = (unsafe { get_mut_from_user_space(arg as *mut i32) }) else {
+                return -EFAULT;
+            };
+            *arg = tty.foreground().into();
+        }
+        TIOCSPGRP => {
+            let Some(&pgid) = (unsafe { get_ref_from_user_space(arg as *const i32) }) else {
+                return -EFAULT;
+            };
+            let Ok(pgid) = Pid::try_from(pgid) else {
+                return -EINVAL;
+            };
+            if unwrap_system().process.table.group(pgid).is_empty() {
+                return -EPERM;
+            }
+            tty.set_foreground(pgid);
+        }
+        TIOCGWINSZ => {
+            let Some(arg) = (unsafe { get_mut_from_user_space(arg as *mut WinSize) }) else {
+                return -EFAULT;
+            };
+            *arg = window_size;
+        }
+        _ => return -EINVAL,
+    }
+    0
+}
+
+/// Gets or sets the flags of `fd`, or duplicates it with `F_DUPFD`.
+pub fn fcntl(fd: usize, cmd: i32, arg: usize) -> isize {
+    let Some(fd) = process_fd(fd) else {
+        return -EBADF;
+    };
+    let mut root = root_filesystem().lock();
+    let result = match cmd {
+        F_DUPFD | F_DUPFD_CLOEXEC => {
+            let Ok(lowest) = FileDescriptor::try_from(arg) else {
+                return -EINVAL;
+            };
+            if lowest >= MAX_OPEN_FILES as FileDescriptor {
+                return -EINVAL;
+            }
+            root.dup_from(fd.pid, fd, lowest, cmd == F_DUPFD_CLOEXEC)
+                .map(|fd| fd as usize)
+        }
+        F_GETFD => root
+            .is_close_on_exec(fd)
+            .map(|close_on_exec| if close_on_exec { FD_CLOEXEC } else { 0 }),
+        F_SETFD => root
+            .set_close_on_exec(fd, (arg & FD_CLOEXEC) != 0)
+            .map(|()| 0),
+        F_GETFL => root.status_flags(fd),
+        F_SETFL => root.set_status_flags(fd, arg).map(|()| 0),
+        _ => return -EINVAL,
+    };
+    to_result(result)
+}
+
 pub fn close(fd: usize) -> isize {
     let Ok(fd) = FileDescriptor::try_from(fd) else {
         return -EBADF;
@@ -165,6 +371,26 @@ pub fn mkdir(path: *const u8) -> isize {
     }
 }
 
+/// Creates the file at `path`, of the type in the `S_IFMT` bits of `mode`,
+/// which must be `S_IFIFO`. The permission bits and `dev` are ignored.
+pub fn mknod(path: *const u8, mode: u32, _dev: u32) -> isize {
+    if (mode & S_IFMT) != S_IFIFO {
+        return -EINVAL;
+    }
+    let path = match unsafe { get_cstr_from_user_space(path) } {
+        Ok(path) => path,
+        Err(CStrError::BadUtf8) => return -EINVAL,
+        Err(CStrError::Fault) => return -EFAULT,
+    };
+    match root_filesystem()
+        .lock()
+        .mkfifo(&running_process().lock(), path)
+    {
+        Err(e) => -e.to_isize(),
+        Ok(()) => 0,
+    }
+}
+
 pub fn fstat(fd: usize, statbuf: *mut Stat) -> isize {
     let Some(statbuf) = (unsafe { get_mut_from_user_space(statbuf) }) else {
         return -EFAULT;
@@ -434,7 +660,6 @@ pub fn mmap(
 ) -> isize {
     crate::println!("mmap fd={fd} addr={addr:?} length={length} prot={prot:#x} flags={flags:#x} offset={offset}");
     let addr = addr as usize;
-    let _ = flags; // TODO: anonymous mapping
     if (prot & PROT_READ) == 0 {
         // non-readable pages can't be created on x86
         return -EINVAL;
@@ -446,26 +671,94 @@ pub fn mmap(
     if length == 0 || length > 0x8000_0000 {
         return -EINVAL;
     }
-    let Ok(fd) = FileDescriptor::try_from(fd) else {
-        return -EBADF;
-    };
-    let fd = ProcessFileDescriptor {
-        pid: running_thread_pid(),
-        fd,
+    let shared = match flags & (MAP_SHARED | MAP_PRIVATE) {
+        MAP_SHARED => true,
+        MAP_PRIVATE => false,
+        _ => return -EINVAL,
     };
+    let fixed = (flags & MAP_FIXED) != 0;
+    if fixed && (addr % PAGE_FRAME_SIZE != 0 || addr == 0) {
+        return -EINVAL;
+    }
+    let writeable = (prot & PROT_WRITE) != 0;
     // align addr to page
     let addr = addr & !(PAGE_FRAME_SIZE - 1);
     // round length up to page frame size
     let length = length.div_ceil(PAGE_FRAME_SIZE) * PAGE_FRAME_SIZE;
-    let mut root = root_filesystem().lock();
-    match root.mmap_file(addr, fd, length, offset, (prot & PROT_WRITE) != 0) {
-        Ok(true) => addr as isize,
-        Ok(false) => {
-            // TODO: figure out an address range that is free
+    if fixed && addr.checked_add(length).map_or(true, |end| end > OFFSET) {
+        return -ENOMEM;
+    }
+
+    let vma = if (flags & MAP_ANONYMOUS) != 0 {
+        let info = VMAInfo::Anonymous { offset: 0 };
+        if shared {
+            VMA::new_shared(info, length, writeable, SharedMemory::new_anonymous())
+        } else {
+            VMA::new(info, length, writeable)
+        }
+    } else {
+        let Ok(fd) = FileDescriptor::try_from(fd) else {
+            return -EBADF;
+        };
+        let fd = ProcessFileDescriptor {
+            pid: running_thread_pid(),
+            fd,
+        };
+        match root_filesystem()
+            .lock()
+            .mmap_file(fd, length, offset, writeable, shared)
+        {
+            Ok(vma) => vma,
+            Err(e) => return -e.to_isize(),
+        }
+    };
+
+    if fixed {
+        // MAP_FIXED replaces whatever was mapped there before.
+        unmap_range(addr, length);
+        let added = running_process().lock().vmas.add_vma(vma, addr);
+        debug_assert!(added);
+        return addr as isize;
+    }
+    let result = running_process()
+        .lock()
+        .vmas
+        .add_vma_near(vma, addr, OFFSET);
+    match result {
+        Ok(addr) => addr as isize,
+        Err(vma) => {
+            root_filesystem().lock().release_mmap(&vma);
             -ENOMEM
         }
-        Err(e) => -e.to_isize(),
     }
 }
 
-// TODO: munmap
+pub fn munmap(addr: *mut core::ffi::c_void, length: usize) -> isize {
+    let addr = addr as usize;
+    if addr % PAGE_FRAME_SIZE != 0 || length == 0 || length > OFFSET.saturating_sub(addr) {
+        return -EINVAL;
+    }
+    let length = length.div_ceil(PAGE_FRAME_SIZE) * PAGE_FRAME_SIZE;
+    unmap_range(addr, length);
+    0
+}
+
+/// Remove the running process' mappings of `start..start + len`, which must be page-aligned.
+fn unmap_range(start: usize, len: usize) {
+    let removed = running_process().lock().vmas.remove_range(start, len);
+    let guard = unwrap_system().threads.running_thread.lock();
+    let mut page_manager = guard
+        .as_ref()
+        .expect("no running thread")
+        .page_manager
+        .lock();
+    for (addr, vma) in removed.iter() {
+        // SAFETY: The pages are no longer part of any VMA, and user frames are allocated one at
+        // a time.
+        unsafe { unmap_user_range(&mut page_manager, addr, vma.size()) };
+    }
+    drop(page_manager);
+    drop(guard);
+    // The removed VMAs are dropped after this, which frees shared memory no one maps anymore.
+    root_filesystem().lock().release_mmaps(&removed);
+}
@@ -0,0 +1,467 @@
+use crate::interrupts::{
+    mutex_irq::{hold_interrupts, MutexIrq},
+    timer, IntrLevel,
+};
+use crate::sync::wait_queue::WaitQueue;
+use crate::system::{running_process, unwrap_system};
+use crate::threading::process::Pid;
+use crate::user_program::signal::send_group_signal;
+use crate::user_program::syscall::{
+    Termios, ECHO, ECHOE, ICANON, ICRNL, ISIG, NCCS, NOFLSH, POLLIN, SIGINT, SIGQUIT, SIGTSTP,
+    VEOF, VERASE, VINTR, VMIN, VQUIT, VSUSP, VTIME,
+};
+use crate::vfs::{Error, Result};
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use core::cmp::min;
+use core::mem::take;
+use core::time::Duration;
+use kidneyos_shared::print;
+use kidneyos_shared::video_memory::VIDEO_MEMORY_WRITER;
+
+/// The longest line which can be typed in canonical mode. Anything typed after
+/// that is dropped.
+const MAX_LINE: usize = 4096;
+
+/// What the backspace key sends, which erases a character by default.
+const BACKSPACE: u8 = 0x08;
+
+/// The settings the terminal starts with: canonical mode with echo, and ^C, ^\
+/// and ^Z sending signals.
+const DEFAULT_TERMIOS: Termios = {
+    let mut cc = [0; NCCS];
+    cc[VINTR] = 0x03;
+    cc[VQUIT] = 0x1c;
+    cc[VERASE] = BACKSPACE;
+    // ^D finishes the line without a newline, so on an empty line a read
+    // returns 0, which programs take as the end of the input.
+    cc[VEOF] = 0x04;
+    cc[VMIN] = 1;
+    cc[VSUSP] = 0x1a;
+    Termios {
+        iflag: ICRNL,
+        oflag: 0,
+        cflag: 0,
+        lflag: ISIG | ICANON | ECHO | ECHOE,
+        line: 0,
+        cc,
+    }
+};
+
+/// The console terminal, which sits between the keyboard and the programs
+/// reading their standard input.
+///
+/// In canonical mode, which is the default, input is edited a line at a time
+/// and echoed, and a read returns at most one line, once it's been finished
+/// with Enter or ^D. In raw mode, each key can be read as soon as it's pressed,
+/// subject to `VMIN` and `VTIME`.
+pub struct Tty {
+    /// Also locked by the keyboard interrupt handler.
+    state: MutexIrq<TtyState>,
+    /// Woken whenever there's new input.
+    pub waiters: WaitQueue,
+}
+
+struct TtyState {
+    termios: Termios,
+    /// Input which can be read. In canonical mode, each entry is a line, and an
+    /// empty one is an end of file. In raw mode, keys are added to the last
+    /// entry.
+    input: VecDeque<Vec<u8>>,
+    /// The line being edited in canonical mode.
+    line: Vec<u8>,
+    /// The process group which is sent the signals for special characters, or
+    /// 0 if there isn't one.
+    foreground: Pid,
+    /// Signals from special characters which haven't been sent yet, and the
+    /// process groups they're for.
+    signals: Vec<(Pid, i32)>,
+}
+
+/// Whether a read should return straight away, or else how long it should
+/// wait for more input.
+#[derive(Debug, PartialEq, Eq)]
+enum ReadWait {
+    Now,
+    Block(Option<Duration>),
+}
+
+impl Tty {
+    pub fn new() -> Self {
+        Self {
+            state: MutexIrq::new(TtyState {
+                termios: DEFAULT_TERMIOS,
+                input: VecDeque::new(),
+                line: Vec::new(),
+                foreground: 0,
+                signals: Vec::new(),
+            }),
+            waiters: WaitQueue::new(),
+        }
+    }
+
+    pub fn termios(&self) -> Termios {
+        self.state.lock().termios
+    }
+
+    /// Changes the terminal's settings, first discarding any input which
+    /// hasn't been read if `flush`. A line being edited when switching to raw
+    /// mode can be read straight away.
+    pub fn set_termios(&self, termios: Termios, flush: bool) {
+        let mut state = self.state.lock();
+        state.termios = termios;
+        if flush {
+            state.input.clear();
+            state.line.clear();
+        }
+        if !state.canonical() && !state.line.is_empty() {
+            let line = take(&mut state.line);
+            state.input.push_back(line);
+        }
+        drop(state);
+        // Readers may be able to return with the new settings.
+        self.waiters.wake_all();
+    }
+
+    pub fn foreground(&self) -> Pid {
+        self.state.lock().foreground
+    }
+
+    pub fn set_foreground(&self, pgid: Pid) {
+        self.state.lock().foreground = pgid;
+    }
+
+    /// Sends the signals for special characters typed so far.
+    ///
+    /// Sending a signal needs locks which the keyboard interrupt handler can't
+    /// take, so this is done on the way back to user mode, and by the kernel
+    /// shell when there's nothing else to do.
+    pub fn send_signals(&self) {
+        let signals = {
+            let mut state = self.state.lock();
+            if state.signals.is_empty() {
+                return;
+            }
+            take(&mut state.signals)
+        };
+        for (pgid, signal) in signals {
+            send_group_signal(pgid, signal);
+        }
+    }
+
+    /// Handles a key pressed on the keyboard. This is called by the keyboard
+    /// interrupt handler.
+    pub fn receive(&self, byte: u8) {
+        let mut state = self.state.lock();
+        let Termios {
+            iflag, lflag, cc, ..
+        } = state.termios;
+        // Enter gives a carriage return, but lines end with a newline.
+        let byte = if byte == b'\r' && iflag & ICRNL != 0 {
+            b'\n'
+        } else {
+            byte
+        };
+        let echo = lflag & ECHO != 0;
+        // Whether `byte` is the special character `index`, which is disabled
+        // by setting it to 0.
+        let special = |index: usize| cc[index] != 0 && byte == cc[index];
+
+        let signal = [(VINTR, SIGINT), (VQUIT, SIGQUIT), (VSUSP, SIGTSTP)]
+            .into_iter()
+            .find(|&(index, _)| special(index));
+        if let Some((_, signal)) = signal.filter(|_| lflag & ISIG != 0) {
+            if echo {
+                echo_byte(byte);
+            }
+            if lflag & NOFLSH == 0 {
+                state.input.clear();
+                state.line.clear();
+            }
+            if state.foreground != 0 {
+                let foreground = state.foreground;
+                state.signals.push((foreground, signal));
+            }
+            drop(state);
+            // Readers are woken so that they notice the signal.
+            self.waiters.wake_all();
+            return;
+        }
+
+        if !state.canonical() {
+            if echo {
+                echo_byte(byte);
+            }
+            match state.input.back_mut() {
+                Some(last) if !last.is_empty() => last.push(byte),
+                _ => state.input.push_back(alloc::vec![byte]),
+            }
+        } else if special(VERASE) {
+            if let Some(erased) = state.line.pop() {
+                if echo && lflag & ECHOE != 0 {
+                    erase_byte(erased);
+                }
+            }
+            return;
+        } else if special(VEOF) {
+            let line = take(&mut state.line);
+            state.input.push_back(line);
+        } else if byte == b'\n' {
+            if echo {
+                echo_byte(byte);
+            }
+            let mut line = take(&mut state.line);
+            line.push(byte);
+            state.input.push_back(line);
+        } else {
+            // Leave room for the newline.
+            if state.line.len() < MAX_LINE - 1 {
+                if echo {
+                    echo_byte(byte);
+                }
+                state.line.push(byte);
+            }
+            return;
+        }
+        drop(state);
+        self.waiters.wake_all();
+    }
+
+    /// Reads the input typed so far, up to `buf.len()` bytes, blocking until
+    /// there is some unless `nonblocking`.
+    pub fn read(&self, buf: &mut [u8], nonblocking: bool) -> Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        // How much input there was when we last looked, and when that changed,
+        // which is when `VTIME` starts counting from.
+        let mut available = 0;
+        let mut since = timer::now();
+        loop {
+            // Any signals we were woken for must reach us before we check.
+            self.send_signals();
+            let _guard = hold_interrupts(IntrLevel::IntrOff);
+            let timeout = {
+                let mut state = self.state.lock();
+                let now = timer::now();
+                if state.available() != available {
+                    available = state.available();
+                    since = now;
+                }
+                match state.read_wait(buf.len(), now - since) {
+                    ReadWait::Now => return Ok(state.take_input(buf)),
+                    ReadWait::Block(timeout) => timeout,
+                }
+            };
+            if running_process().lock().signals.has_deliverable() {
+                return Err(Error::Interrupted);
+            }
+            if nonblocking {
+                return Err(Error::WouldBlock);
+            }
+            self.waiters.wait(timeout);
+        }
+    }
+
+    /// The poll events the terminal is ready to be read for.
+    pub fn read_readiness(&self) -> i16 {
+        if self.state.lock().input.is_empty() {
+            0
+        } else {
+            POLLIN
+        }
+    }
+
+    /// Takes the next line typed in canonical mode, unless the foreground
+    /// process group still has a process which hasn't exited.
+    ///
+    /// This is how the kernel shell shares the terminal with user programs,
+    /// without taking input meant for them.
+    pub fn take_unclaimed_line(&self) -> Option<Vec<u8>> {
+        // The process table can't be locked with interrupts disabled.
+        let foreground = self.foreground();
+        if foreground != 0
+            && unwrap_system()
+                .process
+                .table
+                .group(foreground)
+                .iter()
+                .any(|pcb| pcb.lock().exit_status.is_none())
+        {
+            return None;
+        }
+        let mut state = self.state.lock();
+        if !state.canonical() {
+            return None;
+        }
+        state.input.pop_front()
+    }
+}
+
+impl Default for Tty {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TtyState {
+    fn canonical(&self) -> bool {
+        self.termios.lflag & ICANON != 0
+    }
+
+    /// How many bytes of input can be read.
+    fn available(&self) -> usize {
+        self.input.iter().map(Vec::len).sum()
+    }
+
+    /// Whether a read of `len` bytes can return yet, where `elapsed` is how
+    /// long it's been since the read started or input last arrived.
+    ///
+    /// In raw mode, a read waits for `VMIN` bytes. If `VTIME` is set, it also
+    /// returns once that long has passed without any input, but with `VMIN`
+    /// set only after the first byte has arrived. With neither set, it never
+    /// waits.
+    fn read_wait(&self, len: usize, elapsed: Duration) -> ReadWait {
+        if self.canonical() {
+            return if self.input.is_empty() {
+                ReadWait::Block(None)
+            } else {
+                ReadWait::Now
+            };
+        }
+        let wanted = min(usize::from(self.termios.cc[VMIN]), len);
+        let time = Duration::from_millis(100 * u64::from(self.termios.cc[VTIME]));
+        let available = self.available();
+        if available >= wanted.max(1) {
+            return ReadWait::Now;
+        }
+        if time.is_zero() {
+            return if wanted == 0 {
+                ReadWait::Now
+            } else {
+                ReadWait::Block(None)
+            };
+        }
+        if wanted > 0 && available == 0 {
+            return ReadWait::Block(None);
+        }
+        match time.checked_sub(elapsed) {
+            Some(left) if !left.is_zero() => ReadWait::Block(Some(left)),
+            _ => ReadWait::Now,
+        }
+    }
+
+    /// Moves input into `buf`, which must not be empty. In canonical mode, at
+    /// most one line is taken.
+    fn take_input(&mut self, buf: &mut [u8]) -> usize {
+        let mut count = 0;
+        while let Some(mut entry) = self.input.pop_front() {
+            let len = min(entry.len(), buf.len() - count);
+            buf[count..count + len].copy_from_slice(&entry[..len]);
+            count += len;
+            if len < entry.len() {
+                entry.drain(..len);
+                self.input.push_front(entry);
+                break;
+            }
+            if self.canonical() || count == buf.len() {
+                break;
+            }
+        }
+        count
+    }
+}
+
+/// Whether `byte` is echoed as `^` and a letter.
+fn is_control(byte: u8) -> bool {
+    byte.is_ascii_control() && byte != b'\n' && byte != b'\t'
+}
+
+fn echo_byte(byte: u8) {
+    if is_control(byte) {
+        print!("^{}", (byte ^ 0x40) as char);
+    } else {
+        print!("{}", byte as char);
+    }
+}
+
+fn erase_byte(byte: u8) {
+    let width = if is_control(byte) { 2 } else { 1 };
+    for _ in 0..width {
+        // SAFETY: no other mut references to VIDEO_MEMORY_WRITER here
+        unsafe { VIDEO_MEMORY_WRITER.backspace() };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    fn state(canonical: bool, input: &[&[u8]]) -> TtyState {
+        let mut termios = DEFAULT_TERMIOS;
+        if !canonical {
+            termios.lflag &= !ICANON;
+        }
+        TtyState {
+            termios,
+            input: input.iter().map(|entry| entry.to_vec()).collect(),
+            line: Vec::new(),
+            foreground: 0,
+            signals: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn canonical_reads_take_one_line() {
+        let mut state = state(true, &[b"hello\n", b"", b"world\n"]);
+        let mut buf = [0; 4];
+        assert_eq!(state.take_input(&mut buf), 4);
+        assert_eq!(&buf, b"hell");
+        assert_eq!(state.take_input(&mut buf), 2);
+        assert_eq!(&buf[..2], b"o\n");
+        // ^D on an empty line
+        assert_eq!(state.take_input(&mut buf), 0);
+        assert_eq!(state.input, vec![b"world\n".to_vec()]);
+    }
+
+    #[test]
+    fn raw_reads_take_everything() {
+        let mut state = state(false, &[b"ab", b"", b"cd"]);
+        let mut buf = [0; 8];
+        assert_eq!(state.take_input(&mut buf), 4);
+        assert_eq!(&buf[..4], b"abcd");
+        assert!(state.input.is_empty());
+    }
+
+    #[test]
+    fn raw_reads_wait_for_vmin_and_vtime() {
+        let mut state = state(false, &[b"ab"]);
+        let tenth = Duration::from_millis(100);
+        let mut wait = |vmin, vtime, len, elapsed| {
+            state.termios.cc[VMIN] = vmin;
+            state.termios.cc[VTIME] = vtime;
+            state.read_wait(len, elapsed)
+        };
+        assert_eq!(wait(1, 0, 8, Duration::ZERO), ReadWait::Now);
+        assert_eq!(wait(3, 0, 8, Duration::ZERO), ReadWait::Block(None));
+        // A read never waits for more than fits in its buffer.
+        assert_eq!(wait(3, 0, 2, Duration::ZERO), ReadWait::Now);
+        // VTIME is the longest gap between bytes once the first has arrived.
+        assert_eq!(wait(3, 2, 8, tenth), ReadWait::Block(Some(tenth)));
+        assert_eq!(wait(3, 2, 8, 2 * tenth), ReadWait::Now);
+
+        state.input.clear();
+        let mut wait = |vmin, vtime, elapsed| {
+            state.termios.cc[VMIN] = vmin;
+            state.termios.cc[VTIME] = vtime;
+            state.read_wait(8, elapsed)
+        };
+        assert_eq!(wait(3, 2, 5 * tenth), ReadWait::Block(None));
+        // With VMIN 0, VTIME is how long the whole read waits, and without
+        // it, the read doesn't wait at all.
+        assert_eq!(wait(0, 2, tenth), ReadWait::Block(Some(tenth)));
+        assert_eq!(wait(0, 2, 2 * tenth), ReadWait::Now);
+        assert_eq!(wait(0, 0, Duration::ZERO), ReadWait::Now);
+    }
+}
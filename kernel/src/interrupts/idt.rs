@@ -7,9 +7,13 @@ use kidneyos_shared::{bit_array::BitArray, bitfield};
 use paste::paste;
 
 use crate::interrupts::intr_handler::{
+    alignment_check_handler, bound_range_exceeded_handler, breakpoint_handler, debug_handler,
+    device_not_available_handler, divide_error_handler, double_fault_handler,
     general_protection_fault_handler, ide_prim_interrupt_handler, ide_secd_interrupt_handler,
-    keyboard_handler, page_fault_handler, syscall_handler, timer_interrupt_handler,
-    unhandled_handler,
+    invalid_opcode_handler, invalid_tss_handler, keyboard_handler, machine_check_handler,
+    overflow_handler, page_fault_handler, segment_not_present_handler, simd_floating_point_handler,
+    stack_fault_handler, syscall_handler, timer_interrupt_handler, unhandled_handler,
+    x87_floating_point_handler,
 };
 
 bitfield!(
@@ -72,8 +76,33 @@ pub unsafe fn load() {
             .with_descriptor_privilege_level(3u8)
             .with_present(true);
     }
-    IDT[0xd] = IDT[0xd].with_offset(general_protection_fault_handler as usize as u32);
-    IDT[0xe] = IDT[0xe].with_offset(page_fault_handler as usize as u32);
+    // CPU exceptions. Only the ones that a program can raise on purpose with
+    // an `int` instruction are accessible from user mode, since the others
+    // could push an error code that the handler isn't expecting.
+    let exception_handlers: [(usize, unsafe extern "C" fn() -> !, u8); 17] = [
+        (0x0, divide_error_handler, 0),
+        (0x1, debug_handler, 0),
+        (0x3, breakpoint_handler, 3),
+        (0x4, overflow_handler, 3),
+        (0x5, bound_range_exceeded_handler, 0),
+        (0x6, invalid_opcode_handler, 0),
+        (0x7, device_not_available_handler, 0),
+        (0x8, double_fault_handler, 0),
+        (0xa, invalid_tss_handler, 0),
+        (0xb, segment_not_present_handler, 0),
+        (0xc, stack_fault_handler, 0),
+        (0xd, general_protection_fault_handler, 0),
+        (0xe, page_fault_handler, 0),
+        (0x10, x87_floating_point_handler, 0),
+        (0x11, alignment_check_handler, 0),
+        (0x12, machine_check_handler, 0),
+        (0x13, simd_floating_point_handler, 0),
+    ];
+    for (vector, handler, privilege_level) in exception_handlers {
+        IDT[vector] = IDT[vector]
+            .with_offset(handler as usize as u32)
+            .with_descriptor_privilege_level(privilege_level);
+    }
     IDT[0x20] = IDT[0x20].with_offset(timer_interrupt_handler as usize as u32); // PIC1_OFFSET (IRQ0)
     IDT[0x21] = IDT[0x21].with_offset(keyboard_handler as usize as u32); // Keyboard (IRQ1)
     IDT[0x2E] = IDT[0x2E].with_offset(ide_prim_interrupt_handler as usize as u32); // IDE Primary (IRQ14)
@@ -1,11 +1,20 @@
 use core::arch::asm;
+use kidneyos_shared::{bit_array::BitArray, bitfield};
+use paste::paste;
 
 use crate::drivers::ata::ata_interrupt;
 use crate::drivers::input::keyboard;
-use crate::interrupts::{intr_enable, pic, timer};
-use crate::system::running_process;
+use crate::interrupts::{intr_enable, pic, timer, trap_frame::TrapFrame};
+use crate::mem::util::get_ref_from_user_space;
+use crate::paging;
+use crate::system::{running_process, running_thread_pid};
+use crate::threading::process_functions::terminate_process;
 use crate::threading::scheduling;
-use crate::user_program::syscall;
+use crate::threading::thread_control_block::ExitStatus;
+use crate::user_program::syscall::{SIGBUS, SIGFPE, SIGILL, SIGSEGV, SIGTRAP};
+use crate::user_program::{signal, syscall};
+use kidneyos_shared::global_descriptor_table::{USER_CODE_SELECTOR, USER_DATA_SELECTOR};
+use kidneyos_shared::println;
 
 /* This file contains all the interrupt handlers to be installed in the IDT when the kernel is initialized.
  * Each must be naked function with C linkage and the type fn() -> !
@@ -27,16 +36,32 @@ pub unsafe extern "C" fn unhandled_handler() -> ! {
 #[naked]
 pub unsafe extern "C" fn page_fault_handler() -> ! {
     unsafe fn inner(error_code: u32, return_eip: usize) {
+        bitfield!(
+            PageFaultErrorCode, u32 {} { (present, 0), (write, 1), (user, 2) }
+        );
+
         let vaddr: usize;
         asm!("mov {}, cr2", out(reg) vaddr);
         // important: re-enable interrupts before acquiring lock to prevent deadlock
         intr_enable();
+        let error = PageFaultErrorCode::new(error_code);
+        // a write to a present page may just be a write to a copy-on-write page
+        if error.present() && error.write() && paging::handle_copy_on_write_fault(vaddr) {
+            return;
+        }
         let pcb = running_process();
         let pcb = pcb.lock();
         // try checking for a VMA matching this address
-        if !pcb.vmas.install_pte(vaddr) {
+        if pcb.vmas.install_pte(vaddr) {
+            return;
+        }
+        if !error.user() {
             panic!("page fault with error code {error_code:#b} occurred when trying to access {vaddr:#X} from instruction at {return_eip:#X}");
         }
+        let pid = pcb.pid;
+        drop(pcb);
+        println!("process {pid} killed by page fault when trying to access {vaddr:#X} from instruction at {return_eip:#X}");
+        terminate_process(ExitStatus::Faulted(SIGSEGV));
     }
 
     asm!(
@@ -60,45 +85,270 @@ pub unsafe extern "C" fn page_fault_handler() -> ! {
     )
 }
 
-#[naked]
-pub unsafe extern "C" fn general_protection_fault_handler() -> ! {
-    unsafe fn inner(error_code: u32, return_eip: usize) -> ! {
-        panic!("general protection fault with error code {error_code:#b} occurred from instruction at {return_eip:#X}");
+/// The state on the stack when `exception_entry` calls `handle_exception`.
+#[repr(C)]
+#[derive(Debug)]
+struct ExceptionFrame {
+    edi: u32,
+    esi: u32,
+    ebp: u32,
+    kernel_esp: u32,
+    ebx: u32,
+    edx: u32,
+    ecx: u32,
+    eax: u32,
+
+    vector: u32,
+    error_code: u32,
+
+    eip: u32,
+    cs: u32,
+    eflags: u32,
+    // These are only pushed by the CPU if the exception occurred in user mode.
+    esp: u32,
+    ss: u32,
+}
+
+/// Returns the name of the CPU exception `vector`, and the signal which a
+/// process causing it should be killed with, if a process can cause it at all.
+fn describe_exception(vector: u32) -> (&'static str, Option<i32>) {
+    match vector {
+        0x0 => ("divide error", Some(SIGFPE)),
+        0x1 => ("debug exception", Some(SIGTRAP)),
+        0x3 => ("breakpoint", Some(SIGTRAP)),
+        0x4 => ("overflow", Some(SIGSEGV)),
+        0x5 => ("bound range exceeded", Some(SIGSEGV)),
+        0x6 => ("invalid opcode", Some(SIGILL)),
+        0x7 => ("device not available", Some(SIGFPE)),
+        0x8 => ("double fault", None),
+        0xa => ("invalid TSS", Some(SIGSEGV)),
+        0xb => ("segment not present", Some(SIGBUS)),
+        0xc => ("stack fault", Some(SIGBUS)),
+        0xd => ("general protection fault", Some(SIGSEGV)),
+        0x10 => ("x87 floating-point exception", Some(SIGFPE)),
+        0x11 => ("alignment check", Some(SIGBUS)),
+        0x12 => ("machine check", None),
+        0x13 => ("SIMD floating-point exception", Some(SIGFPE)),
+        _ => ("unknown exception", None),
     }
+}
 
+/// Kills the running process if it caused the exception described by `frame`
+/// in user mode. Otherwise, the kernel itself is at fault and we panic.
+extern "C" fn handle_exception(frame: &ExceptionFrame) -> ! {
+    let (name, signal) = describe_exception(frame.vector);
+    let from_user = frame.cs & 3 == 3;
+    match signal {
+        Some(signal) if from_user => {
+            intr_enable();
+            let pid = running_thread_pid();
+            println!(
+                "process {pid} killed by {name} with error code {:#b} from instruction at {:#X}",
+                frame.error_code, frame.eip
+            );
+            terminate_process(ExitStatus::Faulted(signal));
+        }
+        _ => panic!(
+            "{name} with error code {:#b} occurred from instruction at {:#X}\n{frame:#X?}",
+            frame.error_code, frame.eip
+        ),
+    }
+}
+
+/// The common part of the CPU exception handlers, which expects the exception
+/// vector and an error code to have been pushed.
+#[naked]
+unsafe extern "C" fn exception_entry() -> ! {
     asm!(
         "
+        pusha
+        push esp
         call {}
         ",
-        sym inner,
+        sym handle_exception,
         options(noreturn),
     )
 }
 
+macro_rules! exception_handler {
+    // The CPU pushes an error code for this exception.
+    ($name:ident, $vector:literal, error_code) => {
+        #[naked]
+        pub unsafe extern "C" fn $name() -> ! {
+            asm!(
+                "
+                push {}
+                jmp {}
+                ",
+                const $vector,
+                sym exception_entry,
+                options(noreturn),
+            )
+        }
+    };
+    ($name:ident, $vector:literal) => {
+        #[naked]
+        pub unsafe extern "C" fn $name() -> ! {
+            asm!(
+                "
+                // Push a placeholder error code, so the stack looks the same
+                // as for exceptions which have one.
+                push 0
+                push {}
+                jmp {}
+                ",
+                const $vector,
+                sym exception_entry,
+                options(noreturn),
+            )
+        }
+    };
+}
+
+exception_handler!(divide_error_handler, 0x0);
+exception_handler!(debug_handler, 0x1);
+exception_handler!(breakpoint_handler, 0x3);
+exception_handler!(overflow_handler, 0x4);
+exception_handler!(bound_range_exceeded_handler, 0x5);
+exception_handler!(invalid_opcode_handler, 0x6);
+exception_handler!(device_not_available_handler, 0x7);
+exception_handler!(double_fault_handler, 0x8, error_code);
+exception_handler!(invalid_tss_handler, 0xa, error_code);
+exception_handler!(segment_not_present_handler, 0xb, error_code);
+exception_handler!(stack_fault_handler, 0xc, error_code);
+exception_handler!(general_protection_fault_handler, 0xd, error_code);
+exception_handler!(x87_floating_point_handler, 0x10);
+exception_handler!(alignment_check_handler, 0x11, error_code);
+exception_handler!(machine_check_handler, 0x12);
+exception_handler!(simd_floating_point_handler, 0x13);
+
 #[naked]
 pub unsafe extern "C" fn syscall_handler() -> ! {
     asm!(
         "
+        // Save the program's registers. Together with the frame pushed by the
+        // CPU, this forms a TrapFrame at the top of the kernel stack, which
+        // fork uses to start the child where the parent left off.
+        pusha
+
         // Push arguments to stack.
+        push ebp
+        push edi
+        push esi
         push edx
         push ecx
         push ebx
         push eax
 
-        // TODO: We need to define what our syscall ABI is allowed to clobber
-        // and what it must preserve, then actually do that. We should also
-        // investigate what actual OSs do to ensure that we're not leaking
-        // sensitive kernel data, even if we are respecting our ABI.
-
         call {}
         // eax will contain the handler's return value, which is where it should
         // remain when we return to the program.
 
-        add esp, 16 // Drop arguments from stack.
+        add esp, 28 // Drop arguments from stack.
+
+        // Overwrite the saved eax with the return value so popa restores it.
+        // Every other register is restored to what the program passed in, so
+        // it gets back nothing the kernel left in them.
+        mov [esp + 28], eax
+
+        // Act on any signals before returning to the program, which may
+        // change the registers it returns with.
+        push esp
+        call {}
+        add esp, 4
+
+        popa
 
         iretd
         ",
         sym syscall::handler,
+        sym signal::handle_pending_signals,
+        options(noreturn),
+    )
+}
+
+/// The entry point for syscalls made with `sysenter`.
+///
+/// `sysenter` doesn't save anything, so programs must leave a return address
+/// followed by the sixth argument on their stack, and pass the stack pointer in
+/// ebp instead of the sixth argument. The other arguments are passed like they
+/// are to `syscall_handler`. When the kernel returns with `sysexit`, it resumes
+/// the program at the return address with that address popped, and with ecx and
+/// edx clobbered. Every other register except eax is preserved.
+#[naked]
+pub unsafe extern "C" fn sysenter_handler() -> ! {
+    /// Fills in where the program resumes, then handles the syscall. Returns
+    /// whether the program can still be resumed with `sysexit`, which can only
+    /// restore eip and esp.
+    unsafe extern "C" fn inner(trap_frame: &mut TrapFrame) -> bool {
+        let stack = trap_frame.ebp as usize;
+        let (Some(&return_address), Some(&arg5)) = (
+            get_ref_from_user_space(stack as *const u32),
+            get_ref_from_user_space(stack.wrapping_add(4) as *const u32),
+        ) else {
+            let pid = running_thread_pid();
+            println!("process {pid} killed by sysenter with invalid stack pointer {stack:#X}");
+            terminate_process(ExitStatus::Faulted(SIGSEGV));
+        };
+        trap_frame.eip = return_address;
+        trap_frame.esp = stack as u32 + 4;
+        let (eip, esp) = (trap_frame.eip, trap_frame.esp);
+
+        trap_frame.eax = syscall::handler(
+            trap_frame.eax as usize,
+            trap_frame.ebx as usize,
+            trap_frame.ecx as usize,
+            trap_frame.edx as usize,
+            trap_frame.esi as usize,
+            trap_frame.edi as usize,
+            arg5 as usize,
+        ) as u32;
+        signal::handle_pending_signals(trap_frame);
+
+        // exec, sigreturn and signal delivery change where the program resumes,
+        // and may need every register restored.
+        trap_frame.eip == eip && trap_frame.esp == esp
+    }
+
+    asm!(
+        "
+        // Build the frame the CPU would have pushed for an interrupt, then save
+        // the program's registers on top of it, so that this is a TrapFrame
+        // like the one syscall_handler builds. The program's eflags are as it
+        // left them, except for the interrupt flag which sysenter cleared.
+        push {user_data}
+        push ebp
+        pushfd
+        or dword ptr [esp], 0x200
+        push {user_code}
+        push 0 // Filled in with the return address.
+        pusha
+
+        push esp
+        call {inner}
+        add esp, 4
+
+        test al, al
+        jz 2f
+
+        popa
+        mov edx, [esp]      // eip
+        mov ecx, [esp + 12] // esp
+        // Restore eflags with interrupts still disabled, and enable them on
+        // the way out; sti takes effect only after sysexit.
+        and dword ptr [esp + 8], ~0x200
+        add esp, 8
+        popfd
+        sti
+        sysexit
+
+    2:
+        popa
+        iretd
+        ",
+        user_data = const USER_DATA_SELECTOR,
+        user_code = const USER_CODE_SELECTOR,
+        inner = sym inner,
         options(noreturn),
     )
 }
@@ -115,12 +365,19 @@ pub unsafe extern "C" fn timer_interrupt_handler() -> ! {
         call {} // Yield process
 
         add esp, 4 // Drop arguments from stack
+
+        // Act on signals if we're returning to user mode.
+        push esp
+        call {}
+        add esp, 4
+
         popa
         iretd
         ",
         sym timer::step_sys_clock,
         sym pic::send_eoi,
         sym scheduling::scheduler_yield_and_continue,
+        sym signal::handle_pending_signals,
         options(noreturn),
     )
 }
@@ -1,9 +1,11 @@
 pub mod idt;
 pub mod mutex_irq;
 pub mod pic;
+pub mod sysenter;
 
 mod intr_handler;
 pub mod timer;
+pub mod trap_frame;
 
 use core::{
     arch::asm,
@@ -0,0 +1,38 @@
+// https://wiki.osdev.org/SYSENTER
+
+use core::arch::asm;
+use kidneyos_shared::global_descriptor_table::KERNEL_CODE_SELECTOR;
+
+use crate::interrupts::intr_handler::sysenter_handler;
+
+const IA32_SYSENTER_CS: u32 = 0x174;
+const IA32_SYSENTER_ESP: u32 = 0x175;
+const IA32_SYSENTER_EIP: u32 = 0x176;
+
+unsafe fn write_msr(msr: u32, value: u32) {
+    asm!("wrmsr", in("ecx") msr, in("eax") value, in("edx") 0, options(nostack, preserves_flags));
+}
+
+/// Sets up `sysenter` to enter the kernel at `sysenter_handler`. The user code
+/// and data segments `sysexit` returns to are derived from the kernel code
+/// segment, which is why the GDT is laid out the way it is.
+///
+/// # Safety
+///
+/// The GDT must have been loaded, and `set_kernel_stack` must be called before
+/// a program can run `sysenter`.
+pub unsafe fn load() {
+    write_msr(IA32_SYSENTER_CS, KERNEL_CODE_SELECTOR.into());
+    write_msr(IA32_SYSENTER_EIP, sysenter_handler as usize as u32);
+}
+
+/// Sets the stack `sysenter` switches to, which should be the top of the
+/// running thread's kernel stack.
+///
+/// # Safety
+///
+/// Interrupts must be disabled, and `top` must be the top of the kernel stack
+/// of the thread which runs next.
+pub unsafe fn set_kernel_stack(top: usize) {
+    write_msr(IA32_SYSENTER_ESP, top as u32);
+}
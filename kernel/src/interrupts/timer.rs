@@ -1,5 +1,9 @@
-use super::mutex_irq::MutexIrq;
-use crate::threading::scheduling::scheduler_yield_and_continue;
+use super::mutex_irq::{hold_interrupts, MutexIrq};
+use super::IntrLevel;
+use crate::system::running_thread_tid;
+use crate::threading::process::Tid;
+use crate::threading::thread_sleep::{thread_sleep, thread_wakeup};
+use alloc::collections::BTreeSet;
 use core::time::Duration;
 
 // PIT generates 3579545 / 3 Hz input signal which we wait to receive 0xffff (65535) of before sending a timer interrupt.
@@ -10,6 +14,9 @@ pub const TIMER_INTERRUPT_INTERVAL: Duration =
 
 static SYS_CLOCK: MutexIrq<Duration> = MutexIrq::new(Duration::new(0, 0));
 
+/// Threads which are sleeping, ordered by the time at which they should be woken up.
+static SLEEP_QUEUE: MutexIrq<BTreeSet<(Duration, Tid)>> = MutexIrq::new(BTreeSet::new());
+
 pub fn step_sys_clock() {
     let mut clock = SYS_CLOCK.lock();
     match clock.checked_add(TIMER_INTERRUPT_INTERVAL) {
@@ -18,19 +25,56 @@ pub fn step_sys_clock() {
         }
         None => panic!("System clock overflowed!"),
     }
-}
+    let now = *clock;
+    drop(clock);
 
-#[allow(unused)]
-#[allow(clippy::while_immutable_condition)]
-pub fn sleep(time: Duration) -> usize {
-    let clock = SYS_CLOCK.lock();
-    match clock.checked_add(time) {
-        Some(end) => {
-            while *clock < end {
-                scheduler_yield_and_continue();
-            }
-            0
+    let mut sleep_queue = SLEEP_QUEUE.lock();
+    while let Some(&(wakeup, tid)) = sleep_queue.first() {
+        if wakeup > now {
+            break;
         }
+        sleep_queue.pop_first();
+        thread_wakeup(tid);
+    }
+}
+
+/// Returns the time elapsed since the timer started.
+pub fn now() -> Duration {
+    *SYS_CLOCK.lock()
+}
+
+/// Blocks the running thread until the system clock reaches `wakeup`, or until it is woken early
+/// by [`thread_wakeup`].
+///
+/// Returns the time remaining until `wakeup`, which is zero unless the thread was woken early.
+fn sleep_until(wakeup: Duration) -> Duration {
+    let tid = running_thread_tid();
+
+    // Interrupts must stay off until we're blocked, otherwise the timer could try to wake us up
+    // before we're in the scheduler and the wakeup would be lost.
+    let _guard = hold_interrupts(IntrLevel::IntrOff);
+    if now() >= wakeup {
+        return Duration::ZERO;
+    }
+    SLEEP_QUEUE.lock().insert((wakeup, tid));
+    thread_sleep();
+
+    // We're only still in the queue if something else woke us up.
+    SLEEP_QUEUE.lock().remove(&(wakeup, tid));
+    wakeup.saturating_sub(now())
+}
+
+/// Blocks the running thread for `time`, or until it is woken early by [`thread_wakeup`].
+///
+/// Returns the time remaining, which is zero unless the thread was woken early.
+pub fn sleep_interruptible(time: Duration) -> Duration {
+    sleep_until(now().saturating_add(time))
+}
+
+/// Blocks the running thread for at least `time`.
+pub fn sleep(time: Duration) {
+    match now().checked_add(time) {
+        Some(end) => while sleep_until(end) > Duration::ZERO {},
         None => panic!("Wakeup time is too far into the future!"),
     }
 }
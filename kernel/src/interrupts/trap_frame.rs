@@ -0,0 +1,26 @@
+/// The user-mode register state saved at the top of a thread's kernel stack
+/// when it enters the kernel from user mode through `syscall_handler` or
+/// `sysenter_handler`.
+///
+/// The layout matches `pusha` followed by the frame the CPU pushes when an
+/// interrupt causes a privilege level change, so `popa` followed by `iretd`
+/// will resume the thread with exactly this state.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TrapFrame {
+    pub edi: u32,
+    pub esi: u32,
+    pub ebp: u32,
+    // The kernel stack pointer at the time of the `pusha`, which `popa` skips.
+    pub kernel_esp: u32,
+    pub ebx: u32,
+    pub edx: u32,
+    pub ecx: u32,
+    pub eax: u32,
+
+    pub eip: u32,
+    pub cs: u32,
+    pub eflags: u32,
+    pub esp: u32,
+    pub ss: u32,
+}
@@ -31,14 +31,22 @@ use crate::block::block_core::BlockManager;
 use crate::drivers::ata::ata_core::ide_init;
 use crate::drivers::input::input_core::InputBuffer;
 use crate::fs::fs_manager::RootFileSystem;
+use crate::fs::tty::Tty;
+use crate::paging::FrameRefCounts;
 use crate::sync::mutex::Mutex;
 use crate::sync::rwlock::sleep::RwLock;
-use crate::system::SystemState;
+use crate::system::{unwrap_system, SystemState};
 use crate::threading::process::create_process_state;
 use crate::threading::thread_control_block::ThreadControlBlock;
-use alloc::boxed::Box;
-use interrupts::{idt, pic};
-use kidneyos_shared::{global_descriptor_table, println, video_memory::VIDEO_MEMORY_WRITER};
+use alloc::{alloc::Global, boxed::Box};
+use interrupts::{idt, pic, sysenter};
+use kidneyos_shared::{
+    global_descriptor_table,
+    mem::PAGE_FRAME_SIZE,
+    println,
+    sizes::{KB, MB},
+    video_memory::VIDEO_MEMORY_WRITER,
+};
 use mem::KernelAllocator;
 use threading::{create_thread_state, thread_system_start};
 use vfs::tempfs::TempFS;
@@ -78,6 +86,10 @@ extern "C" fn main(mem_upper: usize, video_memory_skip_lines: usize) -> ! {
         global_descriptor_table::load();
         println!("GDTR set up!");
 
+        println!("Setting up sysenter");
+        sysenter::load();
+        println!("sysenter set up!");
+
         println!("Setting up PIT");
         pic::pic_remap(pic::PIC1_OFFSET, pic::PIC2_OFFSET);
         pic::init_pit();
@@ -98,7 +110,17 @@ extern "C" fn main(mem_upper: usize, video_memory_skip_lines: usize) -> ! {
             ThreadControlBlock::new_with_setup(ide_init, true, 0, &mut root, &mut process);
 
         let block_manager = BlockManager::default();
-        let input_buffer = Mutex::new(InputBuffer::new());
+        let mut input_buffer = InputBuffer::new();
+        // keys go to the terminal, which hands them to whoever reads standard input
+        input_buffer
+            .on_receive
+            .push(|byte| unwrap_system().tty.receive(byte));
+        let input_buffer = Mutex::new(input_buffer);
+        // Cover all of physical memory, up to the end of upper memory.
+        let frame_ref_counts = Mutex::new(FrameRefCounts::new_in(
+            (MB + mem_upper * KB) / PAGE_FRAME_SIZE,
+            Global,
+        ));
 
         threads.scheduler.lock().push(Box::new(ide_tcb));
 
@@ -108,6 +130,8 @@ extern "C" fn main(mem_upper: usize, video_memory_skip_lines: usize) -> ! {
             block_manager: RwLock::new(block_manager),
             root_filesystem: Mutex::new(root),
             input_buffer,
+            tty: Tty::new(),
+            frame_ref_counts,
         });
         println!("initialized system");
 
@@ -0,0 +1,52 @@
+use crate::mem::vma::{VMAInfo, VMA};
+use crate::paging::unmap_user_range;
+use crate::system::{running_process, unwrap_system};
+use kidneyos_shared::mem::{OFFSET, PAGE_FRAME_SIZE};
+
+/// Move the end of the running process' heap to `addr`, growing or shrinking its heap VMA to
+/// match.
+///
+/// Returns the new program break, or the current one if it couldn't be moved. In particular,
+/// `brk(0)` returns the current program break.
+pub fn brk(addr: usize) -> usize {
+    let pcb = running_process();
+    let mut pcb = pcb.lock();
+    let heap_start = pcb.heap_start;
+    let old_break = pcb.program_break;
+    if heap_start == 0 || addr < heap_start || addr >= OFFSET {
+        return old_break;
+    }
+
+    let old_end = old_break.next_multiple_of(PAGE_FRAME_SIZE);
+    let new_end = addr.next_multiple_of(PAGE_FRAME_SIZE);
+    let new_size = new_end - heap_start;
+    // VMAs can't be empty, so the heap VMA only exists while the heap isn't.
+    let resized = match (old_end == heap_start, new_end == heap_start) {
+        (true, true) => true,
+        (true, false) => pcb
+            .vmas
+            .add_vma(VMA::new(VMAInfo::Heap, new_size, true), heap_start),
+        (false, true) => pcb.vmas.remove_vma(heap_start).is_some(),
+        (false, false) => pcb.vmas.resize_vma(heap_start, new_size),
+    };
+    if !resized {
+        return old_break;
+    }
+
+    // New pages are installed lazily by the page fault handler, but pages which were already
+    // installed past the new end have to be given back.
+    if new_end < old_end {
+        let guard = unwrap_system().threads.running_thread.lock();
+        let mut page_manager = guard
+            .as_ref()
+            .expect("no running thread")
+            .page_manager
+            .lock();
+        // SAFETY: The pages are no longer part of any VMA, and the heap's frames are allocated
+        // one at a time when they're installed.
+        unsafe { unmap_user_range(&mut page_manager, new_end, old_end - new_end) };
+    }
+
+    pcb.program_break = addr;
+    addr
+}
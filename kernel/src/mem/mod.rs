@@ -1,3 +1,4 @@
+pub mod brk;
 mod buddy_allocator;
 mod dummy_allocator;
 mod frame_allocator;
@@ -27,6 +28,8 @@ use subblock_allocator::SubblockAllocatorSolution;
 static FIRST_ALLOCATION: AtomicBool = AtomicBool::new(true);
 static TOTAL_NUM_ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
 static TOTAL_NUM_DEALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static TOTAL_NUM_FRAME_ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static TOTAL_NUM_FRAME_DEALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
 
 const MAX_SUPPORTED_ALIGN: usize = 4096;
 /// "Upper memory" (as opposed to "lower memory") starts at 1MB.
@@ -147,7 +150,9 @@ impl KernelAllocator {
             return Err(AllocError);
         };
 
-        subblock_allocator.get_frame_allocator().alloc(frames)
+        let frame = subblock_allocator.get_frame_allocator().alloc(frames)?;
+        TOTAL_NUM_FRAME_ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        Ok(frame)
     }
 
     pub fn frame_dealloc(&mut self, ptr: NonNull<u8>) {
@@ -156,6 +161,7 @@ impl KernelAllocator {
         };
 
         unsafe { subblock_allocator.get_frame_allocator().dealloc(ptr) };
+        TOTAL_NUM_FRAME_DEALLOCATIONS.fetch_add(1, Ordering::Relaxed);
     }
 
     pub fn deinit(&mut self) {
@@ -184,6 +190,44 @@ impl KernelAllocator {
     }
 }
 
+/// A snapshot of how many allocations the kernel allocator has made and freed,
+/// used to check that something gives back everything it allocates.
+#[derive(Clone, Copy, Debug)]
+pub struct AllocationCounts {
+    allocations: usize,
+    deallocations: usize,
+    frame_allocations: usize,
+    frame_deallocations: usize,
+}
+
+impl AllocationCounts {
+    pub fn now() -> Self {
+        Self {
+            allocations: TOTAL_NUM_ALLOCATIONS.load(Ordering::Relaxed),
+            deallocations: TOTAL_NUM_DEALLOCATIONS.load(Ordering::Relaxed),
+            frame_allocations: TOTAL_NUM_FRAME_ALLOCATIONS.load(Ordering::Relaxed),
+            frame_deallocations: TOTAL_NUM_FRAME_DEALLOCATIONS.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Returns how many more heap allocations and frame allocations are
+    /// outstanding now than at the time of this snapshot.
+    pub fn outstanding_since(&self) -> (isize, isize) {
+        let now = Self::now();
+        let heap = (now.allocations - self.allocations) as isize
+            - (now.deallocations - self.deallocations) as isize;
+        let frames = (now.frame_allocations - self.frame_allocations) as isize
+            - (now.frame_deallocations - self.frame_deallocations) as isize;
+        (heap, frames)
+    }
+
+    /// Counts the next frame deallocation as if it had happened before this
+    /// snapshot, for a frame which was allocated before it.
+    pub fn forget_frame_deallocation(&mut self) {
+        self.frame_deallocations += 1;
+    }
+}
+
 // SAFETY:
 //
 // - We don't panic.
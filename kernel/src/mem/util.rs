@@ -1,3 +1,4 @@
+use alloc::vec::Vec;
 use core::mem::size_of;
 use kidneyos_shared::mem::OFFSET as KMEM_OFFSET;
 use kidneyos_shared::mem::PAGE_FRAME_SIZE;
@@ -7,6 +8,13 @@ pub enum CStrError {
     BadUtf8,
 }
 
+/// Why an array of strings couldn't be copied from user space.
+pub enum CStrArrayError {
+    Fault,
+    /// The strings need more room than they're allowed.
+    TooLong,
+}
+
 fn can_access_range<T>(start: *const T, count: usize, write: bool) -> bool {
     let start = start as usize;
     let Some(bytes) = count.checked_mul(size_of::<T>()) else {
@@ -28,6 +36,7 @@ fn can_access_range<T>(start: *const T, count: usize, write: bool) -> bool {
         .as_ref()
         .expect("A syscall was called without a running thread.")
         .page_manager
+        .lock()
         .can_access_range(start, bytes, write)
 }
 
@@ -47,24 +56,72 @@ fn is_range_writeable<T>(start: *const T, count: usize) -> bool {
 /// You must not hold any mutable references to any parts of the string
 /// while it is in scope (as is required by Rust).
 pub unsafe fn get_cstr_from_user_space(ptr: *const u8) -> Result<&'static str, CStrError> {
-    let mut len = 0usize;
+    let len = cstr_len_from_user_space(ptr, usize::MAX).ok_or(CStrError::Fault)?;
+    let slice: &'static [u8] = core::slice::from_raw_parts(ptr, len);
+    core::str::from_utf8(slice).map_err(|_| CStrError::BadUtf8)
+}
+
+/// Find the length of the null-terminated string at `ptr` in userspace, without the terminator
+///
+/// Stops looking once the string turns out to be longer than `max_len`, returning `max_len + 1`.
+/// Returns `None` if the string isn't readable.
+///
+/// # Safety
+///
+/// See [`get_cstr_from_user_space`].
+unsafe fn cstr_len_from_user_space(ptr: *const u8, max_len: usize) -> Option<usize> {
     if !is_range_readable(ptr, 1) {
-        return Err(CStrError::Fault);
+        return None;
     }
-    loop {
-        if *ptr.add(len) == 0 {
-            break;
+    let mut len = 0usize;
+    while *ptr.add(len) != 0 {
+        if len == max_len {
+            return Some(len + 1);
         }
         len += 1;
-        let Some(end) = (ptr as usize).checked_add(len) else {
-            return Err(CStrError::Fault);
-        };
+        let end = (ptr as usize).checked_add(len)?;
         if end % PAGE_FRAME_SIZE == 0 && !is_range_readable(end as *const u8, 1) {
-            return Err(CStrError::Fault);
+            return None;
         }
     }
-    let slice: &'static [u8] = core::slice::from_raw_parts(ptr, len);
-    core::str::from_utf8(slice).map_err(|_| CStrError::BadUtf8)
+    Some(len)
+}
+
+/// Copy a null-terminated array of null-terminated strings (like `argv`) from userspace
+///
+/// The strings can hold any bytes but null. A null `ptr` is treated as an empty array.
+///
+/// Each string is charged its length, its terminator and a pointer against `budget`, which is
+/// reduced by what was copied. Copying stops with `TooLong` as soon as the budget runs out.
+///
+/// # Safety
+///
+/// See [`get_cstr_from_user_space`].
+pub unsafe fn copy_cstr_array_from_user_space(
+    ptr: *const *const u8,
+    budget: &mut usize,
+) -> Result<Vec<Vec<u8>>, CStrArrayError> {
+    let mut strings = Vec::new();
+    if ptr.is_null() {
+        return Ok(strings);
+    }
+    loop {
+        let Some(&string_ptr) = get_ref_from_user_space(ptr.wrapping_add(strings.len())) else {
+            return Err(CStrArrayError::Fault);
+        };
+        if string_ptr.is_null() {
+            return Ok(strings);
+        }
+        let max_len = budget
+            .checked_sub(size_of::<usize>() + 1)
+            .ok_or(CStrArrayError::TooLong)?;
+        let len = cstr_len_from_user_space(string_ptr, max_len).ok_or(CStrArrayError::Fault)?;
+        if len > max_len {
+            return Err(CStrArrayError::TooLong);
+        }
+        *budget -= size_of::<usize>() + len + 1;
+        strings.push(core::slice::from_raw_parts(string_ptr, len).to_vec());
+    }
 }
 
 /// Construct mutable slice from userspace pointer
@@ -1,8 +1,11 @@
 use crate::fs::fs_manager::FileSystemID;
+use crate::sync::mutex::Mutex;
 use crate::system::unwrap_system;
 use crate::vfs::INodeNum;
 use crate::KERNEL_ALLOCATOR;
 use alloc::collections::BTreeMap;
+use alloc::sync::{Arc, Weak};
+use core::ptr::NonNull;
 use kidneyos_shared::mem::{OFFSET, PAGE_FRAME_SIZE};
 
 /// A list of virtual memory areas for a process
@@ -16,6 +19,9 @@ pub struct VMA {
     size: usize,
     writeable: bool,
     // no point in having other permissions since x86 only supports RWX and RX by default.
+    /// Where the pages of a `MAP_SHARED` mapping come from, or `None` if the pages are private
+    /// to the process (and copied on fork).
+    shared: Option<SharedMemoryRef>,
 }
 
 /// Type of VMA and any specific data associated with it
@@ -25,6 +31,13 @@ pub enum VMAInfo {
     Stack,
     /// This VMA contains the heap
     Heap,
+    /// This VMA contains part of the program or its interpreter, whose pages were all mapped
+    /// when it was loaded
+    Image,
+    /// This VMA contains anonymous memory, which starts out zeroed
+    ///
+    /// `offset` is in units of pages, and is only meaningful for shared memory.
+    Anonymous { offset: u32 },
     /// This VMA contains a memory-mapped file
     ///
     /// `offset` is in units of pages
@@ -41,6 +54,8 @@ impl Clone for VMAInfo {
         match self {
             Self::Stack => Self::Stack,
             Self::Heap => Self::Heap,
+            Self::Image => Self::Image,
+            Self::Anonymous { offset } => Self::Anonymous { offset: *offset },
             Self::MMap { fs, inode, offset } => {
                 let fs = *fs;
                 let inode = *inode;
@@ -54,12 +69,82 @@ impl Clone for VMAInfo {
     }
 }
 
+pub type SharedMemoryRef = Arc<Mutex<SharedMemory>>;
+type WeakSharedMemoryRef = Weak<Mutex<SharedMemory>>;
+
+/// The frames of memory mapped with `MAP_SHARED`, which every VMA mapping it installs, so that
+/// they all see each other's writes.
+///
+/// Holds one reference (in the sense of `FrameRefCounts`) to each of its frames.
+#[derive(Debug, Default)]
+pub struct SharedMemory {
+    /// Frames installed so far, by page index into the file or anonymous memory.
+    frames: BTreeMap<u32, usize>,
+}
+
+/// Shared memory of files mapped with `MAP_SHARED`, so that every process mapping a file shares
+/// its pages.
+static SHARED_FILES: Mutex<BTreeMap<(FileSystemID, INodeNum), WeakSharedMemoryRef>> =
+    Mutex::new(BTreeMap::new());
+
+impl SharedMemory {
+    /// New shared anonymous memory.
+    pub fn new_anonymous() -> SharedMemoryRef {
+        Arc::new(Mutex::new(Self::default()))
+    }
+    /// Get the shared memory of a file, which is new if no one maps the file with `MAP_SHARED`.
+    pub fn for_file(fs: FileSystemID, inode: INodeNum) -> SharedMemoryRef {
+        let mut files = SHARED_FILES.lock();
+        files.retain(|_, memory| memory.strong_count() != 0);
+        if let Some(memory) = files.get(&(fs, inode)).and_then(Weak::upgrade) {
+            return memory;
+        }
+        let memory = Self::new_anonymous();
+        files.insert((fs, inode), Arc::downgrade(&memory));
+        memory
+    }
+    /// Iterate over the physical addresses of the frames installed for `pages`.
+    pub fn frames(&self, pages: core::ops::Range<u32>) -> impl '_ + Iterator<Item = (u32, usize)> {
+        self.frames
+            .range(pages)
+            .map(|(&page, &phys_addr)| (page, phys_addr))
+    }
+}
+
+impl Drop for SharedMemory {
+    fn drop(&mut self) {
+        let mut frame_ref_counts = unwrap_system().frame_ref_counts.lock();
+        for &phys_addr in self.frames.values() {
+            if frame_ref_counts.release(phys_addr) {
+                let frame = NonNull::new((phys_addr + OFFSET) as *mut u8).expect("null frame");
+                // SAFETY: Nothing maps the frame anymore.
+                unsafe { KERNEL_ALLOCATOR.frame_dealloc(frame) };
+            }
+        }
+    }
+}
+
 impl VMA {
     pub fn new(info: VMAInfo, size: usize, writeable: bool) -> Self {
         Self {
             info,
             size,
             writeable,
+            shared: None,
+        }
+    }
+    /// New VMA whose pages come from `shared` instead of being private to the process.
+    pub fn new_shared(
+        info: VMAInfo,
+        size: usize,
+        writeable: bool,
+        shared: SharedMemoryRef,
+    ) -> Self {
+        Self {
+            info,
+            size,
+            writeable,
+            shared: Some(shared),
         }
     }
     pub fn info(&self) -> &VMAInfo {
@@ -71,32 +156,43 @@ impl VMA {
     pub fn writeable(&self) -> bool {
         self.writeable
     }
-    #[must_use]
-    unsafe fn install_in_page_table(&self, virt_addr: usize, offset: usize) -> bool {
-        debug_assert_eq!(virt_addr % PAGE_FRAME_SIZE, 0);
-        debug_assert_eq!(offset % PAGE_FRAME_SIZE, 0);
-        let Ok(frame_ptr) = (unsafe { KERNEL_ALLOCATOR.frame_alloc(1) }) else {
-            return false;
+    pub fn shared(&self) -> Option<&SharedMemoryRef> {
+        self.shared.as_ref()
+    }
+    /// Index of the page at `offset` bytes into the VMA, in the file or shared memory backing it.
+    pub fn backing_page(&self, offset: usize) -> u32 {
+        let first_page = match self.info {
+            VMAInfo::Anonymous { offset } | VMAInfo::MMap { offset, .. } => offset,
+            VMAInfo::Stack | VMAInfo::Heap | VMAInfo::Image => 0,
         };
-        let frame_ptr = frame_ptr.as_ptr();
-        let phys_addr = frame_ptr as usize - OFFSET;
-        let mut tcb_guard = unwrap_system().threads.running_thread.lock();
-        let tcb = tcb_guard.as_mut().expect("no running thread");
-        tcb.page_manager
-            .map(phys_addr, virt_addr, self.writeable(), true);
-        drop(tcb_guard);
-        // important we don't use the virtual address here since it may be read-only!
-        let data = core::slice::from_raw_parts_mut(frame_ptr, PAGE_FRAME_SIZE);
+        first_page + (offset / PAGE_FRAME_SIZE) as u32
+    }
+    /// Split the VMA `at` bytes into it, returning the part after that.
+    fn split_off(&mut self, at: usize) -> VMA {
+        debug_assert_eq!(at % PAGE_FRAME_SIZE, 0);
+        debug_assert!(at > 0 && at < self.size);
+        let mut tail = self.clone();
+        tail.size = self.size - at;
+        self.size = at;
+        if let VMAInfo::Anonymous { offset } | VMAInfo::MMap { offset, .. } = &mut tail.info {
+            *offset += (at / PAGE_FRAME_SIZE) as u32;
+        }
+        tail
+    }
+    /// Fill `data` with the initial contents of the page at `offset` bytes into the VMA.
+    ///
+    /// Returns `false` if the mmapped file couldn't be read.
+    fn fill_page(&self, data: &mut [u8], offset: usize) -> bool {
         match &self.info {
-            VMAInfo::Stack | VMAInfo::Heap => {
+            VMAInfo::Stack | VMAInfo::Heap | VMAInfo::Image | VMAInfo::Anonymous { .. } => {
                 // zero memory, to prevent data from being leaked between processes.
                 data.fill(0);
                 true
             }
-            VMAInfo::MMap { fs, inode, offset } => {
+            VMAInfo::MMap { fs, inode, .. } => {
                 let fs = *fs;
                 let inode = *inode;
-                let offset = u64::from(*offset) * PAGE_FRAME_SIZE as u64;
+                let offset = u64::from(self.backing_page(offset)) * PAGE_FRAME_SIZE as u64;
                 let mut root = unwrap_system().root_filesystem.lock();
                 let mut bytes_read = 0;
                 while bytes_read < PAGE_FRAME_SIZE {
@@ -124,6 +220,62 @@ impl VMA {
             }
         }
     }
+    /// Allocate a frame holding the initial contents of the page at `offset` bytes into the VMA,
+    /// returning its physical address.
+    unsafe fn new_frame(&self, offset: usize) -> Option<usize> {
+        let frame_ptr = KERNEL_ALLOCATOR.frame_alloc(1).ok()?;
+        // important we don't use the virtual address here since it may be read-only!
+        let data = core::slice::from_raw_parts_mut(frame_ptr.as_ptr(), PAGE_FRAME_SIZE);
+        if !self.fill_page(data, offset) {
+            KERNEL_ALLOCATOR.frame_dealloc(frame_ptr);
+            return None;
+        }
+        Some(frame_ptr.as_ptr() as usize - OFFSET)
+    }
+    #[must_use]
+    unsafe fn install_in_page_table(&self, virt_addr: usize, offset: usize) -> bool {
+        debug_assert_eq!(virt_addr % PAGE_FRAME_SIZE, 0);
+        debug_assert_eq!(offset % PAGE_FRAME_SIZE, 0);
+        let Some(shared) = &self.shared else {
+            let Some(phys_addr) = self.new_frame(offset) else {
+                return false;
+            };
+            let mut tcb_guard = unwrap_system().threads.running_thread.lock();
+            let tcb = tcb_guard.as_mut().expect("no running thread");
+            tcb.page_manager
+                .lock()
+                .map(phys_addr, virt_addr, self.writeable(), true);
+            return true;
+        };
+
+        let page = self.backing_page(offset);
+        let installed = shared.lock().frames.get(&page).copied();
+        let phys_addr = match installed {
+            Some(phys_addr) => phys_addr,
+            None => {
+                // Filling the frame may read a file, so it's done without holding the lock, which
+                // is taken while the root filesystem is locked to write shared files back.
+                let Some(new_phys_addr) = self.new_frame(offset) else {
+                    return false;
+                };
+                let phys_addr = *shared.lock().frames.entry(page).or_insert(new_phys_addr);
+                if phys_addr != new_phys_addr {
+                    // Someone else installed the page in the meantime.
+                    let frame = NonNull::new((new_phys_addr + OFFSET) as *mut u8).unwrap();
+                    KERNEL_ALLOCATOR.frame_dealloc(frame);
+                }
+                phys_addr
+            }
+        };
+        let mut tcb_guard = unwrap_system().threads.running_thread.lock();
+        let tcb = tcb_guard.as_mut().expect("no running thread");
+        tcb.page_manager
+            .lock()
+            .map_shared(phys_addr, virt_addr, self.writeable());
+        drop(tcb_guard);
+        unwrap_system().frame_ref_counts.lock().share(phys_addr);
+        true
+    }
 }
 
 impl VMAList {
@@ -131,7 +283,8 @@ impl VMAList {
     pub fn new() -> Self {
         Self::default()
     }
-    fn vma_at(&self, addr: usize) -> Option<(usize, &VMA)> {
+    /// The VMA containing `addr`, and where it starts.
+    pub fn vma_at(&self, addr: usize) -> Option<(usize, &VMA)> {
         // find VMA whose address is closest to addr without going over
         let (vma_addr, vma) = self.0.range(..=addr).next_back()?;
         let vma_addr = *vma_addr;
@@ -179,8 +332,244 @@ impl VMAList {
         self.0.insert(addr, vma);
         true
     }
+    /// Change the size of the VMA starting at `addr` to `size`, which must be a multiple of
+    /// `PAGE_FRAME_SIZE`.
+    ///
+    /// Returns `false` if there is no VMA at `addr`, or if growing it would overlap another VMA.
+    #[must_use]
+    pub fn resize_vma(&mut self, addr: usize, size: usize) -> bool {
+        assert_eq!(size % PAGE_FRAME_SIZE, 0);
+        let Some(vma) = self.0.get(&addr) else {
+            return false;
+        };
+        let old_end = addr + vma.size;
+        if size > vma.size && !self.is_address_range_free(old_end..addr + size) {
+            return false;
+        }
+        self.0.get_mut(&addr).unwrap().size = size;
+        true
+    }
+    /// Add a VMA at `hint` if there's room for it there, or otherwise at the highest address where
+    /// it fits without going past `limit`, returning the address.
+    ///
+    /// If there's no room anywhere, the VMA is given back.
+    pub fn add_vma_near(&mut self, vma: VMA, hint: usize, limit: usize) -> Result<usize, VMA> {
+        assert_eq!(hint % PAGE_FRAME_SIZE, 0);
+        let hint_fits = hint != 0
+            && hint.checked_add(vma.size).is_some_and(|end| end <= limit)
+            && self.is_address_range_free(hint..hint + vma.size);
+        let addr = if hint_fits {
+            hint
+        } else {
+            match self.find_free_range(vma.size, limit) {
+                Some(addr) => addr,
+                None => return Err(vma),
+            }
+        };
+        self.0.insert(addr, vma);
+        Ok(addr)
+    }
+    /// Remove the VMA starting at `addr` from the list, returning it.
+    ///
+    /// Pages which were already installed for the VMA stay mapped.
+    pub fn remove_vma(&mut self, addr: usize) -> Option<VMA> {
+        self.0.remove(&addr)
+    }
+    /// Find the highest address at which `size` bytes fit without overlapping any VMA, and
+    /// without going past `limit`.
+    ///
+    /// `size` and `limit` must be multiples of `PAGE_FRAME_SIZE`. The first page is never
+    /// returned, so that null pointers always fault.
+    pub fn find_free_range(&self, size: usize, limit: usize) -> Option<usize> {
+        debug_assert_eq!(size % PAGE_FRAME_SIZE, 0);
+        debug_assert_eq!(limit % PAGE_FRAME_SIZE, 0);
+        let mut end = limit;
+        for (&vma_addr, vma) in self.0.range(..limit).rev() {
+            if vma_addr + vma.size <= end.saturating_sub(size) {
+                break;
+            }
+            end = end.min(vma_addr);
+        }
+        end.checked_sub(size).filter(|&addr| addr != 0)
+    }
+    /// Remove `start..start + len` from the list, splitting VMAs which only partly overlap it.
+    ///
+    /// Returns the parts of VMAs which were in the range. Pages which were already installed for
+    /// them stay mapped.
+    pub fn remove_range(&mut self, start: usize, len: usize) -> VMAList {
+        assert_eq!(start % PAGE_FRAME_SIZE, 0);
+        assert_eq!(len % PAGE_FRAME_SIZE, 0);
+        let end = start + len;
+        // split VMAs crossing either end of the range, so that each VMA is entirely inside or
+        // outside of it.
+        for at in [start, end] {
+            let Some(vma_addr) = self.vma_at(at).map(|(vma_addr, _)| vma_addr) else {
+                continue;
+            };
+            if vma_addr != at {
+                let tail = self.0.get_mut(&vma_addr).unwrap().split_off(at - vma_addr);
+                self.0.insert(at, tail);
+            }
+        }
+        let mut removed = self.0.split_off(&start);
+        let mut after = removed.split_off(&end);
+        self.0.append(&mut after);
+        VMAList(removed)
+    }
+    /// Install PTEs for the pages of `start..start + len` which belong to a VMA but haven't
+    /// been touched yet, so that the kernel can write to them.
+    pub fn install_range(&self, start: usize, len: usize) {
+        let first_page = start & !(PAGE_FRAME_SIZE - 1);
+        for page in (first_page..start.saturating_add(len)).step_by(PAGE_FRAME_SIZE) {
+            let mapped = unwrap_system()
+                .threads
+                .running_thread
+                .lock()
+                .as_ref()
+                .expect("no running thread")
+                .page_manager
+                .lock()
+                .is_mapped(page);
+            if !mapped {
+                // SAFETY: The page isn't mapped. If there's no VMA for it, the caller's access
+                // to it will fail instead.
+                let _ = unsafe { self.install_pte(page) };
+            }
+        }
+    }
     pub fn iter(&self) -> impl '_ + Iterator<Item = (usize, &VMA)> {
         self.0.iter().map(|(&k, v)| (k, v))
     }
-    // TODO: free physical memory allocated by VMAs on process exit
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stack(pages: usize) -> VMA {
+        VMA::new(VMAInfo::Stack, pages * PAGE_FRAME_SIZE, true)
+    }
+
+    fn anonymous(pages: usize) -> VMA {
+        VMA::new(
+            VMAInfo::Anonymous { offset: 0 },
+            pages * PAGE_FRAME_SIZE,
+            true,
+        )
+    }
+
+    fn layout(vmas: &VMAList) -> Vec<(usize, usize, u32)> {
+        vmas.iter()
+            .map(|(addr, vma)| {
+                (
+                    addr / PAGE_FRAME_SIZE,
+                    vma.size() / PAGE_FRAME_SIZE,
+                    vma.backing_page(0),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn find_free_range_empty() {
+        let vmas = VMAList::new();
+        assert_eq!(
+            vmas.find_free_range(2 * PAGE_FRAME_SIZE, 16 * PAGE_FRAME_SIZE),
+            Some(14 * PAGE_FRAME_SIZE)
+        );
+        assert_eq!(vmas.find_free_range(PAGE_FRAME_SIZE, PAGE_FRAME_SIZE), None);
+    }
+
+    #[test]
+    fn find_free_range_skips_vmas() {
+        let mut vmas = VMAList::new();
+        assert!(vmas.add_vma(stack(2), 14 * PAGE_FRAME_SIZE));
+        assert!(vmas.add_vma(stack(1), 11 * PAGE_FRAME_SIZE));
+        // The gap between the two VMAs is too small.
+        assert_eq!(
+            vmas.find_free_range(3 * PAGE_FRAME_SIZE, 16 * PAGE_FRAME_SIZE),
+            Some(8 * PAGE_FRAME_SIZE)
+        );
+        assert_eq!(
+            vmas.find_free_range(2 * PAGE_FRAME_SIZE, 16 * PAGE_FRAME_SIZE),
+            Some(12 * PAGE_FRAME_SIZE)
+        );
+        // A VMA which crosses the limit counts too.
+        assert_eq!(
+            vmas.find_free_range(PAGE_FRAME_SIZE, 15 * PAGE_FRAME_SIZE),
+            Some(13 * PAGE_FRAME_SIZE)
+        );
+    }
+
+    #[test]
+    fn resize_vma() {
+        let mut vmas = VMAList::new();
+        assert!(vmas.add_vma(stack(1), 4 * PAGE_FRAME_SIZE));
+        assert!(vmas.add_vma(stack(1), 8 * PAGE_FRAME_SIZE));
+        assert!(!vmas.resize_vma(5 * PAGE_FRAME_SIZE, PAGE_FRAME_SIZE));
+        assert!(vmas.resize_vma(4 * PAGE_FRAME_SIZE, 4 * PAGE_FRAME_SIZE));
+        assert!(!vmas.resize_vma(4 * PAGE_FRAME_SIZE, 5 * PAGE_FRAME_SIZE));
+        assert!(vmas.resize_vma(4 * PAGE_FRAME_SIZE, 2 * PAGE_FRAME_SIZE));
+        assert!(vmas.add_vma(stack(2), 6 * PAGE_FRAME_SIZE));
+    }
+
+    #[test]
+    fn remove_vma() {
+        let mut vmas = VMAList::new();
+        assert!(vmas.add_vma(stack(2), 4 * PAGE_FRAME_SIZE));
+        assert!(vmas.remove_vma(5 * PAGE_FRAME_SIZE).is_none());
+        assert_eq!(
+            vmas.remove_vma(4 * PAGE_FRAME_SIZE).unwrap().size(),
+            2 * PAGE_FRAME_SIZE
+        );
+        assert!(vmas.add_vma(stack(1), 5 * PAGE_FRAME_SIZE));
+    }
+
+    #[test]
+    fn add_vma_near() {
+        let mut vmas = VMAList::new();
+        assert_eq!(
+            vmas.add_vma_near(stack(2), 4 * PAGE_FRAME_SIZE, 16 * PAGE_FRAME_SIZE)
+                .ok(),
+            Some(4 * PAGE_FRAME_SIZE)
+        );
+        // The hint is taken, so the highest free range is used instead.
+        assert_eq!(
+            vmas.add_vma_near(stack(2), 5 * PAGE_FRAME_SIZE, 16 * PAGE_FRAME_SIZE)
+                .ok(),
+            Some(14 * PAGE_FRAME_SIZE)
+        );
+        assert_eq!(
+            vmas.add_vma_near(stack(1), 0, 16 * PAGE_FRAME_SIZE).ok(),
+            Some(13 * PAGE_FRAME_SIZE)
+        );
+        assert!(vmas
+            .add_vma_near(stack(8), 0, 16 * PAGE_FRAME_SIZE)
+            .is_err());
+    }
+
+    #[test]
+    fn remove_range_splits_vmas() {
+        let mut vmas = VMAList::new();
+        assert!(vmas.add_vma(anonymous(8), 4 * PAGE_FRAME_SIZE));
+        let removed = vmas.remove_range(6 * PAGE_FRAME_SIZE, 2 * PAGE_FRAME_SIZE);
+        assert_eq!(layout(&removed), [(6, 2, 2)]);
+        assert_eq!(layout(&vmas), [(4, 2, 0), (8, 4, 4)]);
+    }
+
+    #[test]
+    fn remove_range_across_vmas() {
+        let mut vmas = VMAList::new();
+        assert!(vmas.add_vma(anonymous(4), 4 * PAGE_FRAME_SIZE));
+        assert!(vmas.add_vma(anonymous(1), 9 * PAGE_FRAME_SIZE));
+        assert!(vmas.add_vma(anonymous(4), 12 * PAGE_FRAME_SIZE));
+        let removed = vmas.remove_range(6 * PAGE_FRAME_SIZE, 8 * PAGE_FRAME_SIZE);
+        assert_eq!(layout(&removed), [(6, 2, 2), (9, 1, 0), (12, 2, 0)]);
+        assert_eq!(layout(&vmas), [(4, 2, 0), (14, 2, 2)]);
+        assert!(vmas
+            .remove_range(0, 4 * PAGE_FRAME_SIZE)
+            .iter()
+            .next()
+            .is_none());
+    }
 }
@@ -1,10 +1,18 @@
+use crate::system::unwrap_system;
+use crate::{Mutex, KERNEL_ALLOCATOR};
 use alloc::alloc::Global;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::ptr::{copy_nonoverlapping, NonNull};
 use kidneyos_shared::{
-    mem::OFFSET,
+    mem::{OFFSET, PAGE_FRAME_SIZE},
     paging::{self, kernel_mapping_ranges},
 };
 
 pub type PageManager<A = Global> = paging::PageManager<A>;
+pub type FrameRefCounts<A = Global> = paging::FrameRefCounts<A>;
+/// The address space of a process, which is shared by all of its threads.
+pub type SharedPageManager = Arc<Mutex<PageManager>>;
 
 pub trait PageManagerDefault {
     fn default() -> Self;
@@ -16,8 +24,120 @@ impl PageManagerDefault for PageManager<Global> {
     }
 }
 
+/// Loads `page_manager` without locking it.
+///
+/// This is needed while switching threads, since another thread of the same
+/// process may have been preempted while holding the lock. Loading only reads
+/// the address of the page directory, which never changes.
+///
+/// # Safety
+///
+/// Same as `PageManager::load`.
+pub unsafe fn load_shared(page_manager: &SharedPageManager) {
+    (*page_manager.as_mut_ptr()).load();
+}
+
+/// Unmaps the pages of `start..start + len` from `page_manager`, and frees the
+/// frames they were mapped to unless someone else still maps them.
+///
+/// # Safety
+///
+/// Same as `PageManager::unmap`. Additionally, the frames must have been
+/// allocated one at a time, like the ones installed for VMAs.
+pub unsafe fn unmap_user_range(page_manager: &mut PageManager, start: usize, len: usize) {
+    let first_page = start & !(PAGE_FRAME_SIZE - 1);
+    let end = (start + len).next_multiple_of(PAGE_FRAME_SIZE);
+    let mut frame_ref_counts = unwrap_system().frame_ref_counts.lock();
+    page_manager
+        .unmap_range(first_page, end - first_page, |phys_addr| {
+            if frame_ref_counts.release(phys_addr) {
+                let frame = NonNull::new((phys_addr + OFFSET) as *mut u8).expect("null frame");
+                KERNEL_ALLOCATOR.frame_dealloc(frame);
+            }
+        })
+        .expect("user pages are below the kernel");
+}
+
+/// Unmaps every user page from `page_manager`, freeing the frames which nobody
+/// else maps.
+///
+/// # Safety
+///
+/// Same as `unmap_user_range`.
+pub unsafe fn unmap_user_pages(page_manager: &mut PageManager) {
+    let pages: Vec<usize> = page_manager
+        .user_mappings()
+        .map(|(virt_addr, _, _)| virt_addr)
+        .collect();
+    for virt_addr in pages {
+        unmap_user_range(page_manager, virt_addr, PAGE_FRAME_SIZE);
+    }
+}
+
+/// Drops a reference to an address space, and frees its pages and page tables
+/// if it was the last one.
+///
+/// Returns `true` if the address space was freed.
+///
+/// # Safety
+///
+/// If this is the last reference, `page_manager` must not be loaded.
+pub unsafe fn release_address_space(page_manager: SharedPageManager) -> bool {
+    let Some(page_manager) = Arc::into_inner(page_manager) else {
+        return false;
+    };
+    let mut page_manager = page_manager.into_inner();
+    unmap_user_pages(&mut page_manager);
+    true
+}
+
 pub unsafe fn enable() -> PageManager {
     let page_manager = PageManager::default();
     page_manager.load();
     page_manager
 }
+
+/// Handle a write to a page that the running thread shares copy-on-write with
+/// another process, by giving it its own writeable copy of the page.
+///
+/// Returns `false` if `virt_addr` is not mapped copy-on-write, or if a frame
+/// for the copy couldn't be allocated.
+///
+/// # Safety
+///
+/// Must be called from the page fault handler for a fault on `virt_addr`, or
+/// before the kernel writes to `virt_addr` on behalf of the running thread.
+#[must_use]
+pub unsafe fn handle_copy_on_write_fault(virt_addr: usize) -> bool {
+    // round down to page
+    let virt_addr = virt_addr & !(PAGE_FRAME_SIZE - 1);
+    let system = unwrap_system();
+    let mut tcb_guard = system.threads.running_thread.lock();
+    let tcb = tcb_guard.as_mut().expect("no running thread");
+    let mut page_manager = tcb.page_manager.lock();
+    let Some(phys_addr) = page_manager.copy_on_write_frame(virt_addr) else {
+        return false;
+    };
+
+    let mut frame_ref_counts = system.frame_ref_counts.lock();
+    if !frame_ref_counts.is_shared(phys_addr) {
+        // Everyone else has already made their own copy, so this one is ours.
+        page_manager.resolve_copy_on_write(virt_addr, phys_addr);
+        return true;
+    }
+
+    let Ok(frame_ptr) = KERNEL_ALLOCATOR.frame_alloc(1) else {
+        return false;
+    };
+    let frame_ptr = frame_ptr.as_ptr();
+    // important we don't use the virtual address here since it's read-only!
+    copy_nonoverlapping(
+        (phys_addr + OFFSET) as *const u8,
+        frame_ptr,
+        PAGE_FRAME_SIZE,
+    );
+    page_manager.resolve_copy_on_write(virt_addr, frame_ptr as usize - OFFSET);
+    // The frame is still shared by someone else, so this never frees it.
+    frame_ref_counts.release(phys_addr);
+    true
+}
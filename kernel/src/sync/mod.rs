@@ -2,3 +2,4 @@
 pub mod mutex;
 pub mod rwlock;
 pub mod semaphore;
+pub mod wait_queue;
@@ -41,6 +41,11 @@ impl<T> Mutex<T> {
     pub fn into_inner(self) -> T {
         self.inner.into_inner()
     }
+
+    #[inline(always)]
+    pub fn as_mut_ptr(&self) -> *mut T {
+        self.inner.as_mut_ptr()
+    }
 }
 
 impl<T: ?Sized> Mutex<T> {
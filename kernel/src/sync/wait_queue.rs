@@ -0,0 +1,79 @@
+use crate::interrupts::mutex_irq::MutexIrq;
+use crate::interrupts::{intr_get_level, timer, IntrLevel};
+use crate::system::running_thread_tid;
+use crate::threading::process::Tid;
+use crate::threading::thread_sleep::{thread_sleep, thread_wakeup};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::time::Duration;
+
+/// Threads waiting for something to change, such as a pipe becoming readable.
+///
+/// Clones share the same queue, so a source of events can hand out its queue to
+/// anyone who wants to wait on it.
+///
+/// Waiting follows the same pattern as futexes: with interrupts disabled, check
+/// whether whatever you're waiting for has already happened, and if not `add`
+/// yourself to every queue of interest, [`block`], then `remove` yourself
+/// again. Keeping interrupts disabled from the check until blocking means a
+/// wakeup can't be missed.
+#[derive(Clone, Default)]
+pub struct WaitQueue {
+    tids: Arc<MutexIrq<Vec<Tid>>>,
+}
+
+impl WaitQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the running thread to the queue.
+    pub fn add(&self) {
+        let tid = running_thread_tid();
+        let mut tids = self.tids.lock();
+        if !tids.contains(&tid) {
+            tids.push(tid);
+        }
+    }
+
+    /// Removes the running thread from the queue, if it's still there.
+    pub fn remove(&self) {
+        let tid = running_thread_tid();
+        self.tids.lock().retain(|&waiter| waiter != tid);
+    }
+
+    /// Whether no thread is waiting.
+    pub fn is_empty(&self) -> bool {
+        self.tids.lock().is_empty()
+    }
+
+    /// Wakes every thread in the queue.
+    pub fn wake_all(&self) {
+        for tid in self.tids.lock().drain(..) {
+            thread_wakeup(tid);
+        }
+    }
+
+    /// Waits on just this queue, see [`block`].
+    pub fn wait(&self, timeout: Option<Duration>) -> bool {
+        self.add();
+        let timed_out = block(timeout);
+        self.remove();
+        timed_out
+    }
+}
+
+/// Blocks the running thread until it's woken, which includes being sent a
+/// signal, or until `timeout` passes. Returns whether it timed out.
+///
+/// Interrupts must be disabled.
+pub fn block(timeout: Option<Duration>) -> bool {
+    assert_eq!(intr_get_level(), IntrLevel::IntrOff);
+    match timeout {
+        Some(timeout) => timer::sleep_interruptible(timeout).is_zero(),
+        None => {
+            thread_sleep();
+            false
+        }
+    }
+}
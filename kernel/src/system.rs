@@ -1,6 +1,8 @@
 use crate::block::block_core::BlockManager;
 use crate::drivers::input::input_core::InputBuffer;
 use crate::fs::fs_manager::RootFileSystem;
+use crate::fs::tty::Tty;
+use crate::paging::FrameRefCounts;
 use crate::sync::mutex::Mutex;
 use crate::sync::rwlock::sleep::RwLock;
 use crate::threading::process::{Pid, ProcessState, Tid};
@@ -15,6 +17,8 @@ pub struct SystemState {
     pub block_manager: RwLock<BlockManager>,
     pub root_filesystem: Mutex<RootFileSystem>,
     pub input_buffer: Mutex<InputBuffer>,
+    pub tty: Tty,
+    pub frame_ref_counts: Mutex<FrameRefCounts>,
 }
 
 impl core::fmt::Debug for SystemState {
@@ -1,5 +1,6 @@
 use crate::{
-    interrupts::{intr_get_level, IntrLevel},
+    interrupts::{intr_get_level, sysenter, IntrLevel},
+    paging::load_shared,
     threading::thread_functions::clean_up_thread,
 };
 use core::mem::offset_of;
@@ -7,6 +8,7 @@ use core::mem::offset_of;
 use super::thread_control_block::{ThreadControlBlock, ThreadStatus};
 use crate::system::unwrap_system;
 use alloc::boxed::Box;
+use kidneyos_shared::task_state_segment::TASK_STATE_SEGMENT;
 
 /// Public facing method to perform a context switch between two threads.
 /// # Safety
@@ -45,8 +47,14 @@ pub unsafe fn switch_threads(
     // Update the status of the current thread.
     (*switch_from).status = status_for_current_thread;
 
-    let page_manager = &(*switch_to).page_manager;
-    page_manager.load();
+    load_shared(&(*switch_to).page_manager);
+    (*switch_from).segments.save();
+    (*switch_to).segments.load();
+
+    // Traps and sysenter from user mode must land on the top of the new
+    // thread's kernel stack, which is where its TrapFrame is expected to be.
+    TASK_STATE_SEGMENT.esp0 = (*switch_to).kernel_stack_top() as u32;
+    sysenter::set_kernel_stack((*switch_to).kernel_stack_top());
 
     let previous = Box::from_raw(context_switch(switch_from, switch_to));
 
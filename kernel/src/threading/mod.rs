@@ -6,6 +6,7 @@ pub mod thread_control_block;
 pub mod thread_functions;
 pub mod thread_sleep;
 
+use crate::mem::AllocationCounts;
 use crate::rush::rush_core::rush_loop;
 use crate::sync::mutex::Mutex;
 use crate::system::unwrap_system;
@@ -18,6 +19,7 @@ use crate::{
 };
 use alloc::boxed::Box;
 use thread_control_block::ThreadControlBlock;
+use thread_functions::{BeforeInit, BEFORE_INIT};
 
 pub struct ThreadState {
     pub running_thread: Mutex<Option<Box<ThreadControlBlock>>>,
@@ -58,8 +60,15 @@ pub fn thread_system_start(kernel_page_manager: PageManager, init_elf: &[u8]) ->
     let elf = Elf::parse_bytes(init_elf).expect("failed to parse provided elf file");
 
     // Create the initial user program thread.
-    let user_tcb = ThreadControlBlock::new_from_elf(elf, &system.process)
+    *BEFORE_INIT.lock() = Some(BeforeInit {
+        counts: AllocationCounts::now(),
+        kernel_processes: system.process.table.count(),
+    });
+    let user_tcb = ThreadControlBlock::new_from_elf(elf, &[], &[], &system.process)
         .expect("Failed to parse Elf for initial program.");
+    system.process.set_init_pid(user_tcb.pid);
+    // Init starts out in its own process group, which is in the foreground.
+    system.tty.set_foreground(user_tcb.pid);
 
     // SAFETY: Interrupts must be disabled.
     *system.threads.running_thread.lock() = Some(Box::new(kernel_tcb));
@@ -2,6 +2,7 @@ use super::thread_control_block::ProcessControlBlock;
 use crate::sync::{mutex::Mutex, rwlock::sleep::RwLock};
 use alloc::collections::BTreeMap;
 use alloc::sync::Arc;
+use alloc::vec::Vec;
 use core::sync::atomic::{AtomicU16, Ordering};
 
 pub type Pid = u16;
@@ -18,6 +19,8 @@ pub struct ProcessState {
     pub table: ProcessTable,
     next_tid: AtomicTid,
     next_pid: AtomicPid,
+    // The process which orphaned processes are reparented to
+    init_pid: AtomicPid,
 }
 
 pub fn create_process_state() -> ProcessState {
@@ -25,6 +28,7 @@ pub fn create_process_state() -> ProcessState {
         table: Default::default(),
         next_tid: AtomicTid::new(1),
         next_pid: AtomicPid::new(1),
+        init_pid: AtomicPid::new(0),
     }
 }
 
@@ -37,6 +41,12 @@ impl ProcessState {
         }
         pid
     }
+    pub fn init_pid(&self) -> Pid {
+        self.init_pid.load(Ordering::SeqCst)
+    }
+    pub fn set_init_pid(&self, pid: Pid) {
+        self.init_pid.store(pid, Ordering::SeqCst);
+    }
     pub fn allocate_tid(&self) -> Tid {
         // SAFETY: Atomically accesses a shared variable.
         let tid = self.next_tid.fetch_add(1, Ordering::SeqCst);
@@ -61,7 +71,6 @@ impl ProcessTable {
         pcb
     }
 
-    #[allow(dead_code)]
     pub fn remove(&self, pid: Pid) -> Option<Arc<Mutex<ProcessControlBlock>>> {
         self.content.write().remove(&pid)
     }
@@ -69,4 +78,19 @@ impl ProcessTable {
     pub fn get(&self, pid: Pid) -> Option<Arc<Mutex<ProcessControlBlock>>> {
         self.content.read().get(&pid).cloned()
     }
+
+    /// The number of processes, including the kernel's own.
+    pub fn count(&self) -> usize {
+        self.content.read().len()
+    }
+
+    /// The processes in the process group `pgid`.
+    pub fn group(&self, pgid: Pid) -> Vec<Arc<Mutex<ProcessControlBlock>>> {
+        self.content
+            .read()
+            .values()
+            .filter(|pcb| pcb.lock().pgid == pgid)
+            .cloned()
+            .collect()
+    }
 }
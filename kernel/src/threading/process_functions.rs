@@ -1,28 +1,491 @@
-use crate::system::{running_process, running_thread_tid};
+use crate::interrupts::{mutex_irq::hold_interrupts, trap_frame::TrapFrame, IntrLevel};
+use crate::mem::util::get_mut_slice_from_user_space;
+use crate::mem::vma::{VMAInfo, VMA};
+use crate::paging::{
+    load_shared, release_address_space, unmap_user_range, PageManager, PageManagerDefault,
+};
+use crate::system::{running_process, running_thread_tid, unwrap_system};
+use crate::user_program::elf::Elf;
+use crate::user_program::signal::send_signal;
+use crate::user_program::stack::STACK_ALIGNMENT;
+use crate::user_program::syscall::SIGCHLD;
+use crate::user_program::tls::ThreadSegments;
+use crate::{vfs, Mutex};
+use alloc::{boxed::Box, sync::Arc, vec, vec::Vec};
+use core::mem::size_of;
+use kidneyos_shared::mem::{OFFSET, PAGE_FRAME_SIZE};
 
 use super::{
+    process::{Pid, Tid},
+    thread_control_block::{
+        ExitStatus, ProcessControlBlock, ProgramImage, ThreadControlBlock, ThreadElfCreateError,
+        USER_THREAD_STACK_SIZE,
+    },
     thread_functions::{self, stop_thread},
-    thread_sleep::thread_wakeup,
+    thread_sleep::{thread_sleep, thread_wakeup},
 };
 
 pub fn exit_process(exit_code: i32) -> ! {
-    let pcb = running_process();
-    let mut pcb = pcb.lock();
-    pcb.exit_code = Some(exit_code);
+    terminate_process(ExitStatus::Exited(exit_code));
+}
 
-    if let Some(wait_tid) = pcb.waiting_thread {
-        thread_wakeup(wait_tid);
-    }
+/// End the running process, e.g. because of a signal, making `status`
+/// available to its parent.
+pub fn terminate_process(status: ExitStatus) -> ! {
+    let system = unwrap_system();
+    let pcb = running_process();
+    let mut pcb_guard = pcb.lock();
+    pcb_guard.exit_status = Some(status);
+    let pid = pcb_guard.pid;
+    let ppid = pcb_guard.ppid;
+    let children = core::mem::take(&mut pcb_guard.children);
 
     let running_tid = running_thread_tid();
 
     // Kill all threads which are part of this process
-    pcb.child_tids.iter().for_each(|tid| {
+    pcb_guard.child_tids.iter().for_each(|tid| {
         if *tid != running_tid {
             stop_thread(*tid)
         }
     });
+    drop(pcb_guard);
+
+    // Close our files and release our mappings. The address space itself is
+    // freed once our last thread has been cleaned up.
+    system.root_filesystem.lock().close_all(pid);
+    let vmas = core::mem::take(&mut pcb.lock().vmas);
+    drop(vmas);
+    // Nothing on this path returns, so nothing would drop our reference.
     drop(pcb);
 
+    // Our children are adopted by init, which becomes responsible for reaping
+    // them.
+    let init_pid = system.process.init_pid();
+    if pid != init_pid {
+        adopt_children(init_pid, children);
+    }
+
+    match system.process.table.get(ppid) {
+        Some(parent) => {
+            wake_waiting_threads(&parent);
+            send_signal(&parent, SIGCHLD);
+        }
+        // No one will ever wait for us, so we reap ourselves.
+        None => drop(system.process.table.remove(pid)),
+    }
+
     thread_functions::exit_thread(-1);
 }
+
+/// Make `children` the children of the process `parent_pid`.
+fn adopt_children(parent_pid: Pid, children: Vec<Pid>) {
+    let table = &unwrap_system().process.table;
+    let Some(parent) = table.get(parent_pid) else {
+        return;
+    };
+
+    let mut any_exited = false;
+    for child in &children {
+        if let Some(child) = table.get(*child) {
+            let mut child = child.lock();
+            child.ppid = parent_pid;
+            any_exited |= child.exit_status.is_some();
+        }
+    }
+    parent.lock().children.extend(children);
+
+    // Let the new parent reap any children which have already exited.
+    if any_exited {
+        wake_waiting_threads(&parent);
+    }
+}
+
+/// Wake up the threads of `pcb` which are waiting for a child to exit.
+fn wake_waiting_threads(pcb: &Mutex<ProcessControlBlock>) {
+    let waiting_threads = core::mem::take(&mut pcb.lock().waiting_threads);
+    for tid in waiting_threads {
+        thread_wakeup(tid);
+    }
+}
+
+#[derive(Debug)]
+pub enum WaitError {
+    /// The process has no children which could be waited for.
+    NoChildren,
+    /// A signal arrived while waiting.
+    Interrupted,
+}
+
+/// Wait for a child of the running process to exit, and reap it.
+///
+/// If `pid` is `Some`, waits for that child in particular, otherwise waits for
+/// any child. If `pgid` is `Some`, only children in that process group are
+/// waited for. If `block` is false, returns `Ok(None)` instead of waiting if
+/// no child has exited yet.
+///
+/// Returns the pid of the child which exited, and how it exited.
+pub fn wait_for_child(
+    pid: Option<Pid>,
+    pgid: Option<Pid>,
+    block: bool,
+) -> Result<Option<(Pid, ExitStatus)>, WaitError> {
+    let table = &unwrap_system().process.table;
+    let pcb = running_process();
+    loop {
+        // Interrupts must stay off until we're blocked, so that we can't miss
+        // the wakeup from a child which exits in the meantime.
+        let _guard = hold_interrupts(IntrLevel::IntrOff);
+        let mut pcb = pcb.lock();
+
+        let mut children = pcb
+            .children
+            .iter()
+            .copied()
+            .filter(|child| pid.map_or(true, |pid| pid == *child))
+            .filter(|child| {
+                pgid.map_or(true, |pgid| {
+                    table
+                        .get(*child)
+                        .is_some_and(|child| child.lock().pgid == pgid)
+                })
+            })
+            .peekable();
+        if children.peek().is_none() {
+            return Err(WaitError::NoChildren);
+        }
+        let exited = children.find_map(|child| {
+            let status = table.get(child)?.lock().exit_status?;
+            Some((child, status))
+        });
+
+        if let Some((child, status)) = exited {
+            pcb.children.retain(|pid| *pid != child);
+            drop(pcb);
+            table.remove(child);
+            return Ok(Some((child, status)));
+        }
+        if !block {
+            return Ok(None);
+        }
+        if pcb.signals.has_deliverable() {
+            return Err(WaitError::Interrupted);
+        }
+
+        let tid = running_thread_tid();
+        if !pcb.waiting_threads.contains(&tid) {
+            pcb.waiting_threads.push(tid);
+        }
+        drop(pcb);
+        thread_sleep();
+    }
+}
+
+/// Create a copy of the running process. The child shares the parent's memory
+/// copy-on-write, and resumes from the same point as the parent, except that
+/// its fork syscall returns 0.
+///
+/// Returns the pid of the child.
+pub fn fork_process() -> vfs::Result<Pid> {
+    let system = unwrap_system();
+    let parent = running_process();
+    let mut parent = parent.lock();
+    let child = parent.fork(&system.process)?;
+    let child_pid = child.lock().pid;
+    parent.children.push(child_pid);
+    drop(parent);
+
+    let mut child_page_manager = PageManager::default();
+    let mut guard = system.threads.running_thread.lock();
+    let parent_thread = guard.as_mut().expect("no running thread");
+    let mut parent_page_manager = parent_thread.page_manager.lock();
+    // SAFETY: The child's page tables aren't loaded, and the parent's pages
+    // only become read-only, which the page fault handler takes care of.
+    let trap_frame = unsafe {
+        parent_page_manager
+            .share_user_pages(&mut child_page_manager, &mut system.frame_ref_counts.lock());
+        // Reload the page tables so that the pages which just became read-only
+        // are recognized.
+        parent_page_manager.load();
+        *parent_thread.trap_frame()
+    };
+    let mut segments = parent_thread.segments;
+    segments.save();
+    drop(parent_page_manager);
+    drop(guard);
+
+    let child_thread = ThreadControlBlock::new_forked(
+        &trap_frame,
+        segments,
+        child_pid,
+        Arc::new(Mutex::new(child_page_manager)),
+        &system.process,
+    );
+    child.lock().child_tids.push(child_thread.tid);
+    system.threads.scheduler.lock().push(Box::new(child_thread));
+
+    Ok(child_pid)
+}
+
+/// Replace the program running in the current process with `elf`.
+///
+/// The process keeps its pid, parent, working directory and open files (except
+/// those marked close-on-exec), but its memory is replaced entirely. On
+/// success, the running thread will start executing the new program when it
+/// returns to user mode.
+pub fn exec_process(
+    elf: Elf,
+    argv: &[Vec<u8>],
+    envp: &[Vec<u8>],
+) -> Result<(), ThreadElfCreateError> {
+    // Load the new program before touching anything, so that we can still
+    // return an error to the old one if this fails.
+    let image = ProgramImage::load(elf, argv, envp)?;
+
+    let system = unwrap_system();
+    let pcb = running_process();
+    let mut pcb = pcb.lock();
+    // Only the thread which called exec survives it.
+    let running_tid = running_thread_tid();
+    for tid in core::mem::replace(&mut pcb.child_tids, vec![running_tid]) {
+        if tid != running_tid {
+            stop_thread(tid);
+        }
+    }
+    pcb.exited_threads.clear();
+    pcb.joining_threads.clear();
+    let old_vmas = core::mem::replace(&mut pcb.vmas, image.vmas);
+    pcb.heap_start = image.heap_start;
+    pcb.program_break = image.heap_start;
+    // The old program's signal handlers don't exist in the new one.
+    pcb.signals.exec();
+    let mut root = system.root_filesystem.lock();
+    root.release_mmaps(&old_vmas);
+    root.close_on_exec_files(pcb.pid);
+    drop(root);
+    drop(pcb);
+
+    let mut guard = system.threads.running_thread.lock();
+    let thread = guard.as_mut().expect("no running thread");
+    let old_page_manager = core::mem::replace(
+        &mut thread.page_manager,
+        Arc::new(Mutex::new(image.page_manager)),
+    );
+    // SAFETY: Only user mappings differ between the page tables, and nothing
+    // in the kernel refers to the old program's memory anymore.
+    unsafe { load_shared(&thread.page_manager) };
+    // Every other thread of ours was stopped above, so this was the last
+    // reference to the old program's address space, whose pages and page
+    // tables are freed here.
+    // SAFETY: The old page manager isn't loaded anymore.
+    let released = unsafe { release_address_space(old_page_manager) };
+    assert!(released, "the old program's address space is still in use");
+    thread.user_stack = None;
+    // Like its memory, the old program's thread-local storage is gone.
+    {
+        // We must not be switched out between recording the new program's
+        // segments and loading them.
+        let _guard = hold_interrupts(IntrLevel::IntrOff);
+        thread.segments = image
+            .thread_pointer
+            .map_or(ThreadSegments::new(), ThreadSegments::with_thread_pointer);
+        // SAFETY: We're the running thread, and interrupts are disabled.
+        unsafe { thread.segments.load() };
+    }
+    thread.eip = image.entry;
+    thread.esp = image.stack_pointer;
+
+    // Start the new program from a clean slate of registers when we return
+    // from the syscall.
+    // SAFETY: We're handling the execve syscall from user mode.
+    let trap_frame = unsafe { &mut *thread.trap_frame() };
+    *trap_frame = TrapFrame {
+        eip: image.entry.as_ptr() as u32,
+        esp: image.stack_pointer.as_ptr() as u32,
+        cs: trap_frame.cs,
+        eflags: trap_frame.eflags,
+        ss: trap_frame.ss,
+        ..TrapFrame::default()
+    };
+
+    Ok(())
+}
+
+#[derive(Debug)]
+pub enum ThreadCreateError {
+    /// There's no room left in the address space for another stack.
+    OutOfMemory,
+    /// The entry point isn't a user address.
+    InvalidEntryPoint,
+    /// The stack given for the thread can't be written to.
+    BadStack,
+}
+
+/// Start a new thread in the running process, which calls `entry` with `arg`
+/// on `stack`. If `stack` is 0, a new stack VMA is allocated for the thread.
+///
+/// `entry` must not return, since there's nothing to return to. Instead, the
+/// thread should end by calling thread_exit.
+///
+/// Returns the tid of the new thread.
+pub fn create_thread(entry: usize, arg: usize, stack: usize) -> Result<Tid, ThreadCreateError> {
+    if entry == 0 || entry >= OFFSET {
+        return Err(ThreadCreateError::InvalidEntryPoint);
+    }
+
+    let system = unwrap_system();
+    let guard = system.threads.running_thread.lock();
+    let thread = guard.as_ref().expect("no running thread");
+    let page_manager = thread.page_manager.clone();
+    // SAFETY: We're handling the thread_create syscall from user mode.
+    let trap_frame = unsafe { *thread.trap_frame() };
+    // Like Linux without CLONE_SETTLS, the new thread starts out with our
+    // thread-local storage, and is expected to set up its own.
+    let mut segments = thread.segments;
+    segments.save();
+    drop(guard);
+
+    let pcb = running_process();
+    let mut pcb = pcb.lock();
+    let user_stack = if stack == 0 {
+        // Leave an unmapped guard page below the stack, so that overflowing it
+        // faults instead of running into whatever is below.
+        let addr = pcb
+            .vmas
+            .find_free_range(USER_THREAD_STACK_SIZE + PAGE_FRAME_SIZE, OFFSET)
+            .ok_or(ThreadCreateError::OutOfMemory)?
+            + PAGE_FRAME_SIZE;
+        let added = pcb
+            .vmas
+            .add_vma(VMA::new(VMAInfo::Stack, USER_THREAD_STACK_SIZE, true), addr);
+        assert!(added, "free range for thread stack was not free");
+        Some(addr)
+    } else {
+        None
+    };
+    let stack_top = user_stack.map_or(stack, |addr| addr + USER_THREAD_STACK_SIZE);
+
+    // Lay out the stack as though `entry` had just been called with `arg`.
+    let esp = (stack_top.saturating_sub(size_of::<usize>()) & !(STACK_ALIGNMENT - 1))
+        .saturating_sub(size_of::<usize>());
+    pcb.vmas.install_range(esp, 2 * size_of::<usize>());
+    let Some(frame) = (unsafe { get_mut_slice_from_user_space(esp as *mut usize, 2) }) else {
+        if let Some(addr) = user_stack {
+            pcb.vmas.remove_vma(addr);
+        }
+        return Err(ThreadCreateError::BadStack);
+    };
+    frame.copy_from_slice(&[0, arg]);
+
+    let trap_frame = TrapFrame {
+        eip: entry as u32,
+        esp: esp as u32,
+        cs: trap_frame.cs,
+        eflags: trap_frame.eflags,
+        ss: trap_frame.ss,
+        ..TrapFrame::default()
+    };
+    let mut new_thread = ThreadControlBlock::new_forked(
+        &trap_frame,
+        segments,
+        pcb.pid,
+        page_manager,
+        &system.process,
+    );
+    new_thread.user_stack = user_stack;
+    let tid = new_thread.tid;
+    pcb.child_tids.push(tid);
+    drop(pcb);
+    system.threads.scheduler.lock().push(Box::new(new_thread));
+
+    Ok(tid)
+}
+
+/// End the running thread, making `exit_code` available to a thread which
+/// joins it. If this is the last thread of its process, the process exits with
+/// `exit_code` instead.
+pub fn exit_user_thread(exit_code: i32) -> ! {
+    let tid = running_thread_tid();
+    let user_stack = unwrap_system()
+        .threads
+        .running_thread
+        .lock()
+        .as_ref()
+        .expect("no running thread")
+        .user_stack;
+
+    let pcb = running_process();
+    let mut pcb_guard = pcb.lock();
+    if pcb_guard.child_tids == [tid] {
+        drop(pcb_guard);
+        drop(pcb);
+        exit_process(exit_code);
+    }
+    pcb_guard.child_tids.retain(|child| *child != tid);
+    pcb_guard.exited_threads.push((tid, exit_code));
+    let stack = user_stack.and_then(|addr| Some((addr, pcb_guard.vmas.remove_vma(addr)?)));
+    let joining_threads = core::mem::take(&mut pcb_guard.joining_threads);
+    drop(pcb_guard);
+    // Nothing on this path returns, so nothing would drop our reference.
+    drop(pcb);
+
+    if let Some((addr, vma)) = stack {
+        let guard = unwrap_system().threads.running_thread.lock();
+        let mut page_manager = guard
+            .as_ref()
+            .expect("no running thread")
+            .page_manager
+            .lock();
+        // SAFETY: We're running on our kernel stack, so nothing uses the user
+        // stack anymore.
+        unsafe { unmap_user_range(&mut page_manager, addr, vma.size()) };
+    }
+
+    for joining in joining_threads {
+        thread_wakeup(joining);
+    }
+    thread_functions::exit_thread(exit_code);
+}
+
+#[derive(Debug)]
+pub enum JoinError {
+    /// There's no thread with this tid in the running process.
+    NoSuchThread,
+    /// A thread tried to join itself.
+    Deadlock,
+    /// A signal arrived while waiting.
+    Interrupted,
+}
+
+/// Wait for the thread `tid` of the running process to exit, and return its
+/// exit code. Each thread can only be joined once.
+pub fn join_thread(tid: Tid) -> Result<i32, JoinError> {
+    let running_tid = running_thread_tid();
+    if tid == running_tid {
+        return Err(JoinError::Deadlock);
+    }
+
+    let pcb = running_process();
+    loop {
+        // Interrupts must stay off until we're blocked, so that we can't miss
+        // the wakeup from the thread exiting in the meantime.
+        let _guard = hold_interrupts(IntrLevel::IntrOff);
+        let mut pcb = pcb.lock();
+
+        if let Some(index) = pcb.exited_threads.iter().position(|(t, _)| *t == tid) {
+            let (_, exit_code) = pcb.exited_threads.swap_remove(index);
+            return Ok(exit_code);
+        }
+        if !pcb.child_tids.contains(&tid) {
+            return Err(JoinError::NoSuchThread);
+        }
+        if pcb.signals.has_deliverable() {
+            return Err(JoinError::Interrupted);
+        }
+
+        if !pcb.joining_threads.contains(&running_tid) {
+            pcb.joining_threads.push(running_tid);
+        }
+        drop(pcb);
+        thread_sleep();
+    }
+}
@@ -1,23 +1,36 @@
 use super::thread_functions::{PrepareThreadContext, SwitchThreadsContext, ThreadFunction};
 use crate::fs::fs_manager::RootFileSystem;
+use crate::fs::read_file;
+use crate::interrupts::trap_frame::TrapFrame;
 use crate::system::{running_thread_ppid, unwrap_system};
 use crate::threading::process::{Pid, ProcessState, Tid};
-use crate::user_program::elf::{ElfArchitecture, ElfProgramType, ElfUsage};
+use crate::user_program::elf::{
+    ElfArchitecture, ElfProgramHeader, ElfProgramType, ElfUsage, R_386_NONE, R_386_RELATIVE,
+};
+use crate::user_program::signal::SignalState;
+use crate::user_program::stack::{setup_user_stack, StackSetupError};
+use crate::user_program::tls::ThreadSegments;
 use crate::{
     fs::fs_manager::FileSystemID,
     mem::vma::{VMAInfo, VMAList, VMA},
-    paging::{PageManager, PageManagerDefault},
+    paging::{unmap_user_pages, PageManager, PageManagerDefault, SharedPageManager},
     user_program::elf::Elf,
-    vfs::{INodeNum, OwnedPath},
+    vfs::{self, INodeNum, OwnedPath},
     Mutex, KERNEL_ALLOCATOR,
 };
 use alloc::sync::Arc;
 use alloc::vec::Vec;
 use core::{
     mem::size_of,
+    ops::Range,
     ptr::{copy_nonoverlapping, write_bytes, NonNull},
 };
 use kidneyos_shared::mem::{OFFSET, PAGE_FRAME_SIZE};
+use kidneyos_shared::paging::PageFlags;
+use kidneyos_syscalls::defs::{
+    AT_BASE, AT_EGID, AT_ENTRY, AT_EUID, AT_GID, AT_PAGESZ, AT_PHDR, AT_PHENT, AT_PHNUM, AT_SECURE,
+    AT_UID,
+};
 
 // The stack size choice is based on that of x86-64 Linux and 32-bit Windows
 // Linux: https://docs.kernel.org/next/x86/kernel-stacks.html
@@ -27,6 +40,12 @@ const KERNEL_THREAD_STACK_SIZE: usize = KERNEL_THREAD_STACK_FRAMES * PAGE_FRAME_
 pub const USER_THREAD_STACK_FRAMES: usize = 4 * 1024;
 pub const USER_THREAD_STACK_SIZE: usize = USER_THREAD_STACK_FRAMES * PAGE_FRAME_SIZE;
 pub const USER_STACK_BOTTOM_VIRT: usize = 0x100000;
+/// Where position-independent executables are loaded, well above the stack.
+pub const PIE_BASE: usize = 0x8000000;
+/// Where program interpreters are loaded, leaving room for the program's heap
+/// to grow below and for mappings to grow down towards it from the top of user
+/// space.
+pub const INTERPRETER_BASE: usize = 0x40000000;
 
 #[allow(unused)]
 #[derive(PartialEq, Debug)]
@@ -38,21 +57,61 @@ pub enum ThreadStatus {
     Dying,
 }
 
+/// How a process ended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExitStatus {
+    /// The process exited by itself with this exit code.
+    Exited(i32),
+    /// The process was terminated by this signal.
+    Signaled(i32),
+    /// The process was killed for causing a CPU fault, which is reported as
+    /// being terminated by this signal.
+    Faulted(i32),
+}
+
+impl ExitStatus {
+    /// Encodes this status the way waitpid reports it.
+    pub fn wait_status(self) -> i32 {
+        match self {
+            Self::Exited(code) => (code & 0xff) << 8,
+            Self::Signaled(signal) => signal & 0x7f,
+            // Like Linux, we set the "core dumped" bit for faults, although we
+            // don't actually dump core.
+            Self::Faulted(signal) => (signal & 0x7f) | 0x80,
+        }
+    }
+}
+
 pub struct ProcessControlBlock {
     pub pid: Pid,
     // The Pid of the process' parent
     pub ppid: Pid,
+    // The process group the process is in, which signals can be sent to as a
+    // whole
+    pub pgid: Pid,
     // The TIDs of this process' children threads
     pub child_tids: Vec<Tid>,
-    // The TIDs of the threads waiting on this process to end
-    pub waiting_thread: Option<Tid>,
-
-    pub exit_code: Option<i32>,
+    // The PIDs of this process' child processes which haven't been reaped yet
+    pub children: Vec<Pid>,
+    // The TIDs of this process' threads which are waiting for a child to end
+    pub waiting_threads: Vec<Tid>,
+    // The TIDs and exit codes of this process' threads which have exited but
+    // haven't been joined yet
+    pub exited_threads: Vec<(Tid, i32)>,
+    // The TIDs of this process' threads which are waiting to join another thread
+    pub joining_threads: Vec<Tid>,
+
+    pub exit_status: Option<ExitStatus>,
+    pub signals: SignalState,
     /// filesystem and inode of current working directory
     pub cwd: (FileSystemID, INodeNum),
     /// path to cwd (needed for getcwd syscall)
     pub cwd_path: OwnedPath,
     pub vmas: VMAList,
+    /// Where the heap VMA starts, just after the program's highest segment
+    pub heap_start: usize,
+    /// The end of the heap, as set by the brk syscall
+    pub program_break: usize,
 }
 
 impl ProcessControlBlock {
@@ -66,6 +125,31 @@ impl ProcessControlBlock {
         root.open_standard_fds(pid);
         // TODO: inherit cwd from parent
         let cwd = root.get_root().unwrap();
+        let vmas = Self::initial_vmas();
+
+        let pcb = Self {
+            pid,
+            ppid: parent_pid,
+            pgid: pid,
+            child_tids: Vec::new(),
+            children: Vec::new(),
+            waiting_threads: Vec::new(),
+            exited_threads: Vec::new(),
+            joining_threads: Vec::new(),
+            exit_status: None,
+            signals: SignalState::default(),
+            vmas,
+            heap_start: 0,
+            program_break: 0,
+            cwd,
+            cwd_path: "/".into(),
+        };
+
+        state.table.add(pcb)
+    }
+
+    /// The VMAs every process starts a new program with.
+    pub fn initial_vmas() -> VMAList {
         let mut vmas = VMAList::new();
         // set up stack
         // TODO: Handle stack section defined in the ELF file?
@@ -74,19 +158,40 @@ impl ProcessControlBlock {
             USER_STACK_BOTTOM_VIRT,
         );
         assert!(stack_avail, "stack virtual address range not available");
+        vmas
+    }
+
+    /// Creates a copy of this process as its child, with its own copies of
+    /// this process' open files, working directory and VMAs.
+    ///
+    /// The child's address space must be set up separately, see
+    /// `PageManager::share_user_pages`.
+    pub fn fork(&self, state: &ProcessState) -> vfs::Result<Arc<Mutex<ProcessControlBlock>>> {
+        let pid = state.allocate_pid();
+        unwrap_system().root_filesystem.lock().fork(self, pid)?;
+        // NOTE: This must happen without holding the root file system lock,
+        // since cloning mmap VMAs increments inode reference counts.
+        let vmas = self.vmas.clone();
 
         let pcb = Self {
             pid,
-            ppid: parent_pid,
+            ppid: self.pid,
+            pgid: self.pgid,
             child_tids: Vec::new(),
-            waiting_thread: None,
-            exit_code: None,
+            children: Vec::new(),
+            waiting_threads: Vec::new(),
+            exited_threads: Vec::new(),
+            joining_threads: Vec::new(),
+            exit_status: None,
+            signals: self.signals.fork(),
             vmas,
-            cwd,
-            cwd_path: "/".into(),
+            heap_start: self.heap_start,
+            program_break: self.program_break,
+            cwd: self.cwd,
+            cwd_path: self.cwd_path.clone(),
         };
 
-        state.table.add(pcb)
+        Ok(state.table.add(pcb))
     }
 }
 
@@ -113,7 +218,13 @@ pub struct ThreadControlBlock {
     pub is_kernel: bool,
     pub status: ThreadStatus,
     pub exit_code: Option<i32>,
-    pub page_manager: PageManager,
+    pub page_manager: SharedPageManager,
+    // The address of the stack VMA allocated for this thread by the
+    // thread_create syscall, which is removed when the thread exits.
+    pub user_stack: Option<usize>,
+    // The thread-local storage segment and the fs and gs selectors, which are
+    // switched along with the thread.
+    pub segments: ThreadSegments,
 }
 
 #[derive(Debug)]
@@ -121,23 +232,398 @@ pub enum ThreadElfCreateError {
     UnsupportedArchitecture,
     NotExecutable,
     InvalidEntryPoint,
+    ArgumentListTooLong,
+    OutOfMemory,
+    /// The interpreter requested by the program couldn't be read.
+    InterpreterNotFound,
+    /// The program needs relocations which only a dynamic linker can apply.
+    UnsupportedRelocation,
 }
 
-impl ThreadControlBlock {
-    pub fn new_from_elf(
+impl From<StackSetupError> for ThreadElfCreateError {
+    fn from(value: StackSetupError) -> Self {
+        match value {
+            StackSetupError::ArgumentListTooLong => Self::ArgumentListTooLong,
+            StackSetupError::OutOfMemory => Self::OutOfMemory,
+        }
+    }
+}
+
+/// A program loaded into a new address space, ready to be run.
+pub struct ProgramImage {
+    pub page_manager: PageManager,
+    pub entry: NonNull<u8>,
+    pub stack_pointer: NonNull<u8>,
+    /// The first page after the program's segments, where its heap starts.
+    pub heap_start: usize,
+    /// The main thread's thread pointer, if the program has thread-local
+    /// storage.
+    pub thread_pointer: Option<usize>,
+    /// The stack, and the parts of the program, its thread-local storage and
+    /// its interpreter which were mapped.
+    pub vmas: VMAList,
+}
+
+impl ProgramImage {
+    /// Loads `elf` into a new address space, with `argv` and `envp` on the
+    /// top of its stack.
+    ///
+    /// Position-independent executables are moved to [`PIE_BASE`]. If `elf`
+    /// requests an interpreter, it's read from the running process's view of
+    /// the filesystem and loaded at [`INTERPRETER_BASE`], and the program
+    /// starts there instead.
+    pub fn load(
         elf: Elf,
-        state: &ProcessState,
-    ) -> Result<ThreadControlBlock, ThreadElfCreateError> {
-        // Shared ELFs can count as a "Relocatable Executable" if the entry point is set.
-        let executable = matches!(elf.header.usage, ElfUsage::Executable | ElfUsage::Shared);
+        argv: &[Vec<u8>],
+        envp: &[Vec<u8>],
+    ) -> Result<Self, ThreadElfCreateError> {
+        check_loadable(&elf)?;
+
+        let mut image = Self {
+            page_manager: PageManager::default(),
+            entry: NonNull::dangling(),
+            stack_pointer: NonNull::dangling(),
+            heap_start: 0,
+            thread_pointer: None,
+            vmas: ProcessControlBlock::initial_vmas(),
+        };
+        if let Err(err) = image.load_into(&elf, argv, envp) {
+            // SAFETY: The page manager was never loaded.
+            unsafe { unmap_user_pages(&mut image.page_manager) };
+            return Err(err);
+        }
+        Ok(image)
+    }
+
+    /// Maps the program, its interpreter if it has one, and its stack into
+    /// the image's page manager, and fills in the rest of the image.
+    fn load_into(
+        &mut self,
+        elf: &Elf,
+        argv: &[Vec<u8>],
+        envp: &[Vec<u8>],
+    ) -> Result<(), ThreadElfCreateError> {
+        let page_manager = &mut self.page_manager;
+        let vmas = &mut self.vmas;
+        let base = load_base(elf, PIE_BASE);
+        let mut heap_start = load_segments(page_manager, vmas, elf, base)?;
+        let mut thread_pointer = None;
+        if let Some(tls) = elf
+            .program_headers
+            .iter()
+            .find(|header| header.program_type == ElfProgramType::ThreadLocal)
+        {
+            let (pointer, end) = load_tls(page_manager, vmas, tls, heap_start)?;
+            thread_pointer = Some(pointer);
+            heap_start = end;
+        }
+        let program_entry = base
+            .checked_add(elf.header.program_entry as usize)
+            .ok_or(ThreadElfCreateError::NotExecutable)?;
+
+        let (entry, interpreter_base) = match elf.interpreter() {
+            // The interpreter relocates the program itself.
+            Some(path) => {
+                let path = core::str::from_utf8(path)
+                    .map_err(|_| ThreadElfCreateError::InterpreterNotFound)?;
+                let data =
+                    read_file(path).map_err(|_| ThreadElfCreateError::InterpreterNotFound)?;
+                let interpreter =
+                    Elf::parse_bytes(&data).map_err(|_| ThreadElfCreateError::NotExecutable)?;
+                check_loadable(&interpreter)?;
+                // Interpreters can't have interpreters of their own, and are
+                // expected to relocate themselves, as on Linux.
+                if interpreter.interpreter().is_some() {
+                    return Err(ThreadElfCreateError::NotExecutable);
+                }
+                let interpreter_base = load_base(&interpreter, INTERPRETER_BASE);
+                load_segments(page_manager, vmas, &interpreter, interpreter_base)?;
+                let entry = interpreter_base
+                    .checked_add(interpreter.header.program_entry as usize)
+                    .ok_or(ThreadElfCreateError::NotExecutable)?;
+                (entry, interpreter_base)
+            }
+            None => {
+                if base != 0 {
+                    relocate(page_manager, elf, base)?;
+                }
+                (program_entry, 0)
+            }
+        };
+        let entry =
+            NonNull::new(entry as *mut u8).ok_or(ThreadElfCreateError::InvalidEntryPoint)?;
+
+        let mut auxv = Vec::new();
+        if let Some(address) = elf.program_headers_address() {
+            let address = base
+                .checked_add(address as usize)
+                .ok_or(ThreadElfCreateError::NotExecutable)?;
+            auxv.extend([
+                (AT_PHDR, address),
+                (AT_PHENT, elf.header.program_header_entry_size as usize),
+                (AT_PHNUM, elf.header.program_header_count as usize),
+            ]);
+        }
+        auxv.extend([
+            (AT_PAGESZ, PAGE_FRAME_SIZE),
+            (AT_BASE, interpreter_base),
+            (AT_ENTRY, program_entry),
+            // There are no users yet, so everything runs as root. Without these,
+            // libcs assume the program might be setuid and tread carefully.
+            (AT_UID, 0),
+            (AT_EUID, 0),
+            (AT_GID, 0),
+            (AT_EGID, 0),
+            (AT_SECURE, 0),
+        ]);
+        self.stack_pointer = setup_user_stack(page_manager, argv, envp, &auxv)?;
+        self.entry = entry;
+        self.heap_start = heap_start;
+        self.thread_pointer = thread_pointer;
+        Ok(())
+    }
+}
+
+/// Checks that `elf` is something we know how to run.
+fn check_loadable(elf: &Elf) -> Result<(), ThreadElfCreateError> {
+    // Shared ELFs can count as a "Relocatable Executable" if the entry point is set.
+    let executable = matches!(elf.header.usage, ElfUsage::Executable | ElfUsage::Shared);
+
+    if !executable {
+        return Err(ThreadElfCreateError::NotExecutable);
+    }
+
+    if elf.header.architecture != ElfArchitecture::X86 {
+        return Err(ThreadElfCreateError::UnsupportedArchitecture);
+    }
+
+    Ok(())
+}
 
-        if !executable {
-            return Err(ThreadElfCreateError::NotExecutable);
+/// Returns the address `elf` should be moved to, which is `base` for
+/// position-independent ELFs, and 0 for ones which must be loaded at their
+/// link-time addresses.
+fn load_base(elf: &Elf, base: usize) -> usize {
+    match elf.header.usage {
+        ElfUsage::Shared => base,
+        _ => 0,
+    }
+}
+
+/// Maps the loadable segments of `elf` into `page_manager`, moved up by `base`,
+/// and adds VMAs for them to `vmas`.
+///
+/// Returns the first page after the segments.
+fn load_segments(
+    page_manager: &mut PageManager,
+    vmas: &mut VMAList,
+    elf: &Elf,
+    base: usize,
+) -> Result<usize, ThreadElfCreateError> {
+    let mut end = 0;
+    for program_header in &elf.program_headers {
+        if program_header.program_type != ElfProgramType::Load {
+            continue;
         }
 
-        if elf.header.architecture != ElfArchitecture::X86 {
-            return Err(ThreadElfCreateError::UnsupportedArchitecture);
+        let segment_start = base
+            .checked_add(program_header.virtual_address as usize)
+            .ok_or(ThreadElfCreateError::NotExecutable)?;
+        let segment_end = segment_start
+            .checked_add(program_header.memory_size as usize)
+            .filter(|&segment_end| segment_end <= OFFSET)
+            .ok_or(ThreadElfCreateError::NotExecutable)?;
+        end = end.max(segment_end.next_multiple_of(PAGE_FRAME_SIZE));
+        // SAFETY: The page manager isn't loaded until the program starts.
+        unsafe {
+            load_segment(
+                page_manager,
+                segment_start..segment_end,
+                program_header.data,
+                program_header.writable,
+            )?;
         }
+        add_image_vma(vmas, segment_start..segment_end, program_header.writable)?;
+    }
+    Ok(end)
+}
+
+/// Adds a VMA to `vmas` for the pages of `range`, which were just mapped for the
+/// program being loaded, so that nothing else is mapped over them.
+///
+/// Segments may share a page at their boundary, which stays in the VMA of the
+/// one before.
+fn add_image_vma(
+    vmas: &mut VMAList,
+    range: Range<usize>,
+    writable: bool,
+) -> Result<(), ThreadElfCreateError> {
+    let mut start = range.start & !(PAGE_FRAME_SIZE - 1);
+    let end = range.end.next_multiple_of(PAGE_FRAME_SIZE);
+    if let Some((vma_addr, vma)) = vmas.vma_at(start) {
+        if let VMAInfo::Image = vma.info() {
+            start = vma_addr + vma.size();
+        }
+    }
+    if start >= end {
+        return Ok(());
+    }
+    // The segment overlaps the stack or another program's VMA.
+    if !vmas.add_vma(VMA::new(VMAInfo::Image, end - start, writable), start) {
+        return Err(ThreadElfCreateError::NotExecutable);
+    }
+    Ok(())
+}
+
+/// Lays out the main thread's thread-local storage at `start`, initialized
+/// from the `PT_TLS` segment `tls`.
+///
+/// On x86, the TLS block ends at the thread pointer, which points to a word
+/// holding its own address.
+///
+/// Returns the thread pointer and the first page after the block.
+fn load_tls(
+    page_manager: &mut PageManager,
+    vmas: &mut VMAList,
+    tls: &ElfProgramHeader,
+    start: usize,
+) -> Result<(usize, usize), ThreadElfCreateError> {
+    let alignment = (tls.alignment as usize).max(size_of::<usize>());
+    if !alignment.is_power_of_two() {
+        return Err(ThreadElfCreateError::NotExecutable);
+    }
+    let layout = || {
+        let block_start = start.checked_next_multiple_of(alignment)?;
+        let size = (tls.memory_size as usize).checked_next_multiple_of(alignment)?;
+        let thread_pointer = block_start.checked_add(size)?;
+        let end = thread_pointer.checked_add(size_of::<usize>())?;
+        Some((block_start, thread_pointer, end)).filter(|_| end <= OFFSET)
+    };
+    let (block_start, thread_pointer, end) = layout().ok_or(ThreadElfCreateError::NotExecutable)?;
+
+    // SAFETY: The page manager isn't loaded until the program starts.
+    unsafe { load_segment(page_manager, block_start..end, tls.data, true)? };
+    add_image_vma(vmas, block_start..end, true)?;
+    let (phys_addr, _) = page_manager
+        .translate(thread_pointer)
+        .expect("TLS block was just mapped");
+    // The page manager isn't loaded, so we write through the kernel's mapping
+    // of the frame instead.
+    unsafe { ((phys_addr + OFFSET) as *mut usize).write(thread_pointer) };
+    Ok((thread_pointer, end.next_multiple_of(PAGE_FRAME_SIZE)))
+}
+
+/// Applies the relocations of `elf`, which has been loaded at `base` instead
+/// of at its link-time addresses.
+///
+/// Only relative relocations are supported, since there's no dynamic linker in
+/// the kernel to resolve symbols.
+fn relocate(
+    page_manager: &mut PageManager,
+    elf: &Elf,
+    base: usize,
+) -> Result<(), ThreadElfCreateError> {
+    let relocations = elf
+        .relocations()
+        .ok_or(ThreadElfCreateError::NotExecutable)?;
+    for relocation in relocations {
+        match relocation.kind {
+            R_386_NONE => {}
+            R_386_RELATIVE => {
+                let addr = base
+                    .checked_add(relocation.offset as usize)
+                    .ok_or(ThreadElfCreateError::NotExecutable)?;
+                if addr % size_of::<u32>() != 0 {
+                    return Err(ThreadElfCreateError::UnsupportedRelocation);
+                }
+                let (phys_addr, _) = page_manager
+                    .translate(addr)
+                    .filter(|(_, flags)| flags.user)
+                    .ok_or(ThreadElfCreateError::NotExecutable)?;
+                // The page manager isn't loaded, so we write through the
+                // kernel's mapping of the frame instead. The addend is the
+                // word being relocated.
+                let word = (phys_addr + OFFSET) as *mut u32;
+                unsafe { word.write(word.read().wrapping_add(base as u32)) };
+            }
+            _ => return Err(ThreadElfCreateError::UnsupportedRelocation),
+        }
+    }
+    Ok(())
+}
+
+/// Maps the pages of the segment `range` into `page_manager`, copies `data` to
+/// its start and zeroes the rest of it.
+///
+/// Segments may share a page at their boundary, in which case the page which is
+/// already mapped is reused. Segments may not overlap the kernel's mappings.
+///
+/// # Safety
+///
+/// `page_manager` must not be loaded.
+unsafe fn load_segment(
+    page_manager: &mut PageManager,
+    range: Range<usize>,
+    data: &[u8],
+    writable: bool,
+) -> Result<(), ThreadElfCreateError> {
+    let first_page = range.start & !(PAGE_FRAME_SIZE - 1);
+    for page in (first_page..range.end).step_by(PAGE_FRAME_SIZE) {
+        let (phys_addr, flags) = match page_manager.translate(page) {
+            Some((_, flags)) if !flags.user => return Err(ThreadElfCreateError::NotExecutable),
+            Some(mapping) => mapping,
+            None => {
+                let frame = KERNEL_ALLOCATOR
+                    .frame_alloc(1)
+                    .map_err(|_| ThreadElfCreateError::OutOfMemory)?
+                    .as_ptr();
+                write_bytes(frame, 0, PAGE_FRAME_SIZE);
+                page_manager.map(frame as usize - OFFSET, page, writable, true);
+                let flags = PageFlags {
+                    write: writable,
+                    user: true,
+                    ..PageFlags::default()
+                };
+                (frame as usize - OFFSET, flags)
+            }
+        };
+        if writable && !flags.write {
+            page_manager.protect(
+                page,
+                PageFlags {
+                    write: true,
+                    ..flags
+                },
+            );
+        }
+
+        // The page manager isn't loaded, so we write through the kernel's
+        // mapping of the frame instead.
+        let start = range.start.max(page);
+        let end = range.end.min(page + PAGE_FRAME_SIZE);
+        let frame = (phys_addr + OFFSET) as *mut u8;
+        write_bytes(frame.add(start - page), 0, end - start);
+        let data_start = start - range.start;
+        let data_end = (end - range.start).min(data.len());
+        if data_start < data_end {
+            copy_nonoverlapping(
+                data[data_start..data_end].as_ptr(),
+                frame.add(start - page),
+                data_end - data_start,
+            );
+        }
+    }
+    Ok(())
+}
+
+impl ThreadControlBlock {
+    pub fn new_from_elf(
+        elf: Elf,
+        argv: &[Vec<u8>],
+        envp: &[Vec<u8>],
+        state: &ProcessState,
+    ) -> Result<ThreadControlBlock, ThreadElfCreateError> {
+        let image = ProgramImage::load(elf, argv, envp)?;
 
         let any_running_thread = unwrap_system().threads.running_thread.lock().is_some();
         let ppid = if !any_running_thread {
@@ -147,75 +633,20 @@ impl ThreadControlBlock {
         };
         let pcb =
             ProcessControlBlock::create(state, &mut unwrap_system().root_filesystem.lock(), ppid);
-        let pcb = pcb.lock();
+        let mut pcb = pcb.lock();
         let pid = pcb.pid;
-        let mut page_manager = PageManager::default();
-
-        for program_header in elf.program_headers {
-            if program_header.program_type != ElfProgramType::Load {
-                continue;
-            }
-
-            // Some ELF files have off-alignment segments (off 4KB).
-            // We need to pad this space with zeroes.
-            let segment_virtual_frame_start =
-                program_header.virtual_address as usize / PAGE_FRAME_SIZE;
-            let segment_virtual_start = segment_virtual_frame_start * PAGE_FRAME_SIZE;
-            let segment_padding = program_header.virtual_address as usize % PAGE_FRAME_SIZE;
-            let segment_padded_size = segment_padding + program_header.data.len();
-
-            let frames = segment_padded_size.div_ceil(PAGE_FRAME_SIZE);
-
-            unsafe {
-                // TODO: Save this physical address somewhere so we can deallocate
-                // it when dropping the thread.
-                let kernel_virt_addr = KERNEL_ALLOCATOR
-                    .frame_alloc(frames)
-                    .expect("no more frames...")
-                    .cast::<u8>()
-                    .as_ptr();
-                let phys_addr = kernel_virt_addr.sub(OFFSET);
-
-                // TODO: Throw an error if this range overlaps any previously mapped
-                // ranges, since `map_range` requires that the input range has not
-                // already been mapped.
-
-                // Map the physical address obtained by the allocation above to the
-                // virtual address assigned by the ELF header.
-                page_manager.map_range(
-                    phys_addr as usize,
-                    segment_virtual_start,
-                    frames * PAGE_FRAME_SIZE,
-                    program_header.writable,
-                    true,
-                );
-
-                write_bytes(kernel_virt_addr, 0, segment_padded_size);
-
-                // Load so we can write to the virtual addresses mapped above.
-                copy_nonoverlapping(
-                    program_header.data.as_ptr(),
-                    kernel_virt_addr.add(segment_padding),
-                    program_header.data.len(),
-                );
-
-                // Zero the sliver of addresses between the end of the region, and
-                // the end of the region we had to map due to page
-                write_bytes(
-                    kernel_virt_addr.add(segment_padded_size),
-                    0,
-                    frames * PAGE_FRAME_SIZE - segment_padded_size,
-                );
-            }
+        pcb.heap_start = image.heap_start;
+        pcb.program_break = image.heap_start;
+        pcb.vmas = image.vmas;
+
+        let mut thread =
+            ThreadControlBlock::new_with_page_manager(image.entry, pid, image.page_manager, state);
+        thread.esp = image.stack_pointer;
+        if let Some(thread_pointer) = image.thread_pointer {
+            thread.segments = ThreadSegments::with_thread_pointer(thread_pointer);
         }
-
-        Ok(ThreadControlBlock::new_with_page_manager(
-            NonNull::new(elf.header.program_entry as *mut u8)
-                .ok_or(ThreadElfCreateError::InvalidEntryPoint)?,
-            pid,
-            page_manager,
-            state,
-        ))
+        pcb.child_tids.push(thread.tid);
+        Ok(thread)
     }
 
     pub fn new_with_page_manager(
@@ -224,7 +655,13 @@ impl ThreadControlBlock {
         page_manager: PageManager,
         state: &ProcessState,
     ) -> Self {
-        let mut new_thread = Self::new(entry_instruction, false, pid, page_manager, state);
+        let mut new_thread = Self::new(
+            entry_instruction,
+            false,
+            pid,
+            Arc::new(Mutex::new(page_manager)),
+            state,
+        );
 
         // Now, we must build the stack frames for our new thread.
         let switch_threads_context = new_thread
@@ -243,6 +680,49 @@ impl ThreadControlBlock {
         new_thread
     }
 
+    /// Creates a thread which resumes in user mode with the registers in
+    /// `trap_frame` and `segments`, except that the syscall it made will
+    /// appear to have returned 0. This is how the child of a fork, and threads
+    /// created by user programs, start running.
+    pub fn new_forked(
+        trap_frame: &TrapFrame,
+        segments: ThreadSegments,
+        pid: Pid,
+        page_manager: SharedPageManager,
+        state: &ProcessState,
+    ) -> Self {
+        let eip = NonNull::new(trap_frame.eip as *mut u8).expect("failed to create eip");
+        let mut new_thread = Self::new(eip, false, pid, page_manager, state);
+        new_thread.esp = NonNull::new(trap_frame.esp as *mut u8).expect("failed to create esp");
+        new_thread.segments = segments;
+
+        // Now, we must build the stack frames for our new thread.
+        // In order (of creation), we have:
+        //  * the trap frame to return to user mode with
+        //  * switch_threads
+        let child_trap_frame = new_thread
+            .allocate_stack_space(size_of::<TrapFrame>())
+            .expect("No Stack Space!");
+        let switch_threads_context = new_thread
+            .allocate_stack_space(size_of::<SwitchThreadsContext>())
+            .expect("No Stack Space!");
+
+        // SAFETY: Manually setting stack bytes a la C.
+        unsafe {
+            *child_trap_frame.as_ptr().cast::<TrapFrame>() = TrapFrame {
+                eax: 0,
+                ..*trap_frame
+            };
+            *switch_threads_context
+                .as_ptr()
+                .cast::<SwitchThreadsContext>() = SwitchThreadsContext::new_forked();
+        }
+
+        // Our thread can now be run via the `switch_threads` method.
+        new_thread.status = ThreadStatus::Ready;
+        new_thread
+    }
+
     #[allow(unused)]
     pub fn new_with_setup(
         eip: ThreadFunction,
@@ -261,7 +741,7 @@ impl ThreadControlBlock {
             ProcessControlBlock::create(state, file_system, parent_pid)
                 .lock()
                 .pid,
-            PageManager::default(),
+            Arc::new(Mutex::new(PageManager::default())),
             state,
         );
 
@@ -297,7 +777,7 @@ impl ThreadControlBlock {
         entry_instruction: NonNull<u8>,
         is_kernel: bool,
         pid: Pid,
-        page_manager: PageManager,
+        page_manager: SharedPageManager,
         state: &ProcessState,
     ) -> Self {
         let tid: Tid = state.allocate_tid();
@@ -317,6 +797,8 @@ impl ThreadControlBlock {
             status: ThreadStatus::Invalid,
             exit_code: None,
             page_manager,
+            user_stack: None,
+            segments: ThreadSegments::new(),
         }
     }
 
@@ -356,10 +838,29 @@ impl ThreadControlBlock {
             is_kernel: true,
             status: ThreadStatus::Running,
             exit_code: None,
-            page_manager,
+            page_manager: Arc::new(Mutex::new(page_manager)),
+            user_stack: None,
+            segments: ThreadSegments::new(),
         }
     }
 
+    /// Returns the address just past the end of this thread's kernel stack.
+    /// This is where the CPU starts pushing when the thread traps into the
+    /// kernel from user mode.
+    pub fn kernel_stack_top(&self) -> usize {
+        self.kernel_stack.as_ptr() as usize + KERNEL_THREAD_STACK_SIZE
+    }
+
+    /// Returns the user-mode state saved when this thread entered the kernel
+    /// through `syscall_handler`.
+    ///
+    /// # Safety
+    ///
+    /// This thread must currently be handling a syscall made from user mode.
+    pub unsafe fn trap_frame(&self) -> *mut TrapFrame {
+        (self.kernel_stack_top() - size_of::<TrapFrame>()) as *mut TrapFrame
+    }
+
     /// If possible without stack-smashing, moves the stack pointer down and returns the new value.
     fn allocate_stack_space(&mut self, bytes: usize) -> Option<NonNull<u8>> {
         if !self.has_stack_space(bytes) {
@@ -403,14 +904,17 @@ impl ThreadControlBlock {
 
         // Most of the TCB is dropped automatically.
         // But the stack must be manually deallocated.
-        // However, the first TCB is the kernel stack and not treated as such.
-        if self.tid != 0 {
+        // However, the kernel thread runs on the stack it booted with, which
+        // isn't ours to free.
+        if self.kernel_stack != NonNull::dangling() {
+            // SAFETY: We're running on another thread's stack, and the stack
+            // was allocated in `map_stacks`.
+            unsafe { KERNEL_ALLOCATOR.frame_dealloc(self.kernel_stack) };
+            self.kernel_stack = NonNull::dangling();
             self.kernel_stack_pointer = NonNull::dangling();
 
             self.eip = NonNull::dangling();
             self.esp = NonNull::dangling();
-
-            // TODO: drop up alloc'd memory
         }
 
         self.status = ThreadStatus::Invalid;
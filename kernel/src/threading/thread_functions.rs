@@ -1,16 +1,18 @@
 use super::process::Tid;
 use super::thread_control_block::{ThreadControlBlock, ThreadStatus};
+use crate::mem::AllocationCounts;
+use crate::paging::release_address_space;
 use crate::system::unwrap_system;
+use crate::Mutex;
 use crate::{
     interrupts::{intr_disable, intr_enable},
     threading::scheduling::scheduler_yield_and_die,
 };
 use alloc::boxed::Box;
 use core::arch::asm;
-use kidneyos_shared::{
-    global_descriptor_table::{USER_CODE_SELECTOR, USER_DATA_SELECTOR},
-    task_state_segment::TASK_STATE_SEGMENT,
-};
+use core::ptr::NonNull;
+use kidneyos_shared::global_descriptor_table::{USER_CODE_SELECTOR, USER_DATA_SELECTOR};
+use kidneyos_shared::println;
 
 /// TODO: Thread arguments: Usually a void ptr, but Rust won't like that...
 /// No arguments allowed for now.
@@ -36,21 +38,51 @@ pub fn exit_thread(exit_code: i32) -> ! {
     scheduler_yield_and_die();
 }
 
-pub unsafe fn clean_up_thread(mut dying_thread: Box<ThreadControlBlock>) {
-    let threads = &unwrap_system().threads;
+/// How things stood just before init was created, to check that the frames it
+/// and its descendants allocated were freed once they're all gone.
+pub struct BeforeInit {
+    pub counts: AllocationCounts,
+    /// How many processes there were, all of which belong to kernel threads,
+    /// whose processes are never removed from the process table.
+    pub kernel_processes: usize,
+}
 
-    dying_thread.reap();
+pub static BEFORE_INIT: Mutex<Option<BeforeInit>> = Mutex::new(None);
 
-    // Page manager must be loaded to be dropped.
-    dying_thread.page_manager.load();
+pub unsafe fn clean_up_thread(mut dying_thread: Box<ThreadControlBlock>) {
+    // Kernel threads were created before init, so freeing their stacks
+    // mustn't count against the user programs.
+    if dying_thread.is_kernel && dying_thread.kernel_stack != NonNull::dangling() {
+        if let Some(before) = BEFORE_INIT.lock().as_mut() {
+            before.counts.forget_frame_deallocation();
+        }
+    }
+    dying_thread.reap();
+    let pid = dying_thread.pid;
+    let page_manager = dying_thread.page_manager.clone();
     drop(dying_thread);
-    threads
-        .running_thread
-        .lock()
-        .as_ref()
-        .unwrap()
-        .page_manager
-        .load();
+
+    // The running thread's page tables are loaded, so the dying thread's can
+    // be freed if it was the last thread using them.
+    if release_address_space(page_manager) && pid == unwrap_system().process.init_pid() {
+        if let Some(before) = BEFORE_INIT.lock().take() {
+            let (heap, frames) = before.counts.outstanding_since();
+            println!(
+                "init exited with {frames} frame allocations and {heap} heap allocations outstanding since it started"
+            );
+            let processes = unwrap_system().process.table.count();
+            check_frames_freed(frames, processes - before.kernel_processes);
+        }
+    }
+}
+
+/// Panics if `frames` frame allocations are outstanding when there are no user
+/// processes left. Files written by programs stay on the heap, but every frame
+/// a program used must have been given back once it's gone.
+fn check_frames_freed(frames: isize, user_processes: usize) {
+    if user_processes == 0 {
+        assert_eq!(frames, 0, "frames leaked by user programs");
+    }
 }
 
 // Focibly stops the thread specified by Tid
@@ -60,11 +92,11 @@ pub fn stop_thread(tid: Tid) {
     unsafe { clean_up_thread(tcb) };
 }
 
-/// A wrapper function to execute a thread's true function.
-unsafe extern "C" fn run_thread(
+/// Finishes switching to a thread which is being run for the first time.
+unsafe extern "C" fn start_thread(
     switched_from: *mut ThreadControlBlock,
     switched_to: *mut ThreadControlBlock,
-) -> ! {
+) {
     let threads = &unwrap_system().threads;
     let mut switched_to = Box::from_raw(switched_to);
 
@@ -72,15 +104,6 @@ unsafe extern "C" fn run_thread(
     // We must only mark this thread as running.
     switched_to.status = ThreadStatus::Running;
 
-    TASK_STATE_SEGMENT.esp0 = switched_to.kernel_stack.as_ptr() as u32;
-
-    let ThreadControlBlock {
-        eip,
-        esp,
-        is_kernel,
-        ..
-    } = *switched_to;
-
     // Reschedule our threads.
     *threads.running_thread.lock() = Some(switched_to);
 
@@ -91,6 +114,21 @@ unsafe extern "C" fn run_thread(
     } else {
         threads.scheduler.lock().push(switched_from);
     }
+}
+
+/// A wrapper function to execute a thread's true function.
+unsafe extern "C" fn run_thread(
+    switched_from: *mut ThreadControlBlock,
+    switched_to: *mut ThreadControlBlock,
+) -> ! {
+    let ThreadControlBlock {
+        eip,
+        esp,
+        is_kernel,
+        ..
+    } = *switched_to;
+
+    start_thread(switched_from, switched_to);
 
     // Our scheduler will operate without interrupts.
     // Every new thread should start with them enabled.
@@ -109,9 +147,7 @@ unsafe extern "C" fn run_thread(
         asm!(
             "
             mov ds, {data_sel:x}
-            mov es, {data_sel:x}
-            mov fs, {data_sel:x}
-            mov gs, {data_sel:x} // SS and CS are handled by iret
+            mov es, {data_sel:x} // fs and gs were loaded by switch_threads, SS and CS are handled by iret
 
             // Set up the stack frame iret expects.
             push {data_sel:e} // stack segment
@@ -162,6 +198,31 @@ unsafe extern "C" fn prepare_thread() -> i32 {
     )
 }
 
+/// Like `prepare_thread`, but for threads created by fork. These resume in user
+/// mode using the `TrapFrame` directly above their `SwitchThreadsContext`.
+#[naked]
+unsafe extern "C" fn prepare_forked_thread() -> i32 {
+    asm!(
+        r#"
+            push edx
+            push eax
+            call {}
+            add esp, 8
+
+            mov ax, {data_sel}
+            mov ds, ax
+            mov es, ax # fs and gs were loaded by switch_threads, SS and CS are handled by iret
+
+            # The stack pointer now points to the TrapFrame.
+            popa
+            iretd
+        "#,
+        sym start_thread,
+        data_sel = const USER_DATA_SELECTOR,
+        options(noreturn)
+    )
+}
+
 /// The context for a use within context_switch.
 #[repr(C, packed)]
 pub struct SwitchThreadsContext {
@@ -182,4 +243,32 @@ impl SwitchThreadsContext {
             eip: prepare_thread,
         }
     }
+
+    pub fn new_forked() -> Self {
+        Self {
+            eip: prepare_forked_thread,
+            ..Self::new()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_may_be_outstanding_while_user_processes_are_left() {
+        check_frames_freed(3, 1);
+    }
+
+    #[test]
+    fn no_frames_outstanding() {
+        check_frames_freed(0, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "frames leaked by user programs")]
+    fn leaked_frames() {
+        check_frames_freed(1, 0);
+    }
 }
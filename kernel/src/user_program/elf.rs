@@ -166,6 +166,7 @@ pub enum ElfProgramType {
     Interpret,
     Note,
     ProgramHeaderTable,
+    ThreadLocal,
     OsSpecific(u32),
 }
 
@@ -214,6 +215,7 @@ impl<'a> ElfProgramHeader<'a> {
             3 => Some(ElfProgramType::Interpret),
             4 => Some(ElfProgramType::Note),
             6 => Some(ElfProgramType::ProgramHeaderTable),
+            7 => Some(ElfProgramType::ThreadLocal),
             0x60000000.. => Some(ElfProgramType::OsSpecific(value)), // OS Specific Headers
             _ => None,
         })(bytes)?;
@@ -252,6 +254,27 @@ impl<'a> ElfProgramHeader<'a> {
     }
 }
 
+// Dynamic section tags we care about.
+const DT_NULL: u32 = 0;
+const DT_REL: u32 = 17;
+const DT_RELSZ: u32 = 18;
+const DT_RELENT: u32 = 19;
+
+// Relocation types for x86.
+pub const R_386_NONE: u8 = 0;
+pub const R_386_RELATIVE: u8 = 8;
+
+// An entry of a relocation table without addends (Elf32_Rel).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ElfRelocation {
+    // Virtual address of the word to relocate, before the ELF is moved to its base address.
+    pub offset: u32,
+    // Type of relocation, one of the R_386 constants.
+    pub kind: u8,
+    // Index of the symbol in the dynamic symbol table, unused for relative relocations.
+    pub symbol: u32,
+}
+
 #[derive(Clone, Debug)]
 pub struct Elf<'a> {
     // Contains elf metadata.
@@ -296,4 +319,202 @@ impl<'a> Elf<'a> {
     pub fn parse_bytes(bytes: &'a [u8]) -> Result<Elf<'a>, nom::Err<Error<&'a [u8]>>> {
         Ok(Self::parse(bytes)?.1)
     }
+
+    // Path of the program interpreter (dynamic linker) requested by PT_INTERP, if any.
+    pub fn interpreter(&self) -> Option<&'a [u8]> {
+        let header = self
+            .program_headers
+            .iter()
+            .find(|header| header.program_type == ElfProgramType::Interpret)?;
+        // The path is null terminated.
+        let data = header.data;
+        Some(data.split(|&byte| byte == 0).next().unwrap_or(data))
+    }
+
+    // Virtual address at which the program headers are loaded, if they are loaded at all.
+    pub fn program_headers_address(&self) -> Option<u32> {
+        if let Some(header) = self
+            .program_headers
+            .iter()
+            .find(|header| header.program_type == ElfProgramType::ProgramHeaderTable)
+        {
+            return Some(header.virtual_address);
+        }
+
+        let offset = self.header.program_headers_offset;
+        self.program_headers
+            .iter()
+            .filter(|header| header.program_type == ElfProgramType::Load)
+            .find(|header| {
+                let file_end = header.file_offset.saturating_add(header.data.len() as u32);
+                (header.file_offset..file_end).contains(&offset)
+            })
+            .and_then(|header| {
+                header
+                    .virtual_address
+                    .checked_add(offset - header.file_offset)
+            })
+    }
+
+    // Returns `size` bytes of file data loaded at the virtual address `address`.
+    fn data_at(&self, address: u32, size: u32) -> Option<&'a [u8]> {
+        self.program_headers
+            .iter()
+            .filter(|header| header.program_type == ElfProgramType::Load)
+            .find_map(|header| {
+                let start = address.checked_sub(header.virtual_address)? as usize;
+                header.data.get(start..start.checked_add(size as usize)?)
+            })
+    }
+
+    // Parses the relocation table (DT_REL) referenced by the PT_DYNAMIC segment.
+    //  Returns an empty list if the ELF isn't dynamic, and `None` if the tables are malformed.
+    pub fn relocations(&self) -> Option<Vec<ElfRelocation>> {
+        let Some(dynamic) = self
+            .program_headers
+            .iter()
+            .find(|header| header.program_type == ElfProgramType::Dynamic)
+        else {
+            return Some(Vec::new());
+        };
+
+        let endian = self.header.endianness.to_nom();
+        let (mut table, mut table_size, mut entry_size) = (None, 0, 8);
+        let mut bytes = dynamic.data;
+        while !bytes.is_empty() {
+            let (next, tag) = u32::<_, Error<_>>(endian)(bytes).ok()?;
+            let (next, value) = u32::<_, Error<_>>(endian)(next).ok()?;
+            bytes = next;
+            match tag {
+                DT_NULL => break,
+                DT_REL => table = Some(value),
+                DT_RELSZ => table_size = value,
+                DT_RELENT => entry_size = value,
+                _ => {}
+            }
+        }
+
+        let Some(table) = table else {
+            return Some(Vec::new());
+        };
+        if entry_size < 8 {
+            return None;
+        }
+        self.data_at(table, table_size)?
+            .chunks_exact(entry_size as usize)
+            .map(|entry| {
+                let (entry, offset) = u32::<_, Error<_>>(endian)(entry).ok()?;
+                let (_, info) = u32::<_, Error<_>>(endian)(entry).ok()?;
+                Some(ElfRelocation {
+                    offset,
+                    kind: info as u8,
+                    symbol: info >> 8,
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(words: &[u32]) -> Vec<u8> {
+        words.iter().flat_map(|word| word.to_le_bytes()).collect()
+    }
+
+    // A position-independent ELF with an interpreter and two relocations, whose single
+    // segment loads the whole file at address 0x1000.
+    fn dynamic_elf() -> Vec<u8> {
+        const LOAD_ADDRESS: u32 = 0x1000;
+        const DYNAMIC: u32 = 148;
+        const INTERP: u32 = 180;
+        const RELOCATIONS: u32 = 192;
+        const SIZE: u32 = 208;
+
+        let mut elf = vec![0x7F, b'E', b'L', b'F', 1, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        elf.extend([3, 0, 3, 0]); // ET_DYN, EM_386
+        elf.extend(words(&[1, LOAD_ADDRESS + 0x20, 52, 0, 0]));
+        elf.extend([52, 0, 32, 0, 3, 0, 0, 0, 0, 0, 0, 0]);
+        // PT_LOAD, PT_DYNAMIC and PT_INTERP
+        elf.extend(words(&[
+            1,
+            0,
+            LOAD_ADDRESS,
+            LOAD_ADDRESS,
+            SIZE,
+            SIZE,
+            7,
+            0x1000,
+        ]));
+        elf.extend(words(&[
+            2,
+            DYNAMIC,
+            LOAD_ADDRESS + DYNAMIC,
+            0,
+            32,
+            32,
+            6,
+            4,
+        ]));
+        elf.extend(words(&[3, INTERP, LOAD_ADDRESS + INTERP, 0, 11, 11, 4, 1]));
+        assert_eq!(elf.len(), DYNAMIC as usize);
+        elf.extend(words(&[DT_REL, LOAD_ADDRESS + RELOCATIONS, DT_RELSZ, 16]));
+        elf.extend(words(&[DT_RELENT, 8, DT_NULL, 0]));
+        elf.extend(b"/lib/ld.so\0\0");
+        assert_eq!(elf.len(), RELOCATIONS as usize);
+        elf.extend(words(&[LOAD_ADDRESS + 4, R_386_RELATIVE as u32]));
+        elf.extend(words(&[LOAD_ADDRESS + 8, (5 << 8) | 1]));
+        elf
+    }
+
+    #[test]
+    fn interpreter() {
+        let bytes = dynamic_elf();
+        let elf = Elf::parse_bytes(&bytes).unwrap();
+        assert_eq!(elf.header.usage, ElfUsage::Shared);
+        assert_eq!(elf.interpreter(), Some(&b"/lib/ld.so"[..]));
+        assert_eq!(elf.program_headers_address(), Some(0x1000 + 52));
+    }
+
+    #[test]
+    fn program_headers_past_the_end_of_memory() {
+        let mut bytes = dynamic_elf();
+        // Load the segment just below the top of memory, so the program headers
+        // would be past it.
+        bytes[60..64].copy_from_slice(&(u32::MAX - 0x20).to_le_bytes());
+        let elf = Elf::parse_bytes(&bytes).unwrap();
+        assert_eq!(elf.program_headers[0].virtual_address, u32::MAX - 0x20);
+        assert_eq!(elf.program_headers_address(), None);
+    }
+
+    #[test]
+    fn relocations() {
+        let bytes = dynamic_elf();
+        let elf = Elf::parse_bytes(&bytes).unwrap();
+        assert_eq!(
+            elf.relocations(),
+            Some(vec![
+                ElfRelocation {
+                    offset: 0x1004,
+                    kind: R_386_RELATIVE,
+                    symbol: 0,
+                },
+                ElfRelocation {
+                    offset: 0x1008,
+                    kind: 1,
+                    symbol: 5,
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn relocations_outside_segments_are_malformed() {
+        let mut bytes = dynamic_elf();
+        // Point DT_REL past the end of the loaded data.
+        bytes[152..156].copy_from_slice(&0x2000u32.to_le_bytes());
+        let elf = Elf::parse_bytes(&bytes).unwrap();
+        assert_eq!(elf.relocations(), None);
+    }
 }
@@ -0,0 +1,160 @@
+// Ordinarily, a function dereferencing a raw pointer argument almost always requires it to be unsafe.
+// Here we should be fine since we are checking the validity of pointers.
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+
+use crate::interrupts::mutex_irq::hold_interrupts;
+use crate::interrupts::{timer, IntrLevel};
+use crate::mem::util::get_ref_from_user_space;
+use crate::paging::handle_copy_on_write_fault;
+use crate::system::{running_process, running_thread_pid, running_thread_tid, unwrap_system};
+use crate::threading::process::{Pid, Tid};
+use crate::threading::thread_sleep::{thread_sleep, thread_wakeup};
+use crate::user_program::syscall::{
+    EAGAIN, EFAULT, EINTR, EINVAL, ENOSYS, ETIMEDOUT, FUTEX_PRIVATE_FLAG, FUTEX_WAIT, FUTEX_WAKE,
+};
+use crate::user_program::time::Timespec;
+use crate::Mutex;
+use alloc::collections::{BTreeMap, VecDeque};
+use core::mem::size_of;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+/// Identifies the futex a thread is waiting on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum FutexKey {
+    /// A futex which is only used within one process (`FUTEX_PRIVATE_FLAG`),
+    /// by its virtual address. Its frame can change under its waiters, such as
+    /// when another thread forks and makes the page copy-on-write again.
+    Private { pid: Pid, uaddr: usize },
+    /// Any other futex, by its physical address, so that futexes also work
+    /// between processes which map the same memory at different addresses.
+    Shared(usize),
+}
+
+/// Threads blocked in FUTEX_WAIT, keyed by the futex they're waiting on.
+static WAIT_QUEUES: Mutex<BTreeMap<FutexKey, VecDeque<Tid>>> = Mutex::new(BTreeMap::new());
+
+pub fn futex(uaddr: usize, op: i32, val: u32, timeout: *const Timespec) -> isize {
+    if uaddr % size_of::<u32>() != 0 {
+        return -EINVAL;
+    }
+    let private = (op & FUTEX_PRIVATE_FLAG) != 0;
+    match op & !FUTEX_PRIVATE_FLAG {
+        FUTEX_WAIT => wait(uaddr, private, val, timeout),
+        FUTEX_WAKE => wake(uaddr, private, val),
+        _ => -ENOSYS,
+    }
+}
+
+/// Returns the futex at `uaddr` along with its key in `WAIT_QUEUES`.
+fn get_futex(uaddr: usize, private: bool) -> Option<(FutexKey, &'static AtomicU32)> {
+    running_process()
+        .lock()
+        .vmas
+        .install_range(uaddr, size_of::<u32>());
+    let futex = unsafe { get_ref_from_user_space(uaddr as *const AtomicU32) }?;
+    if private {
+        let pid = running_thread_pid();
+        return Some((FutexKey::Private { pid, uaddr }, futex));
+    }
+
+    // A copy-on-write page gets a new frame as soon as it's written to, which
+    // would change the futex's key from under its waiters, so we make the copy
+    // up front.
+    // SAFETY: Any user-space futex may be written to, and nothing happens if
+    // the page isn't copy-on-write.
+    let _ = unsafe { handle_copy_on_write_fault(uaddr) };
+
+    let (key, _flags) = unwrap_system()
+        .threads
+        .running_thread
+        .lock()
+        .as_ref()
+        .expect("no running thread")
+        .page_manager
+        .lock()
+        .translate(uaddr)?;
+    Some((FutexKey::Shared(key), futex))
+}
+
+/// Block until the futex at `uaddr` is woken, if it still contains `val`.
+fn wait(uaddr: usize, private: bool, val: u32, timeout: *const Timespec) -> isize {
+    let timeout = if timeout.is_null() {
+        None
+    } else {
+        let Some(timeout) = (unsafe { get_ref_from_user_space(timeout) }) else {
+            return -EFAULT;
+        };
+        match timeout.to_duration() {
+            Some(timeout) => Some(timeout),
+            None => return -EINVAL,
+        }
+    };
+    let Some((key, futex)) = get_futex(uaddr, private) else {
+        return -EFAULT;
+    };
+    let tid = running_thread_tid();
+    let pcb = running_process();
+
+    // Interrupts must stay off from checking the futex's value until we're
+    // blocked, so that a FUTEX_WAKE can't slip in between.
+    let _guard = hold_interrupts(IntrLevel::IntrOff);
+    if futex.load(Ordering::SeqCst) != val {
+        return -EAGAIN;
+    }
+    if pcb.lock().signals.has_deliverable() {
+        return -EINTR;
+    }
+
+    WAIT_QUEUES.lock().entry(key).or_default().push_back(tid);
+    let timed_out = match timeout {
+        Some(timeout) => timer::sleep_interruptible(timeout).is_zero(),
+        None => {
+            thread_sleep();
+            false
+        }
+    };
+
+    // We're only still in the queue if something other than FUTEX_WAKE woke
+    // us up.
+    let mut queues = WAIT_QUEUES.lock();
+    let Some(queue) = queues.get_mut(&key) else {
+        return 0;
+    };
+    let Some(index) = queue.iter().position(|waiter| *waiter == tid) else {
+        return 0;
+    };
+    queue.remove(index);
+    if queue.is_empty() {
+        queues.remove(&key);
+    }
+    drop(queues);
+
+    if pcb.lock().signals.has_deliverable() {
+        -EINTR
+    } else if timed_out {
+        -ETIMEDOUT
+    } else {
+        0
+    }
+}
+
+/// Wake up to `count` threads waiting on the futex at `uaddr`, returning how
+/// many were woken.
+fn wake(uaddr: usize, private: bool, count: u32) -> isize {
+    let Some((key, _)) = get_futex(uaddr, private) else {
+        return -EFAULT;
+    };
+
+    let mut queues = WAIT_QUEUES.lock();
+    let Some(queue) = queues.get_mut(&key) else {
+        return 0;
+    };
+    let woken = queue.len().min(count as usize);
+    for tid in queue.drain(..woken) {
+        thread_wakeup(tid);
+    }
+    if queue.is_empty() {
+        queues.remove(&key);
+    }
+    woken as isize
+}
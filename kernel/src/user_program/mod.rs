@@ -1,4 +1,8 @@
 pub mod elf;
+pub mod futex;
 pub mod random;
+pub mod signal;
+pub mod stack;
 pub mod syscall;
 pub mod time;
+pub mod tls;
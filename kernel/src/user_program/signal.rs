@@ -0,0 +1,535 @@
+// Ordinarily, a function dereferencing a raw pointer argument almost always requires it to be unsafe.
+// Here we should be fine since we are checking the validity of pointers.
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+
+use crate::interrupts::mutex_irq::hold_interrupts;
+use crate::interrupts::trap_frame::TrapFrame;
+use crate::interrupts::IntrLevel;
+use crate::mem::util::{get_mut_from_user_space, get_ref_from_user_space};
+use crate::system::{running_process, unwrap_system};
+use crate::threading::process::Pid;
+use crate::threading::process_functions::terminate_process;
+use crate::threading::thread_control_block::{ExitStatus, ProcessControlBlock};
+use crate::threading::thread_sleep::{thread_sleep, thread_wakeup};
+use crate::user_program::stack::STACK_ALIGNMENT;
+use crate::user_program::syscall::{
+    SigAction, SigSet, EFAULT, EINVAL, EPERM, ESRCH, NSIG, RT_SIGSET_SIZE, SA_NODEFER,
+    SA_RESETHAND, SA_RESTORER, SIGCHLD, SIGCONT, SIGKILL, SIGSEGV, SIGSTOP, SIGTSTP, SIGTTIN,
+    SIGTTOU, SIG_BLOCK, SIG_DFL, SIG_IGN, SIG_SETMASK, SIG_UNBLOCK, SYS_SIGRETURN,
+};
+use crate::Mutex;
+use alloc::vec::Vec;
+use core::mem::{offset_of, size_of};
+
+/// The set containing only `signal`.
+const fn sigset(signal: i32) -> SigSet {
+    1 << (signal - 1)
+}
+
+/// Signals which can't be blocked, ignored or handled.
+const UNBLOCKABLE: SigSet = sigset(SIGKILL) | sigset(SIGSTOP);
+const STOP_SIGNALS: SigSet = sigset(SIGSTOP) | sigset(SIGTSTP) | sigset(SIGTTIN) | sigset(SIGTTOU);
+
+/// The EFLAGS bits a signal handler is allowed to change before returning
+/// with sigreturn: CF, PF, AF, ZF, SF, DF and OF.
+const USER_EFLAGS: u32 = 0xcd5;
+const EFLAGS_DF: u32 = 0x400;
+
+/// What happens to a process when it receives a signal without a handler.
+enum DefaultAction {
+    Terminate,
+    Ignore,
+    Stop,
+    Continue,
+}
+
+fn default_action(signal: i32) -> DefaultAction {
+    match signal {
+        SIGCHLD => DefaultAction::Ignore,
+        SIGCONT => DefaultAction::Continue,
+        SIGSTOP | SIGTSTP | SIGTTIN | SIGTTOU => DefaultAction::Stop,
+        _ => DefaultAction::Terminate,
+    }
+}
+
+/// The signal-related state of a process.
+#[derive(Clone, Debug, Default)]
+pub struct SignalState {
+    /// Signals which have been sent to the process, but not acted on yet.
+    pub pending: SigSet,
+    /// Signals which won't be acted on until they're unblocked.
+    pub blocked: SigSet,
+    /// What to do for each signal, indexed by signal number.
+    pub actions: [SigAction; NSIG as usize],
+    /// Whether the process has been stopped, and is waiting for SIGCONT.
+    pub stopped: bool,
+}
+
+impl SignalState {
+    /// The signal state of a child forked from this process.
+    pub fn fork(&self) -> Self {
+        Self {
+            pending: 0,
+            stopped: false,
+            ..self.clone()
+        }
+    }
+
+    /// Update the signal state for a process replacing its program. Handlers
+    /// are reset since they don't exist in the new program.
+    pub fn exec(&mut self) {
+        for action in &mut self.actions {
+            if action.handler != SIG_IGN {
+                *action = SigAction::default();
+            }
+        }
+    }
+
+    /// Whether any signals are waiting to be acted on.
+    pub fn has_deliverable(&self) -> bool {
+        self.pending & !self.blocked != 0
+    }
+
+    /// Whether `signal` would be discarded if it were delivered now.
+    fn ignores(&self, signal: i32) -> bool {
+        match self.actions[signal as usize].handler {
+            SIG_IGN => true,
+            SIG_DFL => matches!(
+                default_action(signal),
+                DefaultAction::Ignore | DefaultAction::Continue
+            ),
+            _ => false,
+        }
+    }
+
+    /// Sends `signal` to the process.
+    ///
+    /// Returns whether the process' threads should be woken up, either to act
+    /// on the signal or because the process was continued.
+    pub fn send(&mut self, signal: i32) -> bool {
+        let mut wake = false;
+        // Stopping and continuing cancel each other out, and take effect as
+        // soon as they're sent.
+        if signal == SIGCONT || signal == SIGKILL {
+            self.pending &= !STOP_SIGNALS;
+            wake = self.stopped;
+            self.stopped = false;
+        }
+        if STOP_SIGNALS & sigset(signal) != 0 {
+            self.pending &= !sigset(SIGCONT);
+        }
+
+        if self.ignores(signal) {
+            return wake;
+        }
+        self.pending |= sigset(signal);
+        wake || self.blocked & sigset(signal) == 0
+    }
+}
+
+/// What a signal handler finds on its stack when it starts running.
+#[repr(C)]
+struct SignalFrame {
+    /// Where the handler returns to, which makes the sigreturn syscall.
+    return_address: usize,
+    /// The argument to the handler.
+    signal: i32,
+    /// The state to resume the program with after the handler returns.
+    trap_frame: TrapFrame,
+    /// The signal mask to restore after the handler returns.
+    blocked: SigSet,
+    /// `mov eax, SYS_SIGRETURN; int 0x80`, for programs which don't provide
+    /// their own restorer.
+    trampoline: [u8; 8],
+}
+
+const TRAMPOLINE: [u8; 8] = [0xb8, SYS_SIGRETURN as u8, 0, 0, 0, 0xcd, 0x80, 0x90];
+
+/// Acts on the running process' pending signals before it returns to user
+/// mode with the registers in `trap_frame`.
+///
+/// Signals are either handled by their default action, or by changing
+/// `trap_frame` to run the program's handler for them.
+pub extern "C" fn handle_pending_signals(trap_frame: &mut TrapFrame) {
+    // Only act on signals when we're actually returning to user mode.
+    if trap_frame.cs & 3 != 3 {
+        return;
+    }
+    // Special characters typed at the terminal can't send their signals from
+    // the keyboard interrupt handler, so they're sent here.
+    unwrap_system().tty.send_signals();
+
+    let pcb_ref = running_process();
+    loop {
+        let mut pcb = pcb_ref.lock();
+        let deliverable = pcb.signals.pending & !pcb.signals.blocked;
+        if deliverable == 0 {
+            return;
+        }
+        let signal = deliverable.trailing_zeros() as i32 + 1;
+        pcb.signals.pending &= !sigset(signal);
+
+        let action = pcb.signals.actions[signal as usize];
+        match action.handler {
+            SIG_IGN => {}
+            SIG_DFL => match default_action(signal) {
+                DefaultAction::Ignore | DefaultAction::Continue => {}
+                DefaultAction::Terminate => {
+                    drop(pcb);
+                    terminate_process(ExitStatus::Signaled(signal));
+                }
+                DefaultAction::Stop => {
+                    pcb.signals.stopped = true;
+                    drop(pcb);
+                    wait_while_stopped(&pcb_ref);
+                }
+            },
+            _ => {
+                let blocked = pcb.signals.blocked;
+                if action.flags & SA_NODEFER == 0 {
+                    pcb.signals.blocked |= sigset(signal);
+                }
+                pcb.signals.blocked |= action.mask & !UNBLOCKABLE;
+                if action.flags & SA_RESETHAND != 0 {
+                    pcb.signals.actions[signal as usize] = SigAction::default();
+                }
+
+                if !push_signal_frame(&pcb, trap_frame, signal, &action, blocked) {
+                    drop(pcb);
+                    terminate_process(ExitStatus::Signaled(SIGSEGV));
+                }
+                // Any other signals will be handled once this handler returns.
+                return;
+            }
+        }
+    }
+}
+
+/// Blocks the running thread until its process is continued.
+fn wait_while_stopped(pcb: &Mutex<ProcessControlBlock>) {
+    loop {
+        // Keep interrupts off until we're blocked, so that we can't miss the
+        // wakeup from SIGCONT.
+        let _guard = hold_interrupts(IntrLevel::IntrOff);
+        if !pcb.lock().signals.stopped {
+            return;
+        }
+        thread_sleep();
+    }
+}
+
+/// Builds a `SignalFrame` below the user stack pointer in `trap_frame`, and
+/// changes `trap_frame` to enter `action`'s handler with it.
+///
+/// Returns `false` if the frame couldn't be written to the stack.
+fn push_signal_frame(
+    pcb: &ProcessControlBlock,
+    trap_frame: &mut TrapFrame,
+    signal: i32,
+    action: &SigAction,
+    blocked: SigSet,
+) -> bool {
+    let Some(below_frame) = (trap_frame.esp as usize).checked_sub(size_of::<SignalFrame>()) else {
+        return false;
+    };
+    // Align the stack as though the handler had just been called normally.
+    let Some(frame_addr) =
+        (below_frame & !(STACK_ALIGNMENT - 1)).checked_sub(offset_of!(SignalFrame, signal))
+    else {
+        return false;
+    };
+    pcb.vmas.install_range(frame_addr, size_of::<SignalFrame>());
+    let Some(frame) = (unsafe { get_mut_from_user_space(frame_addr as *mut SignalFrame) }) else {
+        return false;
+    };
+
+    let return_address = if action.flags & SA_RESTORER != 0 {
+        action.restorer
+    } else {
+        frame_addr + offset_of!(SignalFrame, trampoline)
+    };
+    *frame = SignalFrame {
+        return_address,
+        signal,
+        trap_frame: *trap_frame,
+        blocked,
+        trampoline: TRAMPOLINE,
+    };
+
+    trap_frame.eip = action.handler as u32;
+    trap_frame.esp = frame_addr as u32;
+    trap_frame.eflags &= !EFLAGS_DF;
+    true
+}
+
+pub fn kill(pid: i32, signal: i32) -> isize {
+    if !(0..NSIG).contains(&signal) {
+        return -EINVAL;
+    }
+    // A pid of 0 means the caller's process group, and -pgid the process
+    // group pgid.
+    if pid <= 0 {
+        let pgid = match pid {
+            0 => running_process().lock().pgid,
+            // Signalling every process isn't supported.
+            -1 => return -EINVAL,
+            _ => match pid.checked_neg().and_then(|pgid| Pid::try_from(pgid).ok()) {
+                Some(pgid) => pgid,
+                None => return -ESRCH,
+            },
+        };
+        // Signal 0 only checks whether the group exists.
+        let sent = if signal == 0 {
+            !unwrap_system().process.table.group(pgid).is_empty()
+        } else {
+            send_group_signal(pgid, signal)
+        };
+        return if sent { 0 } else { -ESRCH };
+    }
+    let Some(pcb) = Pid::try_from(pid)
+        .ok()
+        .and_then(|pid| unwrap_system().process.table.get(pid))
+    else {
+        return -ESRCH;
+    };
+    // Signal 0 only checks whether the process exists.
+    if signal != 0 {
+        send_signal(&pcb, signal);
+    }
+    0
+}
+
+/// Sends `signal` to every process in the process group `pgid`. Returns
+/// whether there were any.
+pub fn send_group_signal(pgid: Pid, signal: i32) -> bool {
+    let group = unwrap_system().process.table.group(pgid);
+    for pcb in &group {
+        send_signal(pcb, signal);
+    }
+    !group.is_empty()
+}
+
+/// Moves the process `pid` into the process group `pgid`. If `pid` is 0, it's
+/// the caller, and if `pgid` is 0, it's a new group led by `pid`.
+///
+/// Only the caller and its children can be moved, and only into a new group
+/// or one which already exists.
+pub fn setpgid(pid: i32, pgid: i32) -> isize {
+    let (Ok(pid), Ok(pgid)) = (Pid::try_from(pid), Pid::try_from(pgid)) else {
+        return -EINVAL;
+    };
+    let caller = running_process();
+    let (caller_pid, children) = {
+        let caller = caller.lock();
+        (caller.pid, caller.children.clone())
+    };
+    let pid = if pid == 0 { caller_pid } else { pid };
+    let pgid = if pgid == 0 { pid } else { pgid };
+    if pid != caller_pid && !children.contains(&pid) {
+        return -ESRCH;
+    }
+    let table = &unwrap_system().process.table;
+    let Some(pcb) = table.get(pid) else {
+        return -ESRCH;
+    };
+    if pgid != pid && table.group(pgid).is_empty() {
+        return -EPERM;
+    }
+    pcb.lock().pgid = pgid;
+    0
+}
+
+/// The process group of the process `pid`, or of the caller if it's 0.
+pub fn getpgid(pid: i32) -> isize {
+    let pcb = match pid {
+        0 => Some(running_process()),
+        _ => Pid::try_from(pid)
+            .ok()
+            .and_then(|pid| unwrap_system().process.table.get(pid)),
+    };
+    match pcb {
+        Some(pcb) => pcb.lock().pgid as isize,
+        None => -ESRCH,
+    }
+}
+
+pub fn send_signal(pcb: &Mutex<ProcessControlBlock>, signal: i32) {
+    let mut pcb = pcb.lock();
+    if pcb.exit_status.is_some() {
+        return;
+    }
+    let tids = if pcb.signals.send(signal) {
+        pcb.child_tids.clone()
+    } else {
+        Vec::new()
+    };
+    drop(pcb);
+
+    // Interrupt whatever the process' threads were waiting for, so they can
+    // act on the signal.
+    for tid in tids {
+        thread_wakeup(tid);
+    }
+}
+
+pub fn sigaction(signal: i32, action: *const SigAction, old_action: *mut SigAction) -> isize {
+    if !(1..NSIG).contains(&signal) {
+        return -EINVAL;
+    }
+    let action = if action.is_null() {
+        None
+    } else {
+        match unsafe { get_ref_from_user_space(action) } {
+            Some(action) => Some(*action),
+            None => return -EFAULT,
+        }
+    };
+    if action.is_some() && UNBLOCKABLE & sigset(signal) != 0 {
+        return -EINVAL;
+    }
+
+    let pcb = running_process();
+    let mut pcb = pcb.lock();
+    let previous = pcb.signals.actions[signal as usize];
+    if let Some(action) = action {
+        pcb.signals.actions[signal as usize] = action;
+        if pcb.signals.ignores(signal) {
+            pcb.signals.pending &= !sigset(signal);
+        }
+    }
+    drop(pcb);
+
+    if !old_action.is_null() {
+        let Some(old_action) = (unsafe { get_mut_from_user_space(old_action) }) else {
+            return -EFAULT;
+        };
+        *old_action = previous;
+    }
+    0
+}
+
+pub fn sigprocmask(how: i32, set: *const SigSet, old_set: *mut SigSet) -> isize {
+    let set = if set.is_null() {
+        None
+    } else {
+        match unsafe { get_ref_from_user_space(set) } {
+            Some(set) => Some(*set),
+            None => return -EFAULT,
+        }
+    };
+
+    let pcb = running_process();
+    let mut pcb = pcb.lock();
+    let previous = pcb.signals.blocked;
+    if let Some(set) = set {
+        let blocked = match how {
+            SIG_BLOCK => previous | set,
+            SIG_UNBLOCK => previous & !set,
+            SIG_SETMASK => set,
+            _ => return -EINVAL,
+        };
+        pcb.signals.blocked = blocked & !UNBLOCKABLE;
+    }
+    drop(pcb);
+
+    if !old_set.is_null() {
+        let Some(old_set) = (unsafe { get_mut_from_user_space(old_set) }) else {
+            return -EFAULT;
+        };
+        *old_set = previous;
+    }
+    0
+}
+
+/// Linux's version of `sigprocmask`, whose sets are `size` bytes long. Only
+/// their first `SigSet` is used.
+pub fn rt_sigprocmask(how: i32, set: *const SigSet, old_set: *mut SigSet, size: usize) -> isize {
+    if size != RT_SIGSET_SIZE {
+        return -EINVAL;
+    }
+    let result = sigprocmask(how, set, old_set);
+    if result == 0 && !old_set.is_null() {
+        let Some(old_set) = (unsafe { get_mut_from_user_space(old_set.cast::<[SigSet; 2]>()) })
+        else {
+            return -EFAULT;
+        };
+        old_set[1] = 0;
+    }
+    result
+}
+
+/// Resumes the program from before its signal handler was entered, using the
+/// `SignalFrame` the handler has just returned from.
+///
+/// Returns the value of eax to resume with.
+pub fn sigreturn() -> isize {
+    let guard = unwrap_system().threads.running_thread.lock();
+    // SAFETY: We're handling the sigreturn syscall from user mode.
+    let trap_frame = unsafe { &mut *guard.as_ref().expect("no running thread").trap_frame() };
+    drop(guard);
+
+    // The handler's return popped `return_address` off the stack.
+    let frame_addr = (trap_frame.esp as usize).wrapping_sub(offset_of!(SignalFrame, signal));
+    let Some(frame) = (unsafe { get_ref_from_user_space(frame_addr as *const SignalFrame) }) else {
+        terminate_process(ExitStatus::Signaled(SIGSEGV));
+    };
+    let saved = frame.trap_frame;
+    running_process().lock().signals.blocked = frame.blocked & !UNBLOCKABLE;
+
+    // Don't let the program give itself kernel segments or privileged flags.
+    *trap_frame = TrapFrame {
+        cs: trap_frame.cs,
+        ss: trap_frame.ss,
+        eflags: (trap_frame.eflags & !USER_EFLAGS) | (saved.eflags & USER_EFLAGS),
+        ..saved
+    };
+    saved.eax as isize
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::user_program::syscall::{SIGINT, SIGTERM};
+
+    #[test]
+    fn send_marks_pending() {
+        let mut signals = SignalState::default();
+        assert!(signals.send(SIGTERM));
+        assert_eq!(signals.pending, sigset(SIGTERM));
+
+        signals.blocked = sigset(SIGINT);
+        assert!(!signals.send(SIGINT));
+        assert_eq!(signals.pending, sigset(SIGTERM) | sigset(SIGINT));
+    }
+
+    #[test]
+    fn send_discards_ignored() {
+        let mut signals = SignalState::default();
+        signals.actions[SIGINT as usize].handler = SIG_IGN;
+        assert!(!signals.send(SIGINT));
+        assert!(!signals.send(SIGCHLD));
+        assert_eq!(signals.pending, 0);
+    }
+
+    #[test]
+    fn continue_cancels_stop() {
+        let mut signals = SignalState::default();
+        signals.send(SIGTSTP);
+        assert_eq!(signals.pending, sigset(SIGTSTP));
+        signals.stopped = true;
+
+        assert!(signals.send(SIGCONT));
+        assert!(!signals.stopped);
+        assert_eq!(signals.pending, 0);
+    }
+
+    #[test]
+    fn exec_keeps_ignored_signals() {
+        let mut signals = SignalState::default();
+        signals.actions[SIGINT as usize].handler = SIG_IGN;
+        signals.actions[SIGTERM as usize].handler = 0x1000;
+        signals.exec();
+        assert_eq!(signals.actions[SIGINT as usize].handler, SIG_IGN);
+        assert_eq!(signals.actions[SIGTERM as usize].handler, SIG_DFL);
+    }
+}
@@ -0,0 +1,113 @@
+// https://refspecs.linuxfoundation.org/elf/abi386-4.pdf (Figure 3-31: Initial Process Stack)
+
+use crate::paging::PageManager;
+use crate::threading::thread_control_block::{USER_STACK_BOTTOM_VIRT, USER_THREAD_STACK_SIZE};
+use crate::KERNEL_ALLOCATOR;
+use alloc::{vec, vec::Vec};
+use core::mem::size_of;
+use core::ptr::{copy_nonoverlapping, NonNull};
+use kidneyos_shared::mem::{OFFSET, PAGE_FRAME_SIZE};
+use kidneyos_syscalls::defs::AT_NULL;
+
+/// The maximum number of bytes the arguments, environment and auxiliary vector
+/// may take up on the stack of a new program.
+pub const ARG_MAX: usize = 32 * PAGE_FRAME_SIZE;
+
+/// The stack pointer must be aligned to this many bytes when a program starts.
+pub const STACK_ALIGNMENT: usize = 16;
+
+#[derive(Debug)]
+pub enum StackSetupError {
+    ArgumentListTooLong,
+    OutOfMemory,
+}
+
+/// Maps the top of the user stack into `page_manager`, and lays out `argv`,
+/// `envp` and `auxv` on it the way `_start` expects to find them:
+///
+/// ```text
+/// argc
+/// argv[0..argc], NULL
+/// envp[..], NULL
+/// auxv[..], (AT_NULL, 0)
+/// ...
+/// argument and environment strings
+/// ```
+///
+/// Returns the initial stack pointer, which points to `argc`.
+pub fn setup_user_stack(
+    page_manager: &mut PageManager,
+    argv: &[Vec<u8>],
+    envp: &[Vec<u8>],
+    auxv: &[(usize, usize)],
+) -> Result<NonNull<u8>, StackSetupError> {
+    let strings_size: usize = argv.iter().chain(envp).map(|s| s.len() + 1).sum();
+    // argc, argv + NULL, envp + NULL, auxv + AT_NULL
+    let vector_words = 1 + (argv.len() + 1) + (envp.len() + 1) + 2 * (auxv.len() + 1);
+    let total_size = strings_size + vector_words * size_of::<usize>() + STACK_ALIGNMENT;
+    if total_size > ARG_MAX {
+        return Err(StackSetupError::ArgumentListTooLong);
+    }
+
+    let stack_top = USER_STACK_BOTTOM_VIRT + USER_THREAD_STACK_SIZE;
+    let frames = total_size.div_ceil(PAGE_FRAME_SIZE);
+    let region_start = stack_top - frames * PAGE_FRAME_SIZE;
+
+    // Lay the region out in a buffer first, then copy it into frames which are
+    // allocated one at a time like every other user page.
+    let mut region = vec![0u8; frames * PAGE_FRAME_SIZE];
+    let region_ptr = region.as_mut_ptr();
+    let kernel_ptr = |user_addr: usize| unsafe { region_ptr.add(user_addr - region_start) };
+
+    // Copy the strings to the very top of the stack.
+    let mut string_addr = stack_top - strings_size;
+    let mut copy_string = |string: &Vec<u8>| {
+        let addr = string_addr;
+        unsafe {
+            copy_nonoverlapping(string.as_ptr(), kernel_ptr(addr), string.len());
+            // The null terminator is already there since the region was zeroed.
+        }
+        string_addr += string.len() + 1;
+        addr
+    };
+    let argv_addrs: Vec<usize> = argv.iter().map(&mut copy_string).collect();
+    let envp_addrs: Vec<usize> = envp.iter().map(&mut copy_string).collect();
+
+    // Then the vectors of pointers below them.
+    let esp =
+        (stack_top - strings_size - vector_words * size_of::<usize>()) & !(STACK_ALIGNMENT - 1);
+    let words = core::iter::once(argv.len())
+        .chain(argv_addrs)
+        .chain([0])
+        .chain(envp_addrs)
+        .chain([0])
+        .chain(auxv.iter().flat_map(|&(key, value)| [key, value]))
+        .chain([AT_NULL, 0]);
+    for (i, word) in words.enumerate() {
+        unsafe {
+            kernel_ptr(esp + i * size_of::<usize>())
+                .cast::<usize>()
+                .write(word)
+        };
+    }
+
+    // The page manager isn't loaded, so we write through the kernel's mapping
+    // of the frames instead.
+    for (i, page) in region.chunks(PAGE_FRAME_SIZE).enumerate() {
+        unsafe {
+            let frame = KERNEL_ALLOCATOR
+                .frame_alloc(1)
+                .map_err(|_| StackSetupError::OutOfMemory)?
+                .as_ptr();
+            copy_nonoverlapping(page.as_ptr(), frame, PAGE_FRAME_SIZE);
+            page_manager.map(
+                frame as usize - OFFSET,
+                region_start + i * PAGE_FRAME_SIZE,
+                true,
+                true,
+            );
+        }
+    }
+
+    Ok(NonNull::new(esp as *mut u8).expect("stack pointer should be non-null"))
+}
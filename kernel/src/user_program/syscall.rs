@@ -1,24 +1,31 @@
 // https://docs.google.com/document/d/1qMMU73HW541wME00Ngl79ou-kQ23zzTlGXJYo9FNh5M
 
+use crate::fs::poll;
 use crate::fs::read_file;
+use crate::fs::socket;
 use crate::fs::syscalls::{
-    chdir, close, dup, dup2, fstat, ftruncate, getcwd, getdents, link, lseek64, mkdir, mmap, mount,
-    open, pipe, read, rename, rmdir, symlink, sync, unlink, unmount, write,
+    chdir, close, dup, dup2, fcntl, fstat, ftruncate, getcwd, getdents, ioctl, link, lseek64,
+    mkdir, mknod, mmap, mount, munmap, open, pipe, pread64, pwrite64, read, readv, rename, rmdir,
+    symlink, sync, unlink, unmount, write, writev,
 };
-use crate::interrupts::{intr_disable, intr_enable};
+use crate::interrupts::timer;
+use crate::mem::brk::brk;
 use crate::mem::util::{
-    get_cstr_from_user_space, get_mut_from_user_space, get_ref_from_user_space, CStrError,
+    copy_cstr_array_from_user_space, get_cstr_from_user_space, get_mut_from_user_space,
+    get_ref_from_user_space, CStrArrayError, CStrError,
 };
-use crate::system::{running_thread_pid, running_thread_ppid, running_thread_tid, unwrap_system};
-use crate::threading::process::Pid;
-use crate::threading::process_functions;
-use crate::threading::scheduling::{scheduler_yield_and_continue, scheduler_yield_and_die};
-use crate::threading::thread_control_block::ThreadControlBlock;
-use crate::threading::thread_sleep::thread_sleep;
+use crate::system::{running_process, running_thread_pid, running_thread_ppid, running_thread_tid};
+use crate::threading::process::{Pid, Tid};
+use crate::threading::process_functions::{self, JoinError, ThreadCreateError, WaitError};
+use crate::threading::scheduling::scheduler_yield_and_continue;
+use crate::threading::thread_control_block::ThreadElfCreateError;
 use crate::user_program::elf::Elf;
+use crate::user_program::futex;
 use crate::user_program::random::getrandom;
+use crate::user_program::signal;
+use crate::user_program::stack::ARG_MAX;
 use crate::user_program::time::{get_rtc, get_tsc, Timespec, CLOCK_MONOTONIC, CLOCK_REALTIME};
-use alloc::boxed::Box;
+use crate::user_program::tls;
 use core::slice::from_raw_parts_mut;
 use kidneyos_shared::println;
 pub use kidneyos_syscalls::defs::*;
@@ -26,8 +33,21 @@ pub use kidneyos_syscalls::defs::*;
 /// This function is responsible for processing syscalls made by user programs.
 /// Its return value is the syscall return value, whose meaning depends on the syscall.
 /// It might not actually return sometimes, such as when the syscall is exit.
-pub extern "C" fn handler(syscall_number: usize, arg0: usize, arg1: usize, arg2: usize) -> isize {
-    println!("syscall number {syscall_number:#X} with arguments: {arg0:#X} {arg1:#X} {arg2:#X}");
+///
+/// Programs pass the syscall number in eax and its arguments in ebx, ecx, edx,
+/// esi, edi and ebp, in that order, as they do on Linux.
+pub extern "C" fn handler(
+    syscall_number: usize,
+    arg0: usize,
+    arg1: usize,
+    arg2: usize,
+    arg3: usize,
+    arg4: usize,
+    arg5: usize,
+) -> isize {
+    println!(
+        "syscall number {syscall_number:#X} with arguments: {arg0:#X} {arg1:#X} {arg2:#X} {arg3:#X} {arg4:#X} {arg5:#X}"
+    );
     // TODO: Start implementing this by branching on syscall_number.
     // Add todo!()'s for any syscalls that aren't implemented.
     // Return an error if an invalid syscall number is provided.
@@ -36,17 +56,32 @@ pub extern "C" fn handler(syscall_number: usize, arg0: usize, arg1: usize, arg2:
         SYS_EXIT => {
             process_functions::exit_process(arg0 as i32);
         }
-        SYS_FORK => {
-            todo!("fork syscall")
+        // Our SYS_EXIT already ends the whole process.
+        SYS_EXIT_GROUP => {
+            process_functions::exit_process(arg0 as i32);
         }
+        SYS_FORK => match process_functions::fork_process() {
+            Ok(pid) => pid as isize,
+            Err(e) => -e.to_isize(),
+        },
         SYS_OPEN => open(arg0 as _, arg1),
         SYS_READ => read(arg0, arg1 as _, arg2 as _),
         SYS_WRITE => write(arg0, arg1 as _, arg2 as _),
+        SYS_READV => readv(arg0, arg1 as _, arg2),
+        SYS_WRITEV => writev(arg0, arg1 as _, arg2),
+        // The offset is split across two registers, low half first.
+        SYS_PREAD64 => pread64(arg0, arg1 as _, arg2, split_i64(arg3, arg4)),
+        SYS_PWRITE64 => pwrite64(arg0, arg1 as _, arg2, split_i64(arg3, arg4)),
+        SYS_IOCTL => ioctl(arg0, arg1, arg2),
+        SYS_FCNTL | SYS_FCNTL64 => fcntl(arg0, arg1 as _, arg2),
+        SYS_POLL => poll::poll(arg0 as _, arg1, arg2 as i32),
+        SYS_SELECT => poll::select(arg0 as i32, arg1 as _, arg2 as _, arg3 as _, arg4 as _),
         SYS_LSEEK64 => lseek64(arg0, arg1 as _, arg2 as _),
         SYS_CLOSE => close(arg0),
         SYS_CHDIR => chdir(arg0 as _),
         SYS_GETCWD => getcwd(arg0 as _, arg1 as _),
         SYS_MKDIR => mkdir(arg0 as _),
+        SYS_MKNOD => mknod(arg0 as _, arg1 as _, arg2 as _),
         SYS_RMDIR => rmdir(arg0 as _),
         SYS_FSTAT => fstat(arg0 as _, arg1 as _),
         SYS_UNLINK => unlink(arg0 as _),
@@ -59,56 +94,56 @@ pub extern "C" fn handler(syscall_number: usize, arg0: usize, arg1: usize, arg2:
         SYS_MOUNT => mount(arg0 as _, arg1 as _, arg2 as _),
         SYS_SYNC => sync(),
         SYS_WAITPID => {
-            let wait_pid = arg0 as Pid;
-
-            if wait_pid == running_thread_pid() {
-                return -1;
-            }
-
-            let status_ptr = match unsafe { get_mut_from_user_space(arg1 as *mut i32) } {
-                Some(ptr) => ptr,
-                None => return -1,
+            // The child to wait for, and the process group it must be in.
+            let (pid, pgid) = match arg0 as i32 {
+                -1 => (None, None),
+                0 => (None, Some(running_process().lock().pgid)),
+                pid if pid > 0 => match Pid::try_from(pid) {
+                    Ok(pid) => (Some(pid), None),
+                    Err(_) => return -ECHILD,
+                },
+                pgid => match Pid::try_from(pgid.unsigned_abs()) {
+                    Ok(pgid) => (None, Some(pgid)),
+                    Err(_) => return -ECHILD,
+                },
             };
-
-            let system = unwrap_system();
-            let pcb_ref = match system.process.table.get(wait_pid) {
-                Some(pcb) => pcb,
-                None => return -1, // Process with wait_pid doesnt exist
+            let status_ptr = if arg1 == 0 {
+                None
+            } else {
+                match unsafe { get_mut_from_user_space(arg1 as *mut i32) } {
+                    Some(ptr) => Some(ptr),
+                    None => return -EFAULT,
+                }
             };
-            let mut parent_pcb = pcb_ref.lock();
-
-            // Can't wait on a thread that alreay has a child waiting
-            if parent_pcb.waiting_thread.is_some() {
-                return -1;
+            let options = arg2 as i32;
+            if options & !WNOHANG != 0 {
+                return -EINVAL;
             }
 
-            parent_pcb.waiting_thread = Some(running_thread_tid());
-            drop(parent_pcb);
-
-            loop {
-                intr_disable();
-                {
-                    let parent_pcb = pcb_ref.lock();
-                    if parent_pcb.exit_code.is_some() {
-                        intr_enable();
-                        break;
+            match process_functions::wait_for_child(pid, pgid, options & WNOHANG == 0) {
+                Ok(Some((pid, status))) => {
+                    if let Some(status_ptr) = status_ptr {
+                        *status_ptr = status.wait_status();
                     }
+                    pid as isize
                 }
-                intr_enable();
-                thread_sleep();
+                Ok(None) => 0,
+                Err(WaitError::NoChildren) => -ECHILD,
+                Err(WaitError::Interrupted) => -EINTR,
             }
-
-            let parent_pcb = pcb_ref.lock();
-            let exit_code = parent_pcb.exit_code.unwrap();
-            *status_ptr = (exit_code & 0xff) << 8;
-
-            let parent_pid = parent_pcb.pid;
-            system.process.table.remove(parent_pid);
-
-            parent_pid as isize
         }
         SYS_DUP => dup(arg0 as _),
         SYS_PIPE => pipe(arg0 as _),
+        SYS_SOCKET => socket::socket(arg0 as _, arg1 as _, arg2 as _),
+        SYS_SOCKETPAIR => socket::socketpair(arg0 as _, arg1 as _, arg2 as _, arg3 as _),
+        SYS_BIND => socket::bind(arg0, arg1 as _, arg2 as _),
+        SYS_LISTEN => socket::listen(arg0, arg1 as _),
+        SYS_ACCEPT4 => socket::accept4(arg0, arg1 as _, arg2 as _, arg3 as _),
+        SYS_CONNECT => socket::connect(arg0, arg1 as _, arg2 as _),
+        SYS_SENDTO => socket::sendto(arg0, arg1 as _, arg2, arg3 as _, arg4 as _, arg5 as _),
+        SYS_RECVFROM => socket::recvfrom(arg0, arg1 as _, arg2, arg3 as _, arg4 as _, arg5 as _),
+        SYS_SHUTDOWN => socket::shutdown(arg0, arg1 as _),
+        SYS_BRK => brk(arg0) as isize,
         SYS_DUP2 => dup2(arg0 as _, arg1 as _),
         SYS_EXECVE => {
             let cstr = match unsafe { get_cstr_from_user_space(arg0 as *const u8) } {
@@ -117,29 +152,128 @@ pub extern "C" fn handler(syscall_number: usize, arg0: usize, arg1: usize, arg2:
                 Err(CStrError::BadUtf8) => return -ENOENT, // ?
             };
 
+            // The arguments and environment must be copied out of the
+            // caller's memory before it goes away. They can't take up more
+            // than fits on the new program's stack, which is checked while
+            // copying so that they can't use up the kernel heap first.
+            let mut budget = ARG_MAX;
+            let mut copy_array = |ptr: usize| match unsafe {
+                copy_cstr_array_from_user_space(ptr as _, &mut budget)
+            } {
+                Ok(strings) => Ok(strings),
+                Err(CStrArrayError::Fault) => Err(-EFAULT),
+                Err(CStrArrayError::TooLong) => Err(-E2BIG),
+            };
+            let argv = match copy_array(arg1) {
+                Ok(argv) => argv,
+                Err(errno) => return errno,
+            };
+            let envp = match copy_array(arg2) {
+                Ok(envp) => envp,
+                Err(errno) => return errno,
+            };
+
             let Ok(data) = read_file(cstr) else {
                 return -EIO;
             };
 
-            let system = unwrap_system();
-
             let elf = Elf::parse_bytes(&data).ok();
 
             let Some(elf) = elf else { return -ENOEXEC };
 
-            let Ok(control) = ThreadControlBlock::new_from_elf(elf, &system.process) else {
-                return -ENOEXEC;
-            };
-
-            system.threads.scheduler.lock().push(Box::new(control));
-
-            scheduler_yield_and_die();
+            // On success, this returns to the start of the new program, whose
+            // registers will all have been cleared.
+            match process_functions::exec_process(elf, &argv, &envp) {
+                Ok(()) => 0,
+                Err(ThreadElfCreateError::ArgumentListTooLong) => -E2BIG,
+                Err(ThreadElfCreateError::OutOfMemory) => -ENOMEM,
+                Err(ThreadElfCreateError::InterpreterNotFound) => -ENOENT,
+                Err(_) => -ENOEXEC,
+            }
         }
         SYS_GETPID => running_thread_pid() as isize,
         SYS_NANOSLEEP => {
-            todo!("nanosleep syscall")
+            let Some(duration) = (unsafe { get_ref_from_user_space(arg0 as *const Timespec) })
+            else {
+                return -EFAULT;
+            };
+            let Some(duration) = duration.to_duration() else {
+                return -EINVAL;
+            };
+
+            let remaining = timer::sleep_interruptible(duration);
+            if remaining.is_zero() {
+                return 0;
+            }
+
+            if arg1 != 0 {
+                let Some(remainder) = (unsafe { get_mut_from_user_space(arg1 as *mut Timespec) })
+                else {
+                    return -EFAULT;
+                };
+                *remainder = Timespec {
+                    tv_sec: remaining.as_secs() as i64,
+                    tv_nsec: remaining.subsec_nanos() as i64,
+                };
+            }
+            -EINTR
         }
         SYS_GETPPID => running_thread_ppid() as isize,
+        SYS_SETPGID => signal::setpgid(arg0 as i32, arg1 as i32),
+        SYS_GETPGID => signal::getpgid(arg0 as i32),
+        SYS_GETPGRP => signal::getpgid(0),
+        SYS_GETTID => running_thread_tid() as isize,
+        SYS_THREAD_CREATE => match process_functions::create_thread(arg0, arg1, arg2) {
+            Ok(tid) => tid as isize,
+            Err(ThreadCreateError::OutOfMemory) => -ENOMEM,
+            Err(ThreadCreateError::InvalidEntryPoint) => -EINVAL,
+            Err(ThreadCreateError::BadStack) => -EFAULT,
+        },
+        SYS_THREAD_JOIN => {
+            let Ok(tid) = Tid::try_from(arg0) else {
+                return -ESRCH;
+            };
+            let exit_code_ptr = if arg1 == 0 {
+                None
+            } else {
+                match unsafe { get_mut_from_user_space(arg1 as *mut i32) } {
+                    Some(ptr) => Some(ptr),
+                    None => return -EFAULT,
+                }
+            };
+
+            match process_functions::join_thread(tid) {
+                Ok(exit_code) => {
+                    if let Some(exit_code_ptr) = exit_code_ptr {
+                        *exit_code_ptr = exit_code;
+                    }
+                    0
+                }
+                Err(JoinError::NoSuchThread) => -ESRCH,
+                Err(JoinError::Deadlock) => -EDEADLK,
+                Err(JoinError::Interrupted) => -EINTR,
+            }
+        }
+        SYS_FUTEX => futex::futex(arg0, arg1 as i32, arg2 as u32, arg3 as _),
+        SYS_SET_THREAD_AREA => tls::set_thread_area(arg0 as _),
+        // We don't clear the tid when the thread exits, which only matters to
+        // threads created with clone, which we don't have.
+        SYS_SET_TID_ADDRESS => running_thread_tid() as isize,
+        SYS_UNAME => {
+            let Some(name) = (unsafe { get_mut_from_user_space(arg0 as *mut UtsName) }) else {
+                return -EFAULT;
+            };
+            *name = uname();
+            0
+        }
+        SYS_THREAD_EXIT => {
+            process_functions::exit_user_thread(arg0 as i32);
+        }
+        SYS_KILL => signal::kill(arg0 as i32, arg1 as i32),
+        SYS_SIGACTION => signal::sigaction(arg0 as i32, arg1 as _, arg2 as _),
+        SYS_SIGPROCMASK => signal::sigprocmask(arg0 as i32, arg1 as _, arg2 as _),
+        SYS_RT_SIGPROCMASK => signal::rt_sigprocmask(arg0 as i32, arg1 as _, arg2 as _, arg3),
+        SYS_SIGRETURN => signal::sigreturn(),
         SYS_SCHED_YIELD => {
             scheduler_yield_and_continue();
             0
@@ -167,20 +301,42 @@ pub extern "C" fn handler(syscall_number: usize, arg0: usize, arg1: usize, arg2:
             let buffer = unsafe { from_raw_parts_mut(buffer_ptr, arg1) };
             getrandom(buffer, arg1, arg2)
         }
-        SYS_MMAP => {
-            let Some(options) = (unsafe { get_ref_from_user_space(arg0 as *const MMapOptions) })
-            else {
-                return -EFAULT;
-            };
-            mmap(
-                options.addr,
-                options.length,
-                options.prot,
-                options.flags,
-                options.fd,
-                options.offset,
-            )
+        // The offset is in pages, so that the whole range of offsets fits in a
+        // register.
+        SYS_MMAP2 => mmap(
+            arg0 as _,
+            arg1,
+            arg2 as _,
+            arg3 as _,
+            arg4 as _,
+            arg5 as i64 * MMAP2_PAGE_SIZE,
+        ),
+        SYS_MUNMAP => munmap(arg0 as *mut core::ffi::c_void, arg1),
+        _ => {
+            println!("unknown syscall number {syscall_number:#X}");
+            -ENOSYS
         }
-        _ => -ENOSYS,
+    }
+}
+
+/// Joins a 64-bit argument passed in two registers.
+fn split_i64(low: usize, high: usize) -> i64 {
+    ((high as u64) << 32 | low as u64) as i64
+}
+
+/// Describes this system to `uname`.
+fn uname() -> UtsName {
+    let field = |value: &str| {
+        let mut field = [0; UTSNAME_LENGTH];
+        field[..value.len()].copy_from_slice(value.as_bytes());
+        field
+    };
+    UtsName {
+        sysname: field("KidneyOS"),
+        nodename: field("kidneyos"),
+        release: field(env!("CARGO_PKG_VERSION")),
+        version: field(""),
+        machine: field("i686"),
+        domainname: field(""),
     }
 }
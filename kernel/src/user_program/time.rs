@@ -1,4 +1,5 @@
 use core::arch::asm;
+use core::time::Duration;
 
 // QEMU default is 100 ticks per second
 // This will need to be changed when compiling for a real system
@@ -14,6 +15,19 @@ pub struct Timespec {
     pub tv_nsec: i64,
 }
 
+impl Timespec {
+    /// Converts this to a `Duration`, or returns `None` if it is negative or
+    /// `tv_nsec` is out of range.
+    pub fn to_duration(&self) -> Option<Duration> {
+        let secs = u64::try_from(self.tv_sec).ok()?;
+        let nanos = u32::try_from(self.tv_nsec).ok()?;
+        if nanos >= 1_000_000_000 {
+            return None;
+        }
+        Some(Duration::new(secs, nanos))
+    }
+}
+
 // Convert the RTC time to a Unix timestamp (seconds since 1970-01-01 00:00:00 UTC)
 fn rtc_to_unix_timestamp(
     year: i32,
@@ -0,0 +1,210 @@
+use crate::interrupts::{mutex_irq::hold_interrupts, IntrLevel};
+use crate::mem::util::get_mut_from_user_space;
+use crate::system::unwrap_system;
+use core::arch::asm;
+use kidneyos_shared::global_descriptor_table::{
+    set_user_tls, USER_DATA_DESCRIPTOR, USER_DATA_SELECTOR, USER_TLS_INDEX, USER_TLS_SELECTOR,
+};
+use kidneyos_shared::segment::SegmentDescriptor;
+use kidneyos_syscalls::defs::{
+    UserDesc, EFAULT, EINVAL, USER_DESC_CONTENTS_CODE, USER_DESC_LIMIT_IN_PAGES,
+    USER_DESC_READ_EXEC_ONLY, USER_DESC_SEG_32BIT, USER_DESC_SEG_NOT_PRESENT,
+};
+
+/// The segments a thread uses for thread-local storage, which are switched
+/// along with it.
+///
+/// The kernel never uses `fs` or `gs` itself, so while a thread is in the
+/// kernel they still hold whatever it loaded into them in user mode.
+#[derive(Clone, Copy, Debug)]
+pub struct ThreadSegments {
+    /// The descriptor for the TLS entry of the GDT while this thread runs.
+    pub tls: SegmentDescriptor,
+    /// The selectors in `fs` and `gs`, saved while the thread isn't running.
+    pub fs: u16,
+    pub gs: u16,
+}
+
+impl ThreadSegments {
+    /// The segments of a thread which hasn't set up any thread-local storage.
+    pub const fn new() -> Self {
+        Self {
+            tls: USER_DATA_DESCRIPTOR,
+            fs: USER_DATA_SELECTOR,
+            gs: USER_DATA_SELECTOR,
+        }
+    }
+
+    /// The segments of a thread whose thread pointer, the address `gs:0`
+    /// refers to, is `thread_pointer`.
+    pub const fn with_thread_pointer(thread_pointer: usize) -> Self {
+        Self {
+            tls: USER_DATA_DESCRIPTOR.with_base(thread_pointer as u32),
+            gs: USER_TLS_SELECTOR,
+            ..Self::new()
+        }
+    }
+
+    /// Saves the selectors currently in `fs` and `gs`.
+    pub fn save(&mut self) {
+        let (fs, gs): (u16, u16);
+        unsafe {
+            asm!(
+                "
+                mov {fs:x}, fs
+                mov {gs:x}, gs
+                ",
+                fs = out(reg) fs,
+                gs = out(reg) gs,
+                options(nomem, nostack, preserves_flags),
+            );
+        }
+        self.fs = fs;
+        self.gs = gs;
+    }
+
+    /// Installs this thread's TLS descriptor and loads its `fs` and `gs`.
+    ///
+    /// # Safety
+    ///
+    /// Interrupts must be disabled, and the thread these segments belong to
+    /// must be the one which runs next.
+    pub unsafe fn load(&self) {
+        set_user_tls(self.tls);
+        // Loading a selector for a descriptor which isn't present would fault,
+        // so those are replaced with the null selector, which faults only
+        // once the program uses it.
+        let usable = |selector| {
+            if selector == USER_TLS_SELECTOR && !self.tls.present() {
+                0
+            } else {
+                selector
+            }
+        };
+        asm!(
+            "
+            mov fs, {fs:x}
+            mov gs, {gs:x}
+            ",
+            fs = in(reg) usable(self.fs),
+            gs = in(reg) usable(self.gs),
+            options(nostack, preserves_flags),
+        );
+    }
+}
+
+impl Default for ThreadSegments {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Whether `desc` asks for its entry to be cleared. Like Linux, this is an
+/// empty descriptor which is either all zeros or marked read-only and not
+/// present.
+fn is_clear(desc: &UserDesc) -> bool {
+    desc.base_addr == 0
+        && desc.limit == 0
+        && (desc.flags == 0 || desc.flags == USER_DESC_READ_EXEC_ONLY | USER_DESC_SEG_NOT_PRESENT)
+}
+
+/// Builds the descriptor a program asks for with `set_thread_area`, or returns
+/// `None` if it isn't a data segment. A request to clear the entry gives a
+/// descriptor which isn't present.
+fn descriptor(desc: &UserDesc) -> Option<SegmentDescriptor> {
+    if is_clear(desc) {
+        return Some(SegmentDescriptor::default());
+    }
+    if desc.flags & USER_DESC_CONTENTS_CODE != 0 {
+        return None;
+    }
+
+    Some(
+        SegmentDescriptor::default()
+            .with_base(desc.base_addr)
+            .with_limit(desc.limit)
+            .with_granularity(desc.flags & USER_DESC_LIMIT_IN_PAGES != 0)
+            .with_size(desc.flags & USER_DESC_SEG_32BIT != 0)
+            .with_present(desc.flags & USER_DESC_SEG_NOT_PRESENT == 0)
+            .with_descriptor_privilege_level(3)
+            .with_type(true)
+            .with_read_write(desc.flags & USER_DESC_READ_EXEC_ONLY == 0),
+    )
+}
+
+/// Sets the running thread's TLS segment to the one described by `u_info`.
+///
+/// There's only one TLS entry, so `entry_number` must be either that entry or
+/// -1, in which case the entry is written back if the call succeeds.
+pub fn set_thread_area(u_info: *mut UserDesc) -> isize {
+    let Some(desc) = (unsafe { get_mut_from_user_space(u_info) }) else {
+        return -EFAULT;
+    };
+    if desc.entry_number != u32::MAX && desc.entry_number != USER_TLS_INDEX as u32 {
+        return -EINVAL;
+    }
+    let Some(tls) = descriptor(desc) else {
+        return -EINVAL;
+    };
+    desc.entry_number = USER_TLS_INDEX as u32;
+
+    // We must not be switched out between updating the GDT and reloading the
+    // segments.
+    let _guard = hold_interrupts(IntrLevel::IntrOff);
+    let mut running_thread = unwrap_system().threads.running_thread.lock();
+    let thread = running_thread.as_mut().expect("no running thread");
+    thread.segments.tls = tls;
+    thread.segments.save();
+    // SAFETY: We're the running thread, and interrupts are disabled.
+    unsafe { thread.segments.load() };
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn descriptor_from_user_desc() {
+        let desc = UserDesc {
+            entry_number: u32::MAX,
+            base_addr: 0x1234_5678,
+            limit: 0xfffff,
+            flags: USER_DESC_SEG_32BIT | USER_DESC_LIMIT_IN_PAGES,
+        };
+        let tls = descriptor(&desc).unwrap();
+        assert_eq!(tls.base(), 0x1234_5678);
+        assert_eq!(tls.limit(), 0xfffff);
+        assert!(tls.present() && tls.read_write() && tls.granularity() && tls.size());
+        assert_eq!(tls.descriptor_privilege_level(), 3);
+        assert!(!tls.executable());
+    }
+
+    #[test]
+    fn code_segments_are_rejected() {
+        let desc = UserDesc {
+            flags: USER_DESC_CONTENTS_CODE,
+            ..UserDesc::default()
+        };
+        assert!(descriptor(&desc).is_none());
+    }
+
+    #[test]
+    fn clearing_descriptors() {
+        let desc = UserDesc {
+            flags: USER_DESC_READ_EXEC_ONLY | USER_DESC_SEG_NOT_PRESENT,
+            ..UserDesc::default()
+        };
+        assert_eq!(descriptor(&desc).unwrap().load(), 0);
+        assert_eq!(descriptor(&UserDesc::default()).unwrap().load(), 0);
+
+        // Not present, but not empty either.
+        let desc = UserDesc {
+            limit: 0xfffff,
+            ..desc
+        };
+        let tls = descriptor(&desc).unwrap();
+        assert!(!tls.present() && !tls.read_write());
+        assert_eq!(tls.limit(), 0xfffff);
+    }
+}
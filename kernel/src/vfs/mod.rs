@@ -58,6 +58,36 @@ pub enum Error {
     HardLinkBetweenFileSystems,
     /// All read handles are closed, a write cannot be performed (EPIPE).
     PipeClosed,
+    /// A blocking call was interrupted by a signal (EINTR).
+    Interrupted,
+    /// The file isn't a terminal (ENOTTY).
+    NotTerminal,
+    /// A non-blocking operation would have had to block (EAGAIN).
+    WouldBlock,
+    /// The file wasn't opened in a way which allows the operation (EACCES).
+    PermissionDenied,
+    /// A named pipe was opened for writing, without blocking, while it has no readers, or a
+    /// socket was opened like a file (ENXIO).
+    NoDeviceOrAddress,
+    /// The file isn't a socket (ENOTSOCK).
+    NotSocket,
+    /// The socket can't do that, such as listening on a datagram socket (EOPNOTSUPP).
+    SocketOperationNotSupported,
+    /// The socket is in the wrong state for the operation, such as accepting on a socket
+    /// which isn't listening (EINVAL).
+    BadSocketState,
+    /// The socket is the wrong type for the one at the other end (EPROTOTYPE).
+    WrongSocketType,
+    /// Binding a socket to a path which already exists (EADDRINUSE).
+    AddressInUse,
+    /// Nothing is listening on the socket connected to (ECONNREFUSED).
+    ConnectionRefused,
+    /// The socket needs to be connected first (ENOTCONN).
+    NotConnected,
+    /// The socket is connected already (EISCONN).
+    AlreadyConnected,
+    /// A datagram too big to send (EMSGSIZE).
+    MessageTooLong,
     /// Error accessing underlying storage device
     IO(String),
 }
@@ -92,6 +122,20 @@ impl core::fmt::Display for Error {
                 write!(f, "hard link between different file systems")
             }
             Self::PipeClosed => write!(f, "write to closed pipe"),
+            Self::Interrupted => write!(f, "interrupted by a signal"),
+            Self::NotTerminal => write!(f, "not a terminal"),
+            Self::WouldBlock => write!(f, "operation would block"),
+            Self::PermissionDenied => write!(f, "permission denied"),
+            Self::NoDeviceOrAddress => write!(f, "no such device or address"),
+            Self::NotSocket => write!(f, "not a socket"),
+            Self::SocketOperationNotSupported => write!(f, "operation not supported on socket"),
+            Self::BadSocketState => write!(f, "socket in wrong state"),
+            Self::WrongSocketType => write!(f, "wrong socket type"),
+            Self::AddressInUse => write!(f, "address already in use"),
+            Self::ConnectionRefused => write!(f, "connection refused"),
+            Self::NotConnected => write!(f, "socket not connected"),
+            Self::AlreadyConnected => write!(f, "socket already connected"),
+            Self::MessageTooLong => write!(f, "message too long"),
             Self::IO(s) => write!(f, "I/O error: {s}"),
         }
     }
@@ -121,6 +165,20 @@ impl Error {
             Error::TooManyLevelsOfLinks => syscall::ELOOP,
             Error::HardLinkBetweenFileSystems => syscall::EXDEV,
             Error::PipeClosed => syscall::EPIPE,
+            Error::Interrupted => syscall::EINTR,
+            Error::NotTerminal => syscall::ENOTTY,
+            Error::WouldBlock => syscall::EAGAIN,
+            Error::PermissionDenied => syscall::EACCES,
+            Error::NoDeviceOrAddress => syscall::ENXIO,
+            Error::NotSocket => syscall::ENOTSOCK,
+            Error::SocketOperationNotSupported => syscall::EOPNOTSUPP,
+            Error::BadSocketState => syscall::EINVAL,
+            Error::WrongSocketType => syscall::EPROTOTYPE,
+            Error::AddressInUse => syscall::EADDRINUSE,
+            Error::ConnectionRefused => syscall::ECONNREFUSED,
+            Error::NotConnected => syscall::ENOTCONN,
+            Error::AlreadyConnected => syscall::EISCONN,
+            Error::MessageTooLong => syscall::EMSGSIZE,
             Error::IO(_) => syscall::EIO,
         }
     }
@@ -149,6 +207,10 @@ pub enum INodeType {
     Link,
     /// Directory
     Directory,
+    /// Named pipe
+    Fifo,
+    /// Unix domain socket
+    Socket,
 }
 
 impl INodeType {
@@ -157,6 +219,8 @@ impl INodeType {
             Self::File => syscall::S_REGULAR_FILE,
             Self::Link => syscall::S_SYMLINK,
             Self::Directory => syscall::S_DIRECTORY,
+            Self::Fifo => syscall::S_FIFO,
+            Self::Socket => syscall::S_SOCKET,
         }
     }
 }
@@ -285,6 +349,17 @@ pub trait FileSystem: Sized + Sync + Send {
     /// The kernel must ensure that `parent` is a directory and that `name` is non-empty and doesn't contain `/`
     /// If `name` already exists (whether as a directory or as a file), returns [`Error::Exists`].
     fn mkdir(&mut self, parent: &mut Self::FileHandle, name: &Path) -> Result<INodeNum>;
+    /// Make named pipe or socket in parent
+    ///
+    /// The kernel must ensure that `parent` is a directory, that `name` is non-empty and doesn't contain `/`,
+    /// and that `r#type` is [`INodeType::Fifo`] or [`INodeType::Socket`].
+    /// If `name` already exists, returns [`Error::Exists`].
+    fn mknod(
+        &mut self,
+        parent: &mut Self::FileHandle,
+        name: &Path,
+        r#type: INodeType,
+    ) -> Result<INodeNum>;
     /// Remove a (link to a) file/symlink in parent
     ///
     /// The kernel must ensure that `parent` is a directory and that `name` is non-empty and doesn't contain `/`
@@ -392,6 +467,13 @@ pub trait SimpleFileSystem: Sized + Send + Sync {
     fn mkdir(&mut self, parent: INodeNum, name: &Path) -> Result<INodeNum> {
         Err(Error::Unsupported)
     }
+    /// Create a named pipe or socket, as `r#type` says, in `parent` called `name`.
+    ///
+    /// The file system only has to remember that it's there; the kernel keeps
+    /// the data written to it. Returns the inode number of the new node.
+    fn mknod(&mut self, parent: INodeNum, name: &Path, r#type: INodeType) -> Result<INodeNum> {
+        Err(Error::Unsupported)
+    }
     /// Unlink the file called `name` in the directory `parent`.
     fn unlink(&mut self, parent: INodeNum, name: &Path) -> Result<()> {
         Err(Error::Unsupported)
@@ -500,6 +582,14 @@ impl<F: SimpleFileSystem> FileSystem for F {
     fn mkdir(&mut self, parent: &mut Self::FileHandle, name: &Path) -> Result<INodeNum> {
         SimpleFileSystem::mkdir(self, parent.0, name)
     }
+    fn mknod(
+        &mut self,
+        parent: &mut Self::FileHandle,
+        name: &Path,
+        r#type: INodeType,
+    ) -> Result<INodeNum> {
+        SimpleFileSystem::mknod(self, parent.0, name, r#type)
+    }
     fn unlink(&mut self, parent: &mut Self::FileHandle, name: &Path) -> Result<()> {
         SimpleFileSystem::unlink(self, parent.0, name)
     }
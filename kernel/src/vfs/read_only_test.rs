@@ -74,7 +74,7 @@ fn read_only_test_on<F: FileSystem>(fs: &mut F, fs_dir_inode: INodeNum, host_dir
                     host_subpath.to_string_lossy()
                 );
             }
-            INodeType::Link => todo!(),
+            INodeType::Link | INodeType::Fifo | INodeType::Socket => todo!(),
         }
     }
 }
@@ -49,6 +49,8 @@ enum TempINodeData {
     File(TempFile),
     Directory(TempDirectory),
     Link(TempLink),
+    /// A named pipe or socket, whose contents are kept by the kernel rather than here.
+    Node(INodeType),
 }
 
 struct TempINode {
@@ -75,6 +77,7 @@ impl TempINode {
             TempINodeData::File(_) => INodeType::File,
             TempINodeData::Directory(_) => INodeType::Directory,
             TempINodeData::Link(_) => INodeType::Link,
+            TempINodeData::Node(r#type) => *r#type,
         }
     }
 }
@@ -120,6 +123,38 @@ impl TempFS {
         self.inodes.insert(self.inode_counter, inode);
         self.inode_counter
     }
+    // adds `inode` to `parent` as `name`, which mustn't exist yet.
+    fn add_new_entry(
+        &mut self,
+        parent: INodeNum,
+        name: &Path,
+        inode: TempINode,
+    ) -> Result<INodeNum> {
+        if name.is_empty() {
+            panic!("Empty name passed to mkdir or mknod");
+        }
+        if name.contains('/') {
+            panic!("File name contains /");
+        }
+        let parent_inode = self.get_inode(parent);
+        let TempINodeData::Directory(parent_dir) = &parent_inode.data else {
+            panic!("Kernel should make sure parent is a directory before making something in it.");
+        };
+        if parent_inode.nlink == 0 {
+            // this directory has been rmdir'd
+            return Err(Error::NotDirectory);
+        }
+        if parent_dir.contains(name) {
+            return Err(Error::Exists);
+        }
+        let inode_num = self.add_inode(inode);
+        let parent_inode = self.get_inode_mut(parent);
+        let TempINodeData::Directory(parent_dir) = &mut parent_inode.data else {
+            panic!("This should never happen due to the check above");
+        };
+        parent_dir.add_entry(name.into(), inode_num);
+        Ok(inode_num)
+    }
     // performs either unlink or rmdir.
     fn unlink_or_rmdir(&mut self, parent: INodeNum, name: &Path, is_rmdir: bool) -> Result<()> {
         if name.is_empty() {
@@ -149,7 +184,7 @@ impl TempFS {
                     return Err(Error::NotEmpty);
                 }
             }
-            TempINodeData::File(_) => {
+            TempINodeData::File(_) | TempINodeData::Node(_) => {
                 if is_rmdir {
                     return Err(Error::NotDirectory);
                 }
@@ -341,6 +376,12 @@ impl SimpleFileSystem for TempFS {
                 nlink: inode.nlink.into(),
                 size: l.path.len() as u64,
             }),
+            TempINodeData::Node(r#type) => Ok(FileInfo {
+                r#type: *r#type,
+                inode: file,
+                nlink: inode.nlink.into(),
+                size: 0,
+            }),
         }
     }
     fn link(&mut self, source: INodeNum, parent: INodeNum, name: &Path) -> Result<()> {
@@ -454,33 +495,17 @@ impl SimpleFileSystem for TempFS {
         if DEBUG_TEMPFS {
             println!("tempfs: mkdir in {parent:?}: {name}");
         }
-        if name.is_empty() {
-            panic!("mkdir called with empty name");
-        }
-        if name.contains('/') {
-            panic!("File name contains /");
-        }
-        let parent_inode = self.get_inode(parent);
-        let TempINodeData::Directory(parent_dir) = &parent_inode.data else {
-            panic!(
-                "Kernel should make sure parent is a directory before making a directory in it."
-            );
-        };
-        if parent_inode.nlink == 0 {
-            // this directory has been rmdir'd
-            return Err(Error::NotDirectory);
-        }
-        if parent_dir.contains(name) {
-            return Err(Error::Exists);
+        self.add_new_entry(parent, name, TempINode::empty_directory())
+    }
+    fn mknod(&mut self, parent: INodeNum, name: &Path, r#type: INodeType) -> Result<INodeNum> {
+        if DEBUG_TEMPFS {
+            println!("tempfs: mknod {:?} in {parent:?}: {name}", r#type);
         }
-        let inode = TempINode::empty_directory();
-        let inode_num = self.add_inode(inode);
-        let parent_inode = self.get_inode_mut(parent);
-        let TempINodeData::Directory(parent_dir) = &mut parent_inode.data else {
-            panic!("This should never happen due to the check above");
-        };
-        parent_dir.add_entry(name.into(), inode_num);
-        Ok(inode_num)
+        assert!(
+            matches!(r#type, INodeType::Fifo | INodeType::Socket),
+            "Kernel should only use mknod for named pipes and sockets."
+        );
+        self.add_new_entry(parent, name, TempINode::new(TempINodeData::Node(r#type)))
     }
     fn sync(&mut self) -> Result<()> {
         // not applicable to in-memory filesystem
@@ -520,12 +545,13 @@ mod tests {
         Open,
         Create,
         Mkdir,
+        Mknod(INodeType),
         Rmdir,
         Unlink,
         Link(F::FileHandle),
         SymLink(&'a Path),
     }
-    // open/create/mkdir/rmdir/unlink an absolute path
+    // open/create/mkdir/mknod/rmdir/unlink an absolute path
     fn do_path<F: FileSystem>(
         fs: &mut F,
         path: &Path,
@@ -550,6 +576,10 @@ mod tests {
                         fs.mkdir(&mut file, item)?;
                         return Ok(None);
                     }
+                    Action::Mknod(r#type) => {
+                        fs.mknod(&mut file, item, r#type)?;
+                        return Ok(None);
+                    }
                     Action::Rmdir => {
                         let inode = lookup(fs, &mut file, item)?;
                         fs.rmdir(&mut file, item)?;
@@ -580,6 +610,11 @@ mod tests {
         do_path(fs, path, Action::Mkdir)?;
         Ok(())
     }
+    // mknod an absolute path
+    fn mknod_path<F: FileSystem>(fs: &mut F, path: &Path, r#type: INodeType) -> Result<()> {
+        do_path(fs, path, Action::Mknod(r#type))?;
+        Ok(())
+    }
     // create an absolute path
     fn create_path<F: FileSystem>(fs: &mut F, path: &Path) -> Result<F::FileHandle> {
         Ok(do_path(fs, path, Action::Create)?.unwrap())
@@ -713,6 +748,40 @@ mod tests {
         assert_eq!(fs.inodes.len(), 1); // should only have root
     }
 
+    #[test]
+    // test mknod
+    fn mknod() {
+        let mut fs = TempFS::new();
+        mkdir_path(&mut fs, "/dir").unwrap();
+        mknod_path(&mut fs, "/dir/fifo", INodeType::Fifo).unwrap();
+        mknod_path(&mut fs, "/dir/socket", INodeType::Socket).unwrap();
+        assert_matches!(
+            mknod_path(&mut fs, "/dir/fifo", INodeType::Socket).unwrap_err(),
+            Error::Exists
+        );
+        assert_matches!(
+            mknod_path(&mut fs, "/dir", INodeType::Fifo).unwrap_err(),
+            Error::Exists
+        );
+        let fifo = open_path(&mut fs, "/dir/fifo").unwrap();
+        let fifo_stat = fs.stat(&fifo).unwrap();
+        assert_eq!(fifo_stat.r#type, INodeType::Fifo);
+        assert_eq!(fifo_stat.size, 0);
+        let entries = readdir_path(&mut fs, "/dir").unwrap();
+        assert_eq!(entries[0].r#type, INodeType::Fifo);
+        assert_eq!(entries[1].r#type, INodeType::Socket);
+        assert_matches!(
+            rmdir_path(&mut fs, "/dir/fifo").unwrap_err(),
+            Error::NotDirectory
+        );
+        unlink_path(&mut fs, "/dir/fifo").unwrap();
+        fs.release(fifo.inode());
+        assert_matches!(
+            open_path(&mut fs, "/dir/fifo").unwrap_err(),
+            Error::NotFound
+        );
+    }
+
     #[test]
     // test link
     fn link() {
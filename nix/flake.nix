@@ -29,6 +29,8 @@
           inherit system;
         };
         i686-cc = i686-pkgs.stdenv.cc;
+        # For stock Linux programs, which are linked statically against musl.
+        i686-musl-cc = pkgs.pkgsCross.musl32.stdenv.cc;
         grub2 =
           if system == "x86_64-linux"
           then pkgs.grub2
@@ -65,6 +67,7 @@
               grcov
               grub2
               i686-cc
+              i686-musl-cc
               mdbook
               mtools
               qemu
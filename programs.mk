@@ -1,4 +1,4 @@
-PROGRAMS := exit example_c example_rust fs execve pipes
+PROGRAMS := exit example_c example_rust fs execve pipes threads malloc tls poll tty socket musl
 
 .PHONY: programs
 programs: $(PROGRAMS)
@@ -14,6 +14,21 @@ example_c:
 fs:
 	cd programs/fs && make
 
+tls:
+	cd programs/tls && make
+
+poll:
+	cd programs/poll && make
+
+tty:
+	cd programs/tty && make
+
+socket:
+	cd programs/socket && make
+
+musl:
+	cd programs/musl && make
+
 example_rust:
 	# We don't want to export CARGO_TARGET_DIR to our destination make.
 	unset CARGO_TARGET_DIR && cd programs/example_rust && make
@@ -26,11 +41,26 @@ pipes:
 	# We don't want to export CARGO_TARGET_DIR to our destination make.
 	unset CARGO_TARGET_DIR && cd programs/pipes && make
 
+threads:
+	# We don't want to export CARGO_TARGET_DIR to our destination make.
+	unset CARGO_TARGET_DIR && cd programs/threads && make
+
+malloc:
+	# We don't want to export CARGO_TARGET_DIR to our destination make.
+	unset CARGO_TARGET_DIR && cd programs/malloc && make
+
 .PHONY: clean
 clean::
 	cd programs/exit && make clean
 	cd programs/example_c && make clean
+	cd programs/tls && make clean
+	cd programs/poll && make clean
+	cd programs/tty && make clean
+	cd programs/socket && make clean
+	cd programs/musl && make clean
 	# We don't want to export CARGO_TARGET_DIR to our destination make.
 	unset CARGO_TARGET_DIR && cd programs/example_rust && make clean
 	unset CARGO_TARGET_DIR && cd programs/execve && make clean
 	unset CARGO_TARGET_DIR && cd programs/pipes && make clean
+	unset CARGO_TARGET_DIR && cd programs/threads && make clean
+	unset CARGO_TARGET_DIR && cd programs/malloc && make clean
@@ -2,7 +2,7 @@
 #![cfg_attr(not(test), no_main)]
 
 use core::ffi::c_char;
-use kidneyos_syscalls::O_CREATE;
+use kidneyos_syscalls::{O_CREATE, O_WRONLY};
 
 const TARGET_PROGRAM: &[u8] =
     include_bytes!("../../example_rust/target/i686-unknown-linux-gnu/release/example_rust");
@@ -12,7 +12,7 @@ const TARGET_PATH: *const c_char = c"/example_rust".as_ptr();
 #[no_mangle]
 pub extern "C" fn _start() -> ! {
     // TempFS - We'll create the file that we want to execute on the fly.
-    let fd = kidneyos_syscalls::open(TARGET_PATH, O_CREATE);
+    let fd = kidneyos_syscalls::open(TARGET_PATH, O_CREATE | O_WRONLY);
 
     if fd < 0 {
         kidneyos_syscalls::exit(fd);
@@ -16,7 +16,7 @@ void _start() {
     const char *test_data = "test data";
     char buf[10] = {0};
     int status;
-    int fd = check(open("/foo", O_CREATE));
+    int fd = check(open("/foo", O_CREATE | O_WRONLY));
     check(write(fd, test_data, 9));
     check(close(fd));
     fd = check(open("/foo", 0));
@@ -34,7 +34,7 @@ void _start() {
     if (unlink("/e/askdfjh") != -ENOENT) exit(__LINE__);
     check(getcwd(buf, 3));
     if (buf[0] != '/' || buf[1] != 'd' || buf[2] != 0) exit(__LINE__);
-    fd = check(open("file", O_CREATE));
+    fd = check(open("file", O_CREATE | O_RDWR));
     check(link("file", "hardlink"));
     check(symlink("file", "symlink"));
     struct Stat file_info = {0};
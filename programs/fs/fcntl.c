@@ -0,0 +1,51 @@
+#include <kidneyos.h>
+
+void _start() {
+    int fd = open("/flags", O_CREATE | O_EXCL | O_WRONLY | O_CLOEXEC);
+    if (fd < 0) exit(1);
+    if (open("/flags", O_CREATE | O_EXCL | O_WRONLY) != -EEXIST) exit(2);
+    if (open("/flags", O_DIRECTORY) != -ENOTDIR) exit(3);
+    if (write(fd, (const uint8_t *)"abc", 3) != 3) exit(4);
+
+    // the access mode is enforced
+    uint8_t buf[8];
+    if (read(fd, buf, sizeof buf) != -EBADF) exit(5);
+
+    if (fcntl(fd, F_GETFL, 0) != O_WRONLY) exit(6);
+    if (fcntl(fd, F_GETFD, 0) != FD_CLOEXEC) exit(7);
+    if (fcntl(fd, F_SETFD, 0) != 0 || fcntl(fd, F_GETFD, 0) != 0) exit(8);
+
+    // appending writes go to the end, even after seeking back
+    if (fcntl(fd, F_SETFL, O_APPEND) != 0) exit(9);
+    if (fcntl(fd, F_GETFL, 0) != (O_WRONLY | O_APPEND)) exit(10);
+    lseek64(fd, 0, SEEK_SET);
+    if (write(fd, (const uint8_t *)"d", 1) != 1) exit(11);
+
+    // F_DUPFD picks the lowest free descriptor from its argument
+    int dup = fcntl(fd, F_DUPFD_CLOEXEC, 20);
+    if (dup != 20) exit(12);
+    if (fcntl(dup, F_GETFD, 0) != FD_CLOEXEC) exit(13);
+    if (fcntl(dup, F_GETFL, 0) != (O_WRONLY | O_APPEND)) exit(14);
+    close(dup);
+    close(fd);
+
+    fd = open("/flags", O_RDONLY);
+    if (read(fd, buf, sizeof buf) != 4) exit(15);
+    if (buf[0] != 'a' || buf[3] != 'd') exit(16);
+    if (write(fd, (const uint8_t *)"e", 1) != -EBADF) exit(17);
+    close(fd);
+
+    fd = open("/flags", O_RDWR | O_TRUNC);
+    if (read(fd, buf, sizeof buf) != 0) exit(18);
+    close(fd);
+
+    // reading an empty pipe doesn't block once it's non-blocking
+    int fds[2];
+    if (pipe(fds) != 0) exit(19);
+    if (fcntl(fds[0], F_GETFL, 0) != O_RDONLY) exit(20);
+    if (fcntl(fds[0], F_SETFL, O_NONBLOCK) != 0) exit(21);
+    if (read(fds[0], buf, 1) != -EAGAIN) exit(22);
+
+    unlink("/flags");
+    exit(0);
+}
@@ -0,0 +1,47 @@
+#include <kidneyos.h>
+
+void _start() {
+    if (mkfifo("/fifo") != 0) exit(1);
+    if (mkfifo("/fifo") != -EEXIST) exit(2);
+    if (mknod("/other", 0, 0) != -EINVAL) exit(3);
+
+    // opening both ends doesn't wait for anyone
+    int fd = open("/fifo", O_RDWR);
+    if (fd < 0) exit(4);
+    struct Stat info;
+    if (fstat(fd, &info) != 0 || info.type != S_FIFO) exit(5);
+    if (lseek64(fd, 0, SEEK_SET) != -ESPIPE) exit(6);
+    if (write(fd, (const uint8_t *)"ab", 2) != 2) exit(7);
+    uint8_t buf[8];
+    if (read(fd, buf, sizeof buf) != 2 || buf[0] != 'a') exit(8);
+    close(fd);
+
+    // non-blocking opens don't wait either, but there's no point writing
+    // without a reader
+    if (open("/fifo", O_WRONLY | O_NONBLOCK) != -ENXIO) exit(9);
+    fd = open("/fifo", O_RDONLY | O_NONBLOCK);
+    if (fd < 0) exit(10);
+    if (read(fd, buf, sizeof buf) != 0) exit(11);
+    close(fd);
+
+    // each end waits for the other to be opened
+    Pid pid = fork();
+    if (pid == 0) {
+        int writer = open("/fifo", O_WRONLY);
+        if (writer < 0) exit(1);
+        if (write(writer, (const uint8_t *)"hello", 5) != 5) exit(2);
+        exit(0);
+    }
+    fd = open("/fifo", O_RDONLY);
+    if (fd < 0) exit(12);
+    if (read(fd, buf, 5) != 5 || buf[0] != 'h' || buf[4] != 'o') exit(13);
+    int32_t status;
+    if (waitpid(pid, &status, 0) != pid) exit(14);
+    if (!wifexited(status) || wexitstatus(status) != 0) exit(15);
+    // the writer is gone
+    if (read(fd, buf, sizeof buf) != 0) exit(16);
+    close(fd);
+
+    if (unlink("/fifo") != 0) exit(17);
+    exit(0);
+}
@@ -1,15 +1,15 @@
 #include <kidneyos.h>
 
 void _start() {
-    int fd=open("/a", O_CREATE);
+    int fd=open("/a", O_CREATE | O_WRONLY);
     if (fd < 0) exit(-fd);
     ftruncate(fd, 4096);
     const char *string = "hello world!\n";
     write(fd, string, 13);
     close(fd);
-    fd = open("/a", 0);
+    fd = open("/a", O_RDWR);
     char *addr = (char *)0x12345000;
-    char *result = mmap(addr, 4096, PROT_READ, 0, fd, 0);
+    char *result = mmap(addr, 4096, PROT_READ, MAP_PRIVATE, fd, 0);
     if (result != addr) exit(-(intptr_t)result);
     int len = 0;
     while (result[len]) {
@@ -17,5 +17,29 @@ void _start() {
         len++;
     }
     write(1, result, len);
+    if (munmap(result, 4096) != 0) exit(-2);
+
+    // writes to a shared mapping go back to the file
+    char *file = mmap(0, 4096, PROT_READ | PROT_WRITE, MAP_SHARED, fd, 0);
+    if ((intptr_t)file < 0) exit(-(intptr_t)file);
+    file[0] = 'j';
+    munmap(file, 4096);
+    char c;
+    lseek64(fd, 0, SEEK_SET);
+    if (read(fd, (uint8_t *)&c, 1) != 1 || c != 'j') exit(-3);
+    close(fd);
+
+    // children see writes to anonymous shared memory, but not to private memory
+    int *shared = mmap(0, 4096, PROT_READ | PROT_WRITE, MAP_SHARED | MAP_ANONYMOUS, -1, 0);
+    int *private = mmap(0, 4096, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+    if ((intptr_t)shared < 0 || (intptr_t)private < 0) exit(-4);
+    Pid pid = fork();
+    if (pid == 0) {
+        *shared = 42;
+        *private = 42;
+        exit(0);
+    }
+    waitpid(pid, 0, 0);
+    if (*shared != 42 || *private != 0) exit(-5);
     exit(0);
 }
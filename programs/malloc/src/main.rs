@@ -0,0 +1,64 @@
+#![cfg_attr(not(test), no_std)]
+#![cfg_attr(not(test), no_main)]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use kidneyos_syscalls::heap::Malloc;
+
+#[cfg(not(test))]
+#[global_allocator]
+static ALLOCATOR: Malloc = Malloc;
+
+const PAGE_SIZE: usize = 4096;
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    let start = kidneyos_syscalls::sbrk(0);
+
+    // Memory past the end of a shrunk heap is given back, and comes back
+    // zeroed when the heap grows again.
+    if kidneyos_syscalls::sbrk(2 * PAGE_SIZE as isize) != start {
+        kidneyos_syscalls::exit(1);
+    }
+    unsafe { core::ptr::write_bytes(start.cast::<u8>(), 0xff, 2 * PAGE_SIZE) };
+    let end = kidneyos_syscalls::sbrk(0);
+    if kidneyos_syscalls::brk(start) != 0 || kidneyos_syscalls::brk(end) != 0 {
+        kidneyos_syscalls::exit(2);
+    }
+    let heap = unsafe { core::slice::from_raw_parts(start.cast::<u8>(), 2 * PAGE_SIZE) };
+    if heap.iter().any(|byte| *byte != 0) {
+        kidneyos_syscalls::exit(3);
+    }
+    if kidneyos_syscalls::brk(start) != 0 {
+        kidneyos_syscalls::exit(4);
+    }
+
+    // Grow well past a single page, so that the heap needs several.
+    let mut numbers = Vec::new();
+    for i in 0..10_000u32 {
+        numbers.push(i);
+    }
+    if numbers.iter().sum::<u32>() != 10_000 * 9_999 / 2 {
+        kidneyos_syscalls::exit(5);
+    }
+    drop(numbers);
+
+    // Freed memory is reused.
+    let first = kidneyos_syscalls::malloc(64);
+    unsafe { kidneyos_syscalls::free(first) };
+    let second = kidneyos_syscalls::malloc(64);
+    if first != second {
+        kidneyos_syscalls::exit(6);
+    }
+
+    kidneyos_syscalls::exit(0);
+
+    loop {}
+}
+
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(_info: &core::panic::PanicInfo) -> ! {
+    loop {}
+}
@@ -0,0 +1,37 @@
+// A stock C program, built statically against musl rather than our syscall
+// library, to check that unmodified Linux binaries run.
+
+#include <stdio.h>
+#include <stdlib.h>
+#include <sys/utsname.h>
+#include <unistd.h>
+
+static __thread int counter = 41;
+
+int main(int argc, char **argv) {
+    struct utsname name;
+    if (uname(&name) != 0) {
+        perror("uname");
+        return EXIT_FAILURE;
+    }
+    printf("Hello from musl on %s %s (%s)\n", name.sysname, name.release, name.machine);
+    printf("argv[0] is %s, stdout %s a terminal\n", argc > 0 ? argv[0] : "missing",
+           isatty(STDOUT_FILENO) ? "is" : "isn't");
+
+    counter++;
+    if (counter != 42) {
+        fprintf(stderr, "thread-local counter is %d\n", counter);
+        return EXIT_FAILURE;
+    }
+
+    char *buffer = malloc(1 << 20);
+    if (buffer == 0) {
+        perror("malloc");
+        return EXIT_FAILURE;
+    }
+    buffer[(1 << 20) - 1] = 1;
+    free(buffer);
+
+    puts("Success!");
+    return EXIT_SUCCESS;
+}
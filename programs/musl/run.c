@@ -0,0 +1,31 @@
+// Puts the musl program on the file system and runs it, since there's no other
+// way to get files onto it yet. Exits with the program's exit code.
+
+#include <kidneyos.h>
+
+extern const uint8_t hello_start[], hello_end[];
+
+__asm__(".section .rodata\n"
+        "hello_start:\n"
+        ".incbin \"build/hello\"\n"
+        "hello_end:\n"
+        ".previous");
+
+void _start() {
+    int fd = open("/hello", O_CREATE | O_WRONLY);
+    if (fd < 0) exit(100);
+    uintptr_t size = hello_end - hello_start;
+    if (write(fd, hello_start, size) != (int32_t)size) exit(101);
+    close(fd);
+
+    Pid pid = fork();
+    if (pid == 0) {
+        const char *argv[] = {"/hello", 0};
+        const char *envp[] = {"TERM=linux", 0};
+        exit(execve("/hello", argv, envp));
+    }
+    int32_t status;
+    if (waitpid(pid, &status, 0) != pid) exit(102);
+    if (!wifexited(status)) exit(103);
+    exit(wexitstatus(status));
+}
@@ -1,7 +1,12 @@
 #![cfg_attr(not(test), no_std)]
 #![cfg_attr(not(test), no_main)]
 
-use kidneyos_syscalls::EPIPE;
+use kidneyos_syscalls::{
+    SigAction, EAGAIN, EPIPE, F_SETFL, O_NONBLOCK, PIPE_BUF, SIGPIPE, SIG_IGN,
+};
+
+/// How many bytes a pipe holds.
+const PIPE_CAPACITY: usize = 64 * 1024;
 
 #[no_mangle]
 pub extern "C" fn _start() -> ! {
@@ -44,6 +49,39 @@ pub extern "C" fn _start() -> ! {
         kidneyos_syscalls::exit(0x200);
     }
 
+    // Without blocking, the pipe fills up and further writes fail.
+    kidneyos_syscalls::fcntl(write, F_SETFL, O_NONBLOCK);
+    let chunk = [0u8; PIPE_BUF];
+    let mut total = 0;
+    loop {
+        let bytes = kidneyos_syscalls::write(write, chunk.as_ptr(), chunk.len());
+        if bytes < 0 {
+            if bytes != -EAGAIN as i32 {
+                kidneyos_syscalls::exit(0x210);
+            }
+            break;
+        }
+        total += bytes as usize;
+    }
+    if total != PIPE_CAPACITY {
+        kidneyos_syscalls::exit(0x220);
+    }
+
+    // Writes of up to PIPE_BUF bytes wait until all of them fit.
+    let mut big = [0u8; PIPE_BUF];
+    if kidneyos_syscalls::read(read, big.as_mut_ptr(), PIPE_BUF - 1) != (PIPE_BUF - 1) as i32 {
+        kidneyos_syscalls::exit(0x230);
+    }
+    if kidneyos_syscalls::write(write, chunk.as_ptr(), chunk.len()) != -EAGAIN as i32 {
+        kidneyos_syscalls::exit(0x240);
+    }
+    if kidneyos_syscalls::read(read, big.as_mut_ptr(), 1) != 1 {
+        kidneyos_syscalls::exit(0x250);
+    }
+    if kidneyos_syscalls::write(write, chunk.as_ptr(), chunk.len()) != PIPE_BUF as i32 {
+        kidneyos_syscalls::exit(0x260);
+    }
+
     kidneyos_syscalls::close(read);
 
     // Should not be EPIPE
@@ -53,7 +91,24 @@ pub extern "C" fn _start() -> ! {
 
     kidneyos_syscalls::close(other_read);
 
-    // Should be EPIPE
+    // Writing with no readers sends SIGPIPE, which kills the writer.
+    let child = kidneyos_syscalls::fork();
+    if child == 0 {
+        kidneyos_syscalls::write(write, data.as_ptr(), data.len());
+        kidneyos_syscalls::exit(0x500);
+    }
+    let mut status = 0;
+    kidneyos_syscalls::waitpid(i32::from(child), &mut status, 0);
+    if !kidneyos_syscalls::wifsignaled(status) || kidneyos_syscalls::wtermsig(status) != SIGPIPE {
+        kidneyos_syscalls::exit(0x510);
+    }
+
+    // With SIGPIPE ignored, the write fails with EPIPE instead.
+    let ignore = SigAction {
+        handler: SIG_IGN,
+        ..SigAction::default()
+    };
+    kidneyos_syscalls::sigaction(SIGPIPE, &ignore, core::ptr::null_mut());
     if kidneyos_syscalls::write(write, data.as_ptr(), data.len()) != -EPIPE as i32 {
         kidneyos_syscalls::exit(0x400);
     }
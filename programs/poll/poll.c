@@ -0,0 +1,59 @@
+#include <kidneyos.h>
+
+static void sleep_ms(int64_t ms) {
+    Timespec duration = {.tv_sec = 0, .tv_nsec = ms * 1000000};
+    nanosleep(&duration, 0);
+}
+
+void _start() {
+    int first[2], second[2];
+    if (pipe(first) != 0 || pipe(second) != 0) exit(1);
+
+    // Nothing has been written yet, so a zero timeout returns straight away
+    // and a short one expires.
+    PollFd fds[2] = {
+        {.fd = first[0], .events = POLLIN},
+        {.fd = second[0], .events = POLLIN},
+    };
+    if (poll(fds, 2, 0) != 0) exit(2);
+    if (poll(fds, 2, 50) != 0) exit(3);
+
+    // Wake up when the child writes to the second pipe.
+    Pid pid = fork();
+    if (pid == 0) {
+        sleep_ms(50);
+        write(second[1], (const uint8_t *)"x", 1);
+        exit(0);
+    }
+    if (poll(fds, 2, -1) != 1) exit(4);
+    if (fds[0].revents != 0 || fds[1].revents != POLLIN) exit(5);
+    uint8_t byte;
+    if (read(second[0], &byte, 1) != 1 || byte != 'x') exit(6);
+    int status;
+    if (waitpid(pid, &status, 0) != pid || status != 0) exit(7);
+
+    // Closing the last write end hangs up the read end.
+    close(first[1]);
+    if (poll(fds, 1, 0) != 1 || fds[0].revents != POLLHUP) exit(8);
+
+    // Bad file descriptors are reported, and negative ones are skipped.
+    PollFd bad[2] = {{.fd = 100, .events = POLLIN}, {.fd = -1, .events = POLLIN}};
+    if (poll(bad, 2, 0) != 1 || bad[0].revents != POLLNVAL || bad[1].revents != 0) exit(9);
+
+    // The write end of a pipe is always writable, and its read end isn't
+    // readable until something is written.
+    FdSet readable = {0}, writable = {0};
+    readable.bits[0] = 1u << second[0];
+    writable.bits[0] = 1u << second[1];
+    TimeVal timeout = {.sec = 0, .usec = 0};
+    if (select(second[1] + 1, &readable, &writable, 0, &timeout) != 1) exit(10);
+    if (readable.bits[0] != 0 || writable.bits[0] != 1u << second[1]) exit(11);
+
+    write(second[1], (const uint8_t *)"y", 1);
+    readable.bits[0] = 1u << second[0];
+    timeout.sec = 1;
+    if (select(second[0] + 1, &readable, 0, 0, &timeout) != 1) exit(12);
+    if (readable.bits[0] != 1u << second[0]) exit(13);
+
+    exit(0);
+}
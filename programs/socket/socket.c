@@ -0,0 +1,100 @@
+#include <kidneyos.h>
+
+static SockAddrUn address(const char *path) {
+    SockAddrUn addr = {.family = AF_UNIX};
+    for (int i = 0; path[i] != 0; i++) addr.path[i] = path[i];
+    return addr;
+}
+
+void _start() {
+    // A pair of stream sockets talk both ways.
+    int pair[2];
+    if (socketpair(AF_UNIX, SOCK_STREAM, 0, pair) != 0) exit(1);
+    if (write(pair[0], (const uint8_t *)"ping", 4) != 4) exit(2);
+    uint8_t buf[16];
+    if (read(pair[1], buf, sizeof buf) != 4 || buf[0] != 'p') exit(3);
+    if (send(pair[1], (const uint8_t *)"pong", 4, 0) != 4) exit(4);
+    if (recv(pair[0], buf, sizeof buf, 0) != 4 || buf[1] != 'o') exit(5);
+    if (recv(pair[0], buf, sizeof buf, MSG_DONTWAIT) != -EAGAIN) exit(6);
+
+    // Shutting down writing ends the other end's stream.
+    if (shutdown(pair[1], SHUT_WR) != 0) exit(7);
+    if (recv(pair[0], buf, sizeof buf, 0) != 0) exit(8);
+    PollFd poll_fd = {.fd = pair[0], .events = POLLIN | POLLOUT};
+    if (poll(&poll_fd, 1, 0) != 1 || !(poll_fd.revents & POLLIN)) exit(9);
+    close(pair[0]);
+    close(pair[1]);
+
+    int fd = open("/", O_RDONLY);
+    if (listen(fd, 1) != -ENOTSOCK) exit(10);
+    close(fd);
+    if (socket(2, SOCK_STREAM, 0) != -EAFNOSUPPORT) exit(11);
+
+    // A server accepts a connection from its child.
+    SockAddrUn addr = address("/server");
+    int server = socket(AF_UNIX, SOCK_STREAM, 0);
+    if (server < 0) exit(12);
+    if (listen(server, 4) != -EINVAL) exit(13); // not bound yet
+    if (bind(server, &addr, sizeof addr) != 0) exit(14);
+    if (bind(server, &addr, sizeof addr) != -EINVAL) exit(15);
+    if (listen(server, 4) != 0) exit(16);
+    PollFd server_poll = {.fd = server, .events = POLLIN};
+    if (poll(&server_poll, 1, 0) != 0) exit(17); // nothing to accept
+    if (open("/server", O_RDWR) != -ENXIO) exit(18);
+
+    Pid pid = fork();
+    if (pid == 0) {
+        int client = socket(AF_UNIX, SOCK_STREAM, 0);
+        if (connect(client, &addr, sizeof addr) != 0) exit(1);
+        if (connect(client, &addr, sizeof addr) != -EISCONN) exit(2);
+        if (send(client, (const uint8_t *)"hello", 5, 0) != 5) exit(3);
+        if (recv(client, buf, sizeof buf, 0) != 2 || buf[0] != 'h' || buf[1] != 'i') exit(4);
+        exit(0);
+    }
+    SockAddrUn peer;
+    SockLen peer_len = sizeof peer;
+    int connection = accept(server, &peer, &peer_len);
+    if (connection < 0) exit(19);
+    if (peer_len != sizeof peer.family || peer.family != AF_UNIX) exit(20);
+    if (recv(connection, buf, 5, 0) != 5 || buf[4] != 'o') exit(21);
+    if (send(connection, (const uint8_t *)"hi", 2, 0) != 2) exit(22);
+    int32_t status;
+    if (waitpid(pid, &status, 0) != pid) exit(23);
+    if (!wifexited(status) || wexitstatus(status) != 0) exit(24);
+    // the client is gone
+    if (recv(connection, buf, sizeof buf, 0) != 0) exit(25);
+    close(connection);
+
+    // The address stays taken while the file is there, but connecting to it
+    // is refused once the socket is closed.
+    int other = socket(AF_UNIX, SOCK_STREAM, 0);
+    if (bind(other, &addr, sizeof addr) != -EADDRINUSE) exit(26);
+    close(server);
+    if (connect(other, &addr, sizeof addr) != -ECONNREFUSED) exit(27);
+    close(other);
+    if (unlink("/server") != 0) exit(28);
+
+    // Datagrams keep their boundaries and say who sent them.
+    SockAddrUn receiver_addr = address("/receiver");
+    SockAddrUn sender_addr = address("/sender");
+    int receiver = socket(AF_UNIX, SOCK_DGRAM, 0);
+    int sender = socket(AF_UNIX, SOCK_DGRAM | SOCK_CLOEXEC, 0);
+    if (bind(receiver, &receiver_addr, sizeof receiver_addr) != 0) exit(29);
+    if (bind(sender, &sender_addr, sizeof sender_addr) != 0) exit(30);
+    if (send(sender, (const uint8_t *)"one", 3, 0) != -ENOTCONN) exit(31);
+    if (sendto(sender, (const uint8_t *)"one", 3, 0, &receiver_addr, sizeof receiver_addr) != 3)
+        exit(32);
+    if (connect(sender, &receiver_addr, sizeof receiver_addr) != 0) exit(33);
+    if (send(sender, (const uint8_t *)"three", 5, 0) != 5) exit(34);
+    peer_len = sizeof peer;
+    if (recvfrom(receiver, buf, sizeof buf, 0, &peer, &peer_len) != 3) exit(35);
+    if (peer.path[0] != '/' || peer.path[1] != 's' || peer_len <= sizeof peer.family) exit(36);
+    // too long to fit, so it's cut short
+    if (recv(receiver, buf, 2, 0) != 2 || buf[0] != 't') exit(37);
+    if (recv(receiver, buf, sizeof buf, MSG_DONTWAIT) != -EAGAIN) exit(38);
+    close(receiver);
+    close(sender);
+    if (unlink("/receiver") != 0 || unlink("/sender") != 0) exit(39);
+
+    exit(0);
+}
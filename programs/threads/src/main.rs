@@ -0,0 +1,90 @@
+#![cfg_attr(not(test), no_std)]
+#![cfg_attr(not(test), no_main)]
+
+use core::ffi::c_void;
+use core::sync::atomic::{AtomicU32, Ordering};
+use kidneyos_syscalls::sync::{Condvar, Mutex};
+
+const THREADS: usize = 4;
+const INCREMENTS: u32 = 1000;
+
+static COUNTER: AtomicU32 = AtomicU32::new(0);
+// The number of threads which are done counting.
+static FINISHED: Mutex<usize> = Mutex::new(0);
+static ALL_FINISHED: Condvar = Condvar::new();
+
+// A stack provided by the program itself, rather than by the kernel.
+static mut STACK: [u8; 4096] = [0; 4096];
+
+extern "C" fn count(arg: *mut c_void) {
+    for _ in 0..INCREMENTS {
+        COUNTER.fetch_add(1, Ordering::SeqCst);
+        kidneyos_syscalls::scheduler_yield();
+    }
+
+    let mut finished = FINISHED.lock();
+    *finished += 1;
+    if *finished == THREADS {
+        ALL_FINISHED.notify_all();
+    }
+    drop(finished);
+
+    // Each thread exits with the argument it was given.
+    kidneyos_syscalls::thread_exit(arg as i32);
+}
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    let mut tids = [0; THREADS];
+    for (i, tid) in tids.iter_mut().enumerate() {
+        let stack = if i == 0 {
+            unsafe { core::ptr::addr_of_mut!(STACK).cast::<u8>().add(4096).cast() }
+        } else {
+            core::ptr::null_mut()
+        };
+        let result = kidneyos_syscalls::thread_create(count, (i + 1) as *mut c_void, stack);
+        if result < 0 {
+            kidneyos_syscalls::exit(1);
+        }
+        *tid = result as kidneyos_syscalls::Tid;
+    }
+
+    if tids.contains(&kidneyos_syscalls::gettid()) {
+        kidneyos_syscalls::exit(2);
+    }
+
+    let mut finished = FINISHED.lock();
+    while *finished != THREADS {
+        finished = ALL_FINISHED.wait(finished);
+    }
+    drop(finished);
+
+    for (i, tid) in tids.iter().enumerate() {
+        let mut exit_code = 0;
+        if kidneyos_syscalls::thread_join(*tid, &mut exit_code) != 0 {
+            kidneyos_syscalls::exit(3);
+        }
+        if exit_code != (i + 1) as i32 {
+            kidneyos_syscalls::exit(4);
+        }
+    }
+
+    // Joining a thread twice fails.
+    if kidneyos_syscalls::thread_join(tids[0], core::ptr::null_mut()) == 0 {
+        kidneyos_syscalls::exit(5);
+    }
+
+    if COUNTER.load(Ordering::SeqCst) != THREADS as u32 * INCREMENTS {
+        kidneyos_syscalls::exit(6);
+    }
+
+    kidneyos_syscalls::exit(0);
+
+    loop {}
+}
+
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(_info: &core::panic::PanicInfo) -> ! {
+    loop {}
+}
@@ -0,0 +1,65 @@
+#include <kidneyos.h>
+
+__thread int counter = 5;
+__thread char zeroed[16];
+
+static void *thread_pointer() {
+    void *pointer;
+    __asm__ volatile("movl %%gs:0, %0" : "=r"(pointer));
+    return pointer;
+}
+
+void _start() {
+    // The loader sets up the main thread's TLS from the PT_TLS segment.
+    if (counter != 5) exit(1);
+    for (int i = 0; i < 16; i++) {
+        if (zeroed[i] != 0) exit(2);
+    }
+    if (*(void **)thread_pointer() != thread_pointer()) exit(3);
+    counter++;
+
+    // A forked child gets a copy of our TLS.
+    Pid pid = fork();
+    if (pid == 0) {
+        if (counter != 6) exit(4);
+        counter = 100;
+        exit(0);
+    }
+    int status;
+    if (waitpid(pid, &status, 0) != pid || status != 0) exit(5);
+    if (counter != 6) exit(6);
+
+    // Point gs at a TLS block of our own.
+    static void *block[2];
+    block[0] = &block[0];
+    block[1] = (void *)0x1234;
+    UserDesc desc = {
+        .entry_number = -1,
+        .base_addr = (uint32_t)block,
+        .limit = 0xfffff,
+        .flags = USER_DESC_SEG_32BIT | USER_DESC_LIMIT_IN_PAGES,
+    };
+    if (set_thread_area(&desc) != 0) exit(7);
+    uint16_t selector = (desc.entry_number << 3) | 3;
+    __asm__ volatile("movw %0, %%gs" : : "r"(selector));
+    void *second;
+    __asm__ volatile("movl %%gs:4, %0" : "=r"(second));
+    if (thread_pointer() != block || second != (void *)0x1234) exit(8);
+
+    // The new segment survives being switched out.
+    scheduler_yield();
+    if (thread_pointer() != block) exit(9);
+
+    // A failed call leaves the descriptor alone.
+    UserDesc code = {.entry_number = -1, .flags = USER_DESC_CONTENTS_CODE};
+    if (set_thread_area(&code) != -EINVAL || code.entry_number != (uint32_t)-1) exit(10);
+
+    // An empty, read-only, not present descriptor clears the entry.
+    UserDesc clear = {
+        .entry_number = -1,
+        .flags = USER_DESC_READ_EXEC_ONLY | USER_DESC_SEG_NOT_PRESENT,
+    };
+    if (set_thread_area(&clear) != 0 || clear.entry_number != desc.entry_number) exit(11);
+
+    exit(0);
+}
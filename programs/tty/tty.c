@@ -0,0 +1,60 @@
+#include <kidneyos.h>
+
+static void sleep_ms(int64_t ms) {
+    Timespec duration = {.tv_sec = 0, .tv_nsec = ms * 1000000};
+    nanosleep(&duration, 0);
+}
+
+void _start() {
+    // The terminal starts out in canonical mode with echo and signals.
+    Termios original;
+    if (tcgetattr(0, &original) != 0) exit(1);
+    if ((original.lflag & (ICANON | ECHO | ISIG)) != (ICANON | ECHO | ISIG)) exit(2);
+    if (original.cc[VINTR] != 0x03 || original.cc[VMIN] != 1) exit(3);
+
+    // Only the terminal takes terminal requests.
+    int fds[2];
+    Termios check;
+    if (pipe(fds) != 0) exit(4);
+    if (tcgetattr(fds[0], &check) != -ENOTTY) exit(5);
+
+    // In raw mode with neither VMIN nor VTIME, reads don't wait for input,
+    // and with just VTIME they wait that long for it.
+    Termios raw = original;
+    raw.lflag &= ~(ICANON | ECHO);
+    raw.cc[VMIN] = 0;
+    raw.cc[VTIME] = 0;
+    if (tcsetattr(0, TCSAFLUSH, &raw) != 0) exit(6);
+    if (tcgetattr(0, &check) != 0 || check.lflag != raw.lflag) exit(7);
+    uint8_t byte;
+    if (read(0, &byte, 1) != 0) exit(8);
+    raw.cc[VTIME] = 1;
+    if (tcsetattr(0, TCSANOW, &raw) != 0) exit(9);
+    if (read(0, &byte, 1) != 0) exit(10);
+    if (tcsetattr(0, TCSANOW, &original) != 0) exit(11);
+
+    // A child can be moved into a group of its own, which can be made the
+    // foreground group and signalled as a whole.
+    Pid pgrp = getpgrp();
+    if (tcsetpgrp(0, pgrp) != 0 || tcgetpgrp(0) != pgrp) exit(12);
+    Pid pid = fork();
+    if (pid == 0) {
+        setpgid(0, 0);
+        for (;;) sleep_ms(1000);
+    }
+    if (setpgid(pid, pid) != 0) exit(13);
+    if (getpgid(pid) != pid || getpgid(0) != pgrp) exit(14);
+    // Waiting for our own group doesn't wait for children in other groups.
+    int status;
+    if (waitpid(0, &status, WNOHANG) != -ECHILD || waitpid(-pid, &status, WNOHANG) != 0) exit(15);
+    if (tcsetpgrp(0, pid) != 0 || tcgetpgrp(0) != pid) exit(16);
+    if (kill(-pid, SIGKILL) != 0) exit(17);
+    if (waitpid(-pid, &status, 0) != pid || status != SIGKILL) exit(18);
+    if (tcsetpgrp(0, pgrp) != 0) exit(19);
+
+    // Groups must exist to join them or bring them to the foreground.
+    if (setpgid(0, pid) != -EPERM) exit(20);
+    if (tcsetpgrp(0, pid) != -EPERM) exit(21);
+
+    exit(0);
+}
@@ -3,28 +3,21 @@
 
 #[no_mangle]
 pub extern "C" fn _start() -> ! {
-    let mut status: i32 = 0;
-    // Currently hard coding the wait process for testing
-    kidneyos_syscalls::waitpid(2, &mut status, 1);
+    let p = kidneyos_syscalls::fork();
 
-    let exit_code = kidneyos_syscalls::wifexitstatus(status) as usize;
-    kidneyos_syscalls::exit(exit_code);
+    if p == 0 {
+        kidneyos_syscalls::exit(1);
+    } else {
+        let mut status: i32 = 0;
+        kidneyos_syscalls::waitpid(i32::from(p), &mut status, 0);
+        if !kidneyos_syscalls::wifexited(status) {
+            kidneyos_syscalls::exit(2);
+        }
 
-    // let p = kidneyos_syscalls::fork();
-
-    // if p == 0 {
-    //     kidneyos_syscalls::exit(1);
-    // } else {
-    //     let mut status: i32 = 0;
-    //     kidneyos_syscalls::waitpid(p, &mut status, 0);
-    //     if kidneyos_syscalls::wifexited(status) {
-    //         kidneyos_syscalls::exit(2);
-    //     }
-
-    //     let exit_code = kidneyos_syscalls::wifexitstatus(status) as usize;
-    //     // Should be 1
-    //     kidneyos_syscalls::exit(exit_code);
-    // }
+        let exit_code = kidneyos_syscalls::wexitstatus(status);
+        // Should be 1
+        kidneyos_syscalls::exit(exit_code);
+    }
 
     loop {}
 }
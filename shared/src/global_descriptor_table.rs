@@ -14,7 +14,7 @@ struct GDTDescriptor {
     offset: u32,
 }
 
-const GDT_LEN: usize = 6;
+const GDT_LEN: usize = 7;
 
 static mut GDT: [SegmentDescriptor; GDT_LEN] = [
     // Null Descriptor
@@ -42,13 +42,7 @@ static mut GDT: [SegmentDescriptor; GDT_LEN] = [
         // Means we can read, since this is a code segment.
         .with_read_write(true),
     // User Mode Data
-    SegmentDescriptor::UNLIMITED
-        .with_present(true)
-        // Allow unprivileged access.
-        .with_descriptor_privilege_level(3u8)
-        .with_type(true)
-        // Means we can write, since this is a data segment.
-        .with_read_write(true),
+    USER_DATA_DESCRIPTOR,
     SegmentDescriptor::default()
         .with_accessed(true)
         // Executable doesn't actually mean executable here, we just have to
@@ -56,8 +50,20 @@ static mut GDT: [SegmentDescriptor; GDT_LEN] = [
         .with_executable(true)
         .with_limit(size_of::<TaskStateSegment>() as u32 - 1)
         .with_present(true),
+    // User Mode Thread-Local Storage, which is switched along with threads
+    USER_DATA_DESCRIPTOR,
 ];
 
+/// The segment user programs use for their data, and which threads' TLS
+/// segments start out as.
+pub const USER_DATA_DESCRIPTOR: SegmentDescriptor = SegmentDescriptor::UNLIMITED
+    .with_present(true)
+    // Allow unprivileged access.
+    .with_descriptor_privilege_level(3u8)
+    .with_type(true)
+    // Means we can write, since this is a data segment.
+    .with_read_write(true);
+
 pub const KERNEL_CODE_SELECTOR: u16 = SegmentSelector::default().with_index(1).load();
 pub const KERNEL_DATA_SELECTOR: u16 = SegmentSelector::default().with_index(2).load();
 pub const USER_CODE_SELECTOR: u16 = SegmentSelector::default()
@@ -69,6 +75,14 @@ pub const USER_DATA_SELECTOR: u16 = SegmentSelector::default()
     .with_index(4)
     .load();
 const TSS_INDEX: usize = 5;
+/// The GDT entry of the running thread's TLS segment. This is the first of the
+/// entries Linux reserves for TLS, so that programs built for Linux find it
+/// where they expect.
+pub const USER_TLS_INDEX: usize = 6;
+pub const USER_TLS_SELECTOR: u16 = SegmentSelector::default()
+    .with_requested_privilege_level(3)
+    .with_index(USER_TLS_INDEX as u16)
+    .load();
 const TSS_SELECTOR: u16 = SegmentSelector::default()
     .with_index(TSS_INDEX as u16)
     .load();
@@ -107,3 +121,16 @@ pub unsafe fn load() {
         options(att_syntax),
     );
 }
+
+/// Replaces the descriptor of the TLS segment.
+///
+/// Segment registers which already hold `USER_TLS_SELECTOR` keep using the old
+/// descriptor until they're reloaded.
+///
+/// # Safety
+///
+/// The GDT must have been loaded, and interrupts must be disabled so that no
+/// other thread runs with the wrong TLS segment.
+pub unsafe fn set_user_tls(descriptor: SegmentDescriptor) {
+    GDT[USER_TLS_INDEX] = descriptor;
+}
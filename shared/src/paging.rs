@@ -111,9 +111,39 @@ bitfield!(
         (dirty, 6),
         (page_attribute_table, 7),
         (global, 8),
+        // Bits 9-11 are ignored by the CPU and available for our own use.
+        (copy_on_write, 9),
+        // Pages of MAP_SHARED mappings, which stay writeable when shared by fork.
+        (shared, 10),
     }
 );
 
+/// The flags of a mapped page.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PageFlags {
+    /// Whether the page can be written to.
+    pub write: bool,
+    /// Whether the page can be accessed from user mode.
+    pub user: bool,
+    /// Whether the page becomes writeable once it's copied, see
+    /// `share_user_pages`.
+    pub copy_on_write: bool,
+    /// Whether the page stays shared when the page tables are shared, see
+    /// `map_shared`.
+    pub shared: bool,
+}
+
+impl PageFlags {
+    fn of(entry: &PageTableEntry) -> Self {
+        Self {
+            write: entry.read_write(),
+            user: entry.user_supervisor(),
+            copy_on_write: entry.copy_on_write(),
+            shared: entry.shared(),
+        }
+    }
+}
+
 fn virt_parts(virt_addr: usize) -> (usize, usize) {
     bitfield!(
         VirtualAddress, u32
@@ -131,6 +161,23 @@ fn virt_parts(virt_addr: usize) -> (usize, usize) {
     )
 }
 
+fn virt_addr_of(page_directory_index: usize, page_table_index: usize) -> usize {
+    page_directory_index * HUGE_PAGE_SIZE + page_table_index * PAGE_FRAME_SIZE
+}
+
+/// Invalidates any TLB entry for the page containing `virt_addr`.
+///
+/// # Safety
+///
+/// Must be executed in ring 0.
+unsafe fn invalidate_page(virt_addr: usize) {
+    asm!("invlpg [{}]", in(reg) virt_addr, options(nostack));
+}
+
+/// A range of virtual addresses went past the end of the address space.
+#[derive(Debug, PartialEq, Eq)]
+pub struct RangeOverflow;
+
 /// Wraps lower-level paging data structures.
 #[derive(Debug)]
 pub struct PageManager<A: Allocator> {
@@ -194,10 +241,17 @@ impl<A: Allocator> PageManager<A> {
     }
 
     /// Returns whether these page tables are loaded.
+    #[cfg(not(test))]
     pub fn is_loaded(&self) -> bool {
         let current_root: usize;
         unsafe { asm!("mov {}, cr3", out(reg) current_root, options(nomem, nostack)) };
-        current_root != self.root.as_ptr() as usize - self.phys_to_alloc_addr_offset
+        current_root == self.root.as_ptr() as usize - self.phys_to_alloc_addr_offset
+    }
+
+    /// Host-side tests never load page tables, and cr3 can only be read in ring 0.
+    #[cfg(test)]
+    pub fn is_loaded(&self) -> bool {
+        false
     }
 
     /// Maps virtual addresses from `virt_addr..(virt_addr + PAGE_FRAME_SIZE)`
@@ -278,6 +332,20 @@ impl<A: Allocator> PageManager<A> {
             .with_page_table_frame(phys_frame);
     }
 
+    /// Like `map` for a user page, except that the page is marked as shared,
+    /// so `share_user_pages` gives the other page tables a mapping to the same
+    /// frame with the same permissions, instead of making it copy-on-write.
+    ///
+    /// # Safety
+    ///
+    /// Same as `map`.
+    pub unsafe fn map_shared(&mut self, phys_addr: usize, virt_addr: usize, write: bool) {
+        self.map(phys_addr, virt_addr, write, true);
+        if let Some(entry) = self.page_table_entry_mut(virt_addr) {
+            *entry = entry.with_shared(true);
+        }
+    }
+
     /// Like map, except with length `HUGE_PAGE_SIZE`. `virt_addr` must have an
     /// alignment of `HUGE_PAGE_SIZE`, but `phys_addr` only needs to be aligned
     /// to `PAGE_FRAME_SIZE`. PSE must be enabled.
@@ -391,6 +459,140 @@ impl<A: Allocator> PageManager<A> {
         self.map_range(start, start, frames_len, write, user);
     }
 
+    /// Removes the mapping for the page containing `virt_addr`, returning the
+    /// physical address of the frame it was mapped to, if it was mapped. The
+    /// frame itself is not freed, but the page table is if it becomes empty.
+    ///
+    /// If these page tables are loaded, the TLB entry for the page is
+    /// invalidated, so the mapping is removed immediately.
+    ///
+    /// # Safety
+    ///
+    /// Nothing may refer to the page anymore, and if these page tables are
+    /// loaded, this must be executed in ring 0.
+    pub unsafe fn unmap(&mut self, virt_addr: usize) -> Option<usize> {
+        let loaded = self.is_loaded();
+        let (pdi, pti) = virt_parts(virt_addr);
+        let page_directory = self.root.as_mut();
+        if !page_directory[pdi].present() || page_directory[pdi].page_size() {
+            return None;
+        }
+
+        let page_table = &mut *page_directory.page_table(pdi, self.phys_to_alloc_addr_offset);
+        let entry = &mut page_table[pti];
+        if !entry.present() {
+            return None;
+        }
+        let phys_addr = entry.page_table_frame() as usize * PAGE_FRAME_SIZE;
+        *entry = PageTableEntry::default();
+
+        if page_table.iter().all(|entry| !entry.present()) {
+            page_directory[pdi] = PageDirectoryEntry::default();
+            let page_table_addr = NonNull::from(page_table).cast::<u8>();
+            self.alloc.deallocate(page_table_addr, PAGE_TABLE_LAYOUT);
+        }
+
+        if loaded {
+            // This also invalidates any cached page directory entries, so the
+            // freed page table can't be used anymore either.
+            invalidate_page(virt_addr);
+        }
+        Some(phys_addr)
+    }
+
+    /// Removes the mappings for the pages of
+    /// `virt_start..(virt_start + len)`, like `unmap`. `virt_start` and `len`
+    /// must be multiples of `PAGE_FRAME_SIZE`. `unmapped` is called with the
+    /// physical address of each frame that was mapped.
+    ///
+    /// Returns `RangeOverflow`, without unmapping anything, if the range
+    /// doesn't fit in the address space.
+    ///
+    /// # Safety
+    ///
+    /// Same as `unmap`, for every page in the range.
+    pub unsafe fn unmap_range<F: FnMut(usize)>(
+        &mut self,
+        virt_start: usize,
+        len: usize,
+        mut unmapped: F,
+    ) -> Result<(), RangeOverflow> {
+        assert_eq!(
+            virt_start % PAGE_FRAME_SIZE,
+            0,
+            "virt_start was not page-frame-aligned"
+        );
+        assert_eq!(
+            len % PAGE_FRAME_SIZE,
+            0,
+            "len was not a multiple of PAGE_FRAME_SIZE"
+        );
+
+        let virt_end = virt_start.checked_add(len).ok_or(RangeOverflow)?;
+        let mut virt_addr = virt_start;
+        while virt_addr < virt_end {
+            let (pdi, _) = virt_parts(virt_addr);
+            if !self.root.as_ref()[pdi].present() {
+                // Skip the rest of the page table that would have been here,
+                // which ends the walk if it's the last one.
+                if pdi == PAGE_DIRECTORY_LEN - 1 {
+                    break;
+                }
+                virt_addr = virt_addr_of(pdi + 1, 0);
+                continue;
+            }
+            if let Some(phys_addr) = self.unmap(virt_addr) {
+                unmapped(phys_addr);
+            }
+            virt_addr += PAGE_FRAME_SIZE;
+        }
+        Ok(())
+    }
+
+    /// Changes the flags of the page containing `virt_addr` to `flags`,
+    /// keeping the frame it's mapped to. Returns `false` if the page isn't
+    /// mapped, or is part of a huge page.
+    ///
+    /// If these page tables are loaded, the TLB entry for the page is
+    /// invalidated, so the new flags take effect immediately.
+    ///
+    /// # Safety
+    ///
+    /// Same as `unmap`, for whatever no longer has access to the page.
+    pub unsafe fn protect(&mut self, virt_addr: usize, flags: PageFlags) -> bool {
+        let loaded = self.is_loaded();
+        let (pdi, pti) = virt_parts(virt_addr);
+        let page_directory = self.root.as_mut();
+        let pde = page_directory[pdi];
+        if !pde.present() || pde.page_size() {
+            return false;
+        }
+
+        let page_table = &mut *page_directory.page_table(pdi, self.phys_to_alloc_addr_offset);
+        let entry = &mut page_table[pti];
+        if !entry.present() {
+            return false;
+        }
+        *entry = entry
+            .with_read_write(flags.write)
+            .with_user_supervisor(flags.user)
+            .with_copy_on_write(flags.copy_on_write)
+            .with_shared(flags.shared);
+
+        // Like in `map`, the page directory entry only ever needs to allow more.
+        if (flags.write || flags.copy_on_write) && !pde.read_write() {
+            page_directory[pdi] = page_directory[pdi].with_read_write(true);
+        }
+        if flags.user && !pde.user_supervisor() {
+            page_directory[pdi] = page_directory[pdi].with_user_supervisor(true);
+        }
+
+        if loaded {
+            invalidate_page(virt_addr);
+        }
+        true
+    }
+
     /// Returns whether `pointer` is valid for reads if `write = false`, and writes if `write = true`.
     pub fn can_access(&self, pointer: usize, write: bool) -> bool {
         let (pdi, pti) = virt_parts(pointer);
@@ -415,7 +617,9 @@ impl<A: Allocator> PageManager<A> {
         if !entry.present() {
             return false;
         }
-        !write || entry.read_write()
+        // Writing to a copy-on-write page will fault, but the fault handler
+        // will give us our own writable copy, so we treat it as writable.
+        !write || entry.read_write() || entry.copy_on_write()
     }
 
     /// Returns whether `pointer..pointer+count` is valid for reads if `write = false`, and writes if `write = true`.
@@ -453,6 +657,185 @@ impl<A: Allocator> PageManager<A> {
     pub fn is_range_writeable(&self, pointer: usize, count: usize) -> bool {
         self.can_access_range(pointer, count, true)
     }
+
+    /// Returns the physical address that `virt_addr` is mapped to, and the
+    /// flags of the page it's in, if it is mapped.
+    pub fn translate(&self, virt_addr: usize) -> Option<(usize, PageFlags)> {
+        let (pdi, pti) = virt_parts(virt_addr);
+        let page_directory = unsafe { self.root.as_ref() };
+
+        let entry = &page_directory.0[pdi];
+        if !entry.present() {
+            return None;
+        }
+
+        if entry.page_size() {
+            // Huge page
+            let frame = entry.page_table_frame() as usize * PAGE_FRAME_SIZE;
+            let flags = PageFlags {
+                write: entry.read_write(),
+                user: entry.user_supervisor(),
+                copy_on_write: false,
+                shared: false,
+            };
+            return Some((frame + virt_addr % HUGE_PAGE_SIZE, flags));
+        }
+
+        let page_table =
+            unsafe { &*page_directory.page_table(pdi, self.phys_to_alloc_addr_offset) };
+        let entry = &page_table.0[pti];
+        if !entry.present() {
+            return None;
+        }
+        let frame = entry.page_table_frame() as usize * PAGE_FRAME_SIZE;
+        Some((frame + virt_addr % PAGE_FRAME_SIZE, PageFlags::of(entry)))
+    }
+
+    /// Returns an iterator over the user pages mapped by these page tables, in
+    /// order of virtual address. Each item is the virtual address of a page,
+    /// the physical address of the frame it's mapped to, and its flags.
+    ///
+    /// Like in `share_user_pages`, huge pages are not included.
+    pub fn user_mappings(&self) -> impl '_ + Iterator<Item = (usize, usize, PageFlags)> {
+        let phys_to_alloc_addr_offset = self.phys_to_alloc_addr_offset;
+        let page_directory = unsafe { self.root.as_ref() };
+
+        page_directory
+            .iter()
+            .enumerate()
+            .filter(|(_, pde)| pde.present() && pde.user_supervisor() && !pde.page_size())
+            .flat_map(move |(pdi, _)| {
+                let page_table =
+                    unsafe { &*page_directory.page_table(pdi, phys_to_alloc_addr_offset) };
+                page_table
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, pte)| pte.present() && pte.user_supervisor())
+                    .map(move |(pti, pte)| {
+                        let phys_addr = pte.page_table_frame() as usize * PAGE_FRAME_SIZE;
+                        (virt_addr_of(pdi, pti), phys_addr, PageFlags::of(pte))
+                    })
+            })
+    }
+
+    /// Returns the page table entry for `virt_addr`, if there is a page table
+    /// (as opposed to a huge page, or nothing) covering it.
+    fn page_table_entry_mut(&mut self, virt_addr: usize) -> Option<&mut PageTableEntry> {
+        let (pdi, pti) = virt_parts(virt_addr);
+        let page_directory = unsafe { self.root.as_mut() };
+
+        let entry = &page_directory[pdi];
+        if !entry.present() || entry.page_size() {
+            return None;
+        }
+
+        let page_table =
+            unsafe { &mut *page_directory.page_table(pdi, self.phys_to_alloc_addr_offset) };
+        Some(&mut page_table[pti])
+    }
+
+    /// Maps every user page in these page tables into `other` at the same
+    /// virtual address, so that both refer to the same physical frames.
+    /// Writeable pages are made read-only and marked copy-on-write in both
+    /// page tables, so that the first write from either side will fault and
+    /// can be resolved with `resolve_copy_on_write`. The reference count of
+    /// every shared frame is incremented in `ref_counts`.
+    ///
+    /// The user pages in `other` must not already be mapped. If these page
+    /// tables are loaded, `load` must be called again before the newly
+    /// read-only pages are guaranteed to be recognized by the CPU.
+    ///
+    /// # Safety
+    ///
+    /// Same as `map`, for both `self` and `other`.
+    pub unsafe fn share_user_pages<R: Allocator>(
+        &mut self,
+        other: &mut Self,
+        ref_counts: &mut FrameRefCounts<R>,
+    ) {
+        let phys_to_alloc_addr_offset = self.phys_to_alloc_addr_offset;
+        let page_directory = self.root.as_mut();
+
+        for pdi in 0..PAGE_DIRECTORY_LEN {
+            let pde = page_directory[pdi];
+            if !pde.present() || !pde.user_supervisor() || pde.page_size() {
+                continue;
+            }
+
+            let page_table = &mut *page_directory.page_table(pdi, phys_to_alloc_addr_offset);
+            for (pti, pte) in page_table.iter_mut().enumerate() {
+                if !pte.present() || !pte.user_supervisor() {
+                    continue;
+                }
+
+                if pte.read_write() && !pte.shared() {
+                    *pte = pte.with_read_write(false).with_copy_on_write(true);
+                }
+
+                let phys_addr = pte.page_table_frame() as usize * PAGE_FRAME_SIZE;
+                let virt_addr = virt_addr_of(pdi, pti);
+                // The page directory entry must be writeable for shared pages,
+                // and for copy-on-write pages to ever become writeable again.
+                other.map(
+                    phys_addr,
+                    virt_addr,
+                    pte.read_write() || pte.copy_on_write(),
+                    true,
+                );
+                if let Some(other_pte) = other.page_table_entry_mut(virt_addr) {
+                    *other_pte = *pte;
+                }
+
+                ref_counts.share(phys_addr);
+            }
+        }
+    }
+
+    /// If `virt_addr` is mapped copy-on-write, returns the physical address
+    /// of the frame it is currently mapped to.
+    pub fn copy_on_write_frame(&mut self, virt_addr: usize) -> Option<usize> {
+        let entry = self.page_table_entry_mut(virt_addr)?;
+        if !entry.present() || !entry.copy_on_write() {
+            return None;
+        }
+        Some(entry.page_table_frame() as usize * PAGE_FRAME_SIZE)
+    }
+
+    /// Replaces the copy-on-write mapping for the page containing `virt_addr`
+    /// with a writeable mapping to `phys_addr`, which must be
+    /// page-frame-aligned. `phys_addr` may be the frame that was already
+    /// mapped if no one else refers to it anymore.
+    ///
+    /// The TLB entry for the page is invalidated, so if these page tables are
+    /// loaded, the new mapping takes effect immediately.
+    ///
+    /// # Safety
+    ///
+    /// Same as `map`. Additionally, `virt_addr` must currently be mapped
+    /// copy-on-write, and this must be executed in ring 0.
+    pub unsafe fn resolve_copy_on_write(&mut self, virt_addr: usize, phys_addr: usize) {
+        assert_eq!(
+            phys_addr % PAGE_FRAME_SIZE,
+            0,
+            "phys_addr was not page-frame-aligned"
+        );
+
+        let Some(entry) = self.page_table_entry_mut(virt_addr) else {
+            panic!("virtual address {:#X} was not mapped", virt_addr);
+        };
+        assert!(
+            entry.present() && entry.copy_on_write(),
+            "virtual address {:#X} was not mapped copy-on-write",
+            virt_addr
+        );
+
+        *entry = entry
+            .with_read_write(true)
+            .with_copy_on_write(false)
+            .with_page_table_frame((phys_addr / PAGE_FRAME_SIZE) as u32);
+
+        invalidate_page(virt_addr);
+    }
 }
 
 impl<A: Allocator + Copy> Clone for PageManager<A> {
@@ -497,6 +880,98 @@ impl<A: Allocator> Drop for PageManager<A> {
     }
 }
 
+unsafe impl<A: Allocator + Send> Send for PageManager<A> {}
+
+/// Counts how many page tables map each physical frame which is shared between
+/// them, e.g. after a fork.
+///
+/// Frames which are only mapped by a single set of page tables are not tracked,
+/// so a count of zero means that a frame is owned exclusively by whoever maps
+/// it.
+pub struct FrameRefCounts<A: Allocator> {
+    counts: NonNull<[u16]>,
+    alloc: A,
+}
+
+impl<A: Allocator> FrameRefCounts<A> {
+    /// Creates reference counts covering the physical addresses
+    /// `0..(frames * PAGE_FRAME_SIZE)`.
+    pub fn new_in(frames: usize, alloc: A) -> Self {
+        let Ok(layout) = Layout::array::<u16>(frames) else {
+            panic!("too many frames");
+        };
+        let Ok(counts) = alloc.allocate_zeroed(layout) else {
+            panic!("allocation failed");
+        };
+
+        Self {
+            counts: NonNull::slice_from_raw_parts(counts.cast::<u16>(), frames),
+            alloc,
+        }
+    }
+
+    fn count_mut(&mut self, phys_addr: usize) -> &mut u16 {
+        let frame = phys_addr / PAGE_FRAME_SIZE;
+        let counts = unsafe { self.counts.as_mut() };
+        let Some(count) = counts.get_mut(frame) else {
+            panic!("physical address {:#X} is not tracked", phys_addr);
+        };
+        count
+    }
+
+    /// Returns whether the frame containing `phys_addr` is mapped by more than
+    /// one set of page tables.
+    pub fn is_shared(&self, phys_addr: usize) -> bool {
+        let counts = unsafe { self.counts.as_ref() };
+        counts
+            .get(phys_addr / PAGE_FRAME_SIZE)
+            .is_some_and(|&count| count != 0)
+    }
+
+    /// Records that the frame containing `phys_addr` has been mapped by one
+    /// more set of page tables.
+    pub fn share(&mut self, phys_addr: usize) {
+        let count = self.count_mut(phys_addr);
+        *count = match *count {
+            // The frame was owned exclusively, so now it's mapped twice.
+            0 => 2,
+            n => n.checked_add(1).expect("frame reference count overflowed"),
+        };
+    }
+
+    /// Records that one set of page tables no longer maps the frame containing
+    /// `phys_addr`.
+    ///
+    /// Returns `true` if that was the last reference to the frame, in which
+    /// case the caller is responsible for freeing it.
+    pub fn release(&mut self, phys_addr: usize) -> bool {
+        let count = self.count_mut(phys_addr);
+        match *count {
+            0 => true,
+            // Only one mapping remains, so it owns the frame exclusively.
+            2 => {
+                *count = 0;
+                false
+            }
+            n => {
+                *count = n - 1;
+                false
+            }
+        }
+    }
+}
+
+impl<A: Allocator> Drop for FrameRefCounts<A> {
+    fn drop(&mut self) {
+        let Ok(layout) = Layout::array::<u16>(self.counts.len()) else {
+            panic!("too many frames");
+        };
+        unsafe { self.alloc.deallocate(self.counts.cast::<u8>(), layout) };
+    }
+}
+
+unsafe impl<A: Allocator + Send> Send for FrameRefCounts<A> {}
+
 /// Enable paging in the CPU.
 ///
 /// # Safety
@@ -643,3 +1118,203 @@ pub fn kernel_mapping_ranges() -> [MappingRange; 5] {
         },
     ]
 }
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+
+    use std::{alloc::Global, vec::Vec};
+
+    const USER: PageFlags = PageFlags {
+        write: true,
+        user: true,
+        copy_on_write: false,
+        shared: false,
+    };
+
+    // Page tables are allocated from the host's heap, which stands in for
+    // physical memory, so none of these frames are ever accessed.
+    fn page_manager() -> PageManager<Global> {
+        PageManager::new_in(Global, 0)
+    }
+
+    fn has_page_table(page_manager: &PageManager<Global>, virt_addr: usize) -> bool {
+        let (pdi, _) = virt_parts(virt_addr);
+        unsafe { page_manager.root.as_ref()[pdi].present() }
+    }
+
+    #[test]
+    fn translate() {
+        let mut page_manager = page_manager();
+        unsafe {
+            page_manager.map(0x5000, 0x40_1000, true, true);
+            page_manager.map(0x6000, 0x40_2000, false, false);
+        }
+
+        assert_eq!(page_manager.translate(0x40_1234), Some((0x5234, USER)));
+        assert_eq!(
+            page_manager.translate(0x40_2000),
+            Some((0x6000, PageFlags::default()))
+        );
+        assert_eq!(page_manager.translate(0x40_3000), None);
+        assert_eq!(page_manager.translate(0x80_0000), None);
+    }
+
+    #[test]
+    fn unmap_frees_empty_page_tables() {
+        let mut page_manager = page_manager();
+        unsafe {
+            page_manager.map(0x5000, 0x40_1000, true, true);
+            page_manager.map(0x6000, 0x40_2000, true, true);
+
+            assert_eq!(page_manager.unmap(0x40_1000), Some(0x5000));
+            assert_eq!(page_manager.unmap(0x40_1000), None);
+            assert!(has_page_table(&page_manager, 0x40_1000));
+            assert_eq!(page_manager.translate(0x40_2000), Some((0x6000, USER)));
+
+            assert_eq!(page_manager.unmap(0x40_2000), Some(0x6000));
+            assert!(!has_page_table(&page_manager, 0x40_2000));
+
+            // A new page table is allocated for the next mapping.
+            page_manager.map(0x7000, 0x40_3000, true, true);
+        }
+        assert_eq!(page_manager.translate(0x40_3000), Some((0x7000, USER)));
+    }
+
+    #[test]
+    fn unmap_range() {
+        let mut page_manager = page_manager();
+        let mut unmapped = Vec::new();
+        unsafe {
+            page_manager.map(0x5000, 0x3F_F000, true, true);
+            page_manager.map(0x6000, 0x40_0000, true, true);
+            page_manager.map(0x7000, 0x40_1000, true, true);
+            page_manager.map(0x8000, 0x100_0000, true, true);
+
+            let result = page_manager
+                .unmap_range(0x3F_F000, 0x100_0000, |phys_addr| unmapped.push(phys_addr));
+            assert_eq!(result, Ok(()));
+        }
+
+        assert_eq!(unmapped, [0x5000, 0x6000, 0x7000]);
+        assert!(!has_page_table(&page_manager, 0x3F_F000));
+        assert!(!has_page_table(&page_manager, 0x40_0000));
+        assert_eq!(page_manager.translate(0x100_0000), Some((0x8000, USER)));
+    }
+
+    #[test]
+    fn unmap_range_at_the_top_of_memory() {
+        let mut page_manager = page_manager();
+        let mut unmapped = Vec::new();
+        unsafe {
+            page_manager.map(0x5000, 0xFFBF_E000, true, true);
+            page_manager.map(0x6000, 0xFFBF_F000, true, true);
+
+            // There's no page table for the last part of the range, which is
+            // at the end of the page directory.
+            let result = page_manager
+                .unmap_range(0xFFBF_F000, 0x40_0000, |phys_addr| unmapped.push(phys_addr));
+            assert_eq!(result, Ok(()));
+            assert_eq!(unmapped, [0x6000]);
+            assert_eq!(page_manager.translate(0xFFBF_E000), Some((0x5000, USER)));
+
+            let result = page_manager.unmap_range(0xFFBF_E000, usize::MAX - 0xFFBF_DFFF, |_| {
+                panic!("nothing should be unmapped")
+            });
+            assert_eq!(result, Err(RangeOverflow));
+            assert_eq!(page_manager.translate(0xFFBF_E000), Some((0x5000, USER)));
+        }
+    }
+
+    #[test]
+    fn protect() {
+        let mut page_manager = page_manager();
+        unsafe {
+            page_manager.map(0x5000, 0x40_1000, false, true);
+            assert!(!page_manager.is_writeable(0x40_1000));
+
+            assert!(page_manager.protect(0x40_1000, USER));
+            assert!(page_manager.is_writeable(0x40_1000));
+
+            let copy_on_write = PageFlags {
+                write: false,
+                copy_on_write: true,
+                ..USER
+            };
+            assert!(page_manager.protect(0x40_1000, copy_on_write));
+            assert_eq!(
+                page_manager.translate(0x40_1000),
+                Some((0x5000, copy_on_write))
+            );
+
+            assert!(!page_manager.protect(0x40_2000, USER));
+        }
+    }
+
+    #[test]
+    fn user_mappings() {
+        let mut page_manager = page_manager();
+        unsafe {
+            page_manager.map(0x5000, 0x80_2000, true, true);
+            page_manager.map(0x6000, 0x40_1000, false, true);
+            page_manager.map(0x7000, 0x40_2000, true, false);
+            page_manager.map_shared(0x8000, 0xC0_0000, true);
+        }
+
+        let mappings: Vec<_> = page_manager.user_mappings().collect();
+        assert_eq!(
+            mappings,
+            [
+                (
+                    0x40_1000,
+                    0x6000,
+                    PageFlags {
+                        write: false,
+                        ..USER
+                    }
+                ),
+                (0x80_2000, 0x5000, USER),
+                (
+                    0xC0_0000,
+                    0x8000,
+                    PageFlags {
+                        shared: true,
+                        ..USER
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn frame_ref_counts() {
+        let mut ref_counts = FrameRefCounts::new_in(4, Global);
+        let frame = 2 * PAGE_FRAME_SIZE;
+        assert!(!ref_counts.is_shared(frame));
+
+        // The first share counts both the owner and the new mapping.
+        ref_counts.share(frame);
+        assert!(ref_counts.is_shared(frame + 0x123));
+        ref_counts.share(frame);
+        assert!(!ref_counts.is_shared(PAGE_FRAME_SIZE));
+
+        assert!(!ref_counts.release(frame));
+        assert!(ref_counts.is_shared(frame));
+        // Down to one mapping, which owns the frame again.
+        assert!(!ref_counts.release(frame));
+        assert!(!ref_counts.is_shared(frame));
+        // Releasing an exclusively owned frame is the last reference.
+        assert!(ref_counts.release(frame));
+
+        // Frames past the end aren't tracked.
+        assert!(!ref_counts.is_shared(4 * PAGE_FRAME_SIZE));
+    }
+
+    #[test]
+    #[should_panic(expected = "is not tracked")]
+    fn frame_ref_counts_out_of_range() {
+        FrameRefCounts::new_in(4, Global).share(4 * PAGE_FRAME_SIZE);
+    }
+}
@@ -8,26 +8,120 @@
 #ifndef KIDNEYOS_SYSCALLS_H
 #define KIDNEYOS_SYSCALLS_H
 
+#include <stdbool.h>
 #include <stdint.h>
 
+/**
+ * The size of the signal sets `rt_sigprocmask` takes, which have room for
+ * Linux's real-time signals. We don't have any, so only the first `SigSet`
+ * is used, and the rest is always empty.
+ */
+#define RT_SIGSET_SIZE 8
+
+/**
+ * The most buffers `readv` and `writev` take at once.
+ */
+#define IOV_MAX 1024
+
+/**
+ * Writes to a pipe of at most this many bytes are atomic: they're never
+ * interleaved with other writes, and wait until there's room for all of them.
+ */
+#define PIPE_BUF 4096
+
+/**
+ * The longest path a `SockAddrUn` holds.
+ */
+#define UNIX_PATH_MAX 108
+
+/**
+ * The number of control characters in a `Termios`.
+ */
+#define NCCS 19
+
+#define UTSNAME_LENGTH 65
+
+#define O_RDONLY 0
+
+#define O_WRONLY 1
+
+#define O_RDWR 2
+
+/**
+ * The bits of the open flags which hold the access mode.
+ */
+#define O_ACCMODE 3
+
 #define O_CREATE 64
 
+#define O_EXCL 128
+
+#define O_NOCTTY 256
+
+#define O_TRUNC 512
+
+#define O_APPEND 1024
+
+#define O_NONBLOCK 2048
+
+/**
+ * Accepted for compatibility; files are always opened with 64-bit offsets.
+ */
+#define O_LARGEFILE 32768
+
+#define O_DIRECTORY 65536
+
+#define O_CLOEXEC 524288
+
+#define F_DUPFD 0
+
+#define F_GETFD 1
+
+#define F_SETFD 2
+
+#define F_GETFL 3
+
+#define F_SETFL 4
+
+#define F_DUPFD_CLOEXEC 1030
+
+/**
+ * The file descriptor flag set by `F_SETFD` to close a file on `execve`.
+ */
+#define FD_CLOEXEC 1
+
 #define SEEK_SET 0
 
 #define SEEK_CUR 1
 
 #define SEEK_END 2
 
+#define EPERM 1
+
 #define ENOENT 2
 
+#define ESRCH 3
+
+#define EINTR 4
+
 #define EIO 5
 
+#define ENXIO 6
+
+#define E2BIG 7
+
 #define ENOEXEC 8
 
 #define EBADF 9
 
+#define ECHILD 10
+
+#define EAGAIN 11
+
 #define ENOMEM 12
 
+#define EACCES 13
+
 #define EFAULT 14
 
 #define EBUSY 16
@@ -46,6 +140,8 @@
 
 #define EMFILE 24
 
+#define ENOTTY 25
+
 #define ENOSPC 28
 
 #define ESPIPE 29
@@ -58,12 +154,36 @@
 
 #define ERANGE 34
 
+#define EDEADLK 35
+
 #define ENOSYS 38
 
 #define ENOTEMPTY 39
 
 #define ELOOP 40
 
+#define ENOTSOCK 88
+
+#define EMSGSIZE 90
+
+#define EPROTOTYPE 91
+
+#define EPROTONOSUPPORT 93
+
+#define EOPNOTSUPP 95
+
+#define EAFNOSUPPORT 97
+
+#define EADDRINUSE 98
+
+#define EISCONN 106
+
+#define ENOTCONN 107
+
+#define ETIMEDOUT 110
+
+#define ECONNREFUSED 111
+
 #define SYS_EXIT 1
 
 #define SYS_FORK 2
@@ -86,6 +206,8 @@
 
 #define SYS_CHDIR 12
 
+#define SYS_MKNOD 14
+
 #define SYS_GETPID 20
 
 #define SYS_MOUNT 21
@@ -94,6 +216,8 @@
 
 #define SYS_SYNC 36
 
+#define SYS_KILL 37
+
 #define SYS_RENAME 38
 
 #define SYS_MKDIR 39
@@ -104,38 +228,129 @@
 
 #define SYS_PIPE 42
 
+#define SYS_BRK 45
+
+#define SYS_IOCTL 54
+
+#define SYS_FCNTL 55
+
+#define SYS_SETPGID 57
+
 #define SYS_DUP2 63
 
 #define SYS_GETPPID 64
 
+#define SYS_GETPGRP 65
+
+#define SYS_SIGACTION 67
+
 #define SYS_SYMLINK 83
 
-#define SYS_MMAP 90
+#define SYS_MUNMAP 91
 
 #define SYS_FTRUNCATE 93
 
 #define SYS_FSTAT 108
 
+#define SYS_SIGRETURN 119
+
+#define SYS_UNAME 122
+
+#define SYS_SIGPROCMASK 126
+
+#define SYS_GETPGID 132
+
 #define SYS_LSEEK64 140
 
 #define SYS_GETDENTS 141
 
+/**
+ * Linux calls this `_newselect`. `select` is an older convention which takes
+ * its arguments in a struct.
+ */
+#define SYS_SELECT 142
+
+#define SYS_READV 145
+
+#define SYS_WRITEV 146
+
 #define SYS_NANOSLEEP 162
 
 #define SYS_SCHED_YIELD 158
 
+#define SYS_POLL 168
+
+#define SYS_RT_SIGPROCMASK 175
+
+#define SYS_PREAD64 180
+
+#define SYS_PWRITE64 181
+
 #define SYS_GETCWD 183
 
+#define SYS_MMAP2 192
+
+/**
+ * Only differs from `fcntl` in file locking, which isn't supported, so it's
+ * the same here.
+ */
+#define SYS_FCNTL64 221
+
+#define SYS_GETTID 224
+
+#define SYS_FUTEX 240
+
+#define SYS_SET_THREAD_AREA 243
+
+#define SYS_EXIT_GROUP 252
+
+#define SYS_SET_TID_ADDRESS 258
+
 #define SYS_CLOCK_GETTIME 265
 
 #define SYS_GETRANDOM 355
 
+#define SYS_SOCKET 359
+
+#define SYS_SOCKETPAIR 360
+
+#define SYS_BIND 361
+
+#define SYS_CONNECT 362
+
+#define SYS_LISTEN 363
+
+#define SYS_ACCEPT4 364
+
+#define SYS_SENDTO 369
+
+#define SYS_RECVFROM 371
+
+#define SYS_SHUTDOWN 373
+
+#define SYS_THREAD_CREATE 4096
+
+#define SYS_THREAD_JOIN 4097
+
+#define SYS_THREAD_EXIT 4098
+
 #define S_REGULAR_FILE 1
 
 #define S_SYMLINK 2
 
 #define S_DIRECTORY 3
 
+#define S_FIFO 4
+
+#define S_SOCKET 5
+
+/**
+ * The bits of a `mknod` mode which give the type of file.
+ */
+#define S_IFMT 61440
+
+#define S_IFIFO 4096
+
 #define CLOCK_REALTIME 0
 
 #define CLOCK_MONOTONIC 1
@@ -146,8 +361,366 @@
 
 #define PROT_EXEC 4
 
+#define MAP_SHARED 1
+
+#define MAP_PRIVATE 2
+
+#define MAP_FIXED 16
+
+#define MAP_ANONYMOUS 32
+
+/**
+ * The unit of `mmap2`'s offset, whatever the size of a page actually is.
+ */
+#define MMAP2_PAGE_SIZE 4096
+
+#define SIGHUP 1
+
+#define SIGINT 2
+
+#define SIGQUIT 3
+
+#define SIGILL 4
+
+#define SIGTRAP 5
+
+#define SIGABRT 6
+
+#define SIGBUS 7
+
+#define SIGFPE 8
+
+#define SIGKILL 9
+
+#define SIGUSR1 10
+
+#define SIGSEGV 11
+
+#define SIGUSR2 12
+
+#define SIGPIPE 13
+
+#define SIGALRM 14
+
+#define SIGTERM 15
+
+#define SIGCHLD 17
+
+#define SIGCONT 18
+
+#define SIGSTOP 19
+
+#define SIGTSTP 20
+
+#define SIGTTIN 21
+
+#define SIGTTOU 22
+
+/**
+ * Signal numbers are all less than this.
+ */
+#define NSIG 32
+
+#define SIG_DFL 0
+
+#define SIG_IGN 1
+
+#define SA_RESTORER 67108864
+
+#define SA_NODEFER 1073741824
+
+#define SA_RESETHAND 2147483648
+
+/**
+ * Option for waitpid to return 0 instead of waiting if no child has exited yet.
+ */
+#define WNOHANG 1
+
+#define FUTEX_WAIT 0
+
+#define FUTEX_WAKE 1
+
+/**
+ * Marks a futex as private to the calling process, so it's keyed by its
+ * virtual address in that process. Other futexes are keyed by physical
+ * address, so processes sharing the memory can use them.
+ */
+#define FUTEX_PRIVATE_FLAG 128
+
+#define POLLIN 1
+
+#define POLLPRI 2
+
+#define POLLOUT 4
+
+#define POLLERR 8
+
+#define POLLHUP 16
+
+#define POLLNVAL 32
+
+#define FD_SETSIZE 1024
+
+/**
+ * The number of words in an `FdSet`.
+ */
+#define FD_SET_WORDS (FD_SETSIZE / 32)
+
+/**
+ * Gets the settings of a terminal into a `Termios`.
+ */
+#define TCGETS 21505
+
+/**
+ * Changes the settings of a terminal to a `Termios`.
+ */
+#define TCSETS 21506
+
+/**
+ * Like `TCSETS`, but after waiting for output to be written, which it always
+ * is straight away.
+ */
+#define TCSETSW 21507
+
+/**
+ * Like `TCSETSW`, but also discards any input which hasn't been read.
+ */
+#define TCSETSF 21508
+
+/**
+ * Gets the foreground process group of a terminal.
+ */
+#define TIOCGPGRP 21519
+
+/**
+ * Sets the foreground process group of a terminal, which receives the signals
+ * sent by special characters such as ^C.
+ */
+#define TIOCSPGRP 21520
+
+/**
+ * Gets the size of a terminal into a `WinSize`.
+ */
+#define TIOCGWINSZ 21523
+
+#define TCSANOW 0
+
+#define TCSADRAIN 1
+
+#define TCSAFLUSH 2
+
+/**
+ * `Termios::iflag`: translate carriage returns to newlines.
+ */
+#define ICRNL 256
+
+/**
+ * `Termios::lflag`: send signals for `VINTR`, `VQUIT` and `VSUSP`.
+ */
+#define ISIG 1
+
+/**
+ * `Termios::lflag`: edit input a line at a time.
+ */
+#define ICANON 2
+
+/**
+ * `Termios::lflag`: echo input.
+ */
+#define ECHO 8
+
+/**
+ * `Termios::lflag`: erase characters from the screen for `VERASE`.
+ */
+#define ECHOE 16
+
+/**
+ * `Termios::lflag`: don't discard input when sending a signal.
+ */
+#define NOFLSH 128
+
+/**
+ * Sends `SIGINT`, ^C by default.
+ */
+#define VINTR 0
+
+/**
+ * Sends `SIGQUIT`, ^\\ by default.
+ */
+#define VQUIT 1
+
+/**
+ * Erases the last character in canonical mode, DEL by default.
+ */
+#define VERASE 2
+
+/**
+ * Ends the input in canonical mode, ^D by default.
+ */
+#define VEOF 4
+
+/**
+ * How long a read waits in raw mode, in tenths of a second.
+ */
+#define VTIME 5
+
+/**
+ * The fewest characters a read returns in raw mode.
+ */
+#define VMIN 6
+
+/**
+ * Sends `SIGTSTP`, ^Z by default.
+ */
+#define VSUSP 10
+
+/**
+ * The only socket domain, for sockets bound to paths.
+ */
+#define AF_UNIX 1
+
+#define SOCK_STREAM 1
+
+#define SOCK_DGRAM 2
+
+/**
+ * Flags which can be added to a socket's type, to set them on its file.
+ */
+#define SOCK_NONBLOCK 2048
+
+#define SOCK_CLOEXEC 524288
+
+/**
+ * A send or receive doesn't block, whether or not the socket does.
+ */
+#define MSG_DONTWAIT 64
+
+#define SHUT_RD 0
+
+#define SHUT_WR 1
+
+#define SHUT_RDWR 2
+
+#define SIG_BLOCK 0
+
+#define SIG_UNBLOCK 1
+
+#define SIG_SETMASK 2
+
+#define USER_DESC_SEG_32BIT 1
+
+/**
+ * The upper bit of `contents`, which makes it a code segment.
+ */
+#define USER_DESC_CONTENTS_CODE 4
+
+#define USER_DESC_READ_EXEC_ONLY 8
+
+#define USER_DESC_LIMIT_IN_PAGES 16
+
+#define USER_DESC_SEG_NOT_PRESENT 32
+
+#define USER_DESC_USEABLE 64
+
+#define AT_NULL 0
+
+#define AT_PHDR 3
+
+#define AT_PHENT 4
+
+#define AT_PHNUM 5
+
+#define AT_PAGESZ 6
+
+#define AT_BASE 7
+
+#define AT_ENTRY 9
+
+#define AT_UID 11
+
+#define AT_EUID 12
+
+#define AT_GID 13
+
+#define AT_EGID 14
+
+#define AT_SECURE 23
+
 typedef uint16_t Pid;
 
+/**
+ * A buffer for `readv` and `writev`.
+ */
+typedef struct IoVec {
+  void *base;
+  uintptr_t len;
+} IoVec;
+
+/**
+ * A file to `poll`, and the events to wait for on it.
+ */
+typedef struct PollFd {
+  /**
+   * The file to poll, or a negative number to skip this entry.
+   */
+  int32_t fd;
+  /**
+   * The `POLL*` events to wait for.
+   */
+  int16_t events;
+  /**
+   * Set by `poll` to the events which happened, which can include `POLLERR`,
+   * `POLLHUP` and `POLLNVAL` even if they weren't asked for.
+   */
+  int16_t revents;
+} PollFd;
+
+/**
+ * A set of file descriptors for `select`, where `fd` is in the set if bit
+ * `fd % 32` of `bits[fd / 32]` is set.
+ */
+typedef struct FdSet {
+  uint32_t bits[FD_SET_WORDS];
+} FdSet;
+
+/**
+ * A timeout for `select`.
+ */
+typedef struct TimeVal {
+  int32_t sec;
+  int32_t usec;
+} TimeVal;
+
+/**
+ * The settings of a terminal, see `TCGETS` and `TCSETS`.
+ *
+ * Only some of the flags and control characters have an effect, but the rest
+ * are kept so programs get back what they set.
+ */
+typedef struct Termios {
+  /**
+   * Input flags, such as `ICRNL`.
+   */
+  uint32_t iflag;
+  /**
+   * Output flags.
+   */
+  uint32_t oflag;
+  /**
+   * Control flags.
+   */
+  uint32_t cflag;
+  /**
+   * Local flags, such as `ICANON`, `ECHO` and `ISIG`.
+   */
+  uint32_t lflag;
+  uint8_t line;
+  /**
+   * Control characters, indexed by `VINTR`, `VMIN` and so on.
+   */
+  uint8_t cc[NCCS];
+} Termios;
+
 typedef struct Stat {
   uint32_t inode;
   uint32_t nlink;
@@ -172,11 +745,96 @@ typedef struct Dirent {
   uint8_t name[0];
 } Dirent;
 
+/**
+ * The address of a Unix domain socket: the path it's bound to, which is
+ * null-terminated unless it fills `path`.
+ */
+typedef struct SockAddrUn {
+  /**
+   * Always `AF_UNIX`.
+   */
+  uint16_t family;
+  uint8_t path[UNIX_PATH_MAX];
+} SockAddrUn;
+
+/**
+ * The length of a socket address.
+ */
+typedef uint32_t SockLen;
+
 typedef struct Timespec {
   int64_t tv_sec;
   int64_t tv_nsec;
 } Timespec;
 
+/**
+ * Describes the system, see `uname`. Each field is a null-terminated string.
+ */
+typedef struct UtsName {
+  uint8_t sysname[UTSNAME_LENGTH];
+  uint8_t nodename[UTSNAME_LENGTH];
+  uint8_t release[UTSNAME_LENGTH];
+  uint8_t version[UTSNAME_LENGTH];
+  uint8_t machine[UTSNAME_LENGTH];
+  uint8_t domainname[UTSNAME_LENGTH];
+} UtsName;
+
+typedef uint16_t Tid;
+
+/**
+ * Describes a thread-local storage segment, see `set_thread_area`.
+ *
+ * This is laid out like Linux's `struct user_desc`, whose flags are bitfields;
+ * see the `USER_DESC_*` constants.
+ */
+typedef struct UserDesc {
+  /**
+   * The GDT entry to set, or -1 to let the kernel choose one, in which case
+   * the chosen entry is written back.
+   */
+  uint32_t entry_number;
+  uint32_t base_addr;
+  uint32_t limit;
+  uint32_t flags;
+} UserDesc;
+
+/**
+ * A set of signals, where signal `n` is in the set if bit `n - 1` is set.
+ */
+typedef uint32_t SigSet;
+
+/**
+ * Describes what should happen when a signal is delivered, see `sigaction`.
+ */
+typedef struct SigAction {
+  /**
+   * `SIG_DFL`, `SIG_IGN`, or the address of a `void handler(int)` function.
+   */
+  uintptr_t handler;
+  /**
+   * Additional signals to block while the handler runs.
+   */
+  SigSet mask;
+  uint32_t flags;
+  /**
+   * The address the handler returns to if `SA_RESTORER` is set, which must
+   * make the sigreturn syscall.
+   */
+  uintptr_t restorer;
+} SigAction;
+
+/**
+ * Makes the syscall `number` with the given arguments, and returns its
+ * result. Syscalls which take fewer arguments ignore the rest.
+ */
+extern int32_t kidneyos_syscall(uintptr_t number,
+                                uintptr_t arg0,
+                                uintptr_t arg1,
+                                uintptr_t arg2,
+                                uintptr_t arg3,
+                                uintptr_t arg4,
+                                uintptr_t arg5);
+
 void exit(int32_t code);
 
 Pid fork(void);
@@ -185,8 +843,84 @@ int32_t read(int32_t fd, uint8_t *buffer, uintptr_t count);
 
 int32_t write(int32_t fd, const uint8_t *buffer, uintptr_t count);
 
+/**
+ * Reads from `fd` into the `iovcnt` buffers in `iov`, filling each before
+ * moving on to the next.
+ */
+int32_t readv(int32_t fd, const struct IoVec *iov, int32_t iovcnt);
+
+/**
+ * Writes the `iovcnt` buffers in `iov` to `fd`, one after another.
+ */
+int32_t writev(int32_t fd, const struct IoVec *iov, int32_t iovcnt);
+
+/**
+ * Reads from `fd` at `offset`, without moving its file position.
+ */
+int32_t pread64(int32_t fd, uint8_t *buffer, uintptr_t count, int64_t offset);
+
+/**
+ * Writes to `fd` at `offset`, without moving its file position.
+ */
+int32_t pwrite64(int32_t fd, const uint8_t *buffer, uintptr_t count, int64_t offset);
+
+/**
+ * Waits for one of the `nfds` files in `fds` to have one of the events it asks
+ * for, for up to `timeout` milliseconds, or forever if it's negative.
+ *
+ * Returns how many files have events, which are stored in their `revents`.
+ */
+int32_t poll(struct PollFd *fds, uintptr_t nfds, int32_t timeout);
+
+/**
+ * Waits for one of the files below `nfds` in `read_fds` to be readable, one in
+ * `write_fds` to be writable, or one in `except_fds` to have an exceptional
+ * condition, for up to `timeout`, or forever if it's null.
+ *
+ * Returns how many files are ready, and leaves only those in the sets.
+ */
+int32_t select(int32_t nfds,
+               struct FdSet *read_fds,
+               struct FdSet *write_fds,
+               struct FdSet *except_fds,
+               struct TimeVal *timeout);
+
+/**
+ * Supports the terminal requests `TCGETS`, `TCSETS`, `TCSETSW`, `TCSETSF`,
+ * `TIOCGPGRP`, `TIOCSPGRP` and `TIOCGWINSZ`.
+ */
+int32_t ioctl(int32_t fd, uintptr_t request, void *arg);
+
+/**
+ * Gets the settings of the terminal `fd`.
+ */
+int32_t tcgetattr(int32_t fd, struct Termios *termios);
+
+/**
+ * Changes the settings of the terminal `fd`. `optional_actions` is one of
+ * `TCSANOW`, `TCSADRAIN` or `TCSAFLUSH`, which also discards unread input.
+ */
+int32_t tcsetattr(int32_t fd, int32_t optional_actions, const struct Termios *termios);
+
+/**
+ * The foreground process group of the terminal `fd`.
+ */
+int32_t tcgetpgrp(int32_t fd);
+
+/**
+ * Makes `pgid` the foreground process group of the terminal `fd`.
+ */
+int32_t tcsetpgrp(int32_t fd, Pid pgid);
+
 int32_t open(const char *name, uintptr_t flags);
 
+/**
+ * Gets or sets the flags of `fd` with `F_GETFD`, `F_SETFD`, `F_GETFL` and
+ * `F_SETFL`, or duplicates it into the lowest free file descriptor which is
+ * at least `arg` with `F_DUPFD` and `F_DUPFD_CLOEXEC`.
+ */
+int32_t fcntl(int32_t fd, int32_t cmd, uintptr_t arg);
+
 int32_t close(int32_t fd);
 
 int64_t lseek64(int32_t fd, int64_t offset, int32_t whence);
@@ -197,6 +931,17 @@ int32_t chdir(const char *path);
 
 int32_t mkdir(const char *path);
 
+/**
+ * Creates a file of the type in the `S_IFMT` bits of `mode`. Only named pipes,
+ * `S_IFIFO`, are supported, and `dev` is ignored.
+ */
+int32_t mknod(const char *path, uint32_t mode, uint32_t dev);
+
+/**
+ * Creates a named pipe at `path`.
+ */
+int32_t mkfifo(const char *path);
+
 int32_t fstat(int32_t fd, struct Stat *statbuf);
 
 int32_t unlink(const char *path);
@@ -219,7 +964,38 @@ int32_t unmount(const char *path);
 
 int32_t mount(const char *device, const char *target, const char *filesystem_type);
 
-Pid waitpid(Pid pid, int32_t *stat, int32_t options);
+/**
+ * Waits for the child `pid` to exit, or any child if `pid` is -1.
+ *
+ * Returns the pid of the child which exited, and stores how it exited in
+ * `stat` if it isn't null, which can be decoded with `wifexited` and friends.
+ */
+int32_t waitpid(int32_t pid, int32_t *stat, int32_t options);
+
+/**
+ * Whether a waitpid status is for a child which exited normally.
+ */
+bool wifexited(int32_t status);
+
+/**
+ * The exit code of a child which exited normally.
+ */
+int32_t wexitstatus(int32_t status);
+
+/**
+ * Whether a waitpid status is for a child which was killed by a signal.
+ */
+bool wifsignaled(int32_t status);
+
+/**
+ * The signal which killed a child.
+ */
+int32_t wtermsig(int32_t status);
+
+/**
+ * Whether a child was killed for causing a CPU fault.
+ */
+bool wcoredump(int32_t status);
 
 int32_t dup(int32_t fd);
 
@@ -227,20 +1003,223 @@ int32_t dup2(int32_t old_fd, int32_t new_fd);
 
 int32_t pipe(int32_t *fds);
 
+/**
+ * Creates a socket of `type` (`SOCK_STREAM` or `SOCK_DGRAM`, with any of
+ * `SOCK_NONBLOCK` and `SOCK_CLOEXEC`) in `domain`, which must be `AF_UNIX`.
+ * `protocol` must be 0.
+ */
+int32_t socket(int32_t domain, int32_t type, int32_t protocol);
+
+/**
+ * Creates two sockets connected to each other, storing their file
+ * descriptors in `fds`.
+ */
+int32_t socketpair(int32_t domain, int32_t type, int32_t protocol, int32_t *fds);
+
+/**
+ * Binds the socket `fd` to the path in `addr`, which mustn't exist yet.
+ */
+int32_t bind(int32_t fd, const struct SockAddrUn *addr, SockLen addr_len);
+
+/**
+ * Starts accepting connections on the bound stream socket `fd`, queueing up
+ * to `backlog` of them until they're accepted.
+ */
+int32_t listen(int32_t fd, int32_t backlog);
+
+/**
+ * Waits for a connection to the listening socket `fd`, and returns a new
+ * socket connected to it, with any of `SOCK_NONBLOCK` and `SOCK_CLOEXEC` in
+ * `flags`. If `addr` isn't null, the other end's address is stored there.
+ */
+int32_t accept4(int32_t fd, struct SockAddrUn *addr, SockLen *addr_len, int32_t flags);
+
+int32_t accept(int32_t fd, struct SockAddrUn *addr, SockLen *addr_len);
+
+/**
+ * Connects the socket `fd` to the one bound to `addr`. Stream sockets can
+ * then send to and receive from it, and datagram sockets send to it by
+ * default.
+ */
+int32_t connect(int32_t fd, const struct SockAddrUn *addr, SockLen addr_len);
+
+/**
+ * Sends `len` bytes from `buf` on the socket `fd`, to the datagram socket
+ * bound to `addr` if it isn't null.
+ */
+int32_t sendto(int32_t fd,
+               const uint8_t *buf,
+               uintptr_t len,
+               int32_t flags,
+               const struct SockAddrUn *addr,
+               SockLen addr_len);
+
+int32_t send(int32_t fd, const uint8_t *buf, uintptr_t len, int32_t flags);
+
+/**
+ * Receives up to `len` bytes into `buf` from the socket `fd`. If `addr` isn't
+ * null, the address of the datagram socket which sent them is stored there.
+ */
+int32_t recvfrom(int32_t fd,
+                 uint8_t *buf,
+                 uintptr_t len,
+                 int32_t flags,
+                 struct SockAddrUn *addr,
+                 SockLen *addr_len);
+
+int32_t recv(int32_t fd, uint8_t *buf, uintptr_t len, int32_t flags);
+
+/**
+ * Stops receiving on the socket `fd`, sending on it, or both, as `how`
+ * (`SHUT_RD`, `SHUT_WR` or `SHUT_RDWR`) says.
+ */
+int32_t shutdown(int32_t fd, int32_t how);
+
+/**
+ * Sets the end of the heap to `addr`.
+ *
+ * Returns 0 on success, or `-ENOMEM` if the heap couldn't be moved there.
+ */
+int32_t brk(void *addr);
+
+/**
+ * Grows the heap by `increment` bytes, or shrinks it if `increment` is
+ * negative.
+ *
+ * Returns the previous end of the heap, which is the start of the new memory
+ * when growing, or `(void *)-1` on failure.
+ */
+void *sbrk(intptr_t increment);
+
 int32_t execve(const char *filename, const char *const *argv, const char *const *envp);
 
 int32_t nanosleep(const struct Timespec *duration, struct Timespec *remainder);
 
+int32_t uname(struct UtsName *name);
+
 Pid getpid(void);
 
 Pid getppid(void);
 
+/**
+ * Moves the process `pid`, which must be the caller or one of its children,
+ * into the process group `pgid`. Either can be 0 for the caller, or for a new
+ * group led by `pid`.
+ */
+int32_t setpgid(Pid pid, Pid pgid);
+
+/**
+ * The process group of the process `pid`, or of the caller if it's 0.
+ */
+int32_t getpgid(Pid pid);
+
+Pid getpgrp(void);
+
+Tid gettid(void);
+
+/**
+ * Starts a new thread in this process, which calls `entry` with `arg`.
+ *
+ * The thread runs on `stack`, which should point just past the end of the
+ * memory set aside for it, or on a newly allocated stack if `stack` is null.
+ * `entry` must not return. Instead, the thread should end by calling
+ * `thread_exit`.
+ *
+ * Returns the tid of the new thread, or a negative error code.
+ */
+int32_t thread_create(void (*entry)(void*), void *arg, void *stack);
+
+/**
+ * Waits for the thread `tid` of this process to exit, and stores its exit
+ * code in `exit_code` if it isn't null.
+ *
+ * Unlike most blocking calls, this is never interrupted by signals.
+ */
+int32_t thread_join(Tid tid, int32_t *exit_code);
+
+/**
+ * Ends the calling thread with `exit_code`, which is passed on to the thread
+ * which joins it. If this is the last thread in the process, the process
+ * exits with `exit_code`.
+ */
+void thread_exit(int32_t exit_code);
+
+/**
+ * With `FUTEX_WAIT`, blocks until another thread wakes the futex at `uaddr`,
+ * as long as it contains `val`, or until `timeout` passes if it isn't null.
+ * With `FUTEX_WAKE`, wakes up to `val` threads waiting on the futex at
+ * `uaddr`, and returns how many were woken.
+ */
+int32_t futex(uint32_t *uaddr, int32_t op, uint32_t val, const struct Timespec *timeout);
+
+/**
+ * Sets the running thread's thread-local storage segment to the one described
+ * by `u_info`. If its `entry_number` is -1, the GDT entry used for it is
+ * written back, and the segment can then be used by loading
+ * `(entry_number << 3) | 3` into `gs`.
+ */
+int32_t set_thread_area(struct UserDesc *u_info);
+
+int32_t kill(int32_t pid, int32_t signal);
+
+/**
+ * If `action` doesn't set `SA_RESTORER`, the kernel arranges for the handler
+ * to return to a trampoline on the stack, which makes the sigreturn syscall.
+ */
+int32_t sigaction(int32_t signal, const struct SigAction *action, struct SigAction *old_action);
+
+int32_t sigprocmask(int32_t how, const SigSet *set, SigSet *old_set);
+
 int32_t scheduler_yield(void);
 
 int32_t clock_gettime(int32_t clock_id, struct Timespec *timespec);
 
 int32_t getrandom(int8_t *buf, uintptr_t size, uintptr_t flags);
 
+/**
+ * Maps `length` bytes at `offset` in `fd`, or anonymous memory if `flags`
+ * includes `MAP_ANONYMOUS`, and returns where they were mapped, or a negative
+ * error code cast to a pointer.
+ *
+ * `offset` must be a multiple of `MMAP2_PAGE_SIZE`.
+ */
 void *mmap(void *addr, uintptr_t length, int32_t prot, int32_t flags, int32_t fd, int64_t offset);
 
+int32_t munmap(void *addr, uintptr_t length);
+
+/**
+ * Allocates `size` bytes, aligned to 16 bytes.
+ *
+ * Returns null if `size` is 0 or there isn't enough memory.
+ */
+void *malloc(uintptr_t size);
+
+/**
+ * Frees memory returned by `malloc`, `calloc` or `realloc`. Does nothing if
+ * `ptr` is null.
+ *
+ * # Safety
+ *
+ * `ptr` must be null or have been returned by one of the functions above,
+ * and must not have been freed already.
+ */
+void free(void *ptr);
+
+/**
+ * Allocates zeroed memory for `count` elements of `size` bytes each.
+ */
+void *calloc(uintptr_t count, uintptr_t size);
+
+/**
+ * Resizes the allocation at `ptr` to `size` bytes, moving it if needed.
+ *
+ * Behaves like `malloc` if `ptr` is null, and like `free` if `size` is 0.
+ * If there isn't enough memory, returns null and leaves `ptr` untouched.
+ *
+ * # Safety
+ *
+ * Same as `free`.
+ */
+void *realloc(void *ptr, uintptr_t size);
+
 #endif  /* KIDNEYOS_SYSCALLS_H */
@@ -23,28 +23,164 @@ pub struct Dirent {
     pub name: [u8; 0],
 }
 
+/// Describes a thread-local storage segment, see `set_thread_area`.
+///
+/// This is laid out like Linux's `struct user_desc`, whose flags are bitfields;
+/// see the `USER_DESC_*` constants.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct UserDesc {
+    /// The GDT entry to set, or -1 to let the kernel choose one, in which case
+    /// the chosen entry is written back.
+    pub entry_number: u32,
+    pub base_addr: u32,
+    pub limit: u32,
+    pub flags: u32,
+}
+
+/// A set of signals, where signal `n` is in the set if bit `n - 1` is set.
+pub type SigSet = u32;
+
+/// The size of the signal sets `rt_sigprocmask` takes, which have room for
+/// Linux's real-time signals. We don't have any, so only the first `SigSet`
+/// is used, and the rest is always empty.
+pub const RT_SIGSET_SIZE: usize = 8;
+
+/// A buffer for `readv` and `writev`.
 #[repr(C)]
 #[derive(Clone, Copy, Debug)]
-pub struct MMapOptions {
-    pub addr: *mut core::ffi::c_void,
-    pub length: usize,
-    pub prot: i32,
-    pub flags: i32,
-    pub fd: i32,
-    pub offset: i64,
+pub struct IoVec {
+    pub base: *mut core::ffi::c_void,
+    pub len: usize,
 }
 
+/// The most buffers `readv` and `writev` take at once.
+pub const IOV_MAX: usize = 1024;
+
+/// Writes to a pipe of at most this many bytes are atomic: they're never
+/// interleaved with other writes, and wait until there's room for all of them.
+pub const PIPE_BUF: usize = 4096;
+
+/// The longest path a `SockAddrUn` holds.
+pub const UNIX_PATH_MAX: usize = 108;
+
+/// The address of a Unix domain socket: the path it's bound to, which is
+/// null-terminated unless it fills `path`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct SockAddrUn {
+    /// Always `AF_UNIX`.
+    pub family: u16,
+    pub path: [u8; UNIX_PATH_MAX],
+}
+
+/// The length of a socket address.
+pub type SockLen = u32;
+
+/// The size of a terminal, see `TIOCGWINSZ`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct WinSize {
+    pub rows: u16,
+    pub cols: u16,
+    pub xpixel: u16,
+    pub ypixel: u16,
+}
+
+/// The number of control characters in a `Termios`.
+pub const NCCS: usize = 19;
+
+/// The settings of a terminal, see `TCGETS` and `TCSETS`.
+///
+/// Only some of the flags and control characters have an effect, but the rest
+/// are kept so programs get back what they set.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Termios {
+    /// Input flags, such as `ICRNL`.
+    pub iflag: u32,
+    /// Output flags.
+    pub oflag: u32,
+    /// Control flags.
+    pub cflag: u32,
+    /// Local flags, such as `ICANON`, `ECHO` and `ISIG`.
+    pub lflag: u32,
+    pub line: u8,
+    /// Control characters, indexed by `VINTR`, `VMIN` and so on.
+    pub cc: [u8; NCCS],
+}
+
+pub const UTSNAME_LENGTH: usize = 65;
+
+/// Describes the system, see `uname`. Each field is a null-terminated string.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct UtsName {
+    pub sysname: [u8; UTSNAME_LENGTH],
+    pub nodename: [u8; UTSNAME_LENGTH],
+    pub release: [u8; UTSNAME_LENGTH],
+    pub version: [u8; UTSNAME_LENGTH],
+    pub machine: [u8; UTSNAME_LENGTH],
+    pub domainname: [u8; UTSNAME_LENGTH],
+}
+
+/// Describes what should happen when a signal is delivered, see `sigaction`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SigAction {
+    /// `SIG_DFL`, `SIG_IGN`, or the address of a `void handler(int)` function.
+    pub handler: usize,
+    /// Additional signals to block while the handler runs.
+    pub mask: SigSet,
+    pub flags: u32,
+    /// The address the handler returns to if `SA_RESTORER` is set, which must
+    /// make the sigreturn syscall.
+    pub restorer: usize,
+}
+
+pub const O_RDONLY: usize = 0;
+pub const O_WRONLY: usize = 1;
+pub const O_RDWR: usize = 2;
+/// The bits of the open flags which hold the access mode.
+pub const O_ACCMODE: usize = 3;
 pub const O_CREATE: usize = 0x40;
+pub const O_EXCL: usize = 0x80;
+pub const O_NOCTTY: usize = 0x100;
+pub const O_TRUNC: usize = 0x200;
+pub const O_APPEND: usize = 0x400;
+pub const O_NONBLOCK: usize = 0x800;
+/// Accepted for compatibility; files are always opened with 64-bit offsets.
+pub const O_LARGEFILE: usize = 0x8000;
+pub const O_DIRECTORY: usize = 0x10000;
+pub const O_CLOEXEC: usize = 0x80000;
+
+pub const F_DUPFD: i32 = 0;
+pub const F_GETFD: i32 = 1;
+pub const F_SETFD: i32 = 2;
+pub const F_GETFL: i32 = 3;
+pub const F_SETFL: i32 = 4;
+pub const F_DUPFD_CLOEXEC: i32 = 1030;
+
+/// The file descriptor flag set by `F_SETFD` to close a file on `execve`.
+pub const FD_CLOEXEC: usize = 1;
 
 pub const SEEK_SET: i32 = 0;
 pub const SEEK_CUR: i32 = 1;
 pub const SEEK_END: i32 = 2;
 
+pub const EPERM: isize = 1;
 pub const ENOENT: isize = 2;
+pub const ESRCH: isize = 3;
+pub const EINTR: isize = 4;
 pub const EIO: isize = 5;
+pub const ENXIO: isize = 6;
+pub const E2BIG: isize = 7;
 pub const ENOEXEC: isize = 8;
 pub const EBADF: isize = 9;
+pub const ECHILD: isize = 10;
+pub const EAGAIN: isize = 11;
 pub const ENOMEM: isize = 12;
+pub const EACCES: isize = 13;
 pub const EFAULT: isize = 14;
 pub const EBUSY: isize = 16;
 pub const EEXIST: isize = 17;
@@ -54,15 +190,28 @@ pub const ENOTDIR: isize = 20;
 pub const EISDIR: isize = 21;
 pub const EINVAL: isize = 22;
 pub const EMFILE: isize = 24;
+pub const ENOTTY: isize = 25;
 pub const ENOSPC: isize = 28;
 pub const ESPIPE: isize = 29;
 pub const EROFS: isize = 30;
 pub const EMLINK: isize = 31;
 pub const EPIPE: isize = 32;
 pub const ERANGE: isize = 34;
+pub const EDEADLK: isize = 35;
 pub const ENOSYS: isize = 38;
 pub const ENOTEMPTY: isize = 39;
 pub const ELOOP: isize = 40;
+pub const ENOTSOCK: isize = 88;
+pub const EMSGSIZE: isize = 90;
+pub const EPROTOTYPE: isize = 91;
+pub const EPROTONOSUPPORT: isize = 93;
+pub const EOPNOTSUPP: isize = 95;
+pub const EAFNOSUPPORT: isize = 97;
+pub const EADDRINUSE: isize = 98;
+pub const EISCONN: isize = 106;
+pub const ENOTCONN: isize = 107;
+pub const ETIMEDOUT: isize = 110;
+pub const ECONNREFUSED: isize = 111;
 
 pub const SYS_EXIT: usize = 0x1;
 pub const SYS_FORK: usize = 0x2;
@@ -75,32 +224,81 @@ pub const SYS_LINK: usize = 0x9;
 pub const SYS_UNLINK: usize = 0x0a;
 pub const SYS_EXECVE: usize = 0x0b;
 pub const SYS_CHDIR: usize = 0xc;
+pub const SYS_MKNOD: usize = 0x0e;
 pub const SYS_GETPID: usize = 0x14;
 pub const SYS_MOUNT: usize = 0x15;
 pub const SYS_UNMOUNT: usize = 0x16;
 pub const SYS_SYNC: usize = 0x24;
+pub const SYS_KILL: usize = 0x25;
 pub const SYS_RENAME: usize = 0x26;
 pub const SYS_MKDIR: usize = 0x27;
 pub const SYS_RMDIR: usize = 0x28;
 pub const SYS_DUP: usize = 0x29;
 pub const SYS_PIPE: usize = 0x2A;
+pub const SYS_BRK: usize = 0x2d;
+pub const SYS_IOCTL: usize = 0x36;
+pub const SYS_FCNTL: usize = 0x37;
+pub const SYS_SETPGID: usize = 0x39;
 pub const SYS_DUP2: usize = 0x3F;
 pub const SYS_GETPPID: usize = 0x40;
+pub const SYS_GETPGRP: usize = 0x41;
+pub const SYS_SIGACTION: usize = 0x43;
 pub const SYS_SYMLINK: usize = 0x53;
-pub const SYS_MMAP: usize = 0x5a;
+pub const SYS_MUNMAP: usize = 0x5b;
 pub const SYS_FTRUNCATE: usize = 0x5d;
 pub const SYS_FSTAT: usize = 0x6c;
+pub const SYS_SIGRETURN: usize = 0x77;
+pub const SYS_UNAME: usize = 0x7a;
+pub const SYS_SIGPROCMASK: usize = 0x7e;
+pub const SYS_GETPGID: usize = 0x84;
 pub const SYS_LSEEK64: usize = 0x8c;
 pub const SYS_GETDENTS: usize = 0x8d;
+/// Linux calls this `_newselect`. `select` is an older convention which takes
+/// its arguments in a struct.
+pub const SYS_SELECT: usize = 0x8e;
+pub const SYS_READV: usize = 0x91;
+pub const SYS_WRITEV: usize = 0x92;
 pub const SYS_NANOSLEEP: usize = 0xa2;
 pub const SYS_SCHED_YIELD: usize = 0x9e;
+pub const SYS_POLL: usize = 0xa8;
+pub const SYS_RT_SIGPROCMASK: usize = 0xaf;
+pub const SYS_PREAD64: usize = 0xb4;
+pub const SYS_PWRITE64: usize = 0xb5;
 pub const SYS_GETCWD: usize = 0xb7;
+pub const SYS_MMAP2: usize = 0xc0;
+/// Only differs from `fcntl` in file locking, which isn't supported, so it's
+/// the same here.
+pub const SYS_FCNTL64: usize = 0xdd;
+pub const SYS_GETTID: usize = 0xe0;
+pub const SYS_FUTEX: usize = 0xf0;
+pub const SYS_SET_THREAD_AREA: usize = 0xf3;
+pub const SYS_EXIT_GROUP: usize = 0xfc;
+pub const SYS_SET_TID_ADDRESS: usize = 0x102;
 pub const SYS_CLOCK_GETTIME: usize = 0x109;
 pub const SYS_GETRANDOM: usize = 0x163;
+pub const SYS_SOCKET: usize = 0x167;
+pub const SYS_SOCKETPAIR: usize = 0x168;
+pub const SYS_BIND: usize = 0x169;
+pub const SYS_CONNECT: usize = 0x16a;
+pub const SYS_LISTEN: usize = 0x16b;
+pub const SYS_ACCEPT4: usize = 0x16c;
+pub const SYS_SENDTO: usize = 0x171;
+pub const SYS_RECVFROM: usize = 0x173;
+pub const SYS_SHUTDOWN: usize = 0x175;
+// KidneyOS-specific syscalls, numbered well past Linux's
+pub const SYS_THREAD_CREATE: usize = 0x1000;
+pub const SYS_THREAD_JOIN: usize = 0x1001;
+pub const SYS_THREAD_EXIT: usize = 0x1002;
 
 pub const S_REGULAR_FILE: u8 = 1;
 pub const S_SYMLINK: u8 = 2;
 pub const S_DIRECTORY: u8 = 3;
+pub const S_FIFO: u8 = 4;
+pub const S_SOCKET: u8 = 5;
+
+/// The bits of a `mknod` mode which give the type of file.
+pub const S_IFMT: u32 = 0o170000;
+pub const S_IFIFO: u32 = 0o010000;
 
 pub const CLOCK_REALTIME: usize = 0;
 pub const CLOCK_MONOTONIC: usize = 1;
@@ -108,3 +306,217 @@ pub const CLOCK_MONOTONIC: usize = 1;
 pub const PROT_READ: i32 = 1;
 pub const PROT_WRITE: i32 = 2;
 pub const PROT_EXEC: i32 = 4;
+
+pub const MAP_SHARED: i32 = 0x01;
+pub const MAP_PRIVATE: i32 = 0x02;
+pub const MAP_FIXED: i32 = 0x10;
+pub const MAP_ANONYMOUS: i32 = 0x20;
+
+/// The unit of `mmap2`'s offset, whatever the size of a page actually is.
+pub const MMAP2_PAGE_SIZE: i64 = 4096;
+
+pub const SIGHUP: i32 = 1;
+pub const SIGINT: i32 = 2;
+pub const SIGQUIT: i32 = 3;
+pub const SIGILL: i32 = 4;
+pub const SIGTRAP: i32 = 5;
+pub const SIGABRT: i32 = 6;
+pub const SIGBUS: i32 = 7;
+pub const SIGFPE: i32 = 8;
+pub const SIGKILL: i32 = 9;
+pub const SIGUSR1: i32 = 10;
+pub const SIGSEGV: i32 = 11;
+pub const SIGUSR2: i32 = 12;
+pub const SIGPIPE: i32 = 13;
+pub const SIGALRM: i32 = 14;
+pub const SIGTERM: i32 = 15;
+pub const SIGCHLD: i32 = 17;
+pub const SIGCONT: i32 = 18;
+pub const SIGSTOP: i32 = 19;
+pub const SIGTSTP: i32 = 20;
+pub const SIGTTIN: i32 = 21;
+pub const SIGTTOU: i32 = 22;
+/// Signal numbers are all less than this.
+pub const NSIG: i32 = 32;
+
+pub const SIG_DFL: usize = 0;
+pub const SIG_IGN: usize = 1;
+
+pub const SA_RESTORER: u32 = 0x0400_0000;
+pub const SA_NODEFER: u32 = 0x4000_0000;
+pub const SA_RESETHAND: u32 = 0x8000_0000;
+
+/// Option for waitpid to return 0 instead of waiting if no child has exited yet.
+pub const WNOHANG: i32 = 1;
+
+pub const FUTEX_WAIT: i32 = 0;
+pub const FUTEX_WAKE: i32 = 1;
+/// Marks a futex as private to the calling process, so it's keyed by its
+/// virtual address in that process. Other futexes are keyed by physical
+/// address, so processes sharing the memory can use them.
+pub const FUTEX_PRIVATE_FLAG: i32 = 128;
+
+/// A file to `poll`, and the events to wait for on it.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PollFd {
+    /// The file to poll, or a negative number to skip this entry.
+    pub fd: i32,
+    /// The `POLL*` events to wait for.
+    pub events: i16,
+    /// Set by `poll` to the events which happened, which can include `POLLERR`,
+    /// `POLLHUP` and `POLLNVAL` even if they weren't asked for.
+    pub revents: i16,
+}
+
+pub const POLLIN: i16 = 0x01;
+pub const POLLPRI: i16 = 0x02;
+pub const POLLOUT: i16 = 0x04;
+pub const POLLERR: i16 = 0x08;
+pub const POLLHUP: i16 = 0x10;
+pub const POLLNVAL: i16 = 0x20;
+
+pub const FD_SETSIZE: usize = 1024;
+/// The number of words in an `FdSet`.
+pub const FD_SET_WORDS: usize = FD_SETSIZE / 32;
+
+/// A set of file descriptors for `select`, where `fd` is in the set if bit
+/// `fd % 32` of `bits[fd / 32]` is set.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct FdSet {
+    pub bits: [u32; FD_SET_WORDS],
+}
+
+impl FdSet {
+    pub const fn new() -> Self {
+        Self {
+            bits: [0; FD_SET_WORDS],
+        }
+    }
+
+    pub fn contains(&self, fd: usize) -> bool {
+        self.bits[fd / 32] & (1 << (fd % 32)) != 0
+    }
+
+    pub fn insert(&mut self, fd: usize) {
+        self.bits[fd / 32] |= 1 << (fd % 32);
+    }
+
+    pub fn remove(&mut self, fd: usize) {
+        self.bits[fd / 32] &= !(1 << (fd % 32));
+    }
+
+    pub fn clear(&mut self) {
+        self.bits = [0; FD_SET_WORDS];
+    }
+}
+
+impl Default for FdSet {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A timeout for `select`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TimeVal {
+    pub sec: i32,
+    pub usec: i32,
+}
+
+/// Gets the settings of a terminal into a `Termios`.
+pub const TCGETS: usize = 0x5401;
+/// Changes the settings of a terminal to a `Termios`.
+pub const TCSETS: usize = 0x5402;
+/// Like `TCSETS`, but after waiting for output to be written, which it always
+/// is straight away.
+pub const TCSETSW: usize = 0x5403;
+/// Like `TCSETSW`, but also discards any input which hasn't been read.
+pub const TCSETSF: usize = 0x5404;
+/// Gets the foreground process group of a terminal.
+pub const TIOCGPGRP: usize = 0x540f;
+/// Sets the foreground process group of a terminal, which receives the signals
+/// sent by special characters such as ^C.
+pub const TIOCSPGRP: usize = 0x5410;
+/// Gets the size of a terminal into a `WinSize`.
+pub const TIOCGWINSZ: usize = 0x5413;
+
+// When `tcsetattr` makes its changes.
+pub const TCSANOW: i32 = 0;
+pub const TCSADRAIN: i32 = 1;
+pub const TCSAFLUSH: i32 = 2;
+
+/// `Termios::iflag`: translate carriage returns to newlines.
+pub const ICRNL: u32 = 0o400;
+
+/// `Termios::lflag`: send signals for `VINTR`, `VQUIT` and `VSUSP`.
+pub const ISIG: u32 = 0o1;
+/// `Termios::lflag`: edit input a line at a time.
+pub const ICANON: u32 = 0o2;
+/// `Termios::lflag`: echo input.
+pub const ECHO: u32 = 0o10;
+/// `Termios::lflag`: erase characters from the screen for `VERASE`.
+pub const ECHOE: u32 = 0o20;
+/// `Termios::lflag`: don't discard input when sending a signal.
+pub const NOFLSH: u32 = 0o200;
+
+// Indices into `Termios::cc`.
+/// Sends `SIGINT`, ^C by default.
+pub const VINTR: usize = 0;
+/// Sends `SIGQUIT`, ^\\ by default.
+pub const VQUIT: usize = 1;
+/// Erases the last character in canonical mode, DEL by default.
+pub const VERASE: usize = 2;
+/// Ends the input in canonical mode, ^D by default.
+pub const VEOF: usize = 4;
+/// How long a read waits in raw mode, in tenths of a second.
+pub const VTIME: usize = 5;
+/// The fewest characters a read returns in raw mode.
+pub const VMIN: usize = 6;
+/// Sends `SIGTSTP`, ^Z by default.
+pub const VSUSP: usize = 10;
+
+/// The only socket domain, for sockets bound to paths.
+pub const AF_UNIX: i32 = 1;
+
+pub const SOCK_STREAM: i32 = 1;
+pub const SOCK_DGRAM: i32 = 2;
+/// Flags which can be added to a socket's type, to set them on its file.
+pub const SOCK_NONBLOCK: i32 = 0x800;
+pub const SOCK_CLOEXEC: i32 = 0x80000;
+
+/// A send or receive doesn't block, whether or not the socket does.
+pub const MSG_DONTWAIT: i32 = 0x40;
+
+// Which directions `shutdown` stops.
+pub const SHUT_RD: i32 = 0;
+pub const SHUT_WR: i32 = 1;
+pub const SHUT_RDWR: i32 = 2;
+
+pub const SIG_BLOCK: i32 = 0;
+pub const SIG_UNBLOCK: i32 = 1;
+pub const SIG_SETMASK: i32 = 2;
+
+pub const USER_DESC_SEG_32BIT: u32 = 0x01;
+/// The upper bit of `contents`, which makes it a code segment.
+pub const USER_DESC_CONTENTS_CODE: u32 = 0x04;
+pub const USER_DESC_READ_EXEC_ONLY: u32 = 0x08;
+pub const USER_DESC_LIMIT_IN_PAGES: u32 = 0x10;
+pub const USER_DESC_SEG_NOT_PRESENT: u32 = 0x20;
+pub const USER_DESC_USEABLE: u32 = 0x40;
+
+// Auxiliary vector entry types, passed to new programs on their stack.
+pub const AT_NULL: usize = 0;
+pub const AT_PHDR: usize = 3;
+pub const AT_PHENT: usize = 4;
+pub const AT_PHNUM: usize = 5;
+pub const AT_PAGESZ: usize = 6;
+pub const AT_BASE: usize = 7;
+pub const AT_ENTRY: usize = 9;
+pub const AT_UID: usize = 11;
+pub const AT_EUID: usize = 12;
+pub const AT_GID: usize = 13;
+pub const AT_EGID: usize = 14;
+pub const AT_SECURE: usize = 23;
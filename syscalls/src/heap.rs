@@ -0,0 +1,219 @@
+//! A simple memory allocator, which gets its memory from `sbrk`.
+//!
+//! Free blocks are kept in a list sorted by address, so that neighbouring
+//! blocks can be merged when they're freed. Allocations take the first free
+//! block which is big enough.
+
+use crate::sbrk;
+use crate::sync::Mutex;
+use core::alloc::{GlobalAlloc, Layout};
+use core::ffi::c_void;
+use core::mem::size_of;
+use core::ptr::{copy_nonoverlapping, null_mut, write_bytes};
+
+/// Every allocation is aligned to this many bytes.
+const ALIGNMENT: usize = 16;
+
+/// Comes right before the memory handed out for each block.
+#[repr(C, align(16))]
+struct Header {
+    /// The size of the block, not including this header.
+    size: usize,
+    /// The next free block, if this one is free.
+    next: *mut Header,
+}
+
+const HEADER_SIZE: usize = size_of::<Header>();
+
+struct FreeList {
+    head: *mut Header,
+}
+
+// SAFETY: The blocks in the list are only accessed while holding the lock.
+unsafe impl Send for FreeList {}
+
+static FREE_LIST: Mutex<FreeList> = Mutex::new(FreeList { head: null_mut() });
+
+unsafe fn block_end(block: *mut Header) -> *mut Header {
+    block.byte_add(HEADER_SIZE + (*block).size)
+}
+
+unsafe fn payload(block: *mut Header) -> *mut c_void {
+    block.add(1).cast()
+}
+
+unsafe fn header(ptr: *mut c_void) -> *mut Header {
+    ptr.cast::<Header>().sub(1)
+}
+
+impl FreeList {
+    /// Removes the first block of at least `size` bytes from the list,
+    /// splitting off whatever it doesn't need.
+    unsafe fn take(&mut self, size: usize) -> Option<*mut Header> {
+        let mut link: *mut *mut Header = &mut self.head;
+        while !(*link).is_null() {
+            let block = *link;
+            if (*block).size >= size {
+                if (*block).size - size >= HEADER_SIZE + ALIGNMENT {
+                    let rest = block.byte_add(HEADER_SIZE + size);
+                    rest.write(Header {
+                        size: (*block).size - size - HEADER_SIZE,
+                        next: (*block).next,
+                    });
+                    (*block).size = size;
+                    *link = rest;
+                } else {
+                    *link = (*block).next;
+                }
+                return Some(block);
+            }
+            link = &mut (*block).next;
+        }
+        None
+    }
+
+    /// Adds `block` to the list, merging it with its neighbours.
+    unsafe fn give(&mut self, block: *mut Header) {
+        let mut previous: *mut Header = null_mut();
+        let mut next = self.head;
+        while !next.is_null() && next < block {
+            previous = next;
+            next = (*next).next;
+        }
+
+        (*block).next = next;
+        if !next.is_null() && block_end(block) == next {
+            (*block).size += HEADER_SIZE + (*next).size;
+            (*block).next = (*next).next;
+        }
+
+        if previous.is_null() {
+            self.head = block;
+        } else if block_end(previous) == block {
+            (*previous).size += HEADER_SIZE + (*block).size;
+            (*previous).next = (*block).next;
+        } else {
+            (*previous).next = block;
+        }
+    }
+}
+
+/// Gets a new block of `size` bytes from the end of the heap.
+unsafe fn grow_heap(size: usize) -> Option<*mut Header> {
+    // Make sure blocks stay aligned, even if someone else moved the heap.
+    let end = sbrk(0) as usize;
+    let padding = end.next_multiple_of(ALIGNMENT) - end;
+    let increment = isize::try_from(padding + HEADER_SIZE + size).ok()?;
+    let start = sbrk(increment);
+    if start as usize == usize::MAX {
+        return None;
+    }
+
+    let block = start.byte_add(padding).cast::<Header>();
+    block.write(Header {
+        size,
+        next: null_mut(),
+    });
+    Some(block)
+}
+
+/// Allocates `size` bytes, aligned to 16 bytes.
+///
+/// Returns null if `size` is 0 or there isn't enough memory.
+#[no_mangle]
+pub extern "C" fn malloc(size: usize) -> *mut c_void {
+    if size == 0 || size > isize::MAX as usize - HEADER_SIZE - ALIGNMENT {
+        return null_mut();
+    }
+    let size = size.next_multiple_of(ALIGNMENT);
+
+    let mut free_list = FREE_LIST.lock();
+    unsafe {
+        match free_list.take(size).or_else(|| grow_heap(size)) {
+            Some(block) => payload(block),
+            None => null_mut(),
+        }
+    }
+}
+
+/// Frees memory returned by `malloc`, `calloc` or `realloc`. Does nothing if
+/// `ptr` is null.
+///
+/// # Safety
+///
+/// `ptr` must be null or have been returned by one of the functions above,
+/// and must not have been freed already.
+#[no_mangle]
+pub unsafe extern "C" fn free(ptr: *mut c_void) {
+    if ptr.is_null() {
+        return;
+    }
+    FREE_LIST.lock().give(header(ptr));
+}
+
+/// Allocates zeroed memory for `count` elements of `size` bytes each.
+#[no_mangle]
+pub extern "C" fn calloc(count: usize, size: usize) -> *mut c_void {
+    let Some(total) = count.checked_mul(size) else {
+        return null_mut();
+    };
+    let ptr = malloc(total);
+    if !ptr.is_null() {
+        unsafe { write_bytes(ptr.cast::<u8>(), 0, total) };
+    }
+    ptr
+}
+
+/// Resizes the allocation at `ptr` to `size` bytes, moving it if needed.
+///
+/// Behaves like `malloc` if `ptr` is null, and like `free` if `size` is 0.
+/// If there isn't enough memory, returns null and leaves `ptr` untouched.
+///
+/// # Safety
+///
+/// Same as `free`.
+#[no_mangle]
+pub unsafe extern "C" fn realloc(ptr: *mut c_void, size: usize) -> *mut c_void {
+    if ptr.is_null() {
+        return malloc(size);
+    }
+    if size == 0 {
+        free(ptr);
+        return null_mut();
+    }
+
+    let old_size = (*header(ptr)).size;
+    if old_size >= size {
+        return ptr;
+    }
+    let new_ptr = malloc(size);
+    if !new_ptr.is_null() {
+        copy_nonoverlapping(ptr.cast::<u8>(), new_ptr.cast::<u8>(), old_size);
+        free(ptr);
+    }
+    new_ptr
+}
+
+/// Lets Rust programs use `malloc` for `alloc`, with
+/// `#[global_allocator] static ALLOCATOR: Malloc = Malloc;`.
+pub struct Malloc;
+
+unsafe impl GlobalAlloc for Malloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if layout.align() > ALIGNMENT {
+            return null_mut();
+        }
+        malloc(layout.size()).cast()
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, _layout: Layout) {
+        free(ptr.cast());
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        if layout.align() > ALIGNMENT {
+            return null_mut();
+        }
+        realloc(ptr.cast(), new_size).cast()
+    }
+}
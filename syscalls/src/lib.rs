@@ -1,9 +1,10 @@
 #![no_std]
 
-use core::arch::asm;
+use core::arch::global_asm;
 use core::ffi::{c_char, c_void};
 
 pub type Pid = u16;
+pub type Tid = u16;
 
 #[repr(C)]
 pub struct Timespec {
@@ -13,91 +14,224 @@ pub struct Timespec {
 
 pub mod defs;
 pub use defs::*;
+pub mod heap;
+pub use heap::{calloc, free, malloc, realloc};
+pub mod sync;
+
+// Makes the syscall whose number is the first argument, with the other six as
+// its arguments, through the kernel's sysenter entry. The kernel preserves
+// everything but eax, ecx and edx, which the C calling convention lets us
+// clobber anyway; we save the other registers the arguments go in ourselves.
+//
+// sysenter doesn't record where to return to, so we call into it instead, and
+// pass the stack holding the return address and the sixth argument in ebp.
+global_asm!(
+    "
+    .pushsection .text.kidneyos_syscall, \"ax\"
+    .globl kidneyos_syscall
+kidneyos_syscall:
+    push ebp
+    push edi
+    push esi
+    push ebx
+    mov eax, [esp + 20]
+    mov ebx, [esp + 24]
+    mov ecx, [esp + 28]
+    mov edx, [esp + 32]
+    mov esi, [esp + 36]
+    mov edi, [esp + 40]
+    push dword ptr [esp + 44]
+    call 2f
+    add esp, 4
+    pop ebx
+    pop esi
+    pop edi
+    pop ebp
+    ret
+2:
+    mov ebp, esp
+    sysenter
+    .popsection
+    "
+);
+
+extern "C" {
+    /// Makes the syscall `number` with the given arguments, and returns its
+    /// result. Syscalls which take fewer arguments ignore the rest.
+    pub fn kidneyos_syscall(
+        number: usize,
+        arg0: usize,
+        arg1: usize,
+        arg2: usize,
+        arg3: usize,
+        arg4: usize,
+        arg5: usize,
+    ) -> i32;
+}
+
+/// Makes the syscall `$number` with up to six arguments, which must be
+/// castable to `usize`. Missing arguments are passed as 0.
+macro_rules! syscall {
+    ($number:expr $(, $arg:expr)* $(,)?) => {{
+        let args: &[usize] = &[$($arg as usize),*];
+        let arg = |i: usize| args.get(i).copied().unwrap_or(0);
+        unsafe { kidneyos_syscall($number, arg(0), arg(1), arg(2), arg(3), arg(4), arg(5)) }
+    }};
+}
 
 #[no_mangle]
 pub extern "C" fn exit(code: i32) {
-    unsafe {
-        asm!(
-            "
-            mov eax, 0x1
-            int 0x80
-            ",
-            in("ebx") code,
-        );
-    }
+    syscall!(SYS_EXIT, code);
 }
 
 #[allow(clippy::cast_possible_truncation)]
 #[no_mangle]
 pub extern "C" fn fork() -> Pid {
-    let result: i32;
-    unsafe {
-        asm!(
-            "
-            mov eax, 0x2
-            int 0x80
-            ", 
-            lateout("eax") result,
-        );
-    }
-    result as Pid
+    syscall!(SYS_FORK) as Pid
 }
 
 #[no_mangle]
 pub extern "C" fn read(fd: i32, buffer: *mut u8, count: usize) -> i32 {
-    let result;
-    unsafe {
-        asm!("
-            int 0x80
-        ", in("eax") SYS_READ, in("ebx") fd, in("ecx") buffer, in("edx") count, lateout("eax") result);
-    }
-    result
+    syscall!(SYS_READ, fd, buffer, count)
 }
 
 #[no_mangle]
 pub extern "C" fn write(fd: i32, buffer: *const u8, count: usize) -> i32 {
-    let result;
-    unsafe {
-        asm!("
-            int 0x80
-        ", in("eax") SYS_WRITE, in("ebx") fd, in("ecx") buffer, in("edx") count, lateout("eax") result);
+    syscall!(SYS_WRITE, fd, buffer, count)
+}
+
+/// Reads from `fd` into the `iovcnt` buffers in `iov`, filling each before
+/// moving on to the next.
+#[no_mangle]
+pub extern "C" fn readv(fd: i32, iov: *const IoVec, iovcnt: i32) -> i32 {
+    syscall!(SYS_READV, fd, iov, iovcnt)
+}
+
+/// Writes the `iovcnt` buffers in `iov` to `fd`, one after another.
+#[no_mangle]
+pub extern "C" fn writev(fd: i32, iov: *const IoVec, iovcnt: i32) -> i32 {
+    syscall!(SYS_WRITEV, fd, iov, iovcnt)
+}
+
+/// Reads from `fd` at `offset`, without moving its file position.
+#[no_mangle]
+#[allow(clippy::cast_possible_truncation)]
+pub extern "C" fn pread64(fd: i32, buffer: *mut u8, count: usize, offset: i64) -> i32 {
+    syscall!(
+        SYS_PREAD64,
+        fd,
+        buffer,
+        count,
+        offset as u32,
+        (offset >> 32) as u32
+    )
+}
+
+/// Writes to `fd` at `offset`, without moving its file position.
+#[no_mangle]
+#[allow(clippy::cast_possible_truncation)]
+pub extern "C" fn pwrite64(fd: i32, buffer: *const u8, count: usize, offset: i64) -> i32 {
+    syscall!(
+        SYS_PWRITE64,
+        fd,
+        buffer,
+        count,
+        offset as u32,
+        (offset >> 32) as u32
+    )
+}
+
+/// Waits for one of the `nfds` files in `fds` to have one of the events it asks
+/// for, for up to `timeout` milliseconds, or forever if it's negative.
+///
+/// Returns how many files have events, which are stored in their `revents`.
+#[no_mangle]
+pub extern "C" fn poll(fds: *mut PollFd, nfds: usize, timeout: i32) -> i32 {
+    syscall!(SYS_POLL, fds, nfds, timeout)
+}
+
+/// Waits for one of the files below `nfds` in `read_fds` to be readable, one in
+/// `write_fds` to be writable, or one in `except_fds` to have an exceptional
+/// condition, for up to `timeout`, or forever if it's null.
+///
+/// Returns how many files are ready, and leaves only those in the sets.
+#[no_mangle]
+pub extern "C" fn select(
+    nfds: i32,
+    read_fds: *mut FdSet,
+    write_fds: *mut FdSet,
+    except_fds: *mut FdSet,
+    timeout: *mut TimeVal,
+) -> i32 {
+    syscall!(SYS_SELECT, nfds, read_fds, write_fds, except_fds, timeout)
+}
+
+/// Supports the terminal requests `TCGETS`, `TCSETS`, `TCSETSW`, `TCSETSF`,
+/// `TIOCGPGRP`, `TIOCSPGRP` and `TIOCGWINSZ`.
+#[no_mangle]
+pub extern "C" fn ioctl(fd: i32, request: usize, arg: *mut c_void) -> i32 {
+    syscall!(SYS_IOCTL, fd, request, arg)
+}
+
+/// Gets the settings of the terminal `fd`.
+#[no_mangle]
+pub extern "C" fn tcgetattr(fd: i32, termios: *mut Termios) -> i32 {
+    ioctl(fd, TCGETS, termios.cast())
+}
+
+/// Changes the settings of the terminal `fd`. `optional_actions` is one of
+/// `TCSANOW`, `TCSADRAIN` or `TCSAFLUSH`, which also discards unread input.
+#[no_mangle]
+#[allow(clippy::cast_possible_truncation)]
+pub extern "C" fn tcsetattr(fd: i32, optional_actions: i32, termios: *const Termios) -> i32 {
+    let request = match optional_actions {
+        TCSANOW => TCSETS,
+        TCSADRAIN => TCSETSW,
+        TCSAFLUSH => TCSETSF,
+        _ => return -(EINVAL as i32),
+    };
+    ioctl(fd, request, termios.cast_mut().cast())
+}
+
+/// The foreground process group of the terminal `fd`.
+#[no_mangle]
+pub extern "C" fn tcgetpgrp(fd: i32) -> i32 {
+    let mut pgid = 0i32;
+    match ioctl(fd, TIOCGPGRP, core::ptr::addr_of_mut!(pgid).cast()) {
+        0 => pgid,
+        error => error,
     }
-    result
+}
+
+/// Makes `pgid` the foreground process group of the terminal `fd`.
+#[no_mangle]
+pub extern "C" fn tcsetpgrp(fd: i32, pgid: Pid) -> i32 {
+    let pgid = i32::from(pgid);
+    ioctl(fd, TIOCSPGRP, core::ptr::addr_of!(pgid).cast_mut().cast())
 }
 
 #[no_mangle]
 pub extern "C" fn open(name: *const c_char, flags: usize) -> i32 {
-    let result;
-    unsafe {
-        asm!("
-            int 0x80
-        ", in("eax") SYS_OPEN, in("ebx") name, in("ecx") flags, lateout("eax") result);
-    }
-    result
+    syscall!(SYS_OPEN, name, flags)
+}
+
+/// Gets or sets the flags of `fd` with `F_GETFD`, `F_SETFD`, `F_GETFL` and
+/// `F_SETFL`, or duplicates it into the lowest free file descriptor which is
+/// at least `arg` with `F_DUPFD` and `F_DUPFD_CLOEXEC`.
+#[no_mangle]
+pub extern "C" fn fcntl(fd: i32, cmd: i32, arg: usize) -> i32 {
+    syscall!(SYS_FCNTL, fd, cmd, arg)
 }
 
 #[no_mangle]
 pub extern "C" fn close(fd: i32) -> i32 {
-    let result;
-    unsafe {
-        asm!("
-            int 0x80
-        ", in("eax") SYS_CLOSE, in("ebx") fd, lateout("eax") result);
-    }
-    result
+    syscall!(SYS_CLOSE, fd)
 }
 
 #[no_mangle]
 pub extern "C" fn lseek64(fd: i32, offset: i64, whence: i32) -> i64 {
     let mut offset = offset;
-    let result: i32;
-    unsafe {
-        asm!("
-            int 0x80
-        ", in("eax") SYS_LSEEK64,
-            in("ebx") fd, in("ecx") core::ptr::addr_of_mut!(offset),
-            in("edx") whence, lateout("eax") result);
-    }
+    let result = syscall!(SYS_LSEEK64, fd, core::ptr::addr_of_mut!(offset), whence);
     if result < 0 {
         result.into()
     } else {
@@ -107,146 +241,83 @@ pub extern "C" fn lseek64(fd: i32, offset: i64, whence: i32) -> i64 {
 
 #[no_mangle]
 pub extern "C" fn getcwd(buf: *mut i8, size: usize) -> i32 {
-    let result;
-    unsafe {
-        asm!("
-            int 0x80
-        ", in("eax") SYS_GETCWD, in("ebx") buf, in("ecx") size, lateout("eax") result);
-    }
-    result
+    syscall!(SYS_GETCWD, buf, size)
 }
 
 #[no_mangle]
 pub extern "C" fn chdir(path: *const c_char) -> i32 {
-    let result;
-    unsafe {
-        asm!("
-            int 0x80
-        ", in("eax") SYS_CHDIR, in("ebx") path, lateout("eax") result);
-    }
-    result
+    syscall!(SYS_CHDIR, path)
 }
 
 #[no_mangle]
 pub extern "C" fn mkdir(path: *const c_char) -> i32 {
-    let result;
-    unsafe {
-        asm!("
-            int 0x80
-        ", in("eax") SYS_MKDIR, in("ebx") path, lateout("eax") result);
-    }
-    result
+    syscall!(SYS_MKDIR, path)
+}
+
+/// Creates a file of the type in the `S_IFMT` bits of `mode`. Only named pipes,
+/// `S_IFIFO`, are supported, and `dev` is ignored.
+#[no_mangle]
+pub extern "C" fn mknod(path: *const c_char, mode: u32, dev: u32) -> i32 {
+    syscall!(SYS_MKNOD, path, mode, dev)
+}
+
+/// Creates a named pipe at `path`.
+#[no_mangle]
+pub extern "C" fn mkfifo(path: *const c_char) -> i32 {
+    mknod(path, S_IFIFO, 0)
 }
 
 #[no_mangle]
 pub extern "C" fn fstat(fd: i32, statbuf: *mut Stat) -> i32 {
-    let result;
-    unsafe {
-        asm!("
-            int 0x80
-        ", in("eax") SYS_FSTAT, in("ebx") fd, in("ecx") statbuf, lateout("eax") result);
-    }
-    result
+    syscall!(SYS_FSTAT, fd, statbuf)
 }
 
 #[no_mangle]
 pub extern "C" fn unlink(path: *const c_char) -> i32 {
-    let result;
-    unsafe {
-        asm!("
-            int 0x80
-        ", in("eax") SYS_UNLINK, in("ebx") path, lateout("eax") result);
-    }
-    result
+    syscall!(SYS_UNLINK, path)
 }
 
 #[no_mangle]
 pub extern "C" fn link(source: *const c_char, dest: *const c_char) -> i32 {
-    let result;
-    unsafe {
-        asm!("
-            int 0x80
-        ", in("eax") SYS_LINK, in("ebx") source, in("ecx") dest, lateout("eax") result);
-    }
-    result
+    syscall!(SYS_LINK, source, dest)
 }
 
 #[no_mangle]
 pub extern "C" fn symlink(source: *const c_char, dest: *const c_char) -> i32 {
-    let result;
-    unsafe {
-        asm!("
-            int 0x80
-        ", in("eax") SYS_SYMLINK, in("ebx") source, in("ecx") dest, lateout("eax") result);
-    }
-    result
+    syscall!(SYS_SYMLINK, source, dest)
 }
 
 #[no_mangle]
 pub extern "C" fn rename(source: *const c_char, dest: *const c_char) -> i32 {
-    let result;
-    unsafe {
-        asm!("
-            int 0x80
-        ", in("eax") SYS_RENAME, in("ebx") source, in("ecx") dest, lateout("eax") result);
-    }
-    result
+    syscall!(SYS_RENAME, source, dest)
 }
 
 #[no_mangle]
 pub extern "C" fn rmdir(path: *const c_char) -> i32 {
-    let result;
-    unsafe {
-        asm!("
-            int 0x80
-        ", in("eax") SYS_RMDIR, in("ebx") path, lateout("eax") result);
-    }
-    result
+    syscall!(SYS_RMDIR, path)
 }
 
 #[no_mangle]
 pub extern "C" fn getdents(fd: i32, output: *mut Dirent, size: usize) -> i32 {
-    let result;
-    unsafe {
-        asm!("
-            int 0x80
-        ", in("eax") SYS_GETDENTS, in("ebx") fd, in("ecx") output, in("edx") size, lateout("eax") result);
-    }
-    result
+    syscall!(SYS_GETDENTS, fd, output, size)
 }
 
 #[no_mangle]
 pub extern "C" fn ftruncate(fd: i32, size: u64) -> i32 {
-    let result;
     #[allow(clippy::cast_possible_truncation)]
     let size_lo = size as u32;
     let size_hi = (size >> 32) as u32;
-    unsafe {
-        asm!("
-            int 0x80
-        ", in("eax") SYS_FTRUNCATE, in("ebx") fd, in("ecx") size_lo, in("edx") size_hi, lateout("eax") result);
-    }
-    result
+    syscall!(SYS_FTRUNCATE, fd, size_lo, size_hi)
 }
 
 #[no_mangle]
 pub extern "C" fn sync() -> i32 {
-    let result;
-    unsafe {
-        asm!("int 0x80", in("eax") SYS_SYNC, lateout("eax") result);
-    }
-    result
+    syscall!(SYS_SYNC)
 }
 
 #[no_mangle]
 pub extern "C" fn unmount(path: *const c_char) -> i32 {
-    let result;
-    unsafe {
-        asm!("
-            int 0x80
-        ", in("eax") SYS_UNMOUNT, in("ebx") path, lateout("eax") result);
-    }
-    result
+    syscall!(SYS_UNMOUNT, path)
 }
 
 #[no_mangle]
@@ -255,79 +326,200 @@ pub extern "C" fn mount(
     target: *const c_char,
     filesystem_type: *const c_char,
 ) -> i32 {
-    let result;
-    unsafe {
-        asm!("
-            int 0x80
-        ", in("eax") SYS_MOUNT, in("ebx") device, in("ecx") target, in("edx") filesystem_type, lateout("eax") result);
-    }
-    result
+    syscall!(SYS_MOUNT, device, target, filesystem_type)
 }
 
+/// Waits for the child `pid` to exit, or any child if `pid` is -1.
+///
+/// Returns the pid of the child which exited, and stores how it exited in
+/// `stat` if it isn't null, which can be decoded with `wifexited` and friends.
 #[no_mangle]
-#[allow(clippy::cast_possible_truncation)]
-pub extern "C" fn waitpid(pid: Pid, stat: *mut i32, options: i32) -> Pid {
-    let result: i32;
-    unsafe {
-        asm!("
-            mov eax, 0x7
-            int 0x80
-            ", 
-            in("ebx") pid,
-            in("ecx") stat,
-            in("edx") options,
-            lateout("eax") result,
-        );
-    }
-    result as Pid
+pub extern "C" fn waitpid(pid: i32, stat: *mut i32, options: i32) -> i32 {
+    syscall!(SYS_WAITPID, pid, stat, options)
 }
 
+/// Whether a waitpid status is for a child which exited normally.
 #[no_mangle]
-pub extern "C" fn dup(fd: i32) -> i32 {
-    let result: i32;
-
-    unsafe {
-        asm!(
-            "int 0x80",
-            in("eax") SYS_DUP,
-            in("ebx") fd,
-            lateout("eax") result,
-        );
-    }
+pub extern "C" fn wifexited(status: i32) -> bool {
+    status & 0x7f == 0
+}
 
-    result
+/// The exit code of a child which exited normally.
+#[no_mangle]
+pub extern "C" fn wexitstatus(status: i32) -> i32 {
+    (status >> 8) & 0xff
 }
 
+/// Whether a waitpid status is for a child which was killed by a signal.
 #[no_mangle]
-pub extern "C" fn dup2(old_fd: i32, new_fd: i32) -> i32 {
-    let result: i32;
-
-    unsafe {
-        asm!(
-            "int 0x80",
-            in("eax") SYS_DUP2,
-            in("ebx") old_fd,
-            in("ecx") new_fd,
-            lateout("eax") result,
-        );
-    }
+pub extern "C" fn wifsignaled(status: i32) -> bool {
+    status & 0x7f != 0
+}
 
-    result
+/// The signal which killed a child.
+#[no_mangle]
+pub extern "C" fn wtermsig(status: i32) -> i32 {
+    status & 0x7f
+}
+
+/// Whether a child was killed for causing a CPU fault.
+#[no_mangle]
+pub extern "C" fn wcoredump(status: i32) -> bool {
+    status & 0x80 != 0
+}
+
+#[no_mangle]
+pub extern "C" fn dup(fd: i32) -> i32 {
+    syscall!(SYS_DUP, fd)
+}
+
+#[no_mangle]
+pub extern "C" fn dup2(old_fd: i32, new_fd: i32) -> i32 {
+    syscall!(SYS_DUP2, old_fd, new_fd)
 }
 #[no_mangle]
 pub extern "C" fn pipe(fds: *mut i32) -> i32 {
-    let result: i32;
-
-    unsafe {
-        asm!(
-            "int 0x80",
-            in("eax") SYS_PIPE,
-            in("ebx") fds,
-            lateout("eax") result,
-        );
+    syscall!(SYS_PIPE, fds)
+}
+
+/// Creates a socket of `type` (`SOCK_STREAM` or `SOCK_DGRAM`, with any of
+/// `SOCK_NONBLOCK` and `SOCK_CLOEXEC`) in `domain`, which must be `AF_UNIX`.
+/// `protocol` must be 0.
+#[no_mangle]
+pub extern "C" fn socket(domain: i32, r#type: i32, protocol: i32) -> i32 {
+    syscall!(SYS_SOCKET, domain, r#type, protocol)
+}
+
+/// Creates two sockets connected to each other, storing their file
+/// descriptors in `fds`.
+#[no_mangle]
+pub extern "C" fn socketpair(domain: i32, r#type: i32, protocol: i32, fds: *mut i32) -> i32 {
+    syscall!(SYS_SOCKETPAIR, domain, r#type, protocol, fds)
+}
+
+/// Binds the socket `fd` to the path in `addr`, which mustn't exist yet.
+#[no_mangle]
+pub extern "C" fn bind(fd: i32, addr: *const SockAddrUn, addr_len: SockLen) -> i32 {
+    syscall!(SYS_BIND, fd, addr, addr_len)
+}
+
+/// Starts accepting connections on the bound stream socket `fd`, queueing up
+/// to `backlog` of them until they're accepted.
+#[no_mangle]
+pub extern "C" fn listen(fd: i32, backlog: i32) -> i32 {
+    syscall!(SYS_LISTEN, fd, backlog)
+}
+
+/// Waits for a connection to the listening socket `fd`, and returns a new
+/// socket connected to it, with any of `SOCK_NONBLOCK` and `SOCK_CLOEXEC` in
+/// `flags`. If `addr` isn't null, the other end's address is stored there.
+#[no_mangle]
+pub extern "C" fn accept4(
+    fd: i32,
+    addr: *mut SockAddrUn,
+    addr_len: *mut SockLen,
+    flags: i32,
+) -> i32 {
+    syscall!(SYS_ACCEPT4, fd, addr, addr_len, flags)
+}
+
+#[no_mangle]
+pub extern "C" fn accept(fd: i32, addr: *mut SockAddrUn, addr_len: *mut SockLen) -> i32 {
+    accept4(fd, addr, addr_len, 0)
+}
+
+/// Connects the socket `fd` to the one bound to `addr`. Stream sockets can
+/// then send to and receive from it, and datagram sockets send to it by
+/// default.
+#[no_mangle]
+pub extern "C" fn connect(fd: i32, addr: *const SockAddrUn, addr_len: SockLen) -> i32 {
+    syscall!(SYS_CONNECT, fd, addr, addr_len)
+}
+
+/// Sends `len` bytes from `buf` on the socket `fd`, to the datagram socket
+/// bound to `addr` if it isn't null.
+#[no_mangle]
+pub extern "C" fn sendto(
+    fd: i32,
+    buf: *const u8,
+    len: usize,
+    flags: i32,
+    addr: *const SockAddrUn,
+    addr_len: SockLen,
+) -> i32 {
+    syscall!(SYS_SENDTO, fd, buf, len, flags, addr, addr_len)
+}
+
+#[no_mangle]
+pub extern "C" fn send(fd: i32, buf: *const u8, len: usize, flags: i32) -> i32 {
+    sendto(fd, buf, len, flags, core::ptr::null(), 0)
+}
+
+/// Receives up to `len` bytes into `buf` from the socket `fd`. If `addr` isn't
+/// null, the address of the datagram socket which sent them is stored there.
+#[no_mangle]
+pub extern "C" fn recvfrom(
+    fd: i32,
+    buf: *mut u8,
+    len: usize,
+    flags: i32,
+    addr: *mut SockAddrUn,
+    addr_len: *mut SockLen,
+) -> i32 {
+    syscall!(SYS_RECVFROM, fd, buf, len, flags, addr, addr_len)
+}
+
+#[no_mangle]
+pub extern "C" fn recv(fd: i32, buf: *mut u8, len: usize, flags: i32) -> i32 {
+    recvfrom(
+        fd,
+        buf,
+        len,
+        flags,
+        core::ptr::null_mut(),
+        core::ptr::null_mut(),
+    )
+}
+
+/// Stops receiving on the socket `fd`, sending on it, or both, as `how`
+/// (`SHUT_RD`, `SHUT_WR` or `SHUT_RDWR`) says.
+#[no_mangle]
+pub extern "C" fn shutdown(fd: i32, how: i32) -> i32 {
+    syscall!(SYS_SHUTDOWN, fd, how)
+}
+
+fn sys_brk(addr: usize) -> usize {
+    syscall!(SYS_BRK, addr) as usize
+}
+
+/// Sets the end of the heap to `addr`.
+///
+/// Returns 0 on success, or `-ENOMEM` if the heap couldn't be moved there.
+#[no_mangle]
+#[allow(clippy::cast_possible_truncation)]
+pub extern "C" fn brk(addr: *mut c_void) -> i32 {
+    if sys_brk(addr as usize) == addr as usize {
+        0
+    } else {
+        -(ENOMEM as i32)
     }
+}
 
-    result
+/// Grows the heap by `increment` bytes, or shrinks it if `increment` is
+/// negative.
+///
+/// Returns the previous end of the heap, which is the start of the new memory
+/// when growing, or `(void *)-1` on failure.
+#[no_mangle]
+pub extern "C" fn sbrk(increment: isize) -> *mut c_void {
+    let old_break = sys_brk(0);
+    let Some(new_break) = old_break.checked_add_signed(increment) else {
+        return usize::MAX as *mut c_void;
+    };
+    if increment != 0 && sys_brk(new_break) != new_break {
+        return usize::MAX as *mut c_void;
+    }
+    old_break as *mut c_void
 }
 
 #[no_mangle]
@@ -336,123 +528,159 @@ pub extern "C" fn execve(
     argv: *const *const c_char,
     envp: *const *const c_char,
 ) -> i32 {
-    let result: i32;
-
-    unsafe {
-        asm!(
-            "int 0x80",
-            in("eax") SYS_EXECVE,
-            in("ebx") filename,
-            in("ecx") argv,
-            in("edx") envp,
-            lateout("eax") result
-        )
-    }
-
-    result
+    syscall!(SYS_EXECVE, filename, argv, envp)
 }
 
 // Seems to reference __kernel_timespec as the inputs for this syscall.
 // Not sure if we have this implemented.
 #[no_mangle]
 pub extern "C" fn nanosleep(duration: *const Timespec, remainder: *mut Timespec) -> i32 {
-    let result: i32;
-    unsafe {
-        asm!(
-            "
-            mov eax, 0xA2
-            int 0x80
-            ", 
-            in("ebx") duration,
-            in("ecx") remainder,
-            lateout("eax") result,
-        );
-    }
-    result
+    syscall!(SYS_NANOSLEEP, duration, remainder)
+}
+
+#[no_mangle]
+pub extern "C" fn uname(name: *mut UtsName) -> i32 {
+    syscall!(SYS_UNAME, name)
 }
 
 #[no_mangle]
 #[allow(clippy::cast_possible_truncation)]
 pub extern "C" fn getpid() -> Pid {
-    let result: i32;
-    unsafe {
-        asm!(
-            "
-            mov eax, 0x14
-            int 0x80
-            ",
-            lateout("eax") result
-        )
-    }
-    result as Pid
+    syscall!(SYS_GETPID) as Pid
 }
 
 #[no_mangle]
 #[allow(clippy::cast_possible_truncation)]
 pub extern "C" fn getppid() -> Pid {
-    let result: i32;
-    unsafe {
-        asm!(
-            "
-            mov eax, 0x40
-            int 0x80
-            ",
-            lateout("eax") result
-        )
+    syscall!(SYS_GETPPID) as Pid
+}
+
+/// Moves the process `pid`, which must be the caller or one of its children,
+/// into the process group `pgid`. Either can be 0 for the caller, or for a new
+/// group led by `pid`.
+#[no_mangle]
+pub extern "C" fn setpgid(pid: Pid, pgid: Pid) -> i32 {
+    syscall!(SYS_SETPGID, pid, pgid)
+}
+
+/// The process group of the process `pid`, or of the caller if it's 0.
+#[no_mangle]
+pub extern "C" fn getpgid(pid: Pid) -> i32 {
+    syscall!(SYS_GETPGID, pid)
+}
+
+#[no_mangle]
+#[allow(clippy::cast_possible_truncation)]
+pub extern "C" fn getpgrp() -> Pid {
+    syscall!(SYS_GETPGRP) as Pid
+}
+
+#[no_mangle]
+#[allow(clippy::cast_possible_truncation)]
+pub extern "C" fn gettid() -> Tid {
+    syscall!(SYS_GETTID) as Tid
+}
+
+/// Starts a new thread in this process, which calls `entry` with `arg`.
+///
+/// The thread runs on `stack`, which should point just past the end of the
+/// memory set aside for it, or on a newly allocated stack if `stack` is null.
+/// `entry` must not return. Instead, the thread should end by calling
+/// `thread_exit`.
+///
+/// Returns the tid of the new thread, or a negative error code.
+#[no_mangle]
+pub extern "C" fn thread_create(
+    entry: extern "C" fn(*mut c_void),
+    arg: *mut c_void,
+    stack: *mut c_void,
+) -> i32 {
+    syscall!(SYS_THREAD_CREATE, entry, arg, stack)
+}
+
+/// Waits for the thread `tid` of this process to exit, and stores its exit
+/// code in `exit_code` if it isn't null.
+///
+/// Unlike most blocking calls, this is never interrupted by signals.
+#[no_mangle]
+pub extern "C" fn thread_join(tid: Tid, exit_code: *mut i32) -> i32 {
+    loop {
+        let result = syscall!(SYS_THREAD_JOIN, usize::from(tid), exit_code);
+        if result as isize != -EINTR {
+            return result;
+        }
     }
-    result as Pid
+}
+
+/// Ends the calling thread with `exit_code`, which is passed on to the thread
+/// which joins it. If this is the last thread in the process, the process
+/// exits with `exit_code`.
+#[no_mangle]
+pub extern "C" fn thread_exit(exit_code: i32) -> ! {
+    syscall!(SYS_THREAD_EXIT, exit_code);
+    // SAFETY: The kernel never returns from this syscall.
+    unsafe { core::hint::unreachable_unchecked() }
+}
+
+/// With `FUTEX_WAIT`, blocks until another thread wakes the futex at `uaddr`,
+/// as long as it contains `val`, or until `timeout` passes if it isn't null.
+/// With `FUTEX_WAKE`, wakes up to `val` threads waiting on the futex at
+/// `uaddr`, and returns how many were woken.
+#[no_mangle]
+pub extern "C" fn futex(uaddr: *mut u32, op: i32, val: u32, timeout: *const Timespec) -> i32 {
+    syscall!(SYS_FUTEX, uaddr, op, val, timeout)
+}
+
+/// Sets the running thread's thread-local storage segment to the one described
+/// by `u_info`. If its `entry_number` is -1, the GDT entry used for it is
+/// written back, and the segment can then be used by loading
+/// `(entry_number << 3) | 3` into `gs`.
+#[no_mangle]
+pub extern "C" fn set_thread_area(u_info: *mut UserDesc) -> i32 {
+    syscall!(SYS_SET_THREAD_AREA, u_info)
+}
+
+#[no_mangle]
+pub extern "C" fn kill(pid: i32, signal: i32) -> i32 {
+    syscall!(SYS_KILL, pid, signal)
+}
+
+/// If `action` doesn't set `SA_RESTORER`, the kernel arranges for the handler
+/// to return to a trampoline on the stack, which makes the sigreturn syscall.
+#[no_mangle]
+pub extern "C" fn sigaction(
+    signal: i32,
+    action: *const SigAction,
+    old_action: *mut SigAction,
+) -> i32 {
+    syscall!(SYS_SIGACTION, signal, action, old_action)
+}
+
+#[no_mangle]
+pub extern "C" fn sigprocmask(how: i32, set: *const SigSet, old_set: *mut SigSet) -> i32 {
+    syscall!(SYS_SIGPROCMASK, how, set, old_set)
 }
 
 #[no_mangle]
 pub extern "C" fn scheduler_yield() -> i32 {
-    let result: i32;
-    unsafe {
-        asm!(
-            "
-            mov eax, 0x9E
-            int 0x80
-            ", 
-            lateout("eax") result,
-        );
-    }
-    result
+    syscall!(SYS_SCHED_YIELD)
 }
 
 #[no_mangle]
 pub extern "C" fn clock_gettime(clock_id: i32, timespec: *mut Timespec) -> i32 {
-    let result: i32;
-    unsafe {
-        asm!(
-            "
-            mov eax, 0x109
-            int 0x80
-            ",
-            in("ebx") clock_id,
-            in("ecx") timespec,
-            lateout("eax") result,
-        )
-    }
-    result
+    syscall!(SYS_CLOCK_GETTIME, clock_id, timespec)
 }
 
 #[no_mangle]
 pub extern "C" fn getrandom(buf: *mut i8, size: usize, flags: usize) -> i32 {
-    let result: i32;
-    unsafe {
-        asm!(
-            "
-            mov eax, 0x163
-            int 0x80
-            ",
-            in("ebx") buf,
-            in("ecx") size,
-            in("edx") flags,
-            lateout("eax") result,
-        )
-    }
-    result
+    syscall!(SYS_GETRANDOM, buf, size, flags)
 }
 
+/// Maps `length` bytes at `offset` in `fd`, or anonymous memory if `flags`
+/// includes `MAP_ANONYMOUS`, and returns where they were mapped, or a negative
+/// error code cast to a pointer.
+///
+/// `offset` must be a multiple of `MMAP2_PAGE_SIZE`.
 #[no_mangle]
 pub extern "C" fn mmap(
     addr: *mut c_void,
@@ -462,24 +690,14 @@ pub extern "C" fn mmap(
     fd: i32,
     offset: i64,
 ) -> *mut c_void {
-    let options = MMapOptions {
-        addr,
-        length,
-        prot,
-        fd,
-        flags,
-        offset,
+    let pages = match u32::try_from(offset / MMAP2_PAGE_SIZE) {
+        Ok(pages) if offset % MMAP2_PAGE_SIZE == 0 => pages,
+        _ => return -EINVAL as *mut c_void,
     };
-    let result: *mut c_void;
-    unsafe {
-        asm!(
-            "
-            int 0x80
-            ",
-            in("eax") SYS_MMAP,
-            in("ebx") &options,
-            lateout("eax") result,
-        )
-    }
-    result
+    syscall!(SYS_MMAP2, addr, length, prot, flags, fd, pages) as *mut c_void
+}
+
+#[no_mangle]
+pub extern "C" fn munmap(addr: *mut c_void, length: usize) -> i32 {
+    syscall!(SYS_MUNMAP, addr, length)
 }